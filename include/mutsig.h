@@ -0,0 +1,144 @@
+#ifndef MUTSIG_H
+#define MUTSIG_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ The outcome of a call.
+ */
+typedef enum MutsigStatus {
+  /*
+   The call succeeded
+   */
+  MUTSIG_STATUS_OK = 0,
+  /*
+   An argument is a null pointer, not UTF-8 or out of range
+   */
+  MUTSIG_STATUS_INVALID_ARGUMENT = 1,
+  /*
+   The counting failed, e.g., as a file can not be read
+   */
+  MUTSIG_STATUS_ERROR = 2,
+  /*
+   The counting panicked
+   */
+  MUTSIG_STATUS_PANIC = 3,
+} MutsigStatus;
+
+/*
+ The counts of the variants of a VCF file per channel and sample, opaque to C.
+ */
+typedef struct MutsigResult MutsigResult;
+
+/*
+ The parameters of the counting.
+ */
+typedef struct MutsigOptions {
+  /*
+   The number of bases of the sequence context on each side of a substitution
+   */
+  uint8_t window;
+  /*
+   The number of threads used for decompressing the VCF
+   */
+  size_t threads;
+  /*
+   The largest number of channels to allocate for the window
+   */
+  size_t max_channels;
+} MutsigOptions;
+
+/*
+ Return the default parameters, i.e., substitutions without context counted by one thread.
+ */
+struct MutsigOptions mutsig_options_default(void);
+
+/*
+ Count the variants of the VCF file at `vcf` against the reference sequences (FASTA) at
+ `reference` with the `options`, or the default ones if null. On success, `*result` is set
+ to the counts, which the caller releases with `mutsig_result_free`. Otherwise, `*result`
+ is set to null and `mutsig_last_error` describes the failure.
+
+ # Safety
+
+ `vcf` and `reference` must be null or NUL-terminated strings, `options` must be null or
+ point to valid options and `result` must be null or point to writable memory.
+ */
+enum MutsigStatus mutsig_count(const char *vcf,
+                               const char *reference,
+                               const struct MutsigOptions *options,
+                               struct MutsigResult **result);
+
+/*
+ Return the message of the last failed `mutsig_count` on this thread, or null if none
+ failed. The message is valid until the next call of `mutsig_count` on this thread.
+ */
+const char *mutsig_last_error(void);
+
+/*
+ Return the number of counted samples, or 0 if `result` is null.
+
+ # Safety
+
+ `result` must be null or a result of `mutsig_count` that was not released.
+ */
+size_t mutsig_result_sample_count(const struct MutsigResult *result);
+
+/*
+ Return the name of the sample at `index`, or null if `result` is null or it has no such
+ sample. The name is owned by the result.
+
+ # Safety
+
+ `result` must be null or a result of `mutsig_count` that was not released.
+ */
+const char *mutsig_result_sample(const struct MutsigResult *result, size_t index);
+
+/*
+ Return the number of channels, or 0 if `result` is null.
+
+ # Safety
+
+ `result` must be null or a result of `mutsig_count` that was not released.
+ */
+size_t mutsig_result_channel_count(const struct MutsigResult *result);
+
+/*
+ Return the label of the channel at `index`, or null if `result` is null or it has no such
+ channel. The label is owned by the result.
+
+ # Safety
+
+ `result` must be null or a result of `mutsig_count` that was not released.
+ */
+const char *mutsig_result_channel(const struct MutsigResult *result, size_t index);
+
+/*
+ Set `*count` to the count of the channel at `channel` in the sample at `sample`.
+
+ # Safety
+
+ `result` must be null or a result of `mutsig_count` that was not released and `count`
+ must be null or point to writable memory.
+ */
+enum MutsigStatus mutsig_result_count(const struct MutsigResult *result,
+                                      size_t channel,
+                                      size_t sample,
+                                      uint64_t *count);
+
+/*
+ Release a result of `mutsig_count`, including its strings. Null is ignored.
+
+ # Safety
+
+ `result` must be null or a result of `mutsig_count` that was not released.
+ */
+void mutsig_result_free(struct MutsigResult *result);
+
+#endif /* MUTSIG_H */
@@ -0,0 +1,186 @@
+use crate::fit::{self, Matrix};
+use std::collections::HashMap;
+use std::io::Write;
+
+/// Combine the matrices of counts per channel (rows) and sample (columns), each labeled by
+/// the name of its file, into one with the union of their samples. The channels of all
+/// matrices must be identical, independent of their order, and are reported in the order of
+/// the first matrix. Samples in several matrices are an error, unless `sum_duplicates` is set
+/// to sum their counts.
+pub fn aggregate(matrices: &[(String, Matrix)], sum_duplicates: bool) -> Result<Matrix, String> {
+    let (first_name, first) = match matrices.first() {
+        Some(m) => m,
+        None => return Err("Can not aggregate matrices, there are none".to_owned()),
+    };
+    let keys: Vec<String> = first.rows().iter().map(|l| fit::channel_key(l)).collect();
+    let mut columns: Vec<String> = Vec::new();
+    let mut origins: Vec<&str> = Vec::new();
+    let mut values: Vec<Vec<f64>> = vec![Vec::new(); keys.len()];
+    for (name, matrix) in matrices {
+        let order = align_rows(first_name, first.rows(), &keys, name, matrix.rows())?;
+        for (column, sample) in matrix.columns().iter().enumerate() {
+            let index = match columns.iter().position(|c| c == sample) {
+                Some(i) if sum_duplicates => i,
+                Some(i) => {
+                    return Err(format!(
+                        "Can not aggregate sample '{}' of '{}', it is also in '{}' (use --sum-duplicates to sum its counts)",
+                        sample, name, origins[i]
+                    ))
+                }
+                None => {
+                    columns.push(sample.clone());
+                    origins.push(name);
+                    values.iter_mut().for_each(|row| row.push(0.0));
+                    columns.len() - 1
+                }
+            };
+            for (row, r) in values.iter_mut().zip(&order) {
+                row[index] += matrix.value(*r, column);
+            }
+        }
+    }
+    Ok(Matrix::new(first.rows().clone(), columns, values))
+}
+
+/// Return the index of each of the `first` channels with the normalized `keys` in the `rows`
+/// of the matrix `name`. The channels must be identical, including the style of their labels.
+fn align_rows(
+    first_name: &str,
+    first: &[String],
+    keys: &[String],
+    name: &str,
+    rows: &[String],
+) -> Result<Vec<usize>, String> {
+    let mut indizes: HashMap<String, usize> = HashMap::new();
+    for (i, label) in rows.iter().enumerate() {
+        if indizes.insert(fit::channel_key(label), i).is_some() {
+            return Err(format!(
+                "Can not aggregate '{}', channel '{}' is repeated",
+                name, label
+            ));
+        }
+    }
+    let mut order = Vec::with_capacity(keys.len());
+    for (label, key) in first.iter().zip(keys) {
+        match indizes.remove(key) {
+            Some(i) if rows[i] == *label => order.push(i),
+            Some(i) => {
+                return Err(format!(
+                    "Can not aggregate '{}', channel '{}' is labeled '{}' in '{}'",
+                    name, rows[i], label, first_name
+                ))
+            }
+            None => {
+                return Err(format!(
+                    "Can not aggregate '{}', channel '{}' of '{}' is missing",
+                    name, label, first_name
+                ))
+            }
+        }
+    }
+    // The first channel of the matrix that is not in the first one
+    if let Some(label) = rows
+        .iter()
+        .find(|l| indizes.contains_key(&fit::channel_key(l)))
+    {
+        return Err(format!(
+            "Can not aggregate '{}', channel '{}' is missing in '{}'",
+            name, label, first_name
+        ));
+    }
+    Ok(order)
+}
+
+/// Write the `matrix` of counts to `writer` as a tab-separated table of the channels (rows)
+/// per sample (columns) as reported by mutsig.
+pub fn write_matrix<W: Write>(mut writer: W, matrix: &Matrix) -> Result<(), String> {
+    let write_error = |e: std::io::Error| format!("Can not write matrix: {}", e);
+    writeln!(writer, "Variant\t{}", matrix.columns().join("\t")).map_err(write_error)?;
+    for (r, label) in matrix.rows().iter().enumerate() {
+        write!(writer, "{}", label).map_err(write_error)?;
+        for c in 0..matrix.columns().len() {
+            // Round off the summation errors
+            let value = (matrix.value(r, c) * 1e6).round() / 1e6;
+            write!(writer, "\t{}", value).map_err(write_error)?;
+        }
+        writeln!(writer).map_err(write_error)?;
+    }
+    writer.flush().map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(name: &str, text: &str) -> (String, Matrix) {
+        (
+            name.to_owned(),
+            fit::parse_matrix(text.as_bytes(), name).unwrap(),
+        )
+    }
+
+    fn write(matrix: &Matrix) -> String {
+        let mut out = Vec::new();
+        write_matrix(&mut out, matrix).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_aggregate_disjoint() {
+        let matrices = vec![
+            matrix("a", "Variant\tS1\tS2\nC>A\t1\t2\nC>T\t3\t4\n"),
+            matrix("b", "Variant\tS3\nC>T\t5\nC>A\t6\n"),
+        ];
+        let combined = aggregate(&matrices, false).unwrap();
+        assert_eq!(
+            write(&combined),
+            "Variant\tS1\tS2\tS3\nC>A\t1\t2\t6\nC>T\t3\t4\t5\n"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_duplicates() {
+        let matrices = vec![
+            matrix("a", "Variant\tS1\tS2\nC>A\t1\t2\nC>T\t3\t4\n"),
+            matrix("b", "Variant\tS2\nC>A\t0.5\nC>T\t1\n"),
+        ];
+        let error = aggregate(&matrices, false).unwrap_err();
+        assert!(
+            error.contains("'S2' of 'b'") && error.contains("'a'"),
+            "{}",
+            error
+        );
+        let combined = aggregate(&matrices, true).unwrap();
+        assert_eq!(
+            write(&combined),
+            "Variant\tS1\tS2\nC>A\t1\t2.5\nC>T\t3\t5\n"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_mismatched_channels() {
+        let first = matrix("a", "Variant\tS1\nC>A\t1\nC>T\t3\n");
+        let missing = matrix("b", "Variant\tS2\nC>A\t1\n");
+        let error = aggregate(&[first, missing], false).unwrap_err();
+        assert_eq!(
+            error,
+            "Can not aggregate 'b', channel 'C>T' of 'a' is missing"
+        );
+
+        let first = matrix("a", "Variant\tS1\nC>A\t1\nC>T\t3\n");
+        let extra = matrix("b", "Variant\tS2\nC>A\t1\nC>T\t1\nT>A\t1\n");
+        let error = aggregate(&[first, extra], false).unwrap_err();
+        assert_eq!(
+            error,
+            "Can not aggregate 'b', channel 'T>A' is missing in 'a'"
+        );
+
+        let first = matrix("a", "Variant\tS1\nACA>A\t1\nACA>T\t3\n");
+        let cosmic = matrix("b", "Variant\tS2\nA[C>A]A\t1\nA[C>T]A\t1\n");
+        let error = aggregate(&[first, cosmic], false).unwrap_err();
+        assert_eq!(
+            error,
+            "Can not aggregate 'b', channel 'A[C>A]A' is labeled 'ACA>A' in 'a'"
+        );
+    }
+}
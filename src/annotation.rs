@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::BufRead;
+use std::path::Path;
+
+/// The transcriptional status of the reference (forward) strand at a genomic position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TranscriptionStrand {
+    /// Overlapped by a gene on the minus strand only, i.e., the reference strand is the template
+    Transcribed,
+    /// Overlapped by a gene on the plus strand only, i.e., the reference strand is the coding strand
+    Untranscribed,
+    /// Overlapped by genes on both strands
+    Bidirectional,
+    /// Not overlapped by any gene
+    Intergenic,
+}
+
+impl TranscriptionStrand {
+    /// Return the transcriptional status of the strand carrying the pyrimidine of a base
+    /// pair, which is the reference strand if `forward` and the reverse strand otherwise.
+    pub fn of_pyrimidine(self, forward: bool) -> TranscriptionStrand {
+        match self {
+            TranscriptionStrand::Transcribed if !forward => TranscriptionStrand::Untranscribed,
+            TranscriptionStrand::Untranscribed if !forward => TranscriptionStrand::Transcribed,
+            s => s,
+        }
+    }
+}
+
+impl fmt::Display for TranscriptionStrand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscriptionStrand::Transcribed => write!(f, "transcribed"),
+            TranscriptionStrand::Untranscribed => write!(f, "untranscribed"),
+            TranscriptionStrand::Bidirectional => write!(f, "bidirectional"),
+            TranscriptionStrand::Intergenic => write!(f, "intergenic"),
+        }
+    }
+}
+
+/// Flag for segments overlapped by a gene on the plus strand.
+const PLUS: u8 = 1;
+/// Flag for segments overlapped by a gene on the minus strand.
+const MINUS: u8 = 2;
+
+/// Gene footprints and their strands read from a GTF or GFF file. The footprints of
+/// each contig are stored as sorted, non-overlapping segments (0-based, half-open)
+/// carrying the strands of all genes overlapping them.
+#[derive(Debug, PartialEq)]
+pub struct Annotation {
+    inner: BTreeMap<String, Vec<(u64, u64, u8)>>,
+}
+
+impl Annotation {
+    /// Read the features of type `feature` (e.g., `gene` or `transcript`) from the
+    /// (optionally gzip compressed) GTF or GFF file at `path`.
+    pub fn from_path<P: AsRef<Path>>(path: P, feature: &str) -> Result<Self, String> {
+        let name = path.as_ref().display().to_string();
+        Annotation::parse(crate::input::open(path)?, &name, feature)
+    }
+
+    /// Parse the features of type `feature` from GTF or GFF formatted `reader`. Both
+    /// formats share the first eight columns with 1-based, inclusive coordinates.
+    fn parse<R: BufRead>(reader: R, name: &str, feature: &str) -> Result<Self, String> {
+        let mut features: BTreeMap<String, Vec<(u64, u64, u8)>> = BTreeMap::new();
+        let mut unstranded = 0;
+        for (i, line) in reader.lines().enumerate() {
+            let line = match line {
+                Ok(l) => l,
+                Err(e) => return Err(format!("Can not read '{}': {}", name, e)),
+            };
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 8 {
+                return Err(format!(
+                    "Expected at least 8 columns in line {} of '{}'",
+                    i + 1,
+                    name
+                ));
+            }
+            if fields[2] != feature {
+                continue;
+            }
+            let (start, end) = match (fields[3].parse::<u64>(), fields[4].parse::<u64>()) {
+                (Ok(s), Ok(e)) if s >= 1 && e >= s => (s - 1, e),
+                _ => {
+                    return Err(format!(
+                        "Invalid coordinates {}-{} in line {} of '{}'",
+                        fields[3],
+                        fields[4],
+                        i + 1,
+                        name
+                    ))
+                }
+            };
+            let strand = match fields[6] {
+                "+" => PLUS,
+                "-" => MINUS,
+                _ => {
+                    unstranded += 1;
+                    continue;
+                }
+            };
+            features
+                .entry(fields[0].to_owned())
+                .or_default()
+                .push((start, end, strand));
+        }
+        if unstranded > 0 {
+            warn!(
+                "Ignored {} {} features without strand in '{}'",
+                unstranded, feature, name
+            );
+        }
+
+        Ok(Annotation {
+            inner: features
+                .into_iter()
+                .map(|(contig, f)| (contig, segments(f)))
+                .collect(),
+        })
+    }
+
+    /// Return the transcriptional status of the reference strand at the 0-based `position` on `contig`.
+    pub fn strand_at(&self, contig: &str, position: u64) -> TranscriptionStrand {
+        let strands = match self.inner.get(contig) {
+            None => 0,
+            Some(v) => {
+                let i = v.partition_point(|&(s, _, _)| s <= position);
+                if i > 0 && v[i - 1].1 > position {
+                    v[i - 1].2
+                } else {
+                    0
+                }
+            }
+        };
+        match strands {
+            PLUS => TranscriptionStrand::Untranscribed,
+            MINUS => TranscriptionStrand::Transcribed,
+            0 => TranscriptionStrand::Intergenic,
+            _ => TranscriptionStrand::Bidirectional,
+        }
+    }
+}
+
+/// Split possibly overlapping `features` into sorted, non-overlapping segments carrying
+/// the strand flags of all features overlapping them. Uncovered gaps are left out.
+fn segments(features: Vec<(u64, u64, u8)>) -> Vec<(u64, u64, u8)> {
+    // Sweep over the feature boundaries and count the active features per strand
+    let mut events: Vec<(u64, i64, i64)> = Vec::with_capacity(features.len() * 2);
+    for (start, end, strand) in features {
+        let (plus, minus) = if strand == PLUS { (1, 0) } else { (0, 1) };
+        events.push((start, plus, minus));
+        events.push((end, -plus, -minus));
+    }
+    events.sort();
+
+    let mut segments: Vec<(u64, u64, u8)> = Vec::new();
+    let (mut plus, mut minus) = (0, 0);
+    let mut i = 0;
+    while i < events.len() {
+        let position = events[i].0;
+        while i < events.len() && events[i].0 == position {
+            plus += events[i].1;
+            minus += events[i].2;
+            i += 1;
+        }
+        let strands = if plus > 0 { PLUS } else { 0 } | if minus > 0 { MINUS } else { 0 };
+        if strands != 0 && i < events.len() {
+            let end = events[i].0;
+            match segments.last_mut() {
+                // Extend the previous segment if it is adjacent and has the same strands
+                Some(last) if last.1 == position && last.2 == strands => last.1 = end,
+                _ => segments.push((position, end, strands)),
+            }
+        }
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testdata(name: &str) -> String {
+        format!("{}/testdata/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn test_segments() {
+        let s = segments(vec![
+            (0, 10, PLUS),
+            (5, 15, MINUS),
+            (8, 12, PLUS),
+            (20, 30, MINUS),
+        ]);
+        assert_eq!(
+            s,
+            vec![
+                (0, 5, PLUS),
+                (5, 12, PLUS | MINUS),
+                (12, 15, MINUS),
+                (20, 30, MINUS)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strand_at() {
+        let a = Annotation::from_path(testdata("ex2.gtf"), "gene").unwrap();
+        // gene_plus covers 1:1-3 and gene_minus 1:3-5 (1-based, inclusive)
+        assert_eq!(a.strand_at("1", 0), TranscriptionStrand::Untranscribed);
+        assert_eq!(a.strand_at("1", 1), TranscriptionStrand::Untranscribed);
+        assert_eq!(a.strand_at("1", 2), TranscriptionStrand::Bidirectional);
+        assert_eq!(a.strand_at("1", 3), TranscriptionStrand::Transcribed);
+        assert_eq!(a.strand_at("1", 4), TranscriptionStrand::Transcribed);
+        assert_eq!(a.strand_at("1", 5), TranscriptionStrand::Intergenic);
+        // The unstranded gene on contig 2 is ignored
+        assert_eq!(a.strand_at("2", 1), TranscriptionStrand::Intergenic);
+        assert_eq!(a.strand_at("3", 1), TranscriptionStrand::Intergenic);
+    }
+
+    #[test]
+    fn test_of_pyrimidine() {
+        let t = TranscriptionStrand::Transcribed;
+        assert_eq!(t.of_pyrimidine(true), t);
+        assert_eq!(t.of_pyrimidine(false), TranscriptionStrand::Untranscribed);
+        let b = TranscriptionStrand::Bidirectional;
+        assert_eq!(b.of_pyrimidine(false), b);
+    }
+
+    #[test]
+    fn test_feature_selection() {
+        let a = Annotation::from_path(testdata("ex2.gtf"), "transcript").unwrap();
+        assert_eq!(a.strand_at("1", 0), TranscriptionStrand::Intergenic);
+        assert_eq!(a.strand_at("1", 1), TranscriptionStrand::Untranscribed);
+        assert_eq!(a.strand_at("1", 2), TranscriptionStrand::Untranscribed);
+        assert_eq!(a.strand_at("1", 3), TranscriptionStrand::Transcribed);
+    }
+
+    #[test]
+    fn test_gzipped_gtf() {
+        let plain = Annotation::from_path(testdata("ex2.gtf"), "gene").unwrap();
+        let gzipped = Annotation::from_path(testdata("ex2.gtf.gz"), "gene").unwrap();
+        assert_eq!(plain, gzipped);
+    }
+
+    #[test]
+    fn test_invalid_coordinates() {
+        let gtf = "1\ttest\tgene\t0\t3\t.\t+\t.\tgene_id \"x\";\n";
+        assert!(Annotation::parse(gtf.as_bytes(), "test.gtf", "gene").is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use crate::error::Error;
+use crate::reference::{Reference, ReferenceProvider};
+use crate::signature::{Signature, Signatures};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// The index of a channel of the signatures, i.e., of a row of the result matrix.
+pub type ChannelIndex = usize;
+
+/// The reason why a substitution can not be classified.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClassifyError {
+    /// The base of the reference at the position differs from the reference allele
+    Mismatch {
+        contig: String,
+        position: i64,
+        context: String,
+        expected: char,
+    },
+    /// The context includes other bases than A, C, G and T
+    AmbiguousContext { context: String },
+    /// The window around the position is cut off by the start or end of the sequence
+    TruncatedContext {
+        contig: String,
+        position: i64,
+        context: String,
+    },
+    /// The context is soft-masked, i.e., lowercase in the reference
+    Softmasked { context: String },
+    /// The position is outside of the sequence
+    OutOfBounds {
+        contig: String,
+        position: i64,
+        message: String,
+    },
+    /// The substitution has no channel, e.g., as its alternative allele is not a base
+    Unclassified { signature: Signature },
+}
+
+impl fmt::Display for ClassifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClassifyError::Mismatch {
+                context, expected, ..
+            } => write!(
+                f,
+                "Loaded codon '{}' does not match to expected reference allele {}",
+                context, expected
+            ),
+            ClassifyError::AmbiguousContext { context } => write!(
+                f,
+                "Can not classify the codon '{}' with non-standard nucleotide",
+                context
+            ),
+            ClassifyError::TruncatedContext { context, .. } => write!(
+                f,
+                "Can not classify the codon '{}' truncated by the end of the sequence",
+                context
+            ),
+            ClassifyError::Softmasked { context } => write!(
+                f,
+                "Can not classify the codon '{}' in a soft-masked region",
+                context
+            ),
+            ClassifyError::OutOfBounds {
+                contig,
+                position,
+                message,
+            } => write!(
+                f,
+                "Can not fetch codon at position {}:{}: {}",
+                contig, position, message
+            ),
+            ClassifyError::Unclassified { signature } => {
+                write!(f, "Can not find the channel of signature '{}'", signature)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClassifyError {}
+
+/// Classifies single base substitutions into the channels of the signatures by their
+/// context in the reference.
+///
+/// ```
+/// use mutsig::classify::{Classifier, ClassifyError};
+/// use mutsig::reference::Reference;
+/// use mutsig::signature::{Signature, Signatures, DEFAULT_MAX_CHANNELS};
+///
+/// let reference = Reference::new("testdata/ex2.fa", 1).unwrap();
+/// let signatures = Signatures::new(1, DEFAULT_MAX_CHANNELS).unwrap();
+/// let classifier = Classifier::new(&reference, &signatures);
+/// // The C>T at the second base of TCATGA
+/// let index = classifier.classify("1", 1, 'C', 'T').unwrap();
+/// assert_eq!(signatures.index_of(&Signature::parse("TCA>T").unwrap()), Some(index));
+/// assert!(matches!(
+///     classifier.classify("1", 1, 'A', 'T'),
+///     Err(ClassifyError::Mismatch { .. })
+/// ));
+/// ```
+pub struct Classifier<'a, R: ReferenceProvider = Reference> {
+    reference: &'a R,
+    signatures: &'a Signatures,
+    skip_softmasked: bool,
+    count_partial_context: bool,
+}
+
+impl<'a, R: ReferenceProvider> Classifier<'a, R> {
+    /// Create a classifier into the `signatures` with the context of their window in the
+    /// `reference`.
+    pub fn new(reference: &'a R, signatures: &'a Signatures) -> Self {
+        Classifier {
+            reference,
+            signatures,
+            skip_softmasked: false,
+            count_partial_context: false,
+        }
+    }
+
+    /// Reject the substitutions whose context is soft-masked in the reference.
+    pub fn skip_softmasked(mut self, skip: bool) -> Self {
+        self.skip_softmasked = skip;
+        self
+    }
+
+    /// Classify the substitutions of a base whose context is ambiguous without their context,
+    /// which requires the channels without context, e.g., by `Signatures::with_partial_context`.
+    pub fn count_partial_context(mut self, count: bool) -> Self {
+        self.count_partial_context = count;
+        self
+    }
+
+    /// Return the channel of the substitution of `ref_allele` by `alt_allele` at `pos0`
+    /// (0-based) on `contig`.
+    pub fn classify(
+        &self,
+        contig: &str,
+        pos0: i64,
+        ref_allele: char,
+        alt_allele: char,
+    ) -> Result<ChannelIndex, ClassifyError> {
+        let ref_allele = ref_allele.to_ascii_uppercase();
+        let alt_allele = alt_allele.to_ascii_uppercase();
+        let context = self.context(contig, pos0, ref_allele)?;
+        let index = u8::try_from(alt_allele)
+            .ok()
+            .and_then(|alt| self.signatures.index_of_bases(context.as_bytes(), alt));
+        match index {
+            Some(i) => Ok(i),
+            None => Err(ClassifyError::Unclassified {
+                signature: Signature::new(context, ref_allele, alt_allele),
+            }),
+        }
+    }
+
+    /// Return the context of the base `ref_allele` at `pos0` (0-based) on `contig`, i.e., the
+    /// bases of the window around it, or only the base itself if its context is ambiguous and
+    /// such substitutions are counted without context.
+    pub fn context(
+        &self,
+        contig: &str,
+        pos0: i64,
+        ref_allele: char,
+    ) -> Result<String, ClassifyError> {
+        let window = self.reference.window_size() as usize;
+        let out_of_bounds = |message: String| ClassifyError::OutOfBounds {
+            contig: contig.to_owned(),
+            position: pos0,
+            message,
+        };
+        if pos0 < 0 {
+            return Err(out_of_bounds("negative position".to_owned()));
+        }
+        let (context, softmasked) = match self.reference.fetch_with_case(contig, pos0) {
+            Ok(fetched) => fetched,
+            Err(Error::TruncatedContext { context, .. }) => {
+                return Err(ClassifyError::TruncatedContext {
+                    contig: contig.to_owned(),
+                    position: pos0,
+                    context,
+                })
+            }
+            Err(e) => return Err(out_of_bounds(e.to_string())),
+        };
+        if softmasked && self.skip_softmasked {
+            return Err(ClassifyError::Softmasked { context });
+        }
+
+        // Check that the context is ACGT only. If requested, a substitution whose window is
+        // ambiguous is still classified without its context if its own base is not.
+        let is_nucleotide = |c: char| c == 'A' || c == 'C' || c == 'G' || c == 'T';
+        let center = context.as_bytes()[window] as char;
+        let ambiguous = !context.chars().all(is_nucleotide);
+        if ambiguous && (!self.count_partial_context || !is_nucleotide(center)) {
+            return Err(ClassifyError::AmbiguousContext { context });
+        }
+
+        // Check that the base at the position matches the reference allele
+        if center != ref_allele.to_ascii_uppercase() {
+            return Err(ClassifyError::Mismatch {
+                contig: contig.to_owned(),
+                position: pos0,
+                context,
+                expected: ref_allele,
+            });
+        }
+        if ambiguous {
+            Ok(center.to_string())
+        } else {
+            Ok(context)
+        }
+    }
+
+    /// Return the number of bases of the context on each side of a substitution.
+    pub fn window(&self) -> usize {
+        self.reference.window_size() as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::MemoryReference;
+    use crate::signature::DEFAULT_MAX_CHANNELS;
+
+    /// Return the reference of `sequence` as contig 1, like the FASTA files of the test data.
+    fn reference(sequence: &str, window: u8) -> MemoryReference {
+        MemoryReference::new(vec![("1", sequence), ("2", "TCTAGA")], window)
+    }
+
+    fn index_of(signatures: &Signatures, label: &str) -> ChannelIndex {
+        signatures
+            .index_of(&Signature::parse(label).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_classify() {
+        let reference = reference("TCATGA", 1);
+        let signatures = Signatures::new(1, DEFAULT_MAX_CHANNELS).unwrap();
+        let classifier = Classifier::new(&reference, &signatures);
+        assert_eq!(
+            classifier.classify("1", 1, 'C', 'T'),
+            Ok(index_of(&signatures, "TCA>T"))
+        );
+        // A purine substitution counts into the channel of its reverse complement
+        assert_eq!(
+            classifier.classify("1", 4, 'g', 'a'),
+            Ok(index_of(&signatures, "TGA>A"))
+        );
+        assert_eq!(
+            classifier.classify("1", 4, 'G', 'A'),
+            Ok(index_of(&signatures, "TCA>T"))
+        );
+        assert!(matches!(
+            classifier.classify("1", 1, 'C', 'N'),
+            Err(ClassifyError::Unclassified { .. })
+        ));
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let reference = reference("TCATGA", 1);
+        let signatures = Signatures::new(1, DEFAULT_MAX_CHANNELS).unwrap();
+        let classifier = Classifier::new(&reference, &signatures);
+        let error = classifier.classify("1", 2, 'C', 'T').unwrap_err();
+        assert_eq!(
+            error,
+            ClassifyError::Mismatch {
+                contig: "1".to_owned(),
+                position: 2,
+                context: "CAT".to_owned(),
+                expected: 'C'
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Loaded codon 'CAT' does not match to expected reference allele C"
+        );
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let reference = reference("TCATGA", 1);
+        let signatures = Signatures::new(1, DEFAULT_MAX_CHANNELS).unwrap();
+        let classifier = Classifier::new(&reference, &signatures);
+        for position in &[-1, 6] {
+            let error = classifier.classify("1", *position, 'T', 'C').unwrap_err();
+            assert!(
+                matches!(&error, ClassifyError::OutOfBounds { position: p, .. } if p == position),
+                "{:?}",
+                error
+            );
+        }
+        // The context at the start and end of the sequence is truncated
+        assert_eq!(
+            classifier.classify("1", 0, 'T', 'C'),
+            Err(ClassifyError::TruncatedContext {
+                contig: "1".to_owned(),
+                position: 0,
+                context: "TC".to_owned()
+            })
+        );
+        assert_eq!(
+            classifier.context("1", 5, 'A'),
+            Err(ClassifyError::TruncatedContext {
+                contig: "1".to_owned(),
+                position: 5,
+                context: "GA".to_owned()
+            })
+        );
+        // Without context, the first and last base can be classified
+        let reference = self::reference("TCATGA", 0);
+        let signatures = Signatures::new(0, DEFAULT_MAX_CHANNELS).unwrap();
+        let classifier = Classifier::new(&reference, &signatures);
+        assert!(classifier.classify("1", 0, 'T', 'C').is_ok());
+        assert!(classifier.classify("1", 5, 'A', 'C').is_ok());
+    }
+
+    #[test]
+    fn test_ambiguous_context() {
+        let reference = reference("NCATGA", 1);
+        let signatures = Signatures::with_partial_context(1);
+        let classifier = Classifier::new(&reference, &signatures);
+        assert_eq!(
+            classifier.classify("1", 1, 'C', 'T'),
+            Err(ClassifyError::AmbiguousContext {
+                context: "NCA".to_owned()
+            })
+        );
+        let classifier = classifier.count_partial_context(true);
+        assert_eq!(classifier.context("1", 1, 'C'), Ok("C".to_owned()));
+        assert_eq!(
+            classifier.classify("1", 1, 'C', 'T'),
+            Ok(index_of(&signatures, "C>T"))
+        );
+        assert_eq!(
+            classifier.classify("1", 2, 'A', 'G'),
+            Ok(index_of(&signatures, "CAT>G"))
+        );
+        // The ambiguous base itself is never classified
+        let reference = self::reference("NCATGA", 0);
+        let signatures = Signatures::with_partial_context(0);
+        let classifier = Classifier::new(&reference, &signatures).count_partial_context(true);
+        assert!(matches!(
+            classifier.classify("1", 0, 'N', 'T'),
+            Err(ClassifyError::AmbiguousContext { .. })
+        ));
+    }
+
+    #[test]
+    fn test_softmasked() {
+        let reference = reference("TCatGA", 1);
+        let signatures = Signatures::new(1, DEFAULT_MAX_CHANNELS).unwrap();
+        let classifier = Classifier::new(&reference, &signatures);
+        assert_eq!(
+            classifier.classify("1", 1, 'C', 'T'),
+            Ok(index_of(&signatures, "TCA>T"))
+        );
+        let classifier = classifier.skip_softmasked(true);
+        assert_eq!(
+            classifier.classify("1", 1, 'C', 'T'),
+            Err(ClassifyError::Softmasked {
+                context: "TCA".to_owned()
+            })
+        );
+    }
+}
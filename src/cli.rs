@@ -0,0 +1,2118 @@
+use crate::count::{
+    channel_count, sbs_rows, scheme_channel_count, stratified_rows, write_counts, CountMode,
+    CountResult, DedupeMode, HomogeneousMissing, Options, Progress, RefMismatch, Scheme,
+    CLUSTER_PREFIXES, CPG_PREFIXES, MITO_PREFIX, REPLICATION_STRAND_PREFIXES,
+    TRANSCRIPTION_STRAND_PREFIXES, UNASSIGNED_LABEL,
+};
+use crate::error::Error;
+use crate::progress::{CountProgress, ProgressMode};
+use crate::runner::MutsigRunner;
+use crate::{
+    aggregate, annotation, compare, dbs, dendrogram, downsample, filter, fit, indel, info_filter,
+    intervals, metrics, mito, opportunity, plot, reference, replication, signature, similarity,
+    stats,
+};
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+
+/// Parse the command line and run the requested analysis.
+pub fn run() -> Result<(), Error> {
+    let matches = clap::App::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .setting(clap::AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            clap::SubCommand::with_name("fit")
+                .about("Estimate the exposures of the samples to reference signatures by non-negative least squares")
+                .arg(
+                    clap::Arg::with_name("COUNTS")
+                        .help("Sets the matrix of the counts per channel and sample as reported by mutsig")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    clap::Arg::with_name("signatures")
+                        .long("signatures")
+                        .help("The reference signatures, i.e., a tab-separated matrix of the probabilities of the channels (rows) per signature (columns) as by COSMIC")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::with_name("rescale-catalog")
+                        .long("rescale-catalog")
+                        .help("Rescale the reference signatures to the context frequencies of the counted territory")
+                        .value_name("DIRECTION")
+                        .takes_value(true)
+                        .possible_values(&["genome-to-exome", "exome-to-genome"])
+                        .requires_all(&["genome-opportunities", "exome-opportunities"]),
+                )
+                .arg(
+                    clap::Arg::with_name("max-signatures")
+                        .long("max-signatures")
+                        .help("Fit each sample to at most this number of signatures, selected forward by their improvement of the reconstruction")
+                        .value_name("K")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("min-improvement")
+                        .long("min-improvement")
+                        .help("The smallest improvement of the reconstruction to select another signature with --max-signatures")
+                        .value_name("IMPROVEMENT")
+                        .takes_value(true)
+                        .default_value("0.01"),
+                )
+                .arg(
+                    clap::Arg::with_name("selection-criterion")
+                        .long("selection-criterion")
+                        .help("The improvement of the reconstruction with --max-signatures, i.e., of the cosine similarity or of the residual relative to the norm of the counts")
+                        .value_name("CRITERION")
+                        .takes_value(true)
+                        .possible_values(&["cosine", "l2"])
+                        .default_value("cosine"),
+                )
+                .arg(
+                    clap::Arg::with_name("plot-exposures")
+                        .long("plot-exposures")
+                        .help("Write a stacked bar plot of the proportions of the exposures per sample to this file (SVG)")
+                        .value_name("FILE")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("plot-order")
+                        .long("plot-order")
+                        .help("The order of the samples in --plot-exposures, i.e., as in the counts, by decreasing total exposure, or grouped by their dominant signature")
+                        .value_name("ORDER")
+                        .takes_value(true)
+                        .possible_values(&["input", "burden", "dominant"])
+                        .default_value("input"),
+                )
+                .arg(
+                    clap::Arg::with_name("min-similarity")
+                        .long("min-similarity")
+                        .help("Flag the samples whose cosine similarity to their reconstruction is below this threshold")
+                        .value_name("SIMILARITY")
+                        .takes_value(true)
+                        .default_value("0.9"),
+                )
+                .arg(
+                    clap::Arg::with_name("genome-opportunities")
+                        .long("genome-opportunities")
+                        .help("The opportunities per channel in the genome for --rescale-catalog, e.g., as by --opportunity-matrix")
+                        .value_name("FILE")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("exome-opportunities")
+                        .long("exome-opportunities")
+                        .help("The opportunities per channel in the exome for --rescale-catalog, e.g., as by --opportunity-matrix")
+                        .value_name("FILE")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("compare")
+                .about("Compare the proportions of the channels between two groups of samples")
+                .arg(
+                    clap::Arg::with_name("COUNTS")
+                        .help("Sets the matrix of the counts per channel and sample as reported by mutsig")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    clap::Arg::with_name("groups")
+                        .long("groups")
+                        .help("The assignment of the samples to two groups, i.e., a tab-separated file of sample and group per line")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("metrics")
+                .about("Summarize the counts per sample by total, Ts/Tv ratio, substitution classes and top channels")
+                .arg(
+                    clap::Arg::with_name("COUNTS")
+                        .help("Sets the counts per channel and sample as reported by mutsig, or in the long format with the columns Sample, Channel and Count")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("aggregate")
+                .about("Combine matrices of counts with identical channels into one with all their samples")
+                .arg(
+                    clap::Arg::with_name("MATRICES")
+                        .help("Sets the matrices of the counts per channel and sample as reported by mutsig")
+                        .required(true)
+                        .multiple(true)
+                        .index(1),
+                )
+                .arg(
+                    clap::Arg::with_name("sum-duplicates")
+                        .long("sum-duplicates")
+                        .help("Sum the counts of samples in several matrices instead of failing"),
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("VCF")
+                .help("Sets the input VCF file to use (local path or http://, https://, s3:// URL)")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            clap::Arg::with_name("REFERENCE")
+                .help("Sets the input reference FASTQ file (must be indexed with faidx)")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            clap::Arg::with_name("samples")
+                .short("s")
+                .long("samples")
+                .help("Include this sample in the analyzes (defaults to all), can be specified multiple times")
+								.takes_value(true)
+								.value_name("SAMPLE")
+								.multiple(true)
+        )
+        .arg(
+            clap::Arg::with_name("ignore-homogeneous")
+                .short("i")
+                .long("ignore-homogeneous")
+                .help("Ignore sites where all samples have the same allele count")
+        )
+        .arg(
+            clap::Arg::with_name("homogeneous-missing")
+                .long("homogeneous-missing")
+                .help("Compare missing genotypes for --ignore-homogeneous by leaving out uncalled samples (ignore), counting missing alleles as the reference (treat-as-ref) or as alleles of their own (treat-as-distinct) [default: treat-as-distinct]")
+                .value_name("MODE")
+                .possible_values(&["ignore", "treat-as-ref", "treat-as-distinct"])
+                .requires("ignore-homogeneous")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("no-genotypes")
+                .long("no-genotypes")
+                .help("Count each alternative allele once per sample instead of reading the genotypes, e.g. if the VCF has no GT field")
+                .conflicts_with("ignore-homogeneous")
+        )
+        .arg(
+            clap::Arg::with_name("compare-allele-content")
+                .long("compare-allele-content")
+                .help("Consider genotypes with the same alleles but different ploidy, e.g. 1 and 1/1, as equal for --ignore-homogeneous")
+                .requires("ignore-homogeneous")
+        )
+        .arg(
+            clap::Arg::with_name("require-complete-genotypes")
+                .long("require-complete-genotypes")
+                .help("Ignore sites where any of the selected samples has a missing genotype")
+        )
+        .arg(
+            clap::Arg::with_name("skip-missing-genotypes")
+                .long("skip-missing-genotypes")
+                .help("Do not count samples with a (partially) missing genotype, e.g. ./1, at a site")
+        )
+        .arg(
+            clap::Arg::with_name("count-mode")
+                .long("count-mode")
+                .help("Count each alternative allele per haplotype (alleles, the default) or each mutation once per site and sample (sites); same as --dosage haplotype or site")
+                .value_name("MODE")
+                .possible_values(&["alleles", "sites"])
+                .conflicts_with("dosage")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("dosage")
+                .long("dosage")
+                .help("Count each alternative allele per haplotype (a 1/1 genotype counts twice), once per site and sample, or once per site weighted by its number of copies in the genotype [default: haplotype]")
+                .value_name("MODE")
+                .possible_values(&["haplotype", "site", "genotype-dosage"])
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("dedupe")
+                .long("dedupe")
+                .help("Skip records repeating the contig, position and alleles of an earlier record")
+        )
+        .arg(
+            clap::Arg::with_name("dedupe-mode")
+                .long("dedupe-mode")
+                .help("Whether to keep the first of duplicate records with different genotypes or to fail [default: first]")
+                .value_name("MODE")
+                .possible_values(&["first", "error"])
+                .requires("dedupe")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("min-distance")
+                .long("min-distance")
+                .help("Do not count a mutation of a sample closer than this number of bases to its previous counted mutation")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("clustered-channels")
+                .long("clustered-channels")
+                .help("Count the mutations of a sample closer than this number of bases to another mutation of the sample into separate clustered channels")
+                .value_name("N")
+                .conflicts_with("min-distance")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("drop-both")
+                .long("drop-both")
+                .help("Drop all mutations of a cluster found by --min-distance, including the first one")
+                .requires("min-distance")
+        )
+        .arg(
+            clap::Arg::with_name("downsample")
+                .long("downsample")
+                .help("Randomly sample at most this number of counted mutations per sample")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("seed")
+                .long("seed")
+                .help("The seed of the random sample drawn by --downsample and of the random tables of --permutations")
+                .value_name("S")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("scheme")
+                .long("scheme")
+                .help("Count single base substitutions in the context of the window (also by transcription strand with --gtf, or as SBS288 with -w 1), doublet base substitutions (DBS78) or small insertions and deletions (ID83)")
+                .value_name("SCHEME")
+                .possible_values(&["sbs", "sbs-ts", "sbs288", "dbs", "id"])
+                .requires_if("sbs-ts", "gtf")
+                .requires_if("sbs288", "gtf")
+                .default_value("sbs")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("bidirectional")
+                .long("bidirectional")
+                .help("The SBS288 class of substitutions overlapped by genes on both strands")
+                .value_name("CLASS")
+                .possible_values(&["transcribed", "unknown"])
+                .default_value("transcribed")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("order")
+                .long("order")
+                .help("Report the single base substitutions in lexicographic order of their context, or grouped by substitution with labels like A[C>T]G as in COSMIC")
+                .value_name("ORDER")
+                .possible_values(&["lexicographic", "cosmic"])
+                .default_value("lexicographic")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("orientation")
+                .long("orientation")
+                .help("Label the single base substitutions by their pyrimidine (C or T) or purine (A or G) reference base")
+                .value_name("BASE")
+                .possible_values(&["pyrimidine", "purine"])
+                .default_value("pyrimidine")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("stratify-cpg")
+                .long("stratify-cpg")
+                .help("Count single base substitutions of a C in a CpG (or of the G on the reverse strand) in a separate block of channels")
+                .conflicts_with_all(&["replication-bed", "channels"])
+        )
+        .arg(
+            clap::Arg::with_name("mito-mode")
+                .long("mito-mode")
+                .help("Count the substitutions on the mitochondrial contig relative to the reference (heavy) strand in separate channels")
+                .conflicts_with("count-partial-context")
+        )
+        .arg(
+            clap::Arg::with_name("mito-contig")
+                .long("mito-contig")
+                .help("The name of the mitochondrial contig for --mito-mode [default: chrM and MT]")
+                .value_name("NAME")
+                .multiple(true)
+                .number_of_values(1)
+                .requires("mito-mode")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("mito-exclude")
+                .long("mito-exclude")
+                .help("Skip the substitutions in this 1-based region of the mitochondrial contig, e.g., the control region 16024-576")
+                .value_name("START-END")
+                .requires("mito-mode")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("channels")
+                .long("channels")
+                .help("Count single base substitutions into the channels defined in this file (one label and comma separated members like ACG>T per line)")
+                .value_name("FILE")
+                .conflicts_with("count-partial-context")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("channels-other")
+                .long("channels-other")
+                .help("Count substitutions not assigned to any channel of --channels into an OTHER channel instead of failing")
+                .requires("channels")
+        )
+        .arg(
+            clap::Arg::with_name("decompose-mnv")
+                .long("decompose-mnv")
+                .help("Decompose multi-nucleotide variants (REF and ALT of same length) into their component SNVs")
+        )
+        .arg(
+            clap::Arg::with_name("biallelic-only")
+                .long("biallelic-only")
+                .help("Ignore records with more than one alternative allele (same as --max-alt-alleles 1)")
+                .conflicts_with("max-alt-alleles")
+        )
+        .arg(
+            clap::Arg::with_name("max-alt-alleles")
+                .long("max-alt-alleles")
+                .help("Ignore records with more than this number of alternative alleles")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("skip-softmasked")
+                .long("skip-softmasked")
+                .help("Ignore variants whose base or window is soft-masked (lowercase) in the reference")
+        )
+        .arg(
+            clap::Arg::with_name("count-partial-context")
+                .long("count-partial-context")
+                .help("Count substitutions with an ambiguous base (e.g., N) in the window into additional channels without context")
+        )
+        .arg(
+            clap::Arg::with_name("window")
+                .short("w")
+                .long("bases-window")
+                .help("The number of bases to consider up and downstream of the mutation position")
+                .value_name("BASES")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("max-channels")
+                .long("max-channels")
+                .help("Refuse windows whose number of channels exceeds this limit [default: 16777216]")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("chromosomes")
+                .long("chromosomes")
+                .help("Only count variants on these contigs (comma-separated), can be specified multiple times")
+                .value_name("LIST")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+        )
+        .arg(
+            clap::Arg::with_name("regions")
+                .long("regions")
+                .help("Only read the records in these regions (contig or contig:start-end, 1-based, comma-separated) by the index (.csi or .tbi) of the VCF file, which may be remote, can be specified multiple times")
+                .value_name("LIST")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+        )
+        .arg(
+            clap::Arg::with_name("primary-only")
+                .long("primary-only")
+                .help("Only count variants on the primary chromosomes 1-22, X and Y (with or without 'chr' prefix)")
+        )
+        .arg(
+            clap::Arg::with_name("skip-unknown-contigs")
+                .long("skip-unknown-contigs")
+                .help("Skip the variants on contigs missing from the reference instead of failing")
+        )
+        .arg(
+            clap::Arg::with_name("on-ref-mismatch")
+                .long("on-ref-mismatch")
+                .help("Skip variants whose reference allele differs from the reference with a warning (warn) or silently (skip), or fail with their position (error) [default: warn]")
+                .value_name("POLICY")
+                .possible_values(&["warn", "skip", "error"])
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("strict")
+                .long("strict")
+                .help("Fail on the first variant whose reference allele differs from the reference, same as --on-ref-mismatch error")
+                .conflicts_with("on-ref-mismatch")
+        )
+        .arg(
+            clap::Arg::with_name("max-ref-mismatch-fraction")
+                .long("max-ref-mismatch-fraction")
+                .help("Exit with status 4 after counting if more than this fraction of the records differ from the reference, which is likely the wrong one, even if the mismatches are only warned about")
+                .value_name("FLOAT")
+                .default_value("0.05")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("fail-if-skipped-frac")
+                .long("fail-if-skipped-frac")
+                .help("Exit with status 5 after counting if more than this fraction of the records was skipped for any reason")
+                .value_name("FLOAT")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("skip-errors")
+                .long("skip-errors")
+                .help("Skip malformed records, e.g. without a contig, with a warning instead of failing")
+        )
+        .arg(
+            clap::Arg::with_name("no-validate-contigs")
+                .long("no-validate-contigs")
+                .help("Do not check the contigs and lengths of the VCF header against the reference before counting")
+        )
+        .arg(
+            clap::Arg::with_name("include-bed")
+                .long("include-bed")
+                .help("Only count variants located in the regions of this BED file")
+                .value_name("BED")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("annotate-bed")
+                .long("annotate-bed")
+                .help("Count the variants in one block per label (name column) of the regions of this BED file")
+                .value_name("BED")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("annotate-priority")
+                .long("annotate-priority")
+                .help("The labels of --annotate-bed in the order of their priority for overlapping regions (comma separated)")
+                .value_name("LIST")
+                .requires("annotate-bed")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("exclude-bed")
+                .long("exclude-bed")
+                .help("Do not count variants located in the regions of this BED file (e.g., a blacklist)")
+                .value_name("BED")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("include-intervals")
+                .long("include-intervals")
+                .help("Only count variants located in the intervals of this Picard interval list")
+                .value_name("INTERVAL_LIST")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("pass-only")
+                .long("pass-only")
+                .help("Only count variants whose FILTER is PASS or missing")
+        )
+        .arg(
+            clap::Arg::with_name("filter-include")
+                .long("filter-include")
+                .help("Only count variants having this FILTER value (or PASS), can be specified multiple times")
+                .value_name("NAME")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+        )
+        .arg(
+            clap::Arg::with_name("filter-exclude")
+                .long("filter-exclude")
+                .help("Do not count variants having this FILTER value, can be specified multiple times")
+                .value_name("NAME")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+        )
+        .arg(
+            clap::Arg::with_name("min-qual")
+                .long("min-qual")
+                .help("Only count variants whose QUAL is at least this value (variants with missing QUAL are kept)")
+                .value_name("FLOAT")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("require-qual")
+                .long("require-qual")
+                .help("Do not count variants with missing QUAL")
+        )
+        .arg(
+            clap::Arg::with_name("info-filter")
+                .long("info-filter")
+                .help("Only count variants whose INFO fields match this expression (e.g., \"TLOD>=6.3 & STR!=1\")")
+                .value_name("EXPR")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("info-missing")
+                .long("info-missing")
+                .help("The result of comparisons on INFO fields missing in a record")
+                .value_name("RESULT")
+                .possible_values(&["false", "true"])
+                .default_value("false")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("max-pop-af")
+                .long("max-pop-af")
+                .help("Do not count alternative alleles whose population allele frequency (INFO field, see --pop-af-tag) is above this value")
+                .value_name("FLOAT")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("pop-af-tag")
+                .long("pop-af-tag")
+                .help("The INFO field holding the population allele frequency of the alternative alleles")
+                .value_name("TAG")
+                .default_value("AF")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("require-pop-af")
+                .long("require-pop-af")
+                .help("Do not count alternative alleles without population allele frequency if --max-pop-af is given")
+        )
+        .arg(
+            clap::Arg::with_name("somatic-only")
+                .long("somatic-only")
+                .help("Only count variants marked as somatic by the INFO field given with --somatic-tag")
+        )
+        .arg(
+            clap::Arg::with_name("somatic-tag")
+                .long("somatic-tag")
+                .help("The INFO flag (e.g., SOMATIC) or field and value (e.g., STATUS=2) marking somatic variants")
+                .value_name("TAG[=VALUE]")
+                .default_value("SOMATIC")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("min-dp")
+                .long("min-dp")
+                .help("Do not count the genotype of a sample if its FORMAT/DP is below this value or missing")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("sample-pass-only")
+                .long("sample-pass-only")
+                .help("Do not count the genotype of a sample unless its FORMAT/FT is PASS or missing")
+        )
+        .arg(
+            clap::Arg::with_name("min-gq")
+                .long("min-gq")
+                .help("Do not count the genotype of a sample if its FORMAT/GQ is below this value")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("missing-gq")
+                .long("missing-gq")
+                .help("Whether to keep or drop genotypes with missing FORMAT/GQ if --min-gq is given")
+                .value_name("ACTION")
+                .possible_values(&["keep", "drop"])
+                .default_value("drop")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("min-vaf")
+                .long("min-vaf")
+                .help("Do not count an alternative allele of a sample if its allele fraction (from FORMAT/AD) is below this value")
+                .value_name("FLOAT")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("max-vaf")
+                .long("max-vaf")
+                .help("Do not count an alternative allele of a sample if its allele fraction (from FORMAT/AD) is above this value")
+                .value_name("FLOAT")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("weight-by-vaf")
+                .long("weight-by-vaf")
+                .help("Weight each mutation by its allele fraction computed from FORMAT/AD instead of counting it once")
+                .conflicts_with_all(&["downsample", "min-distance", "clustered-channels"])
+        )
+        .arg(
+            clap::Arg::with_name("missing-vaf-weight")
+                .long("missing-vaf-weight")
+                .help("The weight of mutations without allele fraction for --weight-by-vaf")
+                .value_name("W")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("min-alt-reads")
+                .long("min-alt-reads")
+                .help("Do not count an alternative allele of a sample if fewer reads (from FORMAT/AD) support it")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("missing-ad")
+                .long("missing-ad")
+                .help("Whether to keep or drop alleles without FORMAT/AD (or zero depth for the allele fraction) if --min-vaf, --max-vaf or --min-alt-reads is given")
+                .value_name("ACTION")
+                .possible_values(&["keep", "drop"])
+                .default_value("keep")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("skipped")
+                .long("skipped")
+                .help("Write the skipped records and the reason for skipping them to this file")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("sample-similarity")
+                .long("sample-similarity")
+                .help("Write the pairwise cosine similarities of the samples' counts to this file")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("sample-jsd")
+                .long("sample-jsd")
+                .help("Write the pairwise Jensen-Shannon divergences (base 2) of the samples' spectra to this file")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("diversity")
+                .long("diversity")
+                .help("Write the Shannon entropy and the effective number of channels of the samples' counts to this file")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("cluster-samples")
+                .long("cluster-samples")
+                .help("Write the dendrogram of the samples clustered by their counts with average linkage to this file (Newick format)")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("cluster-distance")
+                .long("cluster-distance")
+                .help("The distance of the samples for --cluster-samples, i.e., one minus the cosine similarity or the euclidean distance of the fractions")
+                .value_name("DISTANCE")
+                .takes_value(true)
+                .possible_values(&["cosine", "euclidean"])
+                .default_value("cosine"),
+        )
+        .arg(
+            clap::Arg::with_name("callable-bed-per-sample")
+                .long("callable-bed-per-sample")
+                .help("The callable regions of each sample, i.e., a tab-separated file of sample and BED file per line")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("opportunity-matrix"),
+        )
+        .arg(
+            clap::Arg::with_name("opportunity-matrix")
+                .long("opportunity-matrix")
+                .help("Write the number of possible substitutions per channel in the callable regions of each sample to this file")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("callable-bed-per-sample"),
+        )
+        .arg(
+            clap::Arg::with_name("strand-test")
+                .long("strand-test")
+                .help("Write a binomial test of the transcribed vs. untranscribed counts per channel and sample to this file (requires --scheme sbs-ts or sbs288)")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("homogeneity-test")
+                .long("homogeneity-test")
+                .help("Write a chi-square test of the samples' counts being drawn from the same distribution to this file")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("permutations")
+                .long("permutations")
+                .help("Also compute a Monte Carlo p-value of --homogeneity-test from this number of random tables (see --seed)")
+                .value_name("N")
+                .takes_value(true)
+                .requires("homogeneity-test"),
+        )
+        .arg(
+            clap::Arg::with_name("match-signatures")
+                .long("match-signatures")
+                .help("Match the samples' counts to these reference signatures (tab-separated channels × signatures matrix) by cosine similarity")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("match-report"),
+        )
+        .arg(
+            clap::Arg::with_name("match-report")
+                .long("match-report")
+                .help("Write the most similar reference signatures of each sample to this file")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("match-signatures"),
+        )
+        .arg(
+            clap::Arg::with_name("match-top")
+                .long("match-top")
+                .help("The number of most similar reference signatures reported per sample")
+                .value_name("K")
+                .takes_value(true)
+                .default_value("3"),
+        )
+        .arg(
+            clap::Arg::with_name("include-ids-file")
+                .long("include-ids-file")
+                .help("Only count variants whose ID is listed in this file (one ID per line)")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("exclude-ids-file")
+                .long("exclude-ids-file")
+                .help("Do not count variants whose ID is listed in this file (one ID per line)")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("gtf")
+                .long("gtf")
+                .help("Gene annotation (GTF or GFF, optionally gzip compressed) for assigning the transcription strand")
+                .value_name("GTF")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("gtf-feature")
+                .long("gtf-feature")
+                .help("The feature type of the annotation defining the gene footprints")
+                .value_name("FEATURE")
+                .possible_values(&["gene", "transcript"])
+                .default_value("gene")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("replication-bed")
+                .long("replication-bed")
+                .help("BED file of regions named by their replication direction (left or right) for counting single base substitutions by replication strand")
+                .value_name("BED")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("cache-reference")
+                .long("cache-reference")
+                .help("Hold the sequence of the current contig in memory instead of fetching the context of each variant from the reference (faster for coordinate-sorted VCF files)")
+        )
+        .arg(
+            clap::Arg::with_name("threads")
+                .short("t")
+                .long("threads")
+                .help("The number of threads used for decompressing the VCF file and classifying its records")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("sparse")
+                .long("sparse")
+                .help("Store only the channels with counts per sample, which saves memory for many channels and samples with few mutations each (the default for more than 2^26 channels times samples)")
+        )
+        .arg(
+            clap::Arg::with_name("progress")
+                .long("progress")
+                .help("Draw a progress bar of the counted records if the standard error is a terminal (auto), even if it is not (always), or not at all (never)")
+                .value_name("WHEN")
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .takes_value(true),
+        )
+        .get_matches();
+    info!(
+        "Started {} v{}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    if let Some(matches) = matches.subcommand_matches("fit") {
+        return fit_signatures(matches).map_err(Error::Other);
+    }
+    if let Some(matches) = matches.subcommand_matches("compare") {
+        return compare_groups(matches).map_err(Error::Other);
+    }
+    if let Some(matches) = matches.subcommand_matches("metrics") {
+        return summarize_metrics(matches).map_err(Error::Other);
+    }
+    if let Some(matches) = matches.subcommand_matches("aggregate") {
+        return aggregate_matrices(matches).map_err(Error::Other);
+    }
+
+    // Regions to restrict the analysis to
+    let mut include: Option<intervals::Intervals> = None;
+    if let Some(path) = matches.value_of("include-bed") {
+        info!("Restricting analysis to regions from {}", path);
+        include
+            .get_or_insert_with(intervals::Intervals::new)
+            .extend(&intervals::read_bed(path).map_err(Error::Other)?);
+    }
+    let mut interval_list_sequences = Vec::new();
+    if let Some(path) = matches.value_of("include-intervals") {
+        info!("Restricting analysis to intervals from {}", path);
+        let (i, sequences) = intervals::read_interval_list(path).map_err(Error::Other)?;
+        include
+            .get_or_insert_with(intervals::Intervals::new)
+            .extend(&i);
+        interval_list_sequences = sequences;
+    }
+
+    // Labeled regions for stratifying the counts
+    let region_labels = match matches.value_of("annotate-bed") {
+        None => None,
+        Some(path) => {
+            info!("Loading labeled regions from {}", path);
+            let priority: Vec<String> = matches
+                .value_of("annotate-priority")
+                .map(|l| l.split(',').map(|s| s.trim().to_owned()).collect())
+                .unwrap_or_default();
+            let regions = intervals::LabeledIntervals::new(
+                intervals::read_named_bed(path).map_err(Error::Other)?,
+                &priority,
+            )
+            .map_err(Error::InvalidOptions)?;
+            debug!("Found region labels {:?}", regions.labels());
+            Some(regions)
+        }
+    };
+
+    // Gene annotation for assigning the transcription strand
+    let annotation = match matches.value_of("gtf") {
+        None => None,
+        Some(path) => {
+            let feature = matches.value_of("gtf-feature").unwrap_or("gene");
+            info!("Loading {} features from annotation {}", feature, path);
+            Some(annotation::Annotation::from_path(path, feature).map_err(Error::Other)?)
+        }
+    };
+
+    // Variant IDs to include or exclude
+    let include_ids = match matches.value_of("include-ids-file") {
+        None => None,
+        Some(path) => Some(filter::read_ids(path).map_err(Error::Other)?),
+    };
+    let exclude_ids = match matches.value_of("exclude-ids-file") {
+        None => None,
+        Some(path) => Some(filter::read_ids(path).map_err(Error::Other)?),
+    };
+
+    // FILTER values to include or exclude
+    // Contigs to restrict the analysis to
+    let chromosomes: Option<HashSet<String>> = matches.values_of("chromosomes").map(|v| {
+        v.flat_map(|l| l.split(','))
+            .map(|c| c.trim())
+            .filter(|c| !c.is_empty())
+            .map(|c| c.to_owned())
+            .collect()
+    });
+
+    let include_filters: Option<HashSet<String>> = matches
+        .values_of("filter-include")
+        .map(|v| v.map(|f| f.to_owned()).collect());
+    let exclude_filters: Option<HashSet<String>> = matches
+        .values_of("filter-exclude")
+        .map(|v| v.map(|f| f.to_owned()).collect());
+
+    // Minimum QUAL of the records
+    let min_qual = match matches.value_of("min-qual") {
+        Some(v) => match v.parse::<f32>() {
+            Ok(q) if q.is_finite() => Some(q),
+            Ok(_) => {
+                return Err(Error::InvalidOptions(format!(
+                    "Invalid min-qual-parameter '{}'",
+                    v
+                )))
+            }
+            Err(e) => {
+                return Err(Error::InvalidOptions(format!(
+                    "Invalid min-qual-parameter '{}': {}",
+                    v, e
+                )))
+            }
+        },
+        None => None,
+    };
+
+    // Maximum number of alternative alleles of the records
+    let max_alt_alleles = if matches.occurrences_of("biallelic-only") > 0 {
+        Some(1)
+    } else {
+        match matches.value_of("max-alt-alleles") {
+            Some(v) => match v.parse::<usize>() {
+                Ok(n) if n >= 1 => Some(n),
+                Ok(_) => {
+                    return Err(Error::InvalidOptions(format!(
+                        "Invalid max-alt-alleles-parameter '{}': must be at least 1",
+                        v
+                    )))
+                }
+                Err(e) => {
+                    return Err(Error::InvalidOptions(format!(
+                        "Invalid max-alt-alleles-parameter '{}': {}",
+                        v, e
+                    )))
+                }
+            },
+            None => None,
+        }
+    };
+
+    // Minimum distance between the mutations of a sample
+    let min_distance = match matches.value_of("min-distance") {
+        Some(v) => match v.parse::<u64>() {
+            Ok(d) => Some(d),
+            Err(e) => {
+                return Err(Error::InvalidOptions(format!(
+                    "Invalid min-distance-parameter '{}': {}",
+                    v, e
+                )))
+            }
+        },
+        None => None,
+    };
+
+    // Distance below which mutations of a sample are counted as clustered
+    let clustered_cutoff = match matches.value_of("clustered-channels") {
+        Some(v) => match v.parse::<u64>() {
+            Ok(d) => Some(d),
+            Err(e) => {
+                return Err(Error::InvalidOptions(format!(
+                    "Invalid clustered-channels-parameter '{}': {}",
+                    v, e
+                )))
+            }
+        },
+        None => None,
+    };
+
+    // Number of mutations to sample per sample
+    let downsample = match matches.value_of("downsample") {
+        Some(v) => {
+            let size = match v.parse::<usize>() {
+                Ok(n) if n >= 1 => n,
+                Ok(_) => {
+                    return Err(Error::InvalidOptions(format!(
+                        "Invalid downsample-parameter '{}': must be at least 1",
+                        v
+                    )))
+                }
+                Err(e) => {
+                    return Err(Error::InvalidOptions(format!(
+                        "Invalid downsample-parameter '{}': {}",
+                        v, e
+                    )))
+                }
+            };
+            let seed = matches.value_of("seed").unwrap_or("1");
+            match seed.parse::<u64>() {
+                Ok(s) => Some((size, s)),
+                Err(e) => {
+                    return Err(Error::InvalidOptions(format!(
+                        "Invalid seed-parameter '{}': {}",
+                        seed, e
+                    )))
+                }
+            }
+        }
+        None => None,
+    };
+
+    // Expression on the INFO fields of the records
+    let info_filter = match matches.value_of("info-filter") {
+        None => None,
+        Some(e) => Some(info_filter::Expression::parse(e).map_err(Error::InvalidOptions)?),
+    };
+
+    // Maximum population allele frequency of the alleles
+    let max_pop_af = parse_fraction(matches.value_of("max-pop-af"), "max-pop-af")?.map(|af| {
+        (
+            matches.value_of("pop-af-tag").unwrap_or("AF").to_owned(),
+            af,
+        )
+    });
+
+    // INFO field marking somatic variants
+    let somatic = if matches.occurrences_of("somatic-only") > 0 {
+        Some(
+            filter::SomaticTag::parse(matches.value_of("somatic-tag").unwrap_or("SOMATIC"))
+                .map_err(Error::InvalidOptions)?,
+        )
+    } else {
+        None
+    };
+
+    // Minimum depth and genotype quality of the genotypes
+    let min_dp = parse_threshold(matches.value_of("min-dp"), "min-dp")?;
+    let min_gq = parse_threshold(matches.value_of("min-gq"), "min-gq")?;
+    let min_alt_reads = parse_threshold(matches.value_of("min-alt-reads"), "min-alt-reads")?;
+
+    // Window of the variant allele fraction of the alleles
+    let min_vaf = parse_fraction(matches.value_of("min-vaf"), "min-vaf")?;
+    let max_vaf = parse_fraction(matches.value_of("max-vaf"), "max-vaf")?;
+    let max_ref_mismatch_fraction = parse_fraction(
+        matches.value_of("max-ref-mismatch-fraction"),
+        "max-ref-mismatch-fraction",
+    )?;
+    let max_ignored_fraction = parse_fraction(
+        matches.value_of("fail-if-skipped-frac"),
+        "fail-if-skipped-frac",
+    )?;
+
+    if matches.value_of("dosage") == Some("genotype-dosage") {
+        for arg in &["downsample", "min-distance", "clustered-channels"] {
+            if matches.occurrences_of(arg) > 0 {
+                return Err(Error::InvalidOptions(format!(
+                    "Can not count genotype dosages with --{}",
+                    arg
+                )));
+            }
+        }
+    }
+
+    // Weight of the mutations without allele fraction, if the mutations are weighted
+    let missing_vaf_weight = if matches.occurrences_of("weight-by-vaf") > 0 {
+        parse_fraction(matches.value_of("missing-vaf-weight"), "missing-vaf-weight")?.map(f64::from)
+    } else {
+        None
+    };
+
+    let exclude = match matches.value_of("exclude-bed") {
+        None => None,
+        Some(path) => {
+            info!("Excluding regions from {}", path);
+            Some(intervals::read_bed(path).map_err(Error::Other)?)
+        }
+    };
+
+    if let Some(i) = &include {
+        if i.is_empty() {
+            warn!("The included regions are empty, no variant will be counted");
+        } else {
+            debug!("Restricting analysis to {} regions", i.len());
+        }
+    }
+
+    // The classification of the mutations
+    let scheme = match matches.value_of("scheme") {
+        Some(s) if s != "sbs" && matches.occurrences_of("decompose-mnv") > 0 => {
+            return Err(Error::InvalidOptions(format!(
+                "Can not decompose multi-nucleotide variants with scheme '{}'",
+                s
+            )))
+        }
+        Some(s)
+            if (s == "dbs" || s == "id" || s == "sbs288")
+                && matches.occurrences_of("channels") > 0 =>
+        {
+            return Err(Error::InvalidOptions(format!(
+                "Can not count into custom channels with scheme '{}'",
+                s
+            )))
+        }
+        Some(s) if s != "sbs" && matches.occurrences_of("stratify-cpg") > 0 => {
+            return Err(Error::InvalidOptions(format!(
+                "Can not stratify by CpG with scheme '{}'",
+                s
+            )))
+        }
+        Some(s) if s != "sbs" && matches.occurrences_of("replication-bed") > 0 => {
+            return Err(Error::InvalidOptions(format!(
+                "Can not stratify by replication strand with scheme '{}'",
+                s
+            )))
+        }
+        Some("dbs") => Scheme::Dbs(dbs::Doublets::new()),
+        Some("sbs-ts") => Scheme::SbsTs,
+        Some("sbs288") => match matches.value_of("bidirectional") {
+            Some("unknown") => Scheme::Sbs288(annotation::TranscriptionStrand::Intergenic),
+            _ => Scheme::Sbs288(annotation::TranscriptionStrand::Transcribed),
+        },
+        Some("id") => Scheme::Id(indel::Indels::new()),
+        _ if matches.occurrences_of("stratify-cpg") > 0 => Scheme::SbsCpg,
+        _ => match matches.value_of("replication-bed") {
+            Some(path) => {
+                info!("Loading replication directions from {}", path);
+                Scheme::SbsRs(replication::Replication::from_path(path).map_err(Error::Other)?)
+            }
+            None => Scheme::Sbs,
+        },
+    };
+
+    // Window size parameter
+    let window_size = match matches.value_of("window") {
+        Some(v) => match v.parse::<u8>() {
+            Err(e) => {
+                return Err(Error::InvalidOptions(format!(
+                    "Invalid window-parameter '{}': {}",
+                    v, e
+                )))
+            }
+            Ok(w) => w,
+        },
+        None => 0,
+    };
+    let max_channels = match matches.value_of("max-channels") {
+        Some(v) => match v.parse::<usize>() {
+            Err(e) => {
+                return Err(Error::InvalidOptions(format!(
+                    "Invalid max-channels-parameter '{}': {}",
+                    v, e
+                )))
+            }
+            Ok(n) => n,
+        },
+        None => signature::DEFAULT_MAX_CHANNELS,
+    };
+
+    // Counting of the mitochondrial substitutions relative to the reference strand
+    let mito = if matches.occurrences_of("mito-mode") > 0 {
+        if !matches!(scheme, Scheme::Sbs) || matches.occurrences_of("channels") > 0 {
+            return Err(Error::InvalidOptions(
+                "Can not count mitochondrial substitutions by strand with this scheme".to_owned(),
+            ));
+        }
+        let contigs: HashSet<String> = match matches.values_of("mito-contig") {
+            Some(names) => names.map(|n| n.to_owned()).collect(),
+            None => mito::DEFAULT_CONTIGS
+                .iter()
+                .map(|c| c.to_string())
+                .collect(),
+        };
+        let control_region = match matches.value_of("mito-exclude") {
+            Some(region) => Some(mito::parse_region(region).map_err(Error::InvalidOptions)?),
+            None => None,
+        };
+        Some(mito::Mito::new(contigs, control_region, window_size.into()))
+    } else {
+        None
+    };
+
+    let options = Options {
+        scheme,
+        ignore_homogeneous_sites: matches.occurrences_of("ignore-homogeneous") > 0,
+        no_genotypes: matches.occurrences_of("no-genotypes") > 0,
+        compare_allele_content: matches.occurrences_of("compare-allele-content") > 0,
+        homogeneous_missing: match matches.value_of("homogeneous-missing") {
+            Some("ignore") => HomogeneousMissing::Ignore,
+            Some("treat-as-ref") => HomogeneousMissing::TreatAsRef,
+            _ => HomogeneousMissing::TreatAsDistinct,
+        },
+        require_complete_genotypes: matches.occurrences_of("require-complete-genotypes") > 0,
+        skip_missing_genotypes: matches.occurrences_of("skip-missing-genotypes") > 0,
+        count_mode: match matches
+            .value_of("dosage")
+            .or(matches.value_of("count-mode"))
+        {
+            Some("site") | Some("sites") => CountMode::Site,
+            Some("genotype-dosage") => CountMode::GenotypeDosage,
+            _ => CountMode::Haplotype,
+        },
+        dedupe: if matches.occurrences_of("dedupe") > 0 {
+            match matches.value_of("dedupe-mode") {
+                Some("error") => Some(DedupeMode::Error),
+                _ => Some(DedupeMode::First),
+            }
+        } else {
+            None
+        },
+        min_distance,
+        clustered_cutoff,
+        region_labels,
+        mito,
+        drop_both: matches.occurrences_of("drop-both") > 0,
+        downsample,
+        decompose_mnv: matches.occurrences_of("decompose-mnv") > 0,
+        skip_softmasked: matches.occurrences_of("skip-softmasked") > 0,
+        count_partial_context: matches.occurrences_of("count-partial-context") > 0,
+        max_alt_alleles,
+        chromosomes,
+        primary_only: matches.occurrences_of("primary-only") > 0,
+        skip_unknown_contigs: matches.occurrences_of("skip-unknown-contigs") > 0,
+        no_validate_contigs: matches.occurrences_of("no-validate-contigs") > 0,
+        skip_errors: matches.occurrences_of("skip-errors") > 0,
+        on_ref_mismatch: match matches.value_of("on-ref-mismatch") {
+            _ if matches.occurrences_of("strict") > 0 => RefMismatch::Error,
+            Some("skip") => RefMismatch::Skip,
+            Some("error") => RefMismatch::Error,
+            _ => RefMismatch::Warn,
+        },
+        max_ref_mismatch_fraction,
+        max_ignored_fraction,
+        include,
+        exclude,
+        include_ids,
+        exclude_ids,
+        pass_only: matches.occurrences_of("pass-only") > 0,
+        include_filters,
+        exclude_filters,
+        min_qual,
+        require_qual: matches.occurrences_of("require-qual") > 0,
+        info_filter,
+        info_missing: matches.value_of("info-missing") == Some("true"),
+        somatic,
+        max_pop_af,
+        require_pop_af: matches.occurrences_of("require-pop-af") > 0,
+        min_dp,
+        sample_pass_only: matches.occurrences_of("sample-pass-only") > 0,
+        min_gq,
+        keep_missing_gq: matches.value_of("missing-gq") == Some("keep"),
+        min_vaf,
+        max_vaf,
+        min_alt_reads,
+        keep_missing_ad: matches.value_of("missing-ad") != Some("drop"),
+        missing_vaf_weight,
+        sparse: matches.occurrences_of("sparse") > 0,
+        annotation,
+    };
+
+    // Number of threads used for decompressing the VCF and classifying the records
+    let threads = match matches.value_of("threads") {
+        Some(v) => match v.parse::<usize>() {
+            Err(e) => {
+                return Err(Error::InvalidOptions(format!(
+                    "Invalid threads-parameter '{}': {}",
+                    v, e
+                )))
+            }
+            Ok(t) => t,
+        },
+        None => 1,
+    };
+
+    if matches.occurrences_of("channels") > 0
+        && (matches.occurrences_of("order") > 0 || matches.occurrences_of("orientation") > 0)
+    {
+        return Err(Error::InvalidOptions(
+            "Can not reorder or reorient custom channels, they are reported as defined".to_owned(),
+        ));
+    }
+
+    // Callable regions of the samples to compute the opportunities in
+    let callable_beds = match matches.value_of("callable-bed-per-sample") {
+        Some(path) => {
+            if !matches!(options.scheme, Scheme::Sbs) || options.mito.is_some() {
+                return Err(Error::InvalidOptions(
+                    "Can not compute opportunities with this scheme".to_owned(),
+                ));
+            }
+            info!("Loading callable regions of the samples from {}", path);
+            Some((
+                path,
+                opportunity::read_sample_beds(path).map_err(Error::Other)?,
+            ))
+        }
+        None => None,
+    };
+
+    if matches.is_present("strand-test")
+        && !matches!(options.scheme, Scheme::SbsTs | Scheme::Sbs288(_))
+    {
+        return Err(Error::InvalidOptions(
+            "Can not test the strand bias without transcription strands, use --scheme sbs-ts or sbs288"
+                .to_owned(),
+        ));
+    }
+
+    if matches.is_present("homogeneity-test") && options.is_weighted() {
+        return Err(Error::InvalidOptions(
+            "Can not test the homogeneity of weighted counts".to_owned(),
+        ));
+    }
+    let permutations = match matches.value_of("permutations") {
+        Some(v) => match v.parse::<u64>() {
+            Ok(n) if n > 0 => {
+                let seed = matches.value_of("seed").unwrap_or("1");
+                match seed.parse::<u64>() {
+                    Ok(s) => Some((n, s)),
+                    Err(e) => {
+                        return Err(Error::InvalidOptions(format!(
+                            "Invalid seed-parameter '{}': {}",
+                            seed, e
+                        )))
+                    }
+                }
+            }
+            Ok(_) => {
+                return Err(Error::InvalidOptions(format!(
+                    "Invalid permutations-parameter '{}': must be at least 1",
+                    v
+                )))
+            }
+            Err(e) => {
+                return Err(Error::InvalidOptions(format!(
+                    "Invalid permutations-parameter '{}': {}",
+                    v, e
+                )))
+            }
+        },
+        None => None,
+    };
+
+    // Reference signatures to match the samples to
+    let catalog = match matches.value_of("match-signatures") {
+        Some(path) => {
+            info!("Loading reference signatures from {}", path);
+            Some(fit::read_matrix(path).map_err(Error::Other)?)
+        }
+        None => None,
+    };
+    let match_top = match matches.value_of("match-top").unwrap().parse::<usize>() {
+        Ok(k) if k > 0 => k,
+        _ => {
+            return Err(Error::InvalidOptions(format!(
+                "Invalid match-top-parameter '{}'",
+                matches.value_of("match-top").unwrap()
+            )))
+        }
+    };
+
+    // Count the variants into the result matrix
+    let vcf = match matches.value_of("VCF") {
+        None => return Err(Error::InvalidOptions("Require 'VCF' file name".to_owned())),
+        Some(p) => p,
+    };
+    let reference = match matches.value_of("REFERENCE") {
+        None => {
+            return Err(Error::InvalidOptions(
+                "Require 'REFERENCE' file name".to_owned(),
+            ))
+        }
+        Some(p) => p,
+    };
+    let mut runner = MutsigRunner::new(vcf, reference)
+        .window(window_size)
+        .max_channels(max_channels)
+        .threads(threads)
+        .cache_reference(matches.occurrences_of("cache-reference") > 0)
+        .channels_other(matches.occurrences_of("channels-other") > 0)
+        .classification(options)
+        .interval_list_sequences(interval_list_sequences);
+    if let Some(samples) = matches.values_of("samples") {
+        runner = runner.samples(samples);
+    }
+    if let Some(path) = matches.value_of("channels") {
+        runner = runner.channels(path);
+    }
+    if let Some(path) = matches.value_of("skipped") {
+        runner = runner.skipped(path);
+    }
+    if let Some(regions) = matches.values_of("regions") {
+        runner = runner.regions(
+            regions
+                .flat_map(|l| l.split(','))
+                .map(|r| r.trim())
+                .filter(|r| !r.is_empty()),
+        );
+    }
+    let mode = match matches.value_of("progress") {
+        Some("always") => ProgressMode::Always,
+        Some("never") => ProgressMode::Never,
+        _ => ProgressMode::Auto,
+    };
+    let mut bar = None;
+    if let Some(mut progress) = CountProgress::new(mode) {
+        bar = Some(progress.bar());
+        runner = runner.report_progress(move |p: &Progress| progress.update(p));
+    }
+    let counts = runner.run();
+    if let Some(bar) = bar {
+        bar.finish_and_clear();
+    }
+    let CountResult {
+        samples: names,
+        signatures,
+        results,
+        ..
+    } = counts?;
+    let count_options = runner.options();
+    let options = &count_options.classification;
+    let n_samples = names.len();
+
+    let callable = match callable_beds {
+        Some((path, mut beds)) => {
+            let mut callable = Vec::with_capacity(n_samples);
+            for name in &names {
+                match beds.iter().position(|(s, _)| s == name) {
+                    Some(i) => callable.push(beds.remove(i).1),
+                    None => {
+                        return Err(Error::InvalidOptions(format!(
+                            "Can not find callable regions of sample '{}' in '{}'",
+                            name, path
+                        )))
+                    }
+                }
+            }
+            for (sample, _) in beds {
+                warn!("Ignoring callable regions of sample '{}'", sample);
+            }
+            Some(callable)
+        }
+        None => None,
+    };
+
+    // Identify the signatures that we want to report, by their label and index
+    let cosmic_order = matches.value_of("order") == Some("cosmic");
+    let orientation = match matches.value_of("orientation") {
+        Some("purine") => signature::Orientation::Purine,
+        _ => signature::Orientation::Pyrimidine,
+    };
+    let mut forwards: Vec<(String, usize)> = match &options.scheme {
+        Scheme::Sbs => sbs_rows(&signatures, cosmic_order, orientation),
+        Scheme::Sbs288(_) => stratified_rows(
+            &sbs_rows(&signatures, true, orientation),
+            &TRANSCRIPTION_STRAND_PREFIXES,
+            signatures.channel_count(),
+        ),
+        Scheme::SbsTs => stratified_rows(
+            &sbs_rows(&signatures, cosmic_order, orientation),
+            &TRANSCRIPTION_STRAND_PREFIXES,
+            signatures.channel_count(),
+        ),
+        Scheme::SbsRs(_) => stratified_rows(
+            &sbs_rows(&signatures, cosmic_order, orientation),
+            &REPLICATION_STRAND_PREFIXES,
+            signatures.channel_count(),
+        ),
+        Scheme::SbsCpg => {
+            // Drop the rows whose context rules out the CpG or non-CpG block
+            let cpg: BTreeMap<usize, Option<bool>> = signatures
+                .signatures()
+                .iter()
+                .map(|s| (signatures.index_of(s).unwrap(), s.is_cpg()))
+                .collect();
+            let n_signatures = signatures.channel_count();
+            stratified_rows(
+                &sbs_rows(&signatures, cosmic_order, orientation),
+                &CPG_PREFIXES,
+                n_signatures,
+            )
+            .into_iter()
+            .filter(|(_, i)| cpg[&(i % n_signatures)] != Some(*i < n_signatures))
+            .collect()
+        }
+        Scheme::Dbs(doublets) => doublets
+            .labels()
+            .iter()
+            .enumerate()
+            .map(|(i, l)| (l.clone(), i))
+            .collect(),
+        Scheme::Id(indels) => indels
+            .labels()
+            .iter()
+            .enumerate()
+            .map(|(i, l)| (l.clone(), i))
+            .collect(),
+    };
+
+    if let Some(mito) = &options.mito {
+        let offset = scheme_channel_count(&options.scheme, &signatures);
+        let mut mito_signatures = mito.signatures().signatures();
+        if cosmic_order {
+            mito_signatures.sort_by(|a, b| a.cosmic_cmp(b));
+        }
+        forwards.extend(mito_signatures.iter().map(|s| {
+            let label = if cosmic_order {
+                s.cosmic_label()
+            } else {
+                s.to_string()
+            };
+            (
+                format!("{}:{}", MITO_PREFIX, label),
+                offset + mito.signatures().index_of(s).unwrap(),
+            )
+        }));
+    }
+    let mut n_stratified = channel_count(options, &signatures);
+    if let Some(regions) = &options.region_labels {
+        let mut labels: Vec<&str> = regions.labels().iter().map(String::as_str).collect();
+        labels.push(UNASSIGNED_LABEL);
+        forwards = stratified_rows(&forwards, &labels, n_stratified);
+        n_stratified *= labels.len();
+    }
+    if options.clustered_cutoff.is_some() {
+        forwards = stratified_rows(&forwards, &CLUSTER_PREFIXES, n_stratified);
+    }
+
+    // Print the results
+    write_counts(std::io::stdout().lock(), &names, &forwards, &results)?;
+
+    // Compare the samples by their reported counts
+    let profiles: Vec<Vec<f64>> = (0..n_samples)
+        .map(|s| {
+            if results.is_weighted() {
+                forwards
+                    .iter()
+                    .map(|(_, i)| results.weight(*i, s))
+                    .collect()
+            } else {
+                let counts: Vec<u64> = results.column_iter(s).collect();
+                forwards.iter().map(|(_, i)| counts[*i] as f64).collect()
+            }
+        })
+        .collect();
+    if let Some(path) = matches.value_of("sample-similarity") {
+        info!("Writing sample similarities to {}", path);
+        match std::fs::File::create(path) {
+            Ok(f) => {
+                similarity::write_similarity_matrix(std::io::BufWriter::new(f), &names, &profiles)
+                    .map_err(Error::Other)?
+            }
+            Err(e) => {
+                return Err(Error::Io {
+                    action: format!("create '{}'", path),
+                    source: e,
+                })
+            }
+        }
+    }
+    if let Some(path) = matches.value_of("sample-jsd") {
+        info!("Writing sample divergences to {}", path);
+        match std::fs::File::create(path) {
+            Ok(f) => similarity::write_jsd_matrix(std::io::BufWriter::new(f), &names, &profiles)
+                .map_err(Error::Other)?,
+            Err(e) => {
+                return Err(Error::Io {
+                    action: format!("create '{}'", path),
+                    source: e,
+                })
+            }
+        }
+    }
+    if let Some(path) = matches.value_of("diversity") {
+        info!("Writing sample diversity to {}", path);
+        match std::fs::File::create(path) {
+            Ok(f) => metrics::write_diversity(std::io::BufWriter::new(f), &names, &profiles)
+                .map_err(Error::Other)?,
+            Err(e) => {
+                return Err(Error::Io {
+                    action: format!("create '{}'", path),
+                    source: e,
+                })
+            }
+        }
+    }
+    if let Some(callable) = callable {
+        let path = matches.value_of("opportunity-matrix").unwrap();
+        info!("Writing opportunities to {}", path);
+        let reference = reference::Reference::new(&count_options.reference, window_size)?;
+        let opportunities = callable
+            .iter()
+            .map(|intervals| opportunity::count_opportunities(&reference, intervals, &signatures))
+            .collect::<Result<Vec<Vec<u64>>, String>>()
+            .map_err(Error::Other)?;
+        let mut table = format!("Variant\t{}\n", names.join("\t"));
+        for (label, i) in sbs_rows(&signatures, cosmic_order, orientation) {
+            table.push_str(&label);
+            for o in &opportunities {
+                table.push_str(&format!("\t{}", o[i]));
+            }
+            table.push('\n');
+        }
+        if let Err(e) = std::fs::write(path, table) {
+            return Err(Error::Io {
+                action: format!("write '{}'", path),
+                source: e,
+            });
+        }
+    }
+    if let Some(path) = matches.value_of("cluster-samples") {
+        let distance = match matches.value_of("cluster-distance") {
+            Some("euclidean") => dendrogram::Distance::Euclidean,
+            _ => dendrogram::Distance::Cosine,
+        };
+        let tree = dendrogram::average_linkage(&dendrogram::distance_matrix(&profiles, distance))
+            .map_err(Error::Other)?;
+        info!("Writing sample dendrogram to {}", path);
+        if let Err(e) = std::fs::write(path, format!("{}\n", tree.to_newick(&names))) {
+            return Err(Error::Io {
+                action: format!("write '{}'", path),
+                source: e,
+            });
+        }
+    }
+    if let Some(path) = matches.value_of("strand-test") {
+        // Sum the transcribed and untranscribed counts of each channel over all strata
+        let n = signatures.channel_count();
+        let channels = sbs_rows(
+            &signatures,
+            cosmic_order || matches!(options.scheme, Scheme::Sbs288(_)),
+            orientation,
+        );
+        let n_blocks = forwards.len() / (TRANSCRIPTION_STRAND_PREFIXES.len() * n);
+        let counts: Vec<Vec<(u64, u64)>> = (0..n_samples)
+            .map(|s| {
+                channels
+                    .iter()
+                    .map(|(_, i)| {
+                        (0..n_blocks)
+                            .map(|b| {
+                                let offset = b * TRANSCRIPTION_STRAND_PREFIXES.len() * n + i;
+                                (results.get(offset, s), results.get(offset + n, s))
+                            })
+                            .fold((0, 0), |(t, u), (bt, bu)| (t + bt, u + bu))
+                    })
+                    .collect()
+            })
+            .collect();
+        let labels: Vec<String> = channels.into_iter().map(|(l, _)| l).collect();
+        info!("Writing strand bias tests to {}", path);
+        match std::fs::File::create(path) {
+            Ok(f) => write_strand_test(std::io::BufWriter::new(f), &names, &labels, &counts)
+                .map_err(Error::Other)?,
+            Err(e) => {
+                return Err(Error::Io {
+                    action: format!("create '{}'", path),
+                    source: e,
+                })
+            }
+        }
+    }
+    if let Some(path) = matches.value_of("homogeneity-test") {
+        let counts: Vec<Vec<u64>> = profiles
+            .iter()
+            .map(|p| p.iter().map(|v| *v as u64).collect())
+            .collect();
+        let test = stats::homogeneity_test(&counts).map_err(Error::Other)?;
+        if test.min_expected < MIN_EXPECTED_COUNT {
+            warn!(
+                "The smallest expected count of the homogeneity test is {:.2}, the chi-square approximation may be inaccurate (see --permutations)",
+                test.min_expected
+            );
+        }
+        let permutation = permutations.map(|(n, seed)| {
+            info!("Computing {} random tables for the homogeneity test", n);
+            let mut rng = downsample::SplitMix64::new(seed);
+            (
+                n,
+                stats::permutation_test(&counts, test.statistic, n, &mut rng),
+            )
+        });
+        info!("Writing homogeneity test to {}", path);
+        match std::fs::File::create(path) {
+            Ok(f) => write_homogeneity_test(std::io::BufWriter::new(f), &test, permutation)
+                .map_err(Error::Other)?,
+            Err(e) => {
+                return Err(Error::Io {
+                    action: format!("create '{}'", path),
+                    source: e,
+                })
+            }
+        }
+    }
+    if let Some(catalog) = catalog {
+        let labels: Vec<String> = forwards.iter().map(|(l, _)| l.clone()).collect();
+        let order = fit::align_channels(&catalog, &labels).map_err(Error::Other)?;
+        let signatures: Vec<Vec<f64>> = (0..catalog.columns().len())
+            .map(|j| catalog.column(j))
+            .collect();
+        let profiles: Vec<Vec<f64>> = profiles
+            .iter()
+            .map(|p| order.iter().map(|i| p[*i]).collect())
+            .collect();
+        let path = matches.value_of("match-report").unwrap();
+        info!("Writing signature matches to {}", path);
+        match std::fs::File::create(path) {
+            Ok(f) => similarity::write_matches(
+                std::io::BufWriter::new(f),
+                &names,
+                &profiles,
+                catalog.columns(),
+                &signatures,
+                match_top,
+            )
+            .map_err(Error::Other)?,
+            Err(e) => {
+                return Err(Error::Io {
+                    action: format!("create '{}'", path),
+                    source: e,
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fit the counts to the reference signatures and print the exposures of each sample followed
+/// by the residual of the fit.
+fn fit_signatures(matches: &clap::ArgMatches) -> Result<(), String> {
+    let min_similarity = match matches.value_of("min-similarity").unwrap().parse::<f64>() {
+        Ok(v) if (0.0..=1.0).contains(&v) => v,
+        _ => {
+            return Err(
+                "Can not parse --min-similarity, expected a value between 0 and 1".to_owned(),
+            )
+        }
+    };
+    let selection = match matches.value_of("max-signatures") {
+        Some(k) => {
+            let max_signatures = match k.parse::<usize>() {
+                Ok(k) if k > 0 => k,
+                _ => {
+                    return Err(format!(
+                        "Can not parse --max-signatures '{}', expected a positive number",
+                        k
+                    ))
+                }
+            };
+            let min_improvement = match matches.value_of("min-improvement").unwrap().parse::<f64>()
+            {
+                Ok(v) if v >= 0.0 => v,
+                _ => {
+                    return Err(
+                        "Can not parse --min-improvement, expected a non-negative value".to_owned(),
+                    )
+                }
+            };
+            let criterion = match matches.value_of("selection-criterion") {
+                Some("l2") => fit::Criterion::L2,
+                _ => fit::Criterion::Cosine,
+            };
+            Some(fit::Selection {
+                max_signatures,
+                min_improvement,
+                criterion,
+            })
+        }
+        None => None,
+    };
+    let path = matches.value_of("COUNTS").unwrap();
+    info!("Loading counts from {}", path);
+    let counts = fit::read_matrix(path)?;
+    let path = matches.value_of("signatures").unwrap();
+    info!("Loading reference signatures from {}", path);
+    let mut catalog = fit::read_matrix(path)?;
+    if let Some(direction) = matches.value_of("rescale-catalog") {
+        let genome = matches.value_of("genome-opportunities").unwrap();
+        let exome = matches.value_of("exome-opportunities").unwrap();
+        let (source, target) = match direction {
+            "genome-to-exome" => (genome, exome),
+            _ => (exome, genome),
+        };
+        info!(
+            "Rescaling reference signatures from {} to {}",
+            source, target
+        );
+        catalog = fit::rescale_catalog(
+            &catalog,
+            &read_opportunities(source)?,
+            &read_opportunities(target)?,
+        )?;
+    }
+    debug!(
+        "Fitting {} samples to {} signatures",
+        counts.columns().len(),
+        catalog.columns().len()
+    );
+    let fits = match selection {
+        Some(selection) => fit::fit_sparse(&catalog, &counts, selection)?,
+        None => fit::fit(&catalog, &counts)?,
+    };
+
+    // Round off the numerical errors of the solver
+    let round = |v: f64| (v * 1e6).round() / 1e6;
+    println!("Signature\t{}", counts.columns().join("\t"));
+    for (i, signature) in catalog.columns().iter().enumerate() {
+        print!("{}", signature);
+        for f in &fits {
+            print!("\t{}", round(f.exposures[i]));
+        }
+        println!();
+    }
+    print!("{}", RESIDUAL_LABEL);
+    for f in &fits {
+        print!("\t{}", round(f.residual));
+    }
+    println!();
+    print!("{}", SIMILARITY_LABEL);
+    for f in &fits {
+        match f.similarity {
+            Some(v) => print!("\t{}", round(v)),
+            None => print!("\t{}", similarity::NA),
+        }
+    }
+    println!();
+    print!("{}", POOR_FIT_LABEL);
+    for f in &fits {
+        print!("\t{}", f.is_poor(min_similarity));
+    }
+    println!();
+    if selection.is_some() {
+        print!("{}", SELECTED_LABEL);
+        for f in &fits {
+            let selected: Vec<&str> = f
+                .selected
+                .iter()
+                .flatten()
+                .map(|j| catalog.columns()[*j].as_str())
+                .collect();
+            if selected.is_empty() {
+                print!("\t{}", similarity::NA);
+            } else {
+                print!("\t{}", selected.join(","));
+            }
+        }
+        println!();
+    }
+
+    if let Some(path) = matches.value_of("plot-exposures") {
+        let order = match matches.value_of("plot-order") {
+            Some("burden") => plot::Order::Burden,
+            Some("dominant") => plot::Order::Dominant,
+            _ => plot::Order::Input,
+        };
+        let exposures: Vec<Vec<f64>> = fits.iter().map(|f| f.exposures.clone()).collect();
+        let svg = plot::plot_exposures(counts.columns(), catalog.columns(), &exposures, order);
+        info!("Writing exposure plot to {}", path);
+        if let Err(e) = std::fs::write(path, svg) {
+            return Err(format!("Can not write '{}': {}", path, e));
+        }
+    }
+
+    let poor: Vec<&str> = counts
+        .columns()
+        .iter()
+        .zip(&fits)
+        .filter(|(_, f)| f.is_poor(min_similarity))
+        .map(|(s, _)| s.as_str())
+        .collect();
+    if !poor.is_empty() {
+        warn!(
+            "{} samples are poorly explained by the signatures (similarity below {}): {}",
+            poor.len(),
+            min_similarity,
+            poor.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Read the opportunities per channel of a single territory from the file at `path`.
+fn read_opportunities(path: &str) -> Result<fit::Matrix, String> {
+    let opportunities = fit::read_matrix(path)?;
+    if opportunities.columns().len() != 1 {
+        return Err(format!(
+            "Can not read the opportunities of {} territories in '{}', expected one",
+            opportunities.columns().len(),
+            path
+        ));
+    }
+    Ok(opportunities)
+}
+
+/// Compare the proportions of the channels between the two groups of samples and print the
+/// comparison of each channel.
+fn compare_groups(matches: &clap::ArgMatches) -> Result<(), String> {
+    let path = matches.value_of("COUNTS").unwrap();
+    info!("Loading counts from {}", path);
+    let counts = fit::read_matrix(path)?;
+    let path = matches.value_of("groups").unwrap();
+    info!("Loading groups from {}", path);
+    let groups = compare::read_groups(path)?;
+    let (names, comparisons) = compare::compare(&counts, &groups)?;
+    compare::write_comparisons(std::io::stdout().lock(), &names, &comparisons)
+}
+
+/// Combine the matrices of counts and print the matrix of all their samples.
+fn aggregate_matrices(matches: &clap::ArgMatches) -> Result<(), String> {
+    let mut matrices = Vec::new();
+    for path in matches.values_of("MATRICES").unwrap() {
+        info!("Loading counts from {}", path);
+        matrices.push((path.to_owned(), fit::read_matrix(path)?));
+    }
+    let matrix = aggregate::aggregate(&matrices, matches.is_present("sum-duplicates"))?;
+    aggregate::write_matrix(std::io::stdout().lock(), &matrix)
+}
+
+/// Summarize the counts of each sample and print the metrics.
+fn summarize_metrics(matches: &clap::ArgMatches) -> Result<(), String> {
+    let path = matches.value_of("COUNTS").unwrap();
+    info!("Loading counts from {}", path);
+    let counts = metrics::read_counts(path)?;
+    let metrics = metrics::metrics(&counts)?;
+    metrics::write_metrics(std::io::stdout().lock(), &metrics)
+}
+
+/// Write the chi-square homogeneity `test` and its Monte Carlo p-value by the number of
+/// `permutation`s, if computed, to `writer`.
+fn write_homogeneity_test<W: std::io::Write>(
+    mut writer: W,
+    test: &stats::Homogeneity,
+    permutation: Option<(u64, f64)>,
+) -> Result<(), String> {
+    let write_error = |e: std::io::Error| format!("Can not write homogeneity test: {}", e);
+    let (permutations, monte_carlo) = match permutation {
+        Some((n, p)) => (n.to_string(), format!("{:.6e}", p)),
+        None => (similarity::NA.to_owned(), similarity::NA.to_owned()),
+    };
+    writeln!(
+        writer,
+        "Statistic\tDF\tP\tMinExpected\tPermutations\tPermutationP"
+    )
+    .map_err(write_error)?;
+    writeln!(
+        writer,
+        "{}\t{}\t{:.6e}\t{}\t{}\t{}",
+        (test.statistic * 1e6).round() / 1e6,
+        test.df,
+        test.p,
+        (test.min_expected * 1e6).round() / 1e6,
+        permutations,
+        monte_carlo
+    )
+    .map_err(write_error)?;
+    writer.flush().map_err(write_error)
+}
+
+/// Write the two-sided binomial test of the transcribed vs. untranscribed `counts` of each
+/// channel `labels` and sample `names` to `writer`. The q-values are corrected across the
+/// channels of a sample; channels without any count are not tested.
+fn write_strand_test<W: std::io::Write>(
+    mut writer: W,
+    names: &[String],
+    labels: &[String],
+    counts: &[Vec<(u64, u64)>],
+) -> Result<(), String> {
+    let write_error = |e: std::io::Error| format!("Can not write strand bias tests: {}", e);
+    writeln!(
+        writer,
+        "Sample\tChannel\tTranscribed\tUntranscribed\tRatio\tP\tQ"
+    )
+    .map_err(write_error)?;
+    for (name, counts) in names.iter().zip(counts) {
+        let p_values: Vec<Option<f64>> = counts
+            .iter()
+            .map(|(t, u)| match t + u {
+                0 => None,
+                n => Some(stats::binomial_test(*t, n)),
+            })
+            .collect();
+        let tested: Vec<f64> = p_values.iter().flatten().cloned().collect();
+        let mut q_values = stats::benjamini_hochberg(&tested).into_iter();
+        for (c, (t, u)) in counts.iter().enumerate() {
+            let ratio = match u {
+                0 => similarity::NA.to_owned(),
+                u => ((*t as f64 / *u as f64 * 1e6).round() / 1e6).to_string(),
+            };
+            let (p, q) = match p_values[c] {
+                Some(p) => (
+                    format!("{:.6e}", p),
+                    format!("{:.6e}", q_values.next().unwrap()),
+                ),
+                None => (similarity::NA.to_owned(), similarity::NA.to_owned()),
+            };
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                name, labels[c], t, u, ratio, p, q
+            )
+            .map_err(write_error)?;
+        }
+    }
+    writer.flush().map_err(write_error)
+}
+
+/// The smallest expected count of the homogeneity test without a warning about the inaccuracy
+/// of the chi-square approximation.
+const MIN_EXPECTED_COUNT: f64 = 5.0;
+
+/// The label of the row of the residuals of the fitted signatures.
+const RESIDUAL_LABEL: &str = "residual";
+
+/// The label of the row of the similarities of the counts and their reconstruction.
+const SIMILARITY_LABEL: &str = "similarity";
+
+/// The label of the row of the flags of the samples poorly explained by the signatures.
+const POOR_FIT_LABEL: &str = "poor_fit";
+
+/// The label of the row of the signatures selected per sample in the order of their selection.
+const SELECTED_LABEL: &str = "selected";
+
+/// Parse the non-negative integer threshold `value` of the parameter `name`.
+fn parse_threshold(value: Option<&str>, name: &str) -> Result<Option<i32>, Error> {
+    match value {
+        Some(v) => match v.parse::<i32>() {
+            Ok(t) if t >= 0 => Ok(Some(t)),
+            Ok(_) => Err(Error::InvalidOptions(format!(
+                "Invalid {}-parameter '{}'",
+                name, v
+            ))),
+            Err(e) => Err(Error::InvalidOptions(format!(
+                "Invalid {}-parameter '{}': {}",
+                name, v, e
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Parse the fraction `value` (between 0 and 1) of the parameter `name`.
+fn parse_fraction(value: Option<&str>, name: &str) -> Result<Option<f32>, Error> {
+    match value {
+        Some(v) => match v.parse::<f32>() {
+            Ok(f) if (0.0..=1.0).contains(&f) => Ok(Some(f)),
+            Ok(_) => Err(Error::InvalidOptions(format!(
+                "Invalid {}-parameter '{}': must be between 0 and 1",
+                name, v
+            ))),
+            Err(e) => Err(Error::InvalidOptions(format!(
+                "Invalid {}-parameter '{}': {}",
+                name, v, e
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_strand_test() {
+        let names = vec!["S1".to_owned()];
+        let labels = vec!["C>A".to_owned(), "C>G".to_owned(), "C>T".to_owned()];
+        let mut out = Vec::new();
+        write_strand_test(&mut out, &names, &labels, &[vec![(0, 10), (0, 0), (4, 2)]]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            "Sample\tChannel\tTranscribed\tUntranscribed\tRatio\tP\tQ"
+        );
+        assert_eq!(lines[1], "S1\tC>A\t0\t10\t0\t1.953125e-3\t3.906250e-3");
+        // Channels without counts are not tested
+        assert_eq!(lines[2], "S1\tC>G\t0\t0\tNA\tNA\tNA");
+        assert_eq!(lines[3], "S1\tC>T\t4\t2\t2\t6.875000e-1\t6.875000e-1");
+    }
+
+    #[test]
+    fn test_write_homogeneity_test() {
+        let test = stats::homogeneity_test(&[vec![10, 20], vec![20, 10]]).unwrap();
+        let mut out = Vec::new();
+        write_homogeneity_test(&mut out, &test, None).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Statistic\tDF\tP\tMinExpected\tPermutations\tPermutationP\n\
+             6.666667\t1\t9.823275e-3\t15\tNA\tNA\n"
+        );
+        let mut out = Vec::new();
+        write_homogeneity_test(&mut out, &test, Some((99, 0.01))).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("\t15\t99\t1.000000e-2\n"));
+    }
+}
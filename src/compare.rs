@@ -0,0 +1,291 @@
+use crate::fit::Matrix;
+use crate::similarity::NA;
+use crate::stats;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// The smallest expected count of the contingency table tested by the z-test, smaller ones
+/// are tested by Fisher's exact test.
+const MIN_EXPECTED_COUNT: f64 = 5.0;
+
+/// The test of the difference of the proportions of a channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Test {
+    /// The two-proportion z-test
+    Z,
+    /// Fisher's exact test for small expected counts
+    Fisher,
+}
+
+impl fmt::Display for Test {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Test::Z => write!(f, "z"),
+            Test::Fisher => write!(f, "fisher"),
+        }
+    }
+}
+
+/// The comparison of the proportions of a channel between the two groups.
+#[derive(Debug)]
+pub struct Comparison {
+    /// The label of the channel
+    pub channel: String,
+    /// The counts of the channel per group
+    pub counts: [u64; 2],
+    /// The proportions of the channel of all counts per group
+    pub proportions: [f64; 2],
+    /// The test and its p- and q-value, unless the channel is not counted in any group
+    pub test: Option<(Test, f64, f64)>,
+}
+
+impl Comparison {
+    /// Return the effect size, i.e., the difference of the proportions of the first and the
+    /// second group.
+    pub fn difference(&self) -> f64 {
+        self.proportions[0] - self.proportions[1]
+    }
+}
+
+/// Read the assignment of samples to groups from the (optionally gzip compressed) file at `path`.
+pub fn read_groups<P: AsRef<Path>>(path: P) -> Result<Vec<(String, String)>, String> {
+    let name = path.as_ref().display().to_string();
+    parse_groups(crate::input::open(path)?, &name)
+}
+
+/// Parse the assignment of samples to groups from `reader`. Each line holds the name of a
+/// sample and its group, separated by a tab. Empty and comment lines are skipped.
+fn parse_groups<R: BufRead>(reader: R, name: &str) -> Result<Vec<(String, String)>, String> {
+    let mut groups = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => return Err(format!("Can not read '{}': {}", name, e)),
+        };
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').map(|f| f.trim()).collect();
+        match fields[..] {
+            [sample, group] if !sample.is_empty() && !group.is_empty() => {
+                groups.push((sample.to_owned(), group.to_owned()))
+            }
+            _ => {
+                return Err(format!(
+                    "Can not parse line {} of '{}', expected a sample and its group",
+                    i + 1,
+                    name
+                ))
+            }
+        }
+    }
+    Ok(groups)
+}
+
+/// Compare the proportions of each channel of the `counts` between the two groups of the
+/// samples in `assignment`, in the order of their first assignment. The p-values are
+/// corrected across the channels counted in any of the groups.
+pub fn compare(
+    counts: &Matrix,
+    assignment: &[(String, String)],
+) -> Result<(Vec<String>, Vec<Comparison>), String> {
+    let mut groups: Vec<String> = Vec::new();
+    let mut group_of: HashMap<&str, usize> = HashMap::new();
+    for (sample, group) in assignment {
+        let index = match groups.iter().position(|g| g == group) {
+            Some(i) => i,
+            None => {
+                groups.push(group.clone());
+                groups.len() - 1
+            }
+        };
+        if group_of.insert(sample, index).is_some() {
+            return Err(format!("Can not assign sample '{}' to two groups", sample));
+        }
+    }
+    if groups.len() != 2 {
+        return Err(format!(
+            "Can not compare {} groups, expected exactly two",
+            groups.len()
+        ));
+    }
+    for (sample, _) in assignment {
+        if !counts.columns().contains(sample) {
+            warn!("Ignoring sample '{}' without counts", sample);
+        }
+    }
+
+    // Sum the counts of each channel per group
+    let mut sums = vec![[0u64; 2]; counts.rows().len()];
+    for (column, sample) in counts.columns().iter().enumerate() {
+        let group = match group_of.get(sample.as_str()) {
+            Some(g) => *g,
+            None => {
+                warn!("Ignoring sample '{}' without group", sample);
+                continue;
+            }
+        };
+        for (row, sum) in sums.iter_mut().enumerate() {
+            let value = counts.value(row, column);
+            if value < 0.0 || value.fract() != 0.0 {
+                return Err(format!(
+                    "Can not compare count {} of sample '{}', expected a non-negative integer",
+                    value, sample
+                ));
+            }
+            sum[group] += value as u64;
+        }
+    }
+    let totals = [0, 1].map(|g| sums.iter().map(|s| s[g]).sum::<u64>());
+    for (group, total) in groups.iter().zip(&totals) {
+        if *total == 0 {
+            return Err(format!("Can not compare group '{}' without counts", group));
+        }
+    }
+
+    let tests: Vec<Option<(Test, f64)>> = sums
+        .iter()
+        .map(|s| match s {
+            [0, 0] => None,
+            _ => Some(test_proportions(s[0], totals[0], s[1], totals[1])),
+        })
+        .collect();
+    let p_values: Vec<f64> = tests.iter().flatten().map(|(_, p)| *p).collect();
+    let mut q_values = stats::benjamini_hochberg(&p_values).into_iter();
+    let comparisons = counts
+        .rows()
+        .iter()
+        .zip(sums)
+        .zip(tests)
+        .map(|((channel, counts), test)| Comparison {
+            channel: channel.clone(),
+            counts,
+            proportions: [0, 1].map(|g| counts[g] as f64 / totals[g] as f64),
+            test: test.map(|(t, p)| (t, p, q_values.next().unwrap())),
+        })
+        .collect();
+    Ok((groups, comparisons))
+}
+
+/// Test the proportions `x1 / n1` and `x2 / n2` for equality by the z-test, or by Fisher's
+/// exact test if any expected count of the contingency table is small.
+fn test_proportions(x1: u64, n1: u64, x2: u64, n2: u64) -> (Test, f64) {
+    let n = (n1 + n2) as f64;
+    let smallest_expected = [n1, n2]
+        .iter()
+        .flat_map(|r| [x1 + x2, n1 + n2 - x1 - x2].map(|c| (*r * c) as f64 / n))
+        .fold(f64::INFINITY, f64::min);
+    if smallest_expected < MIN_EXPECTED_COUNT {
+        (
+            Test::Fisher,
+            stats::fisher_exact_test(x1, n1 - x1, x2, n2 - x2),
+        )
+    } else {
+        (Test::Z, stats::two_proportion_test(x1, n1, x2, n2))
+    }
+}
+
+/// Write the `comparisons` of the two `groups` to `writer` as a tab-separated table.
+pub fn write_comparisons<W: Write>(
+    mut writer: W,
+    groups: &[String],
+    comparisons: &[Comparison],
+) -> Result<(), String> {
+    let write_error = |e: std::io::Error| format!("Can not write comparison: {}", e);
+    writeln!(
+        writer,
+        "Channel\tCount_{0}\tCount_{1}\tProportion_{0}\tProportion_{1}\tDifference\tTest\tP\tQ",
+        groups[0], groups[1]
+    )
+    .map_err(write_error)?;
+    // Round off the summation errors
+    let round = |v: f64| (v * 1e6).round() / 1e6;
+    for c in comparisons {
+        let (test, p, q) = match c.test {
+            Some((t, p, q)) => (t.to_string(), format!("{:.6e}", p), format!("{:.6e}", q)),
+            None => (NA.to_owned(), NA.to_owned(), NA.to_owned()),
+        };
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            c.channel,
+            c.counts[0],
+            c.counts[1],
+            round(c.proportions[0]),
+            round(c.proportions[1]),
+            round(c.difference()),
+            test,
+            p,
+            q
+        )
+        .map_err(write_error)?;
+    }
+    writer.flush().map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fit;
+
+    #[test]
+    fn test_parse_groups() {
+        let groups = parse_groups("# comment\nS1\tR\n\nS2\tN\n".as_bytes(), "test").unwrap();
+        assert_eq!(
+            groups,
+            vec![
+                ("S1".to_owned(), "R".to_owned()),
+                ("S2".to_owned(), "N".to_owned())
+            ]
+        );
+        assert!(parse_groups("S1\n".as_bytes(), "test").is_err());
+        assert!(parse_groups("S1\tR\tN\n".as_bytes(), "test").is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        let counts = fit::read_matrix("testdata/ex2_cohort.txt").unwrap();
+        let groups = read_groups("testdata/ex2_groups.tsv").unwrap();
+        let (names, comparisons) = compare(&counts, &groups).unwrap();
+        assert_eq!(names, vec!["responder", "nonresponder"]);
+        let get = |label: &str| comparisons.iter().find(|c| c.channel == label).unwrap();
+
+        // The C>T are enriched in the responders
+        let c_t = get("C>T");
+        assert_eq!(c_t.counts, [120, 20]);
+        assert!(c_t.difference() > 0.05);
+        let (test, p, q) = c_t.test.unwrap();
+        assert_eq!(test, Test::Z);
+        assert!(p < 1e-10 && q < 1e-10);
+        // The other channels are not
+        for label in &["C>A", "T>C"] {
+            let (_, _, q) = get(label).test.unwrap();
+            assert!(q > 0.05, "{}", label);
+        }
+        // Few counts are tested exactly
+        assert_eq!(get("T>A").test.unwrap().0, Test::Fisher);
+        // Channels without counts are not tested
+        assert!(get("C>G").test.is_none());
+
+        let mut out = Vec::new();
+        write_comparisons(&mut out, &names, &comparisons).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "Channel\tCount_responder\tCount_nonresponder\tProportion_responder\tProportion_nonresponder\tDifference\tTest\tP\tQ");
+        assert_eq!(lines[2], "C>G\t0\t0\t0\t0\t0\tNA\tNA\tNA");
+    }
+
+    #[test]
+    fn test_compare_invalid() {
+        let counts = fit::read_matrix("testdata/ex2_cohort.txt").unwrap();
+        let one_group = vec![("R1".to_owned(), "responder".to_owned())];
+        assert!(compare(&counts, &one_group).is_err());
+        let twice = vec![
+            ("R1".to_owned(), "responder".to_owned()),
+            ("R1".to_owned(), "nonresponder".to_owned()),
+        ];
+        assert!(compare(&counts, &twice).is_err());
+    }
+}
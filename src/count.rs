@@ -0,0 +1,5085 @@
+use crate::error::Error;
+use crate::reference::ReferenceProvider;
+use crate::{
+    annotation, classify, dbs, dedupe, distance, downsample, filter, genotype, indel, info_filter,
+    intervals, mito, profile, reference, remote, replication, result, signature, summary,
+};
+use rust_htslib::bcf::Read;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc};
+
+/// The input and parameters of counting the variants of a VCF file.
+pub struct CountOptions {
+    /// The path or URL of the VCF file
+    pub vcf: String,
+    /// The path of the reference sequences (FASTA)
+    pub reference: String,
+    /// The number of bases of the sequence context on each side of a substitution
+    pub window: u8,
+    /// The names of the samples to count in this order, or all samples of the VCF if none
+    pub samples: Option<Vec<String>>,
+    /// The number of threads used for decompressing the VCF and classifying its records
+    pub threads: usize,
+    /// Whether to hold the sequence of the current contig in memory instead of fetching the
+    /// window of each variant from the FASTA file
+    pub cache_reference: bool,
+    /// The largest number of channels to allocate for the window
+    pub max_channels: usize,
+    /// The path of a definition of custom channels to count into instead of the substitutions
+    pub channels: Option<String>,
+    /// Whether to count the substitutions of no custom channel into an additional one
+    pub channels_other: bool,
+    /// The path of a file to log the skipped records to
+    pub skipped: Option<String>,
+    /// The regions (`contig` or `contig:start-end`, 1-based) to read by the index of the VCF
+    /// file, which may be remote, instead of all of its records
+    pub regions: Option<Vec<String>>,
+    /// The classification and filters of the variants as configured by the command line
+    pub(crate) classification: Options,
+    /// The sequences of the interval list restricting the counting, if any
+    pub(crate) interval_list_sequences: Vec<String>,
+}
+
+impl CountOptions {
+    /// Count the single base substitutions without context of all samples of the VCF file at
+    /// `vcf` against the reference sequences at `reference`.
+    pub fn new<V: Into<String>, R: Into<String>>(vcf: V, reference: R) -> Self {
+        CountOptions {
+            vcf: vcf.into(),
+            reference: reference.into(),
+            window: 0,
+            samples: None,
+            threads: 1,
+            cache_reference: false,
+            max_channels: signature::DEFAULT_MAX_CHANNELS,
+            channels: None,
+            channels_other: false,
+            skipped: None,
+            regions: None,
+            classification: Options::default(),
+            interval_list_sequences: Vec::new(),
+        }
+    }
+}
+
+/// The counts of the variants of a VCF file per channel and sample.
+pub struct CountResult {
+    /// The names of the counted samples in the order of the columns of the `results`
+    pub samples: Vec<String>,
+    /// The channels, which index the rows of the `results`
+    pub signatures: signature::Signatures,
+    /// The counts per channel (rows) and sample (columns)
+    pub results: result::ResultMatrix,
+    /// The processed and skipped records
+    pub summary: summary::Summary,
+}
+
+impl CountResult {
+    /// Return the profile of the sample `name`, or `None` if it was not counted.
+    pub fn profile(&self, name: &str) -> Option<profile::SampleProfile<'_>> {
+        let sidx = self.samples.iter().position(|s| s == name)?;
+        Some(profile::SampleProfile::new(
+            &self.samples[sidx],
+            &self.signatures,
+            &self.results,
+            sidx,
+        ))
+    }
+}
+
+/// The state of a running count, as reported to the progress callback.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress<'a> {
+    /// The number of records read so far
+    pub records: u64,
+    /// The number of records of the VCF file, if known from its index
+    pub total: Option<u64>,
+    /// The contig of the last record read, if known
+    pub contig: Option<&'a str>,
+    /// The 1-based position of the last record read, if known
+    pub position: Option<u64>,
+    /// The number of records and alleles skipped so far
+    pub skipped: u64,
+}
+
+/// Count the variants of the VCF file of the `options` per channel and sample.
+///
+/// ```
+/// let mut options = mutsig::CountOptions::new("testdata/ex2.vcf", "testdata/ex2.fa");
+/// options.window = 1;
+/// options.samples = Some(vec!["Example2".to_owned()]);
+/// let counts = mutsig::count_vcf(&options).unwrap();
+/// assert_eq!(counts.samples, vec!["Example2"]);
+/// assert_eq!(counts.summary.records(), 2);
+/// ```
+pub fn count_vcf(options: &CountOptions) -> Result<CountResult, Error> {
+    count_vcf_with_progress(options, None)
+}
+
+/// Like `count_vcf()`, but report the `Progress` of the count to `progress` after each record.
+pub(crate) fn count_vcf_with_progress(
+    options: &CountOptions,
+    progress: Option<&mut dyn FnMut(&Progress)>,
+) -> Result<CountResult, Error> {
+    let classification = &options.classification;
+    let window_size = options.window;
+
+    // Load access to the reference
+    info!(
+        "Using reference from {} with window size of {}",
+        options.reference, window_size
+    );
+    let reference = reference::Reference::new(&options.reference, window_size)?
+        .cache_contigs(options.cache_reference);
+
+    // Open the VCF file
+    let mut variants = open_vcf(&options.vcf, options.threads)?;
+
+    // Validate the sequence dictionary of the interval list against the VCF
+    let header = variants.header();
+    if header.contig_count() > 0 && !options.interval_list_sequences.is_empty() {
+        let missing: Vec<&String> = options
+            .interval_list_sequences
+            .iter()
+            .filter(|s| header.name2rid(s.as_bytes()).is_err())
+            .collect();
+        if missing.len() == options.interval_list_sequences.len() {
+            return Err(Error::InvalidOptions(format!(
+                "None of the interval list sequences is declared in the VCF header (e.g., '{}')",
+                missing[0]
+            )));
+        }
+        for s in missing {
+            warn!(
+                "Interval list sequence '{}' is not declared in the VCF header",
+                s
+            );
+        }
+    }
+
+    // Fail before counting if a record may be on a contig missing from the reference or
+    // declared with another length, unless the index lists no records on it
+    if !classification.no_validate_contigs {
+        let records = indexed_contig_records(&options.vcf);
+        let contigs: Vec<Arc<str>> = contig_names(header)
+            .into_iter()
+            .enumerate()
+            .filter(|(rid, _)| {
+                records
+                    .as_ref()
+                    .is_none_or(|records| records.get(*rid).is_some_and(|n| *n > 0))
+            })
+            .map(|(_, name)| name)
+            .collect();
+        let lengths = contig_lengths(header);
+        check_reference_contigs(&contigs, &lengths, &reference, classification)?;
+    }
+
+    // Warn about contigs that can never match
+    for c in classification.chromosomes.iter().flatten() {
+        if header.contig_count() > 0 && header.name2rid(c.as_bytes()).is_err() {
+            warn!("Contig '{}' is not declared in the VCF header", c);
+        }
+    }
+
+    // Warn about FILTER values that can never match
+    let declared = filter::declared_filters(header);
+    for f in classification
+        .include_filters
+        .iter()
+        .chain(classification.exclude_filters.iter())
+        .flatten()
+    {
+        if !declared.contains(f) {
+            warn!("FILTER value '{}' is not declared in the VCF header", f);
+        }
+    }
+
+    // Warn about INFO fields that are never present
+    if let Some(e) = &classification.info_filter {
+        for key in e.keys() {
+            if header.info_type(key.as_bytes()).is_err() {
+                warn!("INFO field '{}' is not declared in the VCF header", key);
+            }
+        }
+    }
+
+    if let Some(somatic) = &classification.somatic {
+        if header.info_type(somatic.tag().as_bytes()).is_err() {
+            warn!(
+                "Somatic status field '{}' is not declared in the VCF header, no variant will be counted",
+                somatic.tag()
+            );
+        }
+    }
+    if let Some((tag, _)) = &classification.max_pop_af {
+        if header.info_type(tag.as_bytes()).is_err() {
+            warn!(
+                "Population allele frequency field '{}' is not declared in the VCF header",
+                tag
+            );
+        }
+    }
+
+    // Fetch information about the samples.
+    let sample_names: Vec<String> = header
+        .samples()
+        .iter()
+        .map(|u| std::str::from_utf8(u).unwrap().to_owned())
+        .collect();
+    // In case a list of samples to analyze is requested, find their indizes
+    // and memorize them.
+    let bcf_sample_indizes: Vec<usize> = match &options.samples {
+        None => (0..sample_names.len()).collect(),
+        Some(values) => {
+            let mut s = Vec::new();
+            for v in values {
+                s.push(index_of(&sample_names, v)?)
+            }
+            s
+        }
+    };
+
+    let n_samples = bcf_sample_indizes.len();
+    debug!(
+        "Processing {} samples ({:?}) from: {:?}",
+        n_samples, bcf_sample_indizes, sample_names
+    );
+
+    // The genotypes can only be decoded if the samples have any
+    if n_samples > 0 && !classification.no_genotypes && header.format_type(b"GT").is_err() {
+        return Err(Error::InvalidOptions(
+            "The VCF file declares no FORMAT/GT field for its samples, use --no-genotypes to count each alternative allele once per sample"
+                .to_owned(),
+        ));
+    }
+
+    // We can only ignore homgeneous sites if we have more than one sample
+    if classification.ignore_homogeneous_sites && n_samples < 2 {
+        return Err(Error::InvalidOptions(
+            "Found only one sample but were told to ignor homgeneous sites - this is not possible"
+                .to_owned(),
+        ));
+    }
+
+    // The mitochondrial channels have no counterpart without context
+    if classification.mito.is_some() && classification.count_partial_context {
+        return Err(Error::InvalidOptions(
+            "Can not count partial contexts in mito mode, its channels all have a context"
+                .to_owned(),
+        ));
+    }
+
+    // Build a list of all signatures
+    let signatures = if let Some(path) = &options.channels {
+        info!("Loading channel definition from {}", path);
+        let definition = signature::read_definition(path)?;
+        signature::Signatures::from_definition(
+            window_size.into(),
+            &definition,
+            options.channels_other,
+        )?
+    } else if classification.count_partial_context {
+        signature::Signatures::with_partial_context(window_size.into())
+    } else {
+        signature::Signatures::new(window_size.into(), options.max_channels)?
+    };
+    signatures.validate(window_size.into())?;
+    debug!("Found a total of {} signature variants", signatures.len());
+
+    // Count the variants into the result matrix
+    let mut summary = summary::Summary::new();
+    if let Some(path) = &options.skipped {
+        match std::fs::File::create(path) {
+            Ok(f) => summary.log_skipped_to(Box::new(std::io::BufWriter::new(f)))?,
+            Err(e) => {
+                return Err(Error::Io {
+                    action: format!("create '{}'", path),
+                    source: e,
+                })
+            }
+        }
+    }
+    // Complete the progress by the number of records listed in the index, unless only some
+    // regions are read
+    let total = progress
+        .as_ref()
+        .filter(|_| options.regions.is_none())
+        .and_then(|_| indexed_record_count(&options.vcf));
+    let mut progress = progress.map(|report| {
+        move |p: &Progress| {
+            report(&Progress {
+                total: total.or(p.total),
+                ..*p
+            })
+        }
+    });
+    let progress = progress.as_mut().map(|p| p as &mut dyn FnMut(&Progress));
+    let results = if let Some(regions) = &options.regions {
+        count_regions(
+            options,
+            regions,
+            &reference,
+            &signatures,
+            &bcf_sample_indizes,
+            &mut summary,
+            progress,
+        )?
+    } else if counts_contigs_in_parallel(options, variants.header()) {
+        info!("Counting the contigs in parallel using the index of the VCF file");
+        count_contigs(
+            options,
+            variants.header().contig_count(),
+            &signatures,
+            &bcf_sample_indizes,
+            &mut summary,
+            progress,
+        )?
+    } else {
+        count_variants(
+            &mut variants,
+            &reference,
+            &signatures,
+            &bcf_sample_indizes,
+            classification,
+            options.threads,
+            &mut summary,
+            progress,
+        )?
+    };
+    if results.overflowed() {
+        summary.overflow();
+    }
+    summary.flush()?;
+    summary.log();
+    let mismatches = summary.skipped(summary::Reason::RefMismatch);
+    if let Some(max_fraction) = classification.max_ref_mismatch_fraction {
+        if mismatches as f64 > f64::from(max_fraction) * summary.records() as f64 {
+            return Err(Error::ReferenceMismatches {
+                mismatches,
+                records: summary.records(),
+                max_fraction,
+            });
+        }
+    }
+    if let Some(max_fraction) = classification.max_ignored_fraction {
+        let ignored = summary.ignored_records();
+        if ignored as f64 > f64::from(max_fraction) * summary.records() as f64 {
+            return Err(Error::TooManyIgnored {
+                ignored,
+                records: summary.records(),
+                max_fraction,
+            });
+        }
+    }
+    for (sidx, (before, after)) in summary.downsampling().iter().enumerate() {
+        info!(
+            "Downsampled sample {} from {} to {} mutations",
+            sample_names[bcf_sample_indizes[sidx]], before, after
+        );
+    }
+
+    let samples = bcf_sample_indizes
+        .iter()
+        .map(|sidx| sample_names[*sidx].clone())
+        .collect();
+    Ok(CountResult {
+        samples,
+        signatures,
+        results,
+        summary,
+    })
+}
+
+/// Write the counts of the `rows`, i.e., their labels and indizes in the `results`, for the
+/// samples `names` to `writer` as a tab-separated matrix.
+pub(crate) fn write_counts<W: std::io::Write>(
+    mut writer: W,
+    names: &[String],
+    rows: &[(String, usize)],
+    results: &result::ResultMatrix,
+) -> Result<(), Error> {
+    let write_error = |e: std::io::Error| Error::Io {
+        action: "write counts".to_owned(),
+        source: e,
+    };
+    writeln!(writer, "Variant\t{}", names.join("\t")).map_err(write_error)?;
+    for (label, index) in rows {
+        write!(writer, "{}", label).map_err(write_error)?;
+        if results.is_weighted() {
+            for s in 0..names.len() {
+                // Round off the summation errors
+                let weight = (results.weight(*index, s) * 1e6).round() / 1e6;
+                write!(writer, "\t{}", weight).map_err(write_error)?;
+            }
+        } else {
+            for count in results.row(*index) {
+                write!(writer, "\t{}", count).map_err(write_error)?;
+            }
+        }
+        writeln!(writer).map_err(write_error)?;
+    }
+    writer.flush().map_err(write_error)
+}
+
+/// The prefixes of the labels of the transcription strand classes in the order of their
+/// blocks: transcribed, untranscribed and unknown.
+pub(crate) const TRANSCRIPTION_STRAND_PREFIXES: [&str; 3] = ["T", "U", "N"];
+
+/// The prefixes of the labels of the replication strand classes in the order of their
+/// blocks: leading, lagging and unknown.
+pub(crate) const REPLICATION_STRAND_PREFIXES: [&str; 3] = ["Leading", "Lagging", "Unknown"];
+
+/// The prefix of the labels of the mitochondrial substitutions.
+pub(crate) const MITO_PREFIX: &str = "mt";
+
+/// The prefix of the labels of the block of variants outside of the labeled regions.
+pub(crate) const UNASSIGNED_LABEL: &str = "unassigned";
+
+/// The prefixes of the labels of the clustered and non-clustered blocks.
+pub(crate) const CLUSTER_PREFIXES: [&str; 2] = ["clustered", "nonclustered"];
+
+/// The prefixes of the labels of the non-CpG and CpG blocks.
+pub(crate) const CPG_PREFIXES: [&str; 2] = ["nonCpG", "CpG"];
+
+/// Return the `rows` repeated in one block per strand class, with the labels prefixed by
+/// the `prefixes` of the blocks and the indizes offset by `n_signatures` per block.
+pub(crate) fn stratified_rows(
+    rows: &[(String, usize)],
+    prefixes: &[&str],
+    n_signatures: usize,
+) -> Vec<(String, usize)> {
+    prefixes
+        .iter()
+        .enumerate()
+        .flat_map(|(block, prefix)| {
+            rows.iter().map(move |(label, index)| {
+                (
+                    format!("{}:{}", prefix, label),
+                    block * n_signatures + index,
+                )
+            })
+        })
+        .collect()
+}
+
+/// Return the labels and indizes of the single base substitutions to report in the
+/// `orientation`, either in lexicographic or COSMIC order, or in the order of a channel
+/// definition.
+pub(crate) fn sbs_rows(
+    signatures: &signature::Signatures,
+    cosmic_order: bool,
+    orientation: signature::Orientation,
+) -> Vec<(String, usize)> {
+    if let Some(labels) = signatures.labels() {
+        return labels
+            .iter()
+            .enumerate()
+            .map(|(i, l)| (l.clone(), i))
+            .collect();
+    }
+    let mut forwards: Vec<signature::Signature> = signatures
+        .signatures()
+        .into_iter()
+        .filter(|s| s.has_orientation(orientation))
+        .collect();
+    if cosmic_order {
+        forwards.sort_by(|a, b| a.cosmic_cmp(b));
+    }
+    forwards
+        .iter()
+        .map(|s| {
+            let label = if cosmic_order {
+                s.cosmic_label()
+            } else {
+                s.to_string()
+            };
+            (label, signatures.index_of(s).unwrap())
+        })
+        .collect()
+}
+
+/// Return the block of the transcription strand class of a substitution, given the
+/// `strand` of the reference at its position and whether its pyrimidine is on the reference
+/// strand (`forward`), i.e., it was not collapsed from a purine reference.
+fn transcription_strand_block(strand: annotation::TranscriptionStrand, forward: bool) -> usize {
+    match strand.of_pyrimidine(forward) {
+        annotation::TranscriptionStrand::Transcribed => 0,
+        annotation::TranscriptionStrand::Untranscribed => 1,
+        _ => 2,
+    }
+}
+
+/// Return the block of the replication strand class of a substitution, given the `strand`
+/// of the reference at its position and whether its pyrimidine is on the reference strand.
+fn replication_strand_block(strand: replication::ReplicationStrand, forward: bool) -> usize {
+    match strand.of_pyrimidine(forward) {
+        replication::ReplicationStrand::Leading => 0,
+        replication::ReplicationStrand::Lagging => 1,
+        replication::ReplicationStrand::Unknown => 2,
+    }
+}
+
+/// Open the VCF file at `path`, which may also be a http(s):// or s3:// URL. If more than one thread is requested, htslib
+/// decompresses the file using a thread pool of that size.
+fn open_vcf(path: &str, threads: usize) -> Result<rust_htslib::bcf::Reader, Error> {
+    let mut variants = if remote::is_url(path) {
+        let url = remote::parse_url(path)?;
+        match rust_htslib::bcf::Reader::from_url(&url) {
+            Err(e) => {
+                return Err(Error::Htslib {
+                    action: format!(
+                        "open remote VCF file '{}' ({})",
+                        path,
+                        remote::access_hint(&url)
+                    ),
+                    source: e,
+                })
+            }
+            Ok(v) => v,
+        }
+    } else {
+        // Report a missing or unreadable file by the error of the file system
+        if let Err(e) = std::fs::metadata(path) {
+            return Err(Error::Io {
+                action: format!("open VCF file '{}'", path),
+                source: e,
+            });
+        }
+        match rust_htslib::bcf::Reader::from_path(path) {
+            Err(e) => {
+                return Err(Error::Htslib {
+                    action: format!("open VCF file '{}'", path),
+                    source: e,
+                })
+            }
+            Ok(v) => v,
+        }
+    };
+    if threads > 1 {
+        if let Err(e) = variants.set_threads(threads) {
+            return Err(Error::Htslib {
+                action: format!("use {} threads for VCF file '{}'", threads, path),
+                source: e,
+            });
+        }
+    }
+    Ok(variants)
+}
+
+/// Return whether the contigs of the VCF file of the `options` can be counted in parallel,
+/// i.e., multiple threads are requested, the local file is indexed (.csi or .tbi) and its
+/// header declares more than one contig, and the mutations are not downsampled, which is the
+/// only filter depending on the mutations of other contigs.
+fn counts_contigs_in_parallel(
+    options: &CountOptions,
+    header: &rust_htslib::bcf::header::HeaderView,
+) -> bool {
+    options.threads > 1
+        && header.contig_count() > 1
+        && options.classification.downsample.is_none()
+        && !remote::is_url(&options.vcf)
+        && ["csi", "tbi"]
+            .iter()
+            .any(|ext| std::path::Path::new(&format!("{}.{}", options.vcf, ext)).exists())
+}
+
+/// Return the number of records of the VCF file at `path` as listed by its index (.csi or
+/// .tbi), or `None` if it is remote, not indexed or its index lacks the statistics.
+fn indexed_record_count(path: &str) -> Option<u64> {
+    indexed_contig_records(path).map(|records| records.iter().sum())
+}
+
+/// Return the number of records per contig, indexed by template-id, of the VCF file at `path`
+/// as listed by its index, or `None` if it is remote, not indexed or its index lacks the
+/// statistics. The contigs without statistics have no records, and the list may end before
+/// the last contigs of the header.
+fn indexed_contig_records(path: &str) -> Option<Vec<u64>> {
+    if remote::is_url(path) {
+        return None;
+    }
+    let index = ["csi", "tbi"]
+        .iter()
+        .map(|ext| format!("{}.{}", path, ext))
+        .find(|p| std::path::Path::new(p).exists())?;
+    let path = std::ffi::CString::new(path).ok()?;
+    let index = std::ffi::CString::new(index).ok()?;
+    unsafe {
+        let idx = rust_htslib::htslib::hts_idx_load2(path.as_ptr(), index.as_ptr());
+        if idx.is_null() {
+            return None;
+        }
+        let mut records = Vec::new();
+        let mut listed = false;
+        for tid in 0..rust_htslib::htslib::hts_idx_nseq(idx) {
+            let (mut mapped, mut unmapped) = (0, 0);
+            if rust_htslib::htslib::hts_idx_get_stat(idx, tid, &mut mapped, &mut unmapped) == 0 {
+                records.push(mapped + unmapped);
+                listed = true;
+            } else {
+                records.push(0);
+            }
+        }
+        rust_htslib::htslib::hts_idx_destroy(idx);
+        if listed {
+            Some(records)
+        } else {
+            None
+        }
+    }
+}
+
+/// Count the variants of the `n_contigs` contigs of the indexed VCF file of the `options` in
+/// parallel, each by its own reader and reference, and merge their results and summaries in
+/// the order of the contigs in the header. The `progress` is reported after each contig.
+fn count_contigs(
+    options: &CountOptions,
+    n_contigs: u32,
+    signatures: &signature::Signatures,
+    bcf_sample_indizes: &[usize],
+    summary: &mut summary::Summary,
+    mut progress: Option<&mut dyn FnMut(&Progress)>,
+) -> Result<result::ResultMatrix, Error> {
+    let keep_skipped = summary.logs_skipped();
+    let next = AtomicU32::new(0);
+    let mut results = new_result_matrix(
+        &options.classification,
+        signatures,
+        bcf_sample_indizes.len(),
+    );
+    std::thread::scope(|scope| {
+        let (outcomes_tx, outcomes) = mpsc::channel();
+        for _ in 0..options.threads.min(n_contigs as usize) {
+            let outcomes_tx = outcomes_tx.clone();
+            let next = &next;
+            scope.spawn(move || loop {
+                let rid = next.fetch_add(1, Ordering::Relaxed);
+                if rid >= n_contigs {
+                    break;
+                }
+                let mut contig_summary = summary::Summary::buffered(keep_skipped);
+                let counts = count_contig(
+                    options,
+                    rid,
+                    signatures,
+                    bcf_sample_indizes,
+                    &mut contig_summary,
+                );
+                let outcome = counts.map(|counts| (counts, contig_summary));
+                if outcomes_tx.send((rid, outcome)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(outcomes_tx);
+
+        // Merge the contigs in order as soon as they are counted
+        let mut pending = BTreeMap::new();
+        let mut merged = 0;
+        for (rid, outcome) in outcomes {
+            pending.insert(rid, outcome);
+            while let Some(outcome) = pending.remove(&merged) {
+                let (counts, contig_summary) = match outcome {
+                    Ok(o) => o,
+                    Err(e) => {
+                        // Stop the other workers
+                        next.store(n_contigs, Ordering::Relaxed);
+                        return Err(e);
+                    }
+                };
+                summary.merge(contig_summary)?;
+                if let Some(counts) = counts {
+                    results.merge(&counts)?;
+                }
+                if let Some(progress) = progress.as_mut() {
+                    progress(&Progress {
+                        records: summary.records(),
+                        total: None,
+                        contig: None,
+                        position: None,
+                        skipped: summary.skipped_total(),
+                    });
+                }
+                merged += 1;
+            }
+        }
+        Ok(())
+    })?;
+    Ok(results)
+}
+
+/// Count the variants of the contig with template-id `rid` of the indexed VCF file of the
+/// `options` into a new result matrix and the `summary`, or none if the index lists no records
+/// on the contig.
+fn count_contig(
+    options: &CountOptions,
+    rid: u32,
+    signatures: &signature::Signatures,
+    bcf_sample_indizes: &[usize],
+    summary: &mut summary::Summary,
+) -> Result<Option<result::ResultMatrix>, Error> {
+    let reference = reference::Reference::new(&options.reference, options.window)?
+        .cache_contigs(options.cache_reference);
+    let mut variants = open_indexed_vcf(&options.vcf)?;
+    match variants.fetch(rid, 0, None) {
+        Ok(()) => {}
+        Err(rust_htslib::errors::Error::GenomicSeek { .. }) => return Ok(None),
+        Err(e) => {
+            return Err(Error::Htslib {
+                action: format!("fetch the records of VCF file '{}'", options.vcf),
+                source: e,
+            })
+        }
+    }
+    Ok(Some(count_variants(
+        &mut variants,
+        &reference,
+        signatures,
+        bcf_sample_indizes,
+        &options.classification,
+        1,
+        summary,
+        None,
+    )?))
+}
+
+/// Open the VCF file at `path`, which may also be a http(s):// or s3:// URL, by its index
+/// (.csi or .tbi), which is looked up next to the file.
+fn open_indexed_vcf(path: &str) -> Result<rust_htslib::bcf::IndexedReader, Error> {
+    // The reader of rust-htslib crashes on files without index instead of failing
+    if !has_index(path) {
+        return Err(Error::Io {
+            action: format!("find the index (.csi or .tbi) of VCF file '{}'", path),
+            source: std::io::Error::new(std::io::ErrorKind::NotFound, "no index"),
+        });
+    }
+    if remote::is_url(path) {
+        let url = remote::parse_url(path)?;
+        rust_htslib::bcf::IndexedReader::from_url(&url).map_err(|e| Error::Htslib {
+            action: format!(
+                "open remote indexed VCF file '{}' ({})",
+                path,
+                remote::access_hint(&url)
+            ),
+            source: e,
+        })
+    } else {
+        rust_htslib::bcf::IndexedReader::from_path(path).map_err(|e| Error::Htslib {
+            action: format!("open indexed VCF file '{}'", path),
+            source: e,
+        })
+    }
+}
+
+/// Check if htslib finds an index (.csi or .tbi) of the VCF file at `path`, which may also be a
+/// URL.
+fn has_index(path: &str) -> bool {
+    let path = match std::ffi::CString::new(path) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    [
+        rust_htslib::htslib::HTS_FMT_CSI,
+        rust_htslib::htslib::HTS_FMT_TBI,
+    ]
+    .iter()
+    .any(|fmt| unsafe {
+        let idx = rust_htslib::htslib::hts_idx_load(path.as_ptr(), *fmt as i32);
+        if idx.is_null() {
+            false
+        } else {
+            rust_htslib::htslib::hts_idx_destroy(idx);
+            true
+        }
+    })
+}
+
+/// A region of a contig, with 0-based inclusive bounds as fetched from an indexed VCF file.
+#[derive(Debug, PartialEq)]
+struct Region {
+    contig: String,
+    start: u64,
+    end: Option<u64>,
+}
+
+/// Parse the `region` given as `contig` or `contig:start-end` with 1-based inclusive bounds.
+/// A contig name containing a colon is taken as a whole unless followed by a range.
+fn parse_region(region: &str) -> Result<Region, Error> {
+    let invalid = |reason: &str| {
+        Error::InvalidOptions(format!("Can not parse region '{}', {}", region, reason))
+    };
+    let range = region.rsplit_once(':').and_then(|(contig, range)| {
+        let (start, end) = range.split_once('-')?;
+        Some((contig, start.parse::<u64>().ok()?, end.parse::<u64>().ok()?))
+    });
+    let region = match range {
+        Some((_, start, end)) if start == 0 || end < start => {
+            return Err(invalid("the range must be 1-based and end after its start"))
+        }
+        Some((contig, start, end)) => Region {
+            contig: contig.to_owned(),
+            start: start - 1,
+            end: Some(end - 1),
+        },
+        None => Region {
+            contig: region.to_owned(),
+            start: 0,
+            end: None,
+        },
+    };
+    if region.contig.is_empty() {
+        return Err(invalid("the contig is missing"));
+    }
+    Ok(region)
+}
+
+/// Count the variants in the `regions` of the VCF file of the `options`, fetched one after
+/// the other by its index, into a new result matrix and the `summary`. As the regions are
+/// counted apart, they must not overlap and can not be downsampled together.
+fn count_regions<R: ReferenceProvider + Send>(
+    options: &CountOptions,
+    regions: &[String],
+    reference: &R,
+    signatures: &signature::Signatures,
+    bcf_sample_indizes: &[usize],
+    summary: &mut summary::Summary,
+    mut progress: Option<&mut dyn FnMut(&Progress)>,
+) -> Result<result::ResultMatrix, Error> {
+    let regions = regions
+        .iter()
+        .map(|r| parse_region(r))
+        .collect::<Result<Vec<Region>, Error>>()?;
+    if regions.len() > 1 && options.classification.downsample.is_some() {
+        return Err(Error::InvalidOptions(
+            "Can not downsample the mutations of several regions, which are counted apart"
+                .to_owned(),
+        ));
+    }
+    for (i, a) in regions.iter().enumerate() {
+        let overlapping = regions[i + 1..].iter().find(|b| {
+            a.contig == b.contig
+                && a.start <= b.end.unwrap_or(u64::MAX)
+                && b.start <= a.end.unwrap_or(u64::MAX)
+        });
+        if let Some(b) = overlapping {
+            return Err(Error::InvalidOptions(format!(
+                "Can not count the overlapping regions {:?} and {:?} on contig '{}', their records would be counted twice",
+                (a.start + 1, a.end.map(|e| e + 1)),
+                (b.start + 1, b.end.map(|e| e + 1)),
+                a.contig
+            )));
+        }
+    }
+
+    let mut variants = open_indexed_vcf(&options.vcf)?;
+    if options.threads > 1 {
+        if let Err(e) = variants.set_threads(options.threads) {
+            return Err(Error::Htslib {
+                action: format!(
+                    "use {} threads for VCF file '{}'",
+                    options.threads, options.vcf
+                ),
+                source: e,
+            });
+        }
+    }
+    let mut results = new_result_matrix(
+        &options.classification,
+        signatures,
+        bcf_sample_indizes.len(),
+    );
+    for region in &regions {
+        let rid = match variants.header().name2rid(region.contig.as_bytes()) {
+            Ok(rid) => rid,
+            Err(_) => {
+                return Err(Error::InvalidOptions(format!(
+                    "Can not find the contig '{}' of a region in the VCF header",
+                    region.contig
+                )))
+            }
+        };
+        match variants.fetch(rid, region.start, region.end) {
+            Ok(()) => {}
+            // The index lists no records on the contig
+            Err(rust_htslib::errors::Error::GenomicSeek { .. }) => continue,
+            Err(e) => {
+                return Err(Error::Htslib {
+                    action: format!("fetch the records of VCF file '{}'", options.vcf),
+                    source: e,
+                })
+            }
+        }
+        let counts = count_variants(
+            &mut variants,
+            reference,
+            signatures,
+            bcf_sample_indizes,
+            &options.classification,
+            options.threads,
+            summary,
+            progress
+                .as_mut()
+                .map(|p| &mut **p as &mut dyn FnMut(&Progress)),
+        )?;
+        results.merge(&counts)?;
+    }
+    Ok(results)
+}
+
+/// The number of records read before they are classified by the worker threads.
+const BATCH_SIZE: usize = 1024;
+
+/// The parameters shared by the workers classifying the records.
+struct RecordContext<'a> {
+    signatures: &'a signature::Signatures,
+    bcf_sample_indizes: &'a [usize],
+    options: &'a Options,
+    n_channels: usize,
+    /// Whether the mutations are counted in the order of the records, i.e., by the reading
+    /// thread, as they are filtered by distance or downsampled
+    ordered: bool,
+    /// Whether the skipped records are logged
+    log_skipped: bool,
+}
+
+/// Whether a record repeats an earlier one, which is decided in the order of the records
+/// before they are classified.
+enum Deduplicated {
+    Unique,
+    Duplicate,
+    Conflict,
+}
+
+/// A record read from the VCF file that is waiting for its classification.
+struct PendingRecord {
+    record: rust_htslib::bcf::Record,
+    deduplicated: Deduplicated,
+    /// Whether the contig of the record is missing from the reference
+    unknown_contig: bool,
+}
+
+/// The outcome of the classification of a record: its counters and skipped records, and the
+/// channels of its mutations per sample (with their weights if the counts are weighted),
+/// unless the worker counted them already.
+struct RecordCounts {
+    summary: summary::Summary,
+    samples: Vec<SampleMutations>,
+}
+
+/// The channels of the mutations of a sample at a record, and their weights if the counts
+/// are weighted.
+type SampleMutations = (Vec<usize>, Vec<f64>);
+
+/// The scratch buffers of a worker, which are cleared rather than reallocated per record.
+struct LoopState {
+    /// The genotypes of the selected samples
+    genotypes: Vec<genotype::Genotype>,
+    /// The FORMAT values of the genotypes
+    gt_buffer: rust_htslib::bcf::record::Buffer,
+    /// The indizes of the counted alternative alleles of a sample
+    allele_indizes: Vec<u32>,
+    /// The uppercase reference allele if it is given in lowercase
+    reference_allele: Vec<u8>,
+    /// The substitutions of the alternative alleles
+    substitutions: RecordSubstitutions,
+    /// Whether any sample carries each alternative allele
+    carried: Vec<bool>,
+    /// The channels of each alternative allele
+    channels: Vec<Vec<usize>>,
+    /// The channels of the mutations of each sample
+    samples: Vec<SampleMutations>,
+    /// The channels of the mutations of a sample along with their weights
+    weighted: Vec<(usize, f64)>,
+}
+
+impl LoopState {
+    fn new() -> Self {
+        LoopState {
+            genotypes: Vec::new(),
+            gt_buffer: rust_htslib::bcf::record::Buffer::new(),
+            allele_indizes: Vec::new(),
+            reference_allele: Vec::new(),
+            substitutions: RecordSubstitutions::default(),
+            carried: Vec::new(),
+            channels: Vec::new(),
+            samples: Vec::new(),
+            weighted: Vec::new(),
+        }
+    }
+}
+
+/// Resize `lists` to `n` empty lists, keeping the allocations of the lists.
+fn clear_lists<T>(lists: &mut Vec<Vec<T>>, n: usize) {
+    lists.truncate(n);
+    for list in lists.iter_mut() {
+        list.clear();
+    }
+    lists.resize_with(n, Vec::new);
+}
+
+/// The part `range` of a batch of records classified by a worker, the `index`-th of the batch.
+struct Job {
+    index: usize,
+    batch: Arc<Vec<PendingRecord>>,
+    contigs: Arc<Vec<Arc<str>>>,
+    range: Range<usize>,
+}
+
+/// Counts the mutations of the samples into a result matrix, possibly filtering them by
+/// their distance or downsampling them, which depends on the order of the mutations.
+struct Counter {
+    results: result::ResultMatrix,
+    downsampler: Option<downsample::Downsampler>,
+    distance_filter: Option<distance::DistanceFilter>,
+    cluster_splitter: Option<distance::ClusterSplitter>,
+}
+
+impl Counter {
+    /// Return whether the mutations have to be counted in the order of the records.
+    fn is_ordered(&self) -> bool {
+        self.downsampler.is_some()
+            || self.distance_filter.is_some()
+            || self.cluster_splitter.is_some()
+    }
+
+    /// Count the mutations of the channels `sig_indizes` with their `weights` in sample
+    /// `sample_index` at position `pos` of the contig with template-id `rid`.
+    fn count(
+        &mut self,
+        summary: &mut summary::Summary,
+        sample_index: usize,
+        rid: u32,
+        pos: u64,
+        mut sig_indizes: Vec<usize>,
+        weights: &[f64],
+    ) {
+        // count weighted mutations, which are neither filtered by distance nor downsampled
+        if self.results.is_weighted() {
+            self.count_directly(sample_index, &sig_indizes, weights);
+            return;
+        }
+        // drop mutations too close to the previous one
+        if let Some(f) = &mut self.distance_filter {
+            if sig_indizes.is_empty() {
+                return;
+            }
+            let (counted, dropped) = f.offer(sample_index, rid, pos, sig_indizes);
+            summary.mask(summary::Reason::Clustered, dropped);
+            sig_indizes = counted;
+        }
+        // move clustered mutations into the clustered channels
+        if let Some(s) = &mut self.cluster_splitter {
+            if sig_indizes.is_empty() {
+                return;
+            }
+            sig_indizes = s.offer(sample_index, rid, pos, sig_indizes);
+        }
+        // increment the signatures
+        for sig_index in sig_indizes {
+            count_mutation(
+                &mut self.results,
+                &mut self.downsampler,
+                sig_index,
+                sample_index,
+            );
+        }
+    }
+
+    /// Count the mutations of the channels `sig_indizes` with their `weights` in sample
+    /// `sample_index` directly into the results, i.e., if they are neither filtered by
+    /// distance nor downsampled.
+    fn count_directly(&mut self, sample_index: usize, sig_indizes: &[usize], weights: &[f64]) {
+        if self.results.is_weighted() {
+            for (sig_index, weight) in sig_indizes.iter().zip(weights) {
+                self.results.increment_by(*sig_index, sample_index, *weight);
+            }
+        } else {
+            for sig_index in sig_indizes {
+                self.results.increment(*sig_index, sample_index);
+            }
+        }
+    }
+
+    /// Count the mutations deferred by the filters of the `n_samples` and return the results.
+    fn finish(mut self, summary: &mut summary::Summary, n_samples: usize) -> result::ResultMatrix {
+        // Count the mutations deferred by the distance filter
+        if let Some(f) = &mut self.distance_filter {
+            for sample_index in 0..n_samples {
+                let (counted, dropped) = f.finish(sample_index);
+                summary.mask(summary::Reason::Clustered, dropped);
+                for sig_index in counted {
+                    count_mutation(
+                        &mut self.results,
+                        &mut self.downsampler,
+                        sig_index,
+                        sample_index,
+                    );
+                }
+            }
+        }
+
+        // Count the mutations deferred by the cluster splitter
+        if let Some(s) = &mut self.cluster_splitter {
+            for sample_index in 0..n_samples {
+                for sig_index in s.finish(sample_index) {
+                    count_mutation(
+                        &mut self.results,
+                        &mut self.downsampler,
+                        sig_index,
+                        sample_index,
+                    );
+                }
+            }
+        }
+
+        // Build the results from the sampled mutations
+        if let Some(d) = self.downsampler {
+            d.count_into(&mut self.results);
+            summary.downsampled(d.totals());
+        }
+        self.results
+    }
+}
+
+/// Count the variants of all records of `variants` into a new result matrix containing
+/// one column per sample in `bcf_sample_indizes`. The records are read in order and
+/// classified by `threads` workers, each with its own handle on the `reference`.
+#[allow(clippy::too_many_arguments)]
+fn count_variants<V: Read, R: ReferenceProvider + Send>(
+    variants: &mut V,
+    reference: &R,
+    signatures: &signature::Signatures,
+    bcf_sample_indizes: &[usize],
+    options: &Options,
+    threads: usize,
+    summary: &mut summary::Summary,
+    mut progress: Option<&mut dyn FnMut(&Progress)>,
+) -> Result<result::ResultMatrix, Error> {
+    let n_samples = bcf_sample_indizes.len();
+    let n_channels = channel_count(options, signatures);
+    let n_stratified = stratified_channel_count(options, signatures);
+    let new_results = || new_result_matrix(options, signatures, n_samples);
+    let mut counter = Counter {
+        results: new_results(),
+        downsampler: options
+            .downsample
+            .map(|(size, seed)| downsample::Downsampler::new(size, n_samples, seed)),
+        distance_filter: options
+            .min_distance
+            .map(|d| distance::DistanceFilter::new(d, options.drop_both, n_samples)),
+        cluster_splitter: options
+            .clustered_cutoff
+            .map(|d| distance::ClusterSplitter::new(d, n_stratified, n_samples)),
+    };
+    let mut deduplicator = options.dedupe.map(|_| dedupe::Deduplicator::new());
+    let context = RecordContext {
+        signatures,
+        bcf_sample_indizes,
+        options,
+        n_channels,
+        ordered: counter.is_ordered(),
+        log_skipped: summary.logs_skipped(),
+    };
+
+    // Fetch information about the contigs. Contigs not declared in the header
+    // are resolved lazily while reading the records.
+    let mut contigs = Arc::new(contig_names(variants.header()));
+    let mut unknown_contigs = vec![false; contigs.len()];
+    if options.skip_unknown_contigs {
+        for rid in missing_reference_contigs(&contigs, reference, options)? {
+            unknown_contigs[rid] = true;
+        }
+    }
+
+    let workers = threads.max(1);
+    let references = (0..workers)
+        .map(|_| reference.try_clone())
+        .collect::<Result<Vec<R>, Error>>()?;
+    std::thread::scope(|scope| {
+        // Start the workers, which classify a part of each batch of records and return the
+        // outcome of each record
+        let context = &context;
+        let (outcomes_tx, outcomes) = mpsc::channel();
+        let mut jobs = Vec::with_capacity(workers);
+        let mut handles = Vec::with_capacity(workers);
+        for reference in references {
+            let (jobs_tx, jobs_rx) = mpsc::channel::<Job>();
+            let outcomes_tx = outcomes_tx.clone();
+            let mut worker_counter = Counter {
+                results: new_results(),
+                downsampler: None,
+                distance_filter: None,
+                cluster_splitter: None,
+            };
+            jobs.push(jobs_tx);
+            handles.push(scope.spawn(move || {
+                let mut state = LoopState::new();
+                for job in jobs_rx {
+                    let counts = classify_records(
+                        &reference,
+                        context,
+                        &job.batch[job.range.clone()],
+                        &job.contigs,
+                        &mut worker_counter,
+                        &mut state,
+                    );
+                    // The records must only be dropped by the reading thread
+                    let index = job.index;
+                    drop(job);
+                    if outcomes_tx.send((index, counts)).is_err() {
+                        break;
+                    }
+                }
+                worker_counter.results
+            }));
+        }
+        drop(outcomes_tx);
+
+        let mut records = variants.records();
+        loop {
+            // Read the next batch of records
+            let mut batch = Vec::with_capacity(BATCH_SIZE);
+            while batch.len() < BATCH_SIZE {
+                let record = match records.next() {
+                    None => break,
+                    Some(Ok(r)) => r,
+                    Some(Err(e)) => {
+                        return Err(Error::Htslib {
+                            action: "read the next VCF record".to_owned(),
+                            source: e,
+                        })
+                    }
+                };
+                summary.record();
+
+                // Resolve the contig name if the VCF header did not declare it
+                if let Some(rid) = record.rid() {
+                    let resolved = contigs.len();
+                    if rid as usize >= resolved {
+                        let contigs = Arc::make_mut(&mut contigs);
+                        for rid in resolved as u32..=rid {
+                            let name = resolve_contig_name(&record, rid)?;
+                            debug!(
+                                "Resolved undeclared template-id {} to contig '{}'",
+                                rid, name
+                            );
+                            contigs.push(Arc::from(name));
+                        }
+                        if !options.no_validate_contigs {
+                            check_reference_contigs(
+                                &contigs[resolved..],
+                                &HashMap::new(),
+                                reference,
+                                options,
+                            )?;
+                        }
+                        unknown_contigs.resize(contigs.len(), false);
+                        if options.skip_unknown_contigs {
+                            for rid in
+                                missing_reference_contigs(&contigs[resolved..], reference, options)?
+                            {
+                                unknown_contigs[resolved + rid] = true;
+                            }
+                        }
+                    }
+                }
+                if let Some(progress) = progress.as_mut() {
+                    progress(&Progress {
+                        records: summary.records(),
+                        total: None,
+                        contig: Some(contig_of(&record, &contigs)),
+                        position: Some(record.pos() as u64 + 1),
+                        skipped: summary.skipped_total(),
+                    });
+                }
+
+                // Find records repeating an earlier record at the same site
+                let mut deduplicated = Deduplicated::Unique;
+                if let (Some(d), Some(rid)) = (&mut deduplicator, record.rid()) {
+                    let alleles = record.alleles().iter().map(|a| a.to_vec()).collect();
+                    let genotypes = match record.genotypes() {
+                        Ok(gts) => (0..record.sample_count() as usize)
+                            .map(|i| gts.get(i))
+                            .collect(),
+                        Err(_) => Vec::new(),
+                    };
+                    deduplicated = match d.offer(rid, record.pos(), alleles, genotypes) {
+                        dedupe::Occurrence::First => Deduplicated::Unique,
+                        dedupe::Occurrence::Conflicting
+                            if options.dedupe == Some(DedupeMode::Error) =>
+                        {
+                            Deduplicated::Conflict
+                        }
+                        _ => Deduplicated::Duplicate,
+                    };
+                }
+                let unknown_contig = record
+                    .rid()
+                    .is_some_and(|rid| unknown_contigs[rid as usize]);
+                batch.push(PendingRecord {
+                    record,
+                    deduplicated,
+                    unknown_contig,
+                });
+            }
+            if batch.is_empty() {
+                break;
+            }
+
+            // Classify the batch split across the workers
+            let batch = Arc::new(batch);
+            let chunk_size = batch.len().div_ceil(workers);
+            let mut chunks = Vec::with_capacity(workers);
+            for (index, start) in (0..batch.len()).step_by(chunk_size).enumerate() {
+                let range = start..(start + chunk_size).min(batch.len());
+                let job = Job {
+                    index,
+                    batch: Arc::clone(&batch),
+                    contigs: Arc::clone(&contigs),
+                    range,
+                };
+                if jobs[index].send(job).is_err() {
+                    return Err(Error::Other(
+                        "Can not classify records, a worker thread stopped".to_owned(),
+                    ));
+                }
+                chunks.push(None);
+            }
+            for _ in 0..chunks.len() {
+                match outcomes.recv() {
+                    Ok((index, counts)) => chunks[index] = Some(counts),
+                    Err(_) => {
+                        return Err(Error::Other(
+                            "Can not classify records, a worker thread stopped".to_owned(),
+                        ))
+                    }
+                }
+            }
+
+            // Merge the outcomes in the order of the records
+            let mut pending = batch.iter();
+            for counts in chunks.into_iter().flatten() {
+                for counts in counts? {
+                    let record = &pending.next().unwrap().record;
+                    summary.merge(counts.summary)?;
+                    for (sample_index, (sig_indizes, weights)) in
+                        counts.samples.into_iter().enumerate()
+                    {
+                        counter.count(
+                            summary,
+                            sample_index,
+                            record.rid().unwrap_or(u32::MAX),
+                            record.pos() as u64,
+                            sig_indizes,
+                            &weights,
+                        );
+                    }
+                }
+            }
+        }
+
+        // Sum up the mutations counted by the workers
+        drop(jobs);
+        for handle in handles {
+            match handle.join() {
+                Ok(results) => counter.results.merge(&results)?,
+                Err(_) => {
+                    return Err(Error::Other(
+                        "Can not classify records, a worker thread panicked".to_owned(),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(counter.finish(summary, n_samples))
+}
+
+/// Return the name of the contig of the `record`, or "." if it has none.
+fn contig_of<'a>(record: &rust_htslib::bcf::Record, contigs: &'a [Arc<str>]) -> &'a str {
+    record
+        .rid()
+        .and_then(|rid| contigs.get(rid as usize))
+        .map_or(".", |c| c)
+}
+
+/// Return the names of the contigs declared in the `header`, indexed by their template-id.
+fn contig_names(header: &rust_htslib::bcf::header::HeaderView) -> Vec<Arc<str>> {
+    (0..header.contig_count())
+        .map(|rid| Arc::from(std::str::from_utf8(header.rid2name(rid).ok().unwrap()).unwrap()))
+        .collect()
+}
+
+/// Return the lengths of the contigs declared with one in the `##contig` lines of `header`.
+fn contig_lengths(header: &rust_htslib::bcf::header::HeaderView) -> HashMap<String, u64> {
+    header
+        .header_records()
+        .into_iter()
+        .filter_map(|r| match r {
+            rust_htslib::bcf::header::HeaderRecord::Contig { values, .. } => {
+                let length = values.get("length")?.parse().ok()?;
+                Some((values.get("ID")?.clone(), length))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Check that the `contigs` are sequences of the `reference` with the same length, if the
+/// VCF header declares one in `lengths`, such that no record fails to fetch its context
+/// halfway through the VCF file. Contigs that are not selected by the `options` are not
+/// checked, as their records are skipped before fetching. The missing contigs are only
+/// logged if their records are skipped, while all other discrepancies are reported at once.
+fn check_reference_contigs<R: ReferenceProvider>(
+    contigs: &[Arc<str>],
+    lengths: &HashMap<String, u64>,
+    reference: &R,
+    options: &Options,
+) -> Result<(), Error> {
+    let mut missing: Vec<String> = missing_reference_contigs(contigs, reference, options)?
+        .into_iter()
+        .map(|rid| contigs[rid].to_string())
+        .collect();
+    let mut discrepancies = Vec::new();
+    if !lengths.is_empty() {
+        let reference_lengths: HashMap<String, u64> =
+            reference.sequence_lengths()?.into_iter().collect();
+        for rid in selected_contigs(contigs, options) {
+            let contig = &contigs[rid][..];
+            if let (Some(length), Some(reference_length)) =
+                (lengths.get(contig), reference_lengths.get(contig))
+            {
+                if length != reference_length {
+                    discrepancies.push(format!(
+                        "'{}' has {} bases in the VCF header but {} in the reference",
+                        contig, length, reference_length
+                    ));
+                }
+            }
+        }
+    }
+    if options.skip_unknown_contigs {
+        for contig in missing.drain(..) {
+            warn!(
+                "Skipping the records on contig '{}', which is not in the reference",
+                contig
+            );
+        }
+    }
+    if !discrepancies.is_empty() {
+        discrepancies.extend(
+            missing
+                .into_iter()
+                .map(|c| format!("'{}' is not in the reference", c)),
+        );
+        Err(Error::InconsistentContigs { discrepancies })
+    } else if missing.is_empty() {
+        Ok(())
+    } else if missing.len() == 1 {
+        Err(Error::UnknownContig {
+            contig: missing.remove(0),
+        })
+    } else {
+        Err(Error::UnknownContigs { contigs: missing })
+    }
+}
+
+/// Return the indizes of the `contigs` selected by the `options`.
+fn selected_contigs<'a>(
+    contigs: &'a [Arc<str>],
+    options: &'a Options,
+) -> impl Iterator<Item = usize> + 'a {
+    (0..contigs.len()).filter(move |rid| {
+        filter::check_contig(
+            &contigs[*rid],
+            options.chromosomes.as_ref(),
+            options.primary_only,
+        )
+        .is_none()
+    })
+}
+
+/// Return the indizes of the `contigs` selected by the `options` that are not sequences of
+/// the `reference`.
+fn missing_reference_contigs<R: ReferenceProvider>(
+    contigs: &[Arc<str>],
+    reference: &R,
+    options: &Options,
+) -> Result<Vec<usize>, Error> {
+    let selected: Vec<usize> = selected_contigs(contigs, options).collect();
+    if selected.is_empty() {
+        return Ok(selected);
+    }
+    let names: HashSet<String> = reference.sequence_names()?.into_iter().collect();
+    Ok(selected
+        .into_iter()
+        .filter(|rid| !names.contains(&contigs[*rid][..]))
+        .collect())
+}
+
+/// Classify the `records` of a worker, which counts their mutations into `counter` unless
+/// they have to be counted in the order of the records. The buffers of `state` are reused
+/// across the records.
+fn classify_records<R: ReferenceProvider>(
+    reference: &R,
+    context: &RecordContext,
+    records: &[PendingRecord],
+    contigs: &[Arc<str>],
+    counter: &mut Counter,
+    state: &mut LoopState,
+) -> Result<Vec<RecordCounts>, Error> {
+    let options = context.options;
+    let classifier = classify::Classifier::new(reference, context.signatures)
+        .skip_softmasked(options.skip_softmasked)
+        .count_partial_context(options.count_partial_context);
+    let mut outcomes = Vec::with_capacity(records.len());
+    for pending in records {
+        let record = &pending.record;
+        let mut summary = summary::Summary::buffered(context.log_skipped);
+        let contig = match record.rid().and_then(|rid| contigs.get(rid as usize)) {
+            Some(contig) => contig,
+            None => {
+                let e = Error::MissingContig {
+                    position: record.pos() as u64 + 1,
+                };
+                if !options.skip_errors {
+                    return Err(e);
+                }
+                warn!("Skipping malformed record: {}", e);
+                summary.skip(summary::Reason::MissingContig);
+                summary.ignore_record();
+                outcomes.push(RecordCounts {
+                    summary,
+                    samples: Vec::new(),
+                });
+                continue;
+            }
+        };
+        let mut classified = false;
+        let samples = match &pending.deduplicated {
+            // Skip records on contigs missing from the reference, if requested
+            _ if pending.unknown_contig => {
+                summary.skip_record(
+                    summary::Reason::UnknownContig,
+                    contig,
+                    record.pos(),
+                    &record.alleles(),
+                )?;
+                Vec::new()
+            }
+            Deduplicated::Unique => {
+                classified = classify_record(
+                    record,
+                    contig,
+                    &classifier,
+                    reference,
+                    context,
+                    &mut summary,
+                    state,
+                )?;
+                if !classified {
+                    Vec::new()
+                } else if context.ordered {
+                    // The reading thread counts the mutations in the order of the records
+                    std::mem::take(&mut state.samples)
+                } else {
+                    for (sample_index, (sig_indizes, weights)) in state.samples.iter().enumerate() {
+                        counter.count_directly(sample_index, sig_indizes, weights);
+                    }
+                    Vec::new()
+                }
+            }
+            // Skip records repeating an earlier record at the same site
+            Deduplicated::Duplicate => {
+                summary.skip_record(
+                    summary::Reason::Duplicate,
+                    contig,
+                    record.pos(),
+                    &record.alleles(),
+                )?;
+                Vec::new()
+            }
+            Deduplicated::Conflict => {
+                return Err(Error::ConflictingDuplicates {
+                    contig: contig.to_string(),
+                    position: record.pos() as u64 + 1,
+                })
+            }
+        };
+        if !classified {
+            summary.ignore_record();
+        }
+        outcomes.push(RecordCounts { summary, samples });
+    }
+    Ok(outcomes)
+}
+
+/// Classify the `record` on `contig` into the channels of its mutations per sample in
+/// `state.samples`, along with their weights if the counts are weighted. Returns whether the
+/// record was classified, or skipped otherwise.
+fn classify_record<R: ReferenceProvider>(
+    record: &rust_htslib::bcf::Record,
+    contig: &str,
+    classifier: &classify::Classifier<R>,
+    reference: &R,
+    context: &RecordContext,
+    summary: &mut summary::Summary,
+    state: &mut LoopState,
+) -> Result<bool, Error> {
+    let LoopState {
+        genotypes,
+        gt_buffer,
+        allele_indizes,
+        reference_allele,
+        substitutions,
+        carried,
+        channels,
+        samples,
+        weighted,
+    } = state;
+    let options = context.options;
+    let signatures = context.signatures;
+    let bcf_sample_indizes = context.bcf_sample_indizes;
+    let n_channels = context.n_channels;
+
+    // Skip records by their IDs
+    if options.include_ids.is_some() || options.exclude_ids.is_some() {
+        let ids = String::from_utf8_lossy(&record.id()).into_owned();
+        if let Some(reason) = filter::check_ids(
+            &ids,
+            options.include_ids.as_ref(),
+            options.exclude_ids.as_ref(),
+        ) {
+            summary.skip_record(reason, contig, record.pos(), &record.alleles())?;
+            return Ok(false);
+        }
+    }
+
+    // Skip records not passing the FILTER column
+    if options.pass_only && !filter::is_pass(record) {
+        trace!(
+            "Ignoring filtered variant at position {}:{}",
+            contig,
+            record.pos() + 1
+        );
+        summary.skip_record(
+            summary::Reason::Filtered,
+            contig,
+            record.pos(),
+            &record.alleles(),
+        )?;
+        return Ok(false);
+    }
+    if options.include_filters.is_some() || options.exclude_filters.is_some() {
+        if let Some(reason) = filter::check_filters(
+            &filter::filter_names(record),
+            options.include_filters.as_ref(),
+            options.exclude_filters.as_ref(),
+        ) {
+            summary.skip_record(reason, contig, record.pos(), &record.alleles())?;
+            return Ok(false);
+        }
+    }
+
+    // Skip records of low or missing quality
+    if options.min_qual.is_some() || options.require_qual {
+        if let Some(reason) =
+            filter::check_qual(record.qual(), options.min_qual, options.require_qual)
+        {
+            trace!(
+                "Ignoring variant with QUAL {} at position {}:{}",
+                record.qual(),
+                contig,
+                record.pos() + 1
+            );
+            summary.skip_record(reason, contig, record.pos(), &record.alleles())?;
+            return Ok(false);
+        }
+    }
+
+    // Skip records not matching the INFO expression
+    if let Some(e) = &options.info_filter {
+        let lookup = |key: &str| info_filter::record_values(record, key);
+        if !e.evaluate(&lookup, options.info_missing)? {
+            summary.skip_record(
+                summary::Reason::InfoFilter,
+                contig,
+                record.pos(),
+                &record.alleles(),
+            )?;
+            return Ok(false);
+        }
+    }
+
+    // Skip records not marked as somatic
+    if let Some(somatic) = &options.somatic {
+        if !somatic.matches(record)? {
+            summary.skip_record(
+                summary::Reason::NotSomatic,
+                contig,
+                record.pos(),
+                &record.alleles(),
+            )?;
+            return Ok(false);
+        }
+    }
+
+    // Skip records on contigs that are not selected
+    if let Some(reason) =
+        filter::check_contig(contig, options.chromosomes.as_ref(), options.primary_only)
+    {
+        summary.skip_record(reason, contig, record.pos(), &record.alleles())?;
+        return Ok(false);
+    }
+
+    // Skip records in the mitochondrial control region
+    if let Some(mito) = &options.mito {
+        if mito.is_control_region(contig, record.pos() as u64) {
+            summary.skip_record(
+                summary::Reason::ExcludedRegions,
+                contig,
+                record.pos(),
+                &record.alleles(),
+            )?;
+            return Ok(false);
+        }
+    }
+
+    // Skip records outside of the included or inside of the excluded regions
+    if let Some(include) = &options.include {
+        if !include.contains(contig, record.pos() as u64) {
+            trace!(
+                "Ignoring variant outside of included regions at position {}:{}",
+                contig,
+                record.pos() + 1
+            );
+            summary.skip_record(
+                summary::Reason::OutsideRegions,
+                contig,
+                record.pos(),
+                &record.alleles(),
+            )?;
+            return Ok(false);
+        }
+    }
+    if let Some(exclude) = &options.exclude {
+        if exclude.contains(contig, record.pos() as u64) {
+            trace!(
+                "Ignoring variant in excluded regions at position {}:{}",
+                contig,
+                record.pos() + 1
+            );
+            summary.skip_record(
+                summary::Reason::ExcludedRegions,
+                contig,
+                record.pos(),
+                &record.alleles(),
+            )?;
+            return Ok(false);
+        }
+    }
+
+    // Skip multi-allelic records before looking at their alleles
+    if let Some(max) = options.max_alt_alleles {
+        let n_alternatives = (record.allele_count() as usize).saturating_sub(1);
+        if n_alternatives > max {
+            trace!(
+                "Ignoring variant with {} alternative alleles at position {}:{}",
+                n_alternatives,
+                contig,
+                record.pos() + 1
+            );
+            summary.skip_record(
+                summary::Reason::Multiallelic,
+                contig,
+                record.pos(),
+                &record.alleles(),
+            )?;
+            return Ok(false);
+        }
+    }
+
+    // Find the substitutions of the alternative alleles, or classify the doublets and indels,
+    // before looking at the genotypes
+    let is_sbs = matches!(
+        options.scheme,
+        Scheme::Sbs | Scheme::SbsTs | Scheme::Sbs288(_) | Scheme::SbsRs(_) | Scheme::SbsCpg
+    );
+    let status = match &options.scheme {
+        Scheme::Dbs(doublets) => {
+            doublet_alleles_from_record(record, contig, reference, doublets, options, summary)
+                .and_then(|a| {
+                    *channels = a;
+                    AlleleRecordStatus::Ok(())
+                })
+        }
+        Scheme::Id(indels) => indel_alleles_from_record(
+            record, contig, reference, indels, options, summary,
+        )
+        .and_then(|a| {
+            *channels = a;
+            AlleleRecordStatus::Ok(())
+        }),
+        _ => alternative_alleles_from_record(
+            record,
+            contig,
+            options,
+            summary,
+            reference_allele,
+            substitutions,
+        ),
+    };
+    if !is_classified(status)? {
+        return Ok(false);
+    }
+
+    // Extract the genotypes from the record in the order of our
+    // expected/wanted samples and re-encode them as our genotype struct
+    genotypes.resize_with(bcf_sample_indizes.len(), || genotype::Genotype::missing(0));
+    if options.no_genotypes {
+        // Without genotypes, each sample carries each alternative allele once
+        for gt in genotypes.iter_mut() {
+            gt.assign(
+                (1..record.allele_count() as i32)
+                    .map(rust_htslib::bcf::record::GenotypeAllele::Unphased),
+            );
+        }
+    } else {
+        let bcf_gts = match record
+            .format_shared_buffer(b"GT", &mut *gt_buffer)
+            .integer()
+        {
+            Ok(gts) => gts,
+            Err(_) => {
+                // The genotypes of a record without GT are all missing
+                trace!(
+                    "Ignoring variant without genotypes at position {}:{}",
+                    contig,
+                    record.pos() + 1
+                );
+                summary.skip_record(
+                    summary::Reason::NoGenotypes,
+                    contig,
+                    record.pos(),
+                    &record.alleles(),
+                )?;
+                return Ok(false);
+            }
+        };
+        for (gt, sample_index) in genotypes.iter_mut().zip(bcf_sample_indizes) {
+            let alleles = bcf_gts[*sample_index].iter();
+            gt.assign(alleles.map(|e| rust_htslib::bcf::record::GenotypeAllele::from(*e)));
+        }
+    }
+    let gts = &mut genotypes[..];
+    trace!("Found genotypes: {:?}", gts);
+
+    // Fail on corrupt records whose genotypes call an allele the record does not have
+    let n_alleles = record.allele_count();
+    if let Some(allele) = gts
+        .iter()
+        .flat_map(|gt| gt.iter())
+        .find(|a| *a >= n_alleles)
+    {
+        return Err(Error::UnknownAllele {
+            contig: contig.to_owned(),
+            position: record.pos() as u64 + 1,
+            allele,
+            alleles: n_alleles,
+        });
+    }
+
+    // Skip sites where not all selected samples are genotyped
+    if options.require_complete_genotypes && !gts.iter().all(|gt| gt.is_fully_called()) {
+        trace!(
+            "Ignoring variant with missing genotypes at position {}:{}",
+            contig,
+            record.pos() + 1
+        );
+        summary.skip_record(
+            summary::Reason::IncompleteGenotypes,
+            contig,
+            record.pos(),
+            &record.alleles(),
+        )?;
+        return Ok(false);
+    }
+
+    // Mask the genotypes of samples that are not fully called
+    if options.skip_missing_genotypes {
+        let mut masked = 0;
+        for gt in gts.iter_mut().filter(|gt| !gt.is_fully_called()) {
+            gt.mask();
+            masked += 1;
+        }
+        summary.mask(summary::Reason::MissingGenotype, masked);
+    }
+
+    // Mask the genotypes of samples failing their per-sample filter
+    if options.sample_pass_only {
+        let filters = filter::format_strings(record, b"FT", bcf_sample_indizes)?;
+        let mut masked = 0;
+        for (gt, ft) in gts.iter_mut().zip(&filters) {
+            if !filter::is_sample_pass(ft.as_deref()) {
+                gt.mask();
+                masked += 1;
+            }
+        }
+        summary.mask(summary::Reason::SampleFiltered, masked);
+    }
+
+    // Mask the genotypes of samples with insufficient depth or quality
+    if let Some(min_dp) = options.min_dp {
+        let depths = filter::format_integers(record, b"DP", bcf_sample_indizes)?;
+        let masked = mask_genotypes(gts, &depths, min_dp, false);
+        summary.mask(summary::Reason::LowDepth, masked);
+    }
+    if let Some(min_gq) = options.min_gq {
+        let qualities = filter::format_integers(record, b"GQ", bcf_sample_indizes)?;
+        let masked = mask_genotypes(gts, &qualities, min_gq, options.keep_missing_gq);
+        summary.mask(summary::Reason::LowGq, masked);
+    }
+    let filter_vaf = options.min_vaf.is_some() || options.max_vaf.is_some();
+    let allelic_depths =
+        if filter_vaf || options.min_alt_reads.is_some() || options.missing_vaf_weight.is_some() {
+            Some(filter::format_integers(record, b"AD", bcf_sample_indizes)?)
+        } else {
+            None
+        };
+
+    // Count nothing if only sites with variance in the genotypes should be counted
+    if options.ignore_homogeneous_sites
+        && !is_varying_position(
+            gts,
+            options.compare_allele_content,
+            options.homogeneous_missing,
+        )
+    {
+        return Ok(false);
+    }
+
+    // Fetch the context of the substitutions of the alleles carried by any sample, such that
+    // the reference is not read for the alleles of other samples
+    if is_sbs {
+        carried.clear();
+        carried.resize(substitutions.alleles.len(), false);
+        for allele in gts.iter().flat_map(|gt| gt.alt_alleles()) {
+            if let Some(c) = carried.get_mut(allele as usize - 1) {
+                *c = true;
+            }
+        }
+        if carried.iter().any(|c| *c) {
+            let status = fetch_codons(
+                record,
+                contig,
+                classifier,
+                options.on_ref_mismatch,
+                summary,
+                substitutions,
+                carried,
+            )
+            .and_then(|()| {
+                debug!("Found substitutions: {:?}", substitutions);
+                substitution_channels(
+                    substitutions,
+                    contig,
+                    record.pos() as u64,
+                    reference,
+                    signatures,
+                    options,
+                    channels,
+                )
+            });
+            if !is_classified(status)? {
+                return Ok(false);
+            }
+        } else {
+            // The reference is not read for a site none of the samples carries an allele of
+            clear_lists(channels, substitutions.alleles.len());
+        }
+    }
+    let signature_indizes = channels;
+    debug!("Found signature indizes: {:?}", signature_indizes);
+
+    // Move the alleles into the block of the label of their region
+    if let Some(regions) = &options.region_labels {
+        let block = regions
+            .label_at(contig, record.pos() as u64)
+            .unwrap_or_else(|| regions.labels().len());
+        for s in signature_indizes.iter_mut().flatten() {
+            *s += block * n_channels;
+        }
+    }
+
+    // Assign the transcription strand of the record
+    if let Some(annotation) = &options.annotation {
+        summary.annotate(annotation.strand_at(contig, record.pos() as u64));
+    }
+
+    // Do not count alleles that are common in the population
+    if let Some((tag, max_af)) = &options.max_pop_af {
+        let afs = filter::info_floats(record, tag)?;
+        let n_alternatives = signature_indizes.len();
+        for (i, s) in signature_indizes.iter_mut().enumerate() {
+            if s.is_empty() {
+                continue;
+            }
+            if let Some(reason) = filter::check_pop_af(
+                afs.as_deref(),
+                i + 1,
+                n_alternatives,
+                *max_af,
+                options.require_pop_af,
+            ) {
+                summary.skip(reason);
+                s.clear();
+            }
+        }
+    }
+
+    // for each sample
+    samples.truncate(bcf_sample_indizes.len());
+    for (sample_signature_indizes, sample_weights) in samples.iter_mut() {
+        sample_signature_indizes.clear();
+        sample_weights.clear();
+    }
+    samples.resize_with(bcf_sample_indizes.len(), Default::default);
+    for (sample_index, (sample_signature_indizes, sample_weights)) in samples.iter_mut().enumerate()
+    {
+        // for each called alternative allele of that sample
+        allele_indizes.clear();
+        if options.count_mode == CountMode::Haplotype {
+            allele_indizes.extend(gts[sample_index].called_alts());
+        } else {
+            allele_indizes.extend(gts[sample_index].alt_alleles());
+        }
+        for allele_index in allele_indizes.iter().copied() {
+            // skip it if its allele fraction is outside of the window
+            // or too few reads support it
+            if let Some(ad) = &allelic_depths {
+                let ad = &ad[sample_index];
+                let allele = allele_index as usize;
+                let vaf_reason = if filter_vaf {
+                    filter::check_vaf(
+                        ad,
+                        allele,
+                        options.min_vaf,
+                        options.max_vaf,
+                        options.keep_missing_ad,
+                    )
+                } else {
+                    None
+                };
+                let reason = vaf_reason.or_else(|| {
+                    options.min_alt_reads.and_then(|min| {
+                        filter::check_alt_reads(ad, allele, min, options.keep_missing_ad)
+                    })
+                });
+                if let Some(reason) = reason {
+                    summary.mask(reason, 1);
+                    continue;
+                }
+            }
+            let alleles = &signature_indizes[allele_index as usize - 1];
+            sample_signature_indizes.extend_from_slice(alleles);
+            // weight each mutation by the allele fraction and dosage if requested
+            if options.is_weighted() {
+                let mut weight = match options.missing_vaf_weight {
+                    Some(missing_weight) => allelic_depths
+                        .as_ref()
+                        .and_then(|ad| filter::vaf(&ad[sample_index], allele_index as usize))
+                        .map_or(missing_weight, f64::from),
+                    None => 1.0,
+                };
+                if options.count_mode == CountMode::GenotypeDosage {
+                    weight *= gts[sample_index].dosage_of(allele_index) as f64;
+                }
+                sample_weights.extend(alleles.iter().map(|_| weight));
+            }
+        }
+        // count each signature at most once per site if requested
+        if options.count_mode != CountMode::Haplotype {
+            if options.is_weighted() {
+                // keep the highest weight of each signature
+                weighted.clear();
+                weighted.extend(
+                    sample_signature_indizes
+                        .drain(..)
+                        .zip(sample_weights.drain(..)),
+                );
+                weighted.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.total_cmp(&a.1)));
+                weighted.dedup_by_key(|(i, _)| *i);
+                for (i, w) in weighted.drain(..) {
+                    sample_signature_indizes.push(i);
+                    sample_weights.push(w);
+                }
+            } else {
+                sample_signature_indizes.sort_unstable();
+                sample_signature_indizes.dedup();
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Return the number of channels of the scheme and the mitochondrial substitutions, which
+/// follow the channels of the scheme.
+pub(crate) fn channel_count(options: &Options, signatures: &signature::Signatures) -> usize {
+    let n_mito = options.mito.as_ref().map_or(0, |m| m.signatures().len());
+    scheme_channel_count(&options.scheme, signatures) + n_mito
+}
+
+/// Return the number of channels including the blocks of the labeled regions.
+fn stratified_channel_count(options: &Options, signatures: &signature::Signatures) -> usize {
+    let n_channels = channel_count(options, signatures);
+    match &options.region_labels {
+        Some(regions) => (regions.labels().len() + 1) * n_channels,
+        None => n_channels,
+    }
+}
+
+/// Create a result matrix without any counts of all rows, i.e., the stratified channels and
+/// their cluster classes, for `n_samples`. It is stored sparsely if requested or if it has
+/// more than `result::SPARSE_CELLS` cells.
+fn new_result_matrix(
+    options: &Options,
+    signatures: &signature::Signatures,
+    n_samples: usize,
+) -> result::ResultMatrix {
+    let n_stratified = stratified_channel_count(options, signatures);
+    let n_rows = match options.clustered_cutoff {
+        Some(_) => CLUSTER_PREFIXES.len() * n_stratified,
+        None => n_stratified,
+    };
+    let sparse = options.sparse || n_rows.saturating_mul(n_samples) > result::SPARSE_CELLS;
+    match (options.is_weighted(), sparse) {
+        (false, false) => result::ResultMatrix::new(n_rows, n_samples),
+        (true, false) => result::ResultMatrix::new_weighted(n_rows, n_samples),
+        (false, true) => result::ResultMatrix::new_sparse(n_rows, n_samples),
+        (true, true) => result::ResultMatrix::new_weighted_sparse(n_rows, n_samples),
+    }
+}
+
+/// Return the number of channels of the `scheme`, including all of its strata.
+pub(crate) fn scheme_channel_count(scheme: &Scheme, signatures: &signature::Signatures) -> usize {
+    match scheme {
+        Scheme::Sbs => signatures.len(),
+        Scheme::SbsTs | Scheme::Sbs288(_) => {
+            TRANSCRIPTION_STRAND_PREFIXES.len() * signatures.channel_count()
+        }
+        Scheme::SbsRs(_) => REPLICATION_STRAND_PREFIXES.len() * signatures.channel_count(),
+        Scheme::SbsCpg => CPG_PREFIXES.len() * signatures.channel_count(),
+        Scheme::Dbs(doublets) => doublets.len(),
+        Scheme::Id(indels) => indels.len(),
+    }
+}
+
+/// Count a mutation of signature `sig_index` in sample `sample_index`, either directly
+/// into `results` or by offering it to the `downsampler`.
+fn count_mutation(
+    results: &mut result::ResultMatrix,
+    downsampler: &mut Option<downsample::Downsampler>,
+    sig_index: usize,
+    sample_index: usize,
+) {
+    match downsampler {
+        Some(d) => d.add(sig_index, sample_index),
+        None => results.increment(sig_index, sample_index),
+    }
+}
+
+/// Mask the genotypes `gts` of the samples whose integer FORMAT `values` are below `min`
+/// or missing (unless `keep_missing`). Returns the number of masked genotypes.
+fn mask_genotypes(
+    gts: &mut [genotype::Genotype],
+    values: &[Vec<Option<i32>>],
+    min: i32,
+    keep_missing: bool,
+) -> u64 {
+    let mut masked = 0;
+    for (gt, value) in gts.iter_mut().zip(values) {
+        let pass = match value.first() {
+            Some(Some(v)) => *v >= min,
+            _ => keep_missing,
+        };
+        if !pass {
+            gt.mask();
+            masked += 1;
+        }
+    }
+    masked
+}
+
+/// How the alternative alleles of a sample at a site are counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum CountMode {
+    /// Count each alternative allele per haplotype, e.g., twice for a 1/1 genotype
+    #[default]
+    Haplotype,
+    /// Count each signature at most once per site and sample
+    Site,
+    /// Count each alternative allele once per site and sample, weighted by its dosage,
+    /// i.e., the number of its copies in the genotype (e.g., 2 for a 1/1 genotype)
+    GenotypeDosage,
+}
+
+/// How the missing genotypes of the samples are compared to check if a site is homogeneous.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum HomogeneousMissing {
+    /// Leave the samples without any called allele out of the comparison, e.g., a site with
+    /// `./.`, `1/1` and `1/1` is homogeneous
+    Ignore,
+    /// Count the missing alleles as the reference allele, e.g., `./1` equals `0/1`
+    TreatAsRef,
+    /// Count the missing alleles as an allele of their own, such that `./.` only equals
+    /// `./.` and `./1` differs from `0/1` and `1/1`
+    #[default]
+    TreatAsDistinct,
+}
+
+/// How records whose reference allele differs from the reference are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum RefMismatch {
+    /// Skip the record with a warning
+    #[default]
+    Warn,
+    /// Skip the record silently, it is only counted in the summary
+    Skip,
+    /// Fail, as the reference is likely not the one the variants were called against
+    Error,
+}
+
+/// The classification of the mutations into channels.
+#[derive(Default)]
+pub(crate) enum Scheme {
+    /// Single base substitutions in the context of the window
+    #[default]
+    Sbs,
+    /// Single base substitutions stratified by the transcription strand of their pyrimidine
+    SbsTs,
+    /// Single base substitutions stratified as in SBS288, with the class of substitutions
+    /// overlapped by genes on both strands
+    Sbs288(annotation::TranscriptionStrand),
+    /// Single base substitutions stratified by the replication strand of their pyrimidine
+    SbsRs(replication::Replication),
+    /// Single base substitutions stratified by whether their base is part of a CpG
+    SbsCpg,
+    /// Doublet base substitutions
+    Dbs(dbs::Doublets),
+    /// Small insertions and deletions
+    Id(indel::Indels),
+}
+
+/// How duplicate records with different genotypes are handled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum DedupeMode {
+    /// Count the first record only
+    First,
+    /// Fail, as it is unclear which record is right
+    Error,
+}
+
+/// Options controlling which records and alleles are counted.
+#[derive(Default)]
+pub(crate) struct Options {
+    /// The classification of the mutations
+    pub(crate) scheme: Scheme,
+    /// Ignore sites where all samples have the same allele count
+    pub(crate) ignore_homogeneous_sites: bool,
+    /// Count each alternative allele once per sample instead of decoding the genotypes
+    pub(crate) no_genotypes: bool,
+    /// Compare the genotypes of homogeneous sites by their alleles regardless of the ploidy
+    pub(crate) compare_allele_content: bool,
+    /// How the missing genotypes are compared for homogeneous sites
+    pub(crate) homogeneous_missing: HomogeneousMissing,
+    /// Ignore sites where any of the selected samples has a missing genotype
+    pub(crate) require_complete_genotypes: bool,
+    /// Do not count samples with a (partially) missing genotype at a site
+    pub(crate) skip_missing_genotypes: bool,
+    /// How the alternative alleles of a sample are counted
+    pub(crate) count_mode: CountMode,
+    /// Skip records repeating an earlier record, if requested
+    pub(crate) dedupe: Option<DedupeMode>,
+    /// Do not count mutations of a sample closer than this distance to the previous one
+    pub(crate) min_distance: Option<u64>,
+    /// Count mutations of a sample closer than this distance to another one as clustered
+    pub(crate) clustered_cutoff: Option<u64>,
+    /// Count the variants in one block per label of the region they are located in
+    pub(crate) region_labels: Option<intervals::LabeledIntervals>,
+    /// Count the substitutions on the mitochondrial contig without collapsing the strands
+    pub(crate) mito: Option<mito::Mito>,
+    /// Drop all mutations of a cluster rather than only the later ones
+    pub(crate) drop_both: bool,
+    /// Randomly sample at most this number of counted mutations per sample using the seed
+    pub(crate) downsample: Option<(usize, u64)>,
+    /// Decompose multi-nucleotide variants into their component SNVs
+    pub(crate) decompose_mnv: bool,
+    /// Do not count records whose base or window is soft-masked in the reference
+    pub(crate) skip_softmasked: bool,
+    /// Count substitutions with an ambiguous base in their window without their context
+    pub(crate) count_partial_context: bool,
+    /// Do not count records with more than this number of alternative alleles
+    pub(crate) max_alt_alleles: Option<usize>,
+    /// Only count records on these contigs
+    pub(crate) chromosomes: Option<HashSet<String>>,
+    /// Only count records on the primary chromosomes
+    pub(crate) primary_only: bool,
+    /// Skip the records on contigs missing from the reference instead of failing
+    pub(crate) skip_unknown_contigs: bool,
+    /// Do not check the contigs of the VCF header against the reference before counting
+    pub(crate) no_validate_contigs: bool,
+    /// Skip malformed records with a warning instead of failing
+    pub(crate) skip_errors: bool,
+    /// How records whose reference allele differs from the reference are handled
+    pub(crate) on_ref_mismatch: RefMismatch,
+    /// Fail after counting if more than this fraction of the records differ from the reference
+    pub(crate) max_ref_mismatch_fraction: Option<f32>,
+    /// Fail after counting if more than this fraction of the records is not counted
+    pub(crate) max_ignored_fraction: Option<f32>,
+    /// Only count records located in these regions
+    pub(crate) include: Option<intervals::Intervals>,
+    /// Do not count records located in these regions
+    pub(crate) exclude: Option<intervals::Intervals>,
+    /// Only count records having one of these IDs
+    pub(crate) include_ids: Option<HashSet<String>>,
+    /// Do not count records having one of these IDs
+    pub(crate) exclude_ids: Option<HashSet<String>>,
+    /// Only count records whose FILTER is `PASS` or missing
+    pub(crate) pass_only: bool,
+    /// Only count records having one of these FILTER values
+    pub(crate) include_filters: Option<HashSet<String>>,
+    /// Do not count records having one of these FILTER values
+    pub(crate) exclude_filters: Option<HashSet<String>>,
+    /// Only count records with a QUAL of at least this value
+    pub(crate) min_qual: Option<f32>,
+    /// Do not count records with missing QUAL
+    pub(crate) require_qual: bool,
+    /// Only count records whose INFO fields match this expression
+    pub(crate) info_filter: Option<info_filter::Expression>,
+    /// The result of comparisons on INFO fields missing in a record
+    pub(crate) info_missing: bool,
+    /// Only count records marked as somatic by this INFO field
+    pub(crate) somatic: Option<filter::SomaticTag>,
+    /// Do not count alternative alleles whose population allele frequency in the INFO field is above the value
+    pub(crate) max_pop_af: Option<(String, f32)>,
+    /// Do not count alternative alleles without population allele frequency if `max_pop_af` is given
+    pub(crate) require_pop_af: bool,
+    /// Do not count genotypes with a FORMAT/DP below this value or missing
+    pub(crate) min_dp: Option<i32>,
+    /// Do not count genotypes with a FORMAT/GQ below this value
+    pub(crate) min_gq: Option<i32>,
+    /// Do not count genotypes whose FORMAT/FT is not PASS
+    pub(crate) sample_pass_only: bool,
+    /// Count genotypes with missing FORMAT/GQ in spite of `min_gq`
+    pub(crate) keep_missing_gq: bool,
+    /// Do not count alternative alleles with an allele fraction below this value
+    pub(crate) min_vaf: Option<f32>,
+    /// Do not count alternative alleles with an allele fraction above this value
+    pub(crate) max_vaf: Option<f32>,
+    /// Do not count alternative alleles supported by fewer reads
+    pub(crate) min_alt_reads: Option<i32>,
+    /// Count alleles without allelic depths in spite of `min_vaf`, `max_vaf` or `min_alt_reads`
+    pub(crate) keep_missing_ad: bool,
+    /// Weight each mutation by its allele fraction, or by this value if it can not be computed
+    pub(crate) missing_vaf_weight: Option<f64>,
+    /// Store the counts sparsely, even if the result matrix has fewer cells than
+    /// `result::SPARSE_CELLS`
+    pub(crate) sparse: bool,
+    /// Gene annotation for assigning the transcription strand to the records
+    pub(crate) annotation: Option<annotation::Annotation>,
+}
+
+impl Options {
+    /// Check if the mutations are counted with weights, i.e., by allele fraction or dosage.
+    pub(crate) fn is_weighted(&self) -> bool {
+        self.missing_vaf_weight.is_some() || self.count_mode == CountMode::GenotypeDosage
+    }
+}
+
+enum AlleleRecordStatus<T> {
+    Err(Error),
+    Issue(String),
+    Ignore(String),
+    Ok(T),
+}
+
+impl<T> AlleleRecordStatus<T> {
+    /// Continue with `f` on the alleles of a successful status.
+    fn and_then<U, F: FnOnce(T) -> AlleleRecordStatus<U>>(self, f: F) -> AlleleRecordStatus<U> {
+        match self {
+            AlleleRecordStatus::Err(e) => AlleleRecordStatus::Err(e),
+            AlleleRecordStatus::Issue(e) => AlleleRecordStatus::Issue(e),
+            AlleleRecordStatus::Ignore(e) => AlleleRecordStatus::Ignore(e),
+            AlleleRecordStatus::Ok(a) => f(a),
+        }
+    }
+}
+
+/// Return whether the alleles of a record were classified by `status`, after logging why not
+/// unless it failed.
+fn is_classified(status: AlleleRecordStatus<()>) -> Result<bool, Error> {
+    match status {
+        AlleleRecordStatus::Ok(()) => Ok(true),
+        AlleleRecordStatus::Err(e) => Err(e),
+        AlleleRecordStatus::Ignore(e) => {
+            trace!("{}", e);
+            Ok(false)
+        }
+        AlleleRecordStatus::Issue(e) => {
+            warn!("{}", e);
+            Ok(false)
+        }
+    }
+}
+
+/// Match the substitutions of the alternative alleles of a record at `position` on `contig`
+/// into the `channels` of the `signatures`, one list per alternative allele. If the
+/// substitutions are stratified, the channels of a stratum form a block offset by the number
+/// of channels per stratum.
+fn substitution_channels<R: ReferenceProvider>(
+    substitutions: &RecordSubstitutions,
+    contig: &str,
+    position: u64,
+    reference: &R,
+    signatures: &signature::Signatures,
+    options: &Options,
+    channels: &mut Vec<Vec<usize>>,
+) -> AlleleRecordStatus<()> {
+    let n_signatures = signatures.channel_count();
+    clear_lists(channels, substitutions.alleles.len());
+    for (allele, indizes) in substitutions.alleles.iter().zip(channels.iter_mut()) {
+        for (offset, alternative) in allele {
+            let codon = substitutions.codon(*offset);
+            let unclassified = || {
+                AlleleRecordStatus::Issue(format!(
+                    "Ignoring substitution {}>{} without a channel at position {}:{}",
+                    String::from_utf8_lossy(codon),
+                    *alternative as char,
+                    contig,
+                    position + *offset as u64 + 1
+                ))
+            };
+            // The mitochondrial substitutions are neither collapsed nor stratified
+            if let Some(mito) = options.mito.as_ref().filter(|m| m.is_mito(contig)) {
+                let offset = scheme_channel_count(&options.scheme, signatures);
+                match mito.signatures().index_of_bases(codon, *alternative) {
+                    Some(index) => indizes.push(offset + index),
+                    None => return unclassified(),
+                }
+                continue;
+            }
+            let index = match signatures.index_of_bases(codon, *alternative) {
+                Some(index) => index,
+                None => return unclassified(),
+            };
+            // Whether the pyrimidine of the substitution is the reference base
+            let forward = matches!(codon[codon.len() / 2], b'C' | b'T');
+            let position = position + *offset as u64;
+            // The block of the stratum, e.g., of the strand class given whether the pyrimidine
+            // of the substitution is on the reference strand
+            let block = match &options.scheme {
+                Scheme::SbsTs => {
+                    let strand = options
+                        .annotation
+                        .as_ref()
+                        .map_or(annotation::TranscriptionStrand::Intergenic, |a| {
+                            a.strand_at(contig, position)
+                        });
+                    transcription_strand_block(strand, forward)
+                }
+                Scheme::Sbs288(bidirectional) => {
+                    let strand = options
+                        .annotation
+                        .as_ref()
+                        .map_or(annotation::TranscriptionStrand::Intergenic, |a| {
+                            a.strand_at(contig, position)
+                        });
+                    match strand {
+                        // The class of the pyrimidine does not depend on its strand
+                        annotation::TranscriptionStrand::Bidirectional => {
+                            transcription_strand_block(*bidirectional, true)
+                        }
+                        _ => transcription_strand_block(strand, forward),
+                    }
+                }
+                Scheme::SbsRs(replication) => {
+                    replication_strand_block(replication.strand_at(contig, position), forward)
+                }
+                Scheme::SbsCpg => match reference.is_cpg(contig, position) {
+                    Ok(true) => 1,
+                    Ok(false) => 0,
+                    Err(e) => return AlleleRecordStatus::Err(e),
+                },
+                _ => 0,
+            };
+            indizes.push(block * n_signatures + index);
+        }
+    }
+    AlleleRecordStatus::Ok(())
+}
+
+/// The substitutions of the alternative alleles of a record.
+#[derive(Debug, Default)]
+struct RecordSubstitutions {
+    /// The codon in the reference of each substituted offset to the position of the record
+    codons: Vec<(usize, String)>,
+    /// The offsets and alternative bases of the substitutions of each alternative allele
+    alleles: Vec<Vec<(usize, u8)>>,
+}
+
+impl RecordSubstitutions {
+    /// Return the codon of the substitution at `offset` to the position of the record.
+    fn codon(&self, offset: usize) -> &[u8] {
+        let (_, codon) = self.codons.iter().find(|(o, _)| *o == offset).unwrap();
+        codon.as_bytes()
+    }
+}
+
+/// Extract the alternative alleles from a VCF record on `contig` into `substitutions`.
+/// If succesful, the substitutions, by their offset to the position of the record, are filled
+/// in with one entry per alternative allele, whose codons are fetched by `fetch_codons()`. An
+/// alternative allele resembles more than one substitution if it is a decomposed
+/// multi-nucleotide variant, and none if it can not be classified (symbolic alleles,
+/// breakends, indels). The latter are counted as skipped in `summary`. The buffer
+/// `reference_allele` holds the uppercased reference allele if it is given in lowercase.
+fn alternative_alleles_from_record(
+    record: &rust_htslib::bcf::Record,
+    contig: &str,
+    options: &Options,
+    summary: &mut summary::Summary,
+    reference_allele: &mut Vec<u8>,
+    substitutions: &mut RecordSubstitutions,
+) -> AlleleRecordStatus<()> {
+    let position = record.pos() as usize;
+
+    // Iterator on all alleles
+    let record_alleles = record.alleles();
+    let mut allele_iter = record_alleles.iter();
+    // Expect the first allele to be the reference allele
+    let mut reference_bases: &[u8] = allele_iter.next().unwrap();
+    if reference_bases.iter().any(u8::is_ascii_lowercase) {
+        reference_allele.clear();
+        reference_allele.extend_from_slice(reference_bases);
+        reference_allele.make_ascii_uppercase();
+        reference_bases = reference_allele;
+    }
+
+    // Evaluate each alternative allele independently and decompose it into the
+    // substitutions (offset to the position and alternative base) it consists of.
+    // Only SNVs and - if requested - MNVs of same length can be classified.
+    let alleles = &mut substitutions.alleles;
+    clear_lists(alleles, allele_iter.len());
+    for (a, substituted) in allele_iter.zip(alleles.iter_mut()) {
+        if a == b"*" {
+            // An overlapping deletion, the base is not substituted
+            summary.skip(summary::Reason::SpanningDeletion);
+        } else if is_symbolic_allele(a) {
+            summary.skip(summary::Reason::Symbolic);
+        } else if a.len() != reference_bases.len() || (a.len() > 1 && !options.decompose_mnv) {
+            summary.skip(summary::Reason::NonSnv);
+        } else {
+            substituted.extend(
+                a.iter()
+                    .map(|n| n.to_ascii_uppercase())
+                    .enumerate()
+                    .filter(|(offset, n)| *n != reference_bases[*offset]),
+            );
+        }
+    }
+    let alternatives = &*alleles;
+
+    if alternatives.is_empty() {
+        return AlleleRecordStatus::Ignore(format!(
+            "Ignoring no-alternative variant at position {}:{}",
+            contig,
+            position + 1
+        ));
+    }
+    if alternatives.iter().all(|a| a.is_empty()) {
+        return AlleleRecordStatus::Ignore(format!(
+            "Ignoring non-SNV variant at position {}:{}",
+            contig,
+            position + 1
+        ));
+    }
+
+    AlleleRecordStatus::Ok(())
+}
+
+/// Fetch the codons of the `substitutions` of a VCF record on `contig` from the reference of
+/// the `classifier`, but only of the alternative alleles that are `carried` by any sample. The
+/// substitutions of the other alleles are dropped, such that they have no channels. A record
+/// whose codon can not be classified, e.g., for an ambiguous base, is skipped in `summary`,
+/// while one not matching the reference is handled by the `on_ref_mismatch` policy.
+fn fetch_codons<R: ReferenceProvider>(
+    record: &rust_htslib::bcf::Record,
+    contig: &str,
+    classifier: &classify::Classifier<R>,
+    on_ref_mismatch: RefMismatch,
+    summary: &mut summary::Summary,
+    substitutions: &mut RecordSubstitutions,
+    carried: &[bool],
+) -> AlleleRecordStatus<()> {
+    let position = record.pos() as usize;
+    let RecordSubstitutions { codons, alleles } = substitutions;
+    for (allele, carried) in alleles.iter_mut().zip(carried) {
+        if !carried {
+            allele.clear();
+        }
+    }
+
+    // Fetch the codon for each substituted offset
+    let reference_bases = record.alleles()[0];
+    codons.clear();
+    for (offset, _) in alleles.iter().flatten() {
+        if codons.iter().any(|(o, _)| o == offset) {
+            continue;
+        }
+        let reference_nucleotide = reference_bases[*offset].to_ascii_uppercase() as char;
+        let reason =
+            match classifier.context(contig, (position + offset) as i64, reference_nucleotide) {
+                Ok(codon) => {
+                    if codon.len() == 1 && classifier.window() > 0 {
+                        summary.partial_context();
+                    }
+                    codons.push((*offset, codon));
+                    continue;
+                }
+                Err(e @ classify::ClassifyError::OutOfBounds { .. }) => {
+                    return AlleleRecordStatus::Err(e.into())
+                }
+                Err(classify::ClassifyError::Mismatch { context, .. }) => {
+                    let window = classifier.window().min(context.len() - 1);
+                    return reference_mismatch(
+                        record,
+                        contig,
+                        position + offset,
+                        &reference_nucleotide.to_string(),
+                        &context[window..window + 1],
+                        on_ref_mismatch,
+                        summary,
+                    );
+                }
+                Err(classify::ClassifyError::Softmasked { context }) => (
+                    summary::Reason::Softmasked,
+                    format!(
+                        "Ignoring variant in soft-masked region at position {}:{}: {}",
+                        contig,
+                        position + offset + 1,
+                        context
+                    ),
+                ),
+                Err(classify::ClassifyError::TruncatedContext { context, .. }) => (
+                    summary::Reason::TruncatedContext,
+                    format!(
+                        "Ignoring codon truncated by the end of the contig at position {}:{}: {}",
+                        contig,
+                        position + offset + 1,
+                        context
+                    ),
+                ),
+                Err(classify::ClassifyError::AmbiguousContext { context }) => (
+                    summary::Reason::AmbiguousContext,
+                    format!(
+                        "Ignoring codon with non-standard nucleotide at position {}:{}: {}",
+                        contig,
+                        position + offset + 1,
+                        context
+                    ),
+                ),
+                Err(e @ classify::ClassifyError::Unclassified { .. }) => {
+                    if let Err(e) = summary.skip_record(
+                        summary::Reason::Unclassified,
+                        contig,
+                        record.pos(),
+                        &record.alleles(),
+                    ) {
+                        return AlleleRecordStatus::Err(e);
+                    }
+                    return AlleleRecordStatus::Issue(e.to_string());
+                }
+            };
+        if let Err(e) = summary.skip_record(reason.0, contig, record.pos(), &record.alleles()) {
+            return AlleleRecordStatus::Err(e);
+        }
+        return AlleleRecordStatus::Ignore(reason.1);
+    }
+
+    AlleleRecordStatus::Ok(())
+}
+
+/// Classify the alternative alleles of a VCF record on `contig` as doublet base substitutions.
+/// If succesful, the index of the doublet channel is returned for each alternative allele,
+/// and none if it is not a doublet substitution of two adjacent bases (SNVs, indels, symbolic
+/// alleles). The latter are counted as skipped in `summary`.
+fn doublet_alleles_from_record<R: ReferenceProvider>(
+    record: &rust_htslib::bcf::Record,
+    contig: &str,
+    reference: &R,
+    doublets: &dbs::Doublets,
+    options: &Options,
+    summary: &mut summary::Summary,
+) -> AlleleRecordStatus<Vec<Vec<usize>>> {
+    let record_alleles = record.alleles();
+    let reference_allele = String::from_utf8_lossy(record_alleles[0]).to_uppercase();
+
+    let alternatives: Vec<Option<String>> = record_alleles
+        .iter()
+        .skip(1)
+        .map(|a| {
+            if a == b"*" {
+                summary.skip(summary::Reason::SpanningDeletion);
+                None
+            } else if is_symbolic_allele(a) {
+                summary.skip(summary::Reason::Symbolic);
+                None
+            } else {
+                let alternative = String::from_utf8_lossy(a).to_uppercase();
+                if doublets.index_of(&reference_allele, &alternative).is_some() {
+                    Some(alternative)
+                } else {
+                    summary.skip(summary::Reason::NonDbs);
+                    None
+                }
+            }
+        })
+        .collect();
+    if alternatives.iter().all(|a| a.is_none()) {
+        return AlleleRecordStatus::Ignore(format!(
+            "Ignoring non-DBS variant at position {}:{}",
+            contig,
+            record.pos() + 1
+        ));
+    }
+
+    if let Err(status) = check_reference_allele(
+        record,
+        contig,
+        reference,
+        &reference_allele,
+        options,
+        summary,
+    ) {
+        return status;
+    }
+
+    AlleleRecordStatus::Ok(
+        alternatives
+            .iter()
+            .map(|a| match a {
+                Some(a) => vec![doublets.index_of(&reference_allele, a).unwrap()],
+                None => Vec::new(),
+            })
+            .collect(),
+    )
+}
+
+/// Classify the alternative alleles of a VCF record on `contig` as small insertions and
+/// deletions. If succesful, the index of the indel channel is returned for each alternative
+/// allele, and none if it is not a simple indel sharing its first base with the reference
+/// allele (SNVs, MNVs, complex indels, symbolic alleles). The latter are counted as skipped
+/// in `summary`.
+fn indel_alleles_from_record<R: ReferenceProvider>(
+    record: &rust_htslib::bcf::Record,
+    contig: &str,
+    reference: &R,
+    indels: &indel::Indels,
+    options: &Options,
+    summary: &mut summary::Summary,
+) -> AlleleRecordStatus<Vec<Vec<usize>>> {
+    let record_alleles = record.alleles();
+    let reference_allele = String::from_utf8_lossy(record_alleles[0]).to_uppercase();
+
+    // The inserted or deleted bases following the shared first base
+    let alternatives: Vec<Option<(bool, String)>> = record_alleles
+        .iter()
+        .skip(1)
+        .map(|a| {
+            let alternative = String::from_utf8_lossy(a).to_uppercase();
+            if a == b"*" {
+                summary.skip(summary::Reason::SpanningDeletion);
+                None
+            } else if is_symbolic_allele(a) {
+                summary.skip(summary::Reason::Symbolic);
+                None
+            } else if alternative.len() == 1
+                && reference_allele.len() > 1
+                && reference_allele.starts_with(&alternative)
+            {
+                Some((false, reference_allele[1..].to_owned()))
+            } else if reference_allele.len() == 1
+                && alternative.len() > 1
+                && alternative.starts_with(&reference_allele)
+            {
+                Some((true, alternative[1..].to_owned()))
+            } else {
+                summary.skip(summary::Reason::NonIndel);
+                None
+            }
+        })
+        .collect();
+    if alternatives.iter().all(|a| a.is_none()) {
+        return AlleleRecordStatus::Ignore(format!(
+            "Ignoring non-indel variant at position {}:{}",
+            contig,
+            record.pos() + 1
+        ));
+    }
+    if let Err(status) = check_reference_allele(
+        record,
+        contig,
+        reference,
+        &reference_allele,
+        options,
+        summary,
+    ) {
+        return status;
+    }
+
+    let mut indizes = Vec::new();
+    for alternative in alternatives {
+        let (inserted, sequence) = match alternative {
+            Some(a) => a,
+            None => {
+                indizes.push(Vec::new());
+                continue;
+            }
+        };
+        // The reference around the indel, enough to find the repeats and microhomology
+        let start = (record.pos() + 1) as u64;
+        let end = if inserted {
+            start
+        } else {
+            start + sequence.len() as u64
+        };
+        let fetched = reference
+            .fetch_sequence(contig, start.saturating_sub(sequence.len() as u64), start)
+            .and_then(|before| {
+                let after =
+                    reference.fetch_sequence(contig, end, end + 5 * sequence.len() as u64)?;
+                Ok((before, after))
+            });
+        let (before, after) = match fetched {
+            Ok(f) => f,
+            Err(e) => return AlleleRecordStatus::Err(e),
+        };
+        let label = indel::classify(inserted, &sequence, &before, &after);
+        indizes.push(vec![indels.index_of(&label).unwrap()]);
+    }
+    AlleleRecordStatus::Ok(indizes)
+}
+
+/// Check the `reference_allele` of a VCF record on `contig` against the reference. Records
+/// in soft-masked regions are skipped if requested, and the status to return instead of the
+/// alleles is given for these and mismatching reference alleles.
+fn check_reference_allele<T, R: ReferenceProvider>(
+    record: &rust_htslib::bcf::Record,
+    contig: &str,
+    reference: &R,
+    reference_allele: &str,
+    options: &Options,
+    summary: &mut summary::Summary,
+) -> Result<(), AlleleRecordStatus<T>> {
+    let (sequence, softmasked) =
+        match reference.fetch_range_with_case(contig, record.pos(), reference_allele.len()) {
+            Ok(s) => s,
+            Err(e) => return Err(AlleleRecordStatus::Err(e)),
+        };
+    if softmasked && options.skip_softmasked {
+        if let Err(e) = summary.skip_record(
+            summary::Reason::Softmasked,
+            contig,
+            record.pos(),
+            &record.alleles(),
+        ) {
+            return Err(AlleleRecordStatus::Err(e));
+        }
+        return Err(AlleleRecordStatus::Ignore(format!(
+            "Ignoring variant in soft-masked region at position {}:{}: {}",
+            contig,
+            record.pos() + 1,
+            sequence
+        )));
+    }
+    if sequence != reference_allele {
+        return Err(reference_mismatch(
+            record,
+            contig,
+            record.pos() as usize,
+            reference_allele,
+            &sequence,
+            options.on_ref_mismatch,
+            summary,
+        ));
+    }
+    Ok(())
+}
+
+/// Handle a record on `contig` whose reference allele differs from the reference, i.e., the
+/// `expected` bases at the 0-based `position` were `observed` in the reference instead, by
+/// the `policy`. The record fails for `RefMismatch::Error` and is skipped in `summary`
+/// otherwise.
+fn reference_mismatch<T>(
+    record: &rust_htslib::bcf::Record,
+    contig: &str,
+    position: usize,
+    expected: &str,
+    observed: &str,
+    policy: RefMismatch,
+    summary: &mut summary::Summary,
+) -> AlleleRecordStatus<T> {
+    if policy == RefMismatch::Error {
+        return AlleleRecordStatus::Err(Error::ReferenceMismatch {
+            contig: contig.to_owned(),
+            pos: position as u64 + 1,
+            expected: expected.to_owned(),
+            found: observed.to_owned(),
+        });
+    }
+    let message = format!(
+        "the VCF file expects {} at position {}:{} but the reference has {}",
+        expected,
+        contig,
+        position + 1,
+        observed
+    );
+    if let Err(e) = summary.skip_record(
+        summary::Reason::RefMismatch,
+        contig,
+        record.pos(),
+        &record.alleles(),
+    ) {
+        return AlleleRecordStatus::Err(e);
+    }
+    if policy == RefMismatch::Warn {
+        AlleleRecordStatus::Issue(format!("Ignoring variant, {}", message))
+    } else {
+        AlleleRecordStatus::Ignore(format!("Ignoring variant, {}", message))
+    }
+}
+
+/// Helper function to check if an alternative allele is a symbolic allele (e.g., `<NON_REF>`),
+/// a breakend (e.g., `G]17:198982]` or `.A`) or the spanning deletion `*`.
+fn is_symbolic_allele(allele: &[u8]) -> bool {
+    allele == b"*"
+        || allele.starts_with(b"<")
+        || allele.iter().any(|&c| c == b'[' || c == b']')
+        || (allele.len() > 1 && (allele.starts_with(b".") || allele.ends_with(b".")))
+}
+
+/// Resolve the contig name for template-id `rid` of a record if the VCF header
+/// does not declare it. The name is looked up from the record's header, as htslib adds
+/// undeclared contigs while parsing, and checked against the reference by the caller. A
+/// contig without name fails, as it can not be matched to a sequence of the reference.
+fn resolve_contig_name(record: &rust_htslib::bcf::Record, rid: u32) -> Result<String, Error> {
+    let header = record.header();
+    if rid < header.contig_count() {
+        if let Some(name) = header
+            .rid2name(rid)
+            .ok()
+            .and_then(|n| std::str::from_utf8(n).ok())
+        {
+            return Ok(name.to_owned());
+        }
+    }
+    Err(Error::UnnamedContig {
+        rid,
+        position: record.pos() as u64 + 1,
+    })
+}
+
+/// Helper function to check if there is variation in the called alleles of the genotypes,
+/// with the `missing` genotypes compared as requested. If `allele_content` is set, genotypes
+/// of different ploidy carrying the same alleles are not considered varying. As the
+/// comparison is an equivalence, each genotype only has to be compared to the first one.
+fn is_varying_position(
+    gts: &[genotype::Genotype],
+    allele_content: bool,
+    missing: HomogeneousMissing,
+) -> bool {
+    let mut compared = gts
+        .iter()
+        .filter(|gt| missing != HomogeneousMissing::Ignore || !gt.is_missing());
+    let first = match compared.next() {
+        Some(gt) => gt,
+        None => return false,
+    };
+    compared.any(|gt| match (missing, allele_content) {
+        (HomogeneousMissing::TreatAsRef, false) => !first
+            .iter_missing_as_reference()
+            .eq(gt.iter_missing_as_reference()),
+        (HomogeneousMissing::TreatAsRef, true) => {
+            !genotype::distinct(first.iter_missing_as_reference())
+                .eq(genotype::distinct(gt.iter_missing_as_reference()))
+        }
+        (_, false) => !first.same_called_alleles(gt),
+        (_, true) => !first.has_same_allele_content(gt),
+    })
+}
+
+/// Helper function to find the index of a sample in the sample list
+fn index_of(hay: &[String], needle: &str) -> Result<usize, Error> {
+    hay.iter()
+        .position(|h| h == needle)
+        .ok_or_else(|| Error::UnknownSample {
+            name: needle.to_owned(),
+            available: hay.to_vec(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics;
+
+    fn testdata(name: &str) -> String {
+        format!("{}/testdata/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    /// Count the variants of the VCF at `path` for all samples against `ex2.fa`.
+    fn count(
+        path: &str,
+        window_size: u8,
+        options: &Options,
+    ) -> (
+        signature::Signatures,
+        result::ResultMatrix,
+        summary::Summary,
+    ) {
+        count_threaded(path, window_size, options, 1)
+    }
+
+    fn count_threaded(
+        path: &str,
+        window_size: u8,
+        options: &Options,
+        threads: usize,
+    ) -> (
+        signature::Signatures,
+        result::ResultMatrix,
+        summary::Summary,
+    ) {
+        let reference = reference::Reference::new(testdata("ex2.fa"), window_size).unwrap();
+        let signatures =
+            signature::Signatures::new(window_size.into(), signature::DEFAULT_MAX_CHANNELS)
+                .unwrap();
+        let mut variants = open_vcf(path, threads).unwrap();
+        let n_samples = variants.header().sample_count() as usize;
+        let mut summary = summary::Summary::new();
+        let results = count_variants(
+            &mut variants,
+            &reference,
+            &signatures,
+            &(0..n_samples).collect::<Vec<usize>>(),
+            options,
+            threads,
+            &mut summary,
+            None,
+        )
+        .unwrap();
+        (signatures, results, summary)
+    }
+
+    #[test]
+    fn test_count_vcf_profile() {
+        let counts =
+            count_vcf(&CountOptions::new(testdata("ex2.vcf"), testdata("ex2.fa"))).unwrap();
+        let c_a = signature::Signature::parse("C>A").unwrap();
+        let profile = counts.profile("Example2").unwrap();
+        assert_eq!(profile.name(), "Example2");
+        assert_eq!(profile.count(&c_a), Some(4));
+        assert_eq!(profile.fraction(&c_a), Some(1.0));
+        assert!(counts.profile("Example3").is_none());
+    }
+
+    #[test]
+    fn test_count_vcf_errors() {
+        let options =
+            |vcf: &str, reference: &str| CountOptions::new(testdata(vcf), testdata(reference));
+        let e = count_vcf(&options("missing.vcf", "ex2.fa")).err().unwrap();
+        assert!(
+            matches!(&e, Error::Io { source, .. } if source.kind() == std::io::ErrorKind::NotFound),
+            "{}",
+            e
+        );
+        assert_eq!(e.exit_code(), 3);
+        let e = count_vcf(&options("ex2.vcf", "missing.fa")).err().unwrap();
+        assert!(matches!(e, Error::Htslib { .. }), "{}", e);
+
+        let mut unknown = options("ex2.vcf", "ex2.fa");
+        unknown.samples = Some(vec!["Example3".to_owned()]);
+        let e = count_vcf(&unknown).err().unwrap();
+        assert!(
+            matches!(&e, Error::UnknownSample { name, available } if name == "Example3" && available.len() == 2)
+        );
+
+        let mut window = options("ex2.vcf", "ex2.fa");
+        window.window = 2;
+        window.max_channels = 96;
+        let e = count_vcf(&window).err().unwrap();
+        assert!(matches!(e, Error::InvalidWindow { window: 2, .. }), "{}", e);
+        assert_eq!(e.exit_code(), 2);
+    }
+
+    #[test]
+    fn test_parse_region() {
+        let region = |contig: &str, start, end| Region {
+            contig: contig.to_owned(),
+            start,
+            end,
+        };
+        assert_eq!(parse_region("chr1").unwrap(), region("chr1", 0, None));
+        assert_eq!(
+            parse_region("chr1:100-200").unwrap(),
+            region("chr1", 99, Some(199))
+        );
+        assert_eq!(
+            parse_region("HLA-A*01:01").unwrap(),
+            region("HLA-A*01:01", 0, None)
+        );
+        assert_eq!(
+            parse_region("HLA-A*01:01:5-5").unwrap(),
+            region("HLA-A*01:01", 4, Some(4))
+        );
+        for invalid in ["", ":1-2", "chr1:0-2", "chr1:5-4"] {
+            let e = parse_region(invalid).err().unwrap();
+            assert_eq!(e.exit_code(), 2, "{}", e);
+        }
+    }
+
+    #[test]
+    fn test_count_regions() {
+        let records = |regions: &[&str]| {
+            let mut options = CountOptions::new(testdata("ex2_indexed.vcf.gz"), testdata("ex2.fa"));
+            options.regions = Some(regions.iter().map(|r| r.to_string()).collect());
+            count_vcf(&options).map(|c| c.summary.records())
+        };
+        assert_eq!(records(&["1:2-3"]).unwrap(), 2);
+        assert_eq!(records(&["2"]).unwrap(), 5);
+        assert_eq!(records(&["1:2-3", "2"]).unwrap(), 7);
+        assert_eq!(records(&["1:6-9"]).unwrap(), 0);
+        assert_eq!(records(&["3"]).unwrap(), 0);
+
+        let e = records(&["1:2-3", "1:3-4"]).err().unwrap();
+        assert!(matches!(e, Error::InvalidOptions(_)), "{}", e);
+        let e = records(&["4"]).err().unwrap();
+        assert!(matches!(e, Error::InvalidOptions(_)), "{}", e);
+        // The regions are read by the index
+        let mut unindexed = CountOptions::new(testdata("ex2.vcf"), testdata("ex2.fa"));
+        unindexed.regions = Some(vec!["1".to_owned()]);
+        let e = count_vcf(&unindexed).err().unwrap();
+        assert!(matches!(e, Error::Io { .. }), "{}", e);
+        assert_eq!(e.exit_code(), 3);
+        assert!(has_index(&testdata("ex2_indexed.vcf.gz")));
+        assert!(!has_index(&testdata("ex2.vcf")));
+    }
+
+    #[test]
+    fn test_threaded_decompression() {
+        let (signatures, expected, _) = count(&testdata("ex2.vcf"), 1, &Options::default());
+        let (_, results, summary) =
+            count_threaded(&testdata("ex2.vcf.gz"), 1, &Options::default(), 2);
+        assert_eq!(summary.records(), 2);
+        for v in 0..signatures.len() {
+            for s in 0..2 {
+                assert_eq!(results.get(v, s), expected.get(v, s));
+            }
+        }
+    }
+
+    /// Reads the remote VCF at the URL given by `MUTSIG_TEST_REMOTE_VCF`.
+    #[test]
+    #[ignore]
+    fn test_open_remote_vcf() {
+        let url = std::env::var("MUTSIG_TEST_REMOTE_VCF").unwrap();
+        let mut variants = open_vcf(&url, 1).unwrap();
+        assert!(variants.records().next().is_some());
+    }
+
+    #[test]
+    fn test_resolve_undeclared_contigs() {
+        let mut variants =
+            rust_htslib::bcf::Reader::from_path(testdata("ex2_no_contigs.vcf")).unwrap();
+        assert_eq!(variants.header().contig_count(), 0);
+
+        let names: Vec<String> = variants
+            .records()
+            .map(|r| {
+                let record = r.unwrap();
+                resolve_contig_name(&record, record.rid().unwrap()).unwrap()
+            })
+            .collect();
+        assert_eq!(names, vec!["1".to_owned(), "1".to_owned()]);
+
+        // A contig unknown to the header is not guessed from the order of the reference
+        let mut record = variants.empty_record();
+        record.set_rid(Some(1));
+        record.set_pos(4);
+        assert!(matches!(
+            resolve_contig_name(&record, 1),
+            Err(Error::UnnamedContig {
+                rid: 1,
+                position: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn test_record_without_contig() {
+        let reference = reference::Reference::new(testdata("ex2.fa"), 0).unwrap();
+        let signatures = signature::Signatures::new(0, signature::DEFAULT_MAX_CHANNELS).unwrap();
+        let mut variants = rust_htslib::bcf::Reader::from_path(testdata("ex2.vcf")).unwrap();
+        let contigs = contig_names(variants.header());
+        let mut record = variants.records().next().unwrap().unwrap();
+        record.set_rid(None);
+        let classify = |options: &Options| {
+            let context = RecordContext {
+                signatures: &signatures,
+                bcf_sample_indizes: &[0, 1],
+                options,
+                n_channels: signatures.len(),
+                ordered: false,
+                log_skipped: false,
+            };
+            let mut counter = Counter {
+                results: result::ResultMatrix::new(signatures.len(), 2),
+                downsampler: None,
+                distance_filter: None,
+                cluster_splitter: None,
+            };
+            let pending = PendingRecord {
+                record: record.clone(),
+                deduplicated: Deduplicated::Unique,
+                unknown_contig: false,
+            };
+            classify_records(
+                &reference,
+                &context,
+                &[pending],
+                &contigs,
+                &mut counter,
+                &mut LoopState::new(),
+            )
+        };
+        let e = classify(&Options::default()).err().unwrap();
+        assert!(matches!(e, Error::MissingContig { position: 2 }), "{}", e);
+
+        // Unless malformed records are skipped
+        let options = Options {
+            skip_errors: true,
+            ..Options::default()
+        };
+        let outcomes = classify(&options).unwrap();
+        assert_eq!(outcomes.len(), 1);
+        let summary = &outcomes[0].summary;
+        assert_eq!(summary.skipped(summary::Reason::MissingContig), 1);
+        assert_eq!(summary.ignored_records(), 1);
+        assert!(outcomes[0].samples.is_empty());
+    }
+
+    #[test]
+    fn test_symbolic_alleles_are_skipped() {
+        assert!(is_symbolic_allele(b"<NON_REF>"));
+        assert!(is_symbolic_allele(b"*"));
+        assert!(is_symbolic_allele(b"G]17:198982]"));
+        assert!(is_symbolic_allele(b".A"));
+        assert!(!is_symbolic_allele(b"T"));
+        assert!(!is_symbolic_allele(b"AT"));
+
+        let (signatures, results, summary) =
+            count(&testdata("ex2_gvcf.vcf"), 0, &Options::default());
+
+        // The C>T next to the <NON_REF> allele must still be counted
+        let c_t = signatures
+            .index_of(&signature::Signature::new("C", 'C', 'T'))
+            .unwrap();
+        assert_eq!(results.get(c_t, 0), 1);
+        assert_eq!(results.get(c_t, 1), 1);
+        let c_a = signatures
+            .index_of(&signature::Signature::new("C", 'C', 'A'))
+            .unwrap();
+        assert_eq!(results.get(c_a, 0), 1);
+        assert_eq!(results.get(c_a, 1), 2);
+
+        assert_eq!(summary.records(), 4);
+        assert_eq!(summary.skipped(summary::Reason::Symbolic), 3);
+        assert_eq!(summary.skipped(summary::Reason::NonSnv), 1);
+    }
+
+    #[test]
+    fn test_spanning_deletion() {
+        let (signatures, results, summary) = count(
+            &testdata("ex2_spanning_deletion.vcf"),
+            0,
+            &Options::default(),
+        );
+        let c_t = signatures
+            .index_of(&signature::Signature::new("C", 'C', 'T'))
+            .unwrap();
+        // The genotype 1/2 counts the C>T only, 0/2 and the '*'-only record nothing
+        assert_eq!(results.get(c_t, 0), 1);
+        assert_eq!(results.get(c_t, 1), 0);
+        let total: u64 = (0..signatures.len())
+            .map(|v| results.get(v, 0) + results.get(v, 1))
+            .sum();
+        assert_eq!(total, 1);
+        assert_eq!(summary.skipped(summary::Reason::SpanningDeletion), 2);
+        assert_eq!(summary.skipped(summary::Reason::Symbolic), 0);
+    }
+
+    #[test]
+    fn test_include_intervals() {
+        let (include, _) = intervals::read_interval_list(testdata("ex2.interval_list")).unwrap();
+        let options = Options {
+            include: Some(include),
+            ..Options::default()
+        };
+        let (signatures, results, summary) = count(&testdata("ex2.vcf"), 0, &options);
+        let c_a = signatures
+            .index_of(&signature::Signature::new("C", 'C', 'A'))
+            .unwrap();
+        // Only the C>A at 1:2 is located in the intervals, the G>T at 1:5 is not
+        assert_eq!(results.get(c_a, 0), 1);
+        assert_eq!(results.get(c_a, 1), 2);
+        assert_eq!(summary.skipped(summary::Reason::OutsideRegions), 1);
+    }
+
+    #[test]
+    fn test_exclude_bed() {
+        let options = Options {
+            exclude: Some(intervals::read_bed(testdata("ex2.bed.gz")).unwrap()),
+            ..Options::default()
+        };
+        let (signatures, results, summary) = count(&testdata("ex2.vcf"), 0, &options);
+        let c_a = signatures
+            .index_of(&signature::Signature::new("C", 'C', 'A'))
+            .unwrap();
+        // The C>A at 1:2 is excluded, the G>T at 1:5 is not
+        assert_eq!(results.get(c_a, 0), 1);
+        assert_eq!(results.get(c_a, 1), 2);
+        assert_eq!(summary.skipped(summary::Reason::ExcludedRegions), 1);
+    }
+
+    #[test]
+    fn test_include_and_exclude_ids() {
+        let options = Options {
+            include_ids: Some(filter::read_ids(testdata("ex2_ids.txt")).unwrap()),
+            exclude_ids: Some(["rs5".to_owned()].iter().cloned().collect()),
+            ..Options::default()
+        };
+        let (signatures, results, summary) = count(&testdata("ex2_ids.vcf"), 0, &options);
+        let c_a = signatures
+            .index_of(&signature::Signature::new("C", 'C', 'A'))
+            .unwrap();
+        // Only rs2 is counted: '.' is never included and rs5;COSV5 is excluded
+        assert_eq!(results.get(c_a, 0), 1);
+        assert_eq!(results.get(c_a, 1), 2);
+        let total: u64 = (0..signatures.len()).map(|v| results.get(v, 0)).sum();
+        assert_eq!(total, 1);
+        assert_eq!(summary.skipped(summary::Reason::IdNotIncluded), 1);
+        assert_eq!(summary.skipped(summary::Reason::IdExcluded), 1);
+    }
+
+    #[test]
+    fn test_pass_only() {
+        let options = Options {
+            pass_only: true,
+            ..Options::default()
+        };
+        let reference = reference::Reference::new(testdata("ex2.fa"), 0).unwrap();
+        let signatures = signature::Signatures::new(0, signature::DEFAULT_MAX_CHANNELS).unwrap();
+        let mut variants = open_vcf(&testdata("ex2_filter.vcf"), 1).unwrap();
+        let mut summary = summary::Summary::new();
+        let skipped = std::env::temp_dir().join("mutsig_test_pass_only.tsv");
+        summary
+            .log_skipped_to(Box::new(std::fs::File::create(&skipped).unwrap()))
+            .unwrap();
+        let results = count_variants(
+            &mut variants,
+            &reference,
+            &signatures,
+            &[0, 1],
+            &options,
+            1,
+            &mut summary,
+            None,
+        )
+        .unwrap();
+        summary.flush().unwrap();
+
+        // The PASS and the missing FILTER are counted
+        let total: u64 = (0..signatures.len()).map(|v| results.get(v, 0)).sum();
+        assert_eq!(total, 2);
+        assert_eq!(summary.skipped(summary::Reason::Filtered), 3);
+        assert_eq!(
+            std::fs::read_to_string(&skipped).unwrap(),
+            "#CHROM\tPOS\tREF\tALT\tREASON\n\
+             1\t4\tT\tC\tFILTERED\n\
+             1\t5\tG\tT\tFILTERED\n\
+             2\t2\tC\tT\tFILTERED\n"
+        );
+        std::fs::remove_file(&skipped).unwrap();
+    }
+
+    #[test]
+    fn test_filter_include_exclude() {
+        let set = |f: &[&str]| -> Option<HashSet<String>> {
+            Some(f.iter().map(|f| f.to_string()).collect())
+        };
+        // Tolerate slippage, but not in combination with germline_risk
+        let options = Options {
+            include_filters: set(&["PASS", "slippage"]),
+            exclude_filters: set(&["germline_risk"]),
+            ..Options::default()
+        };
+        let (signatures, results, summary) = count(&testdata("ex2_filter.vcf"), 0, &options);
+        let total: u64 = (0..signatures.len()).map(|v| results.get(v, 0)).sum();
+        assert_eq!(total, 3);
+        assert_eq!(summary.skipped(summary::Reason::Filtered), 2);
+    }
+
+    #[test]
+    fn test_min_qual() {
+        let options = Options {
+            min_qual: Some(30.0),
+            ..Options::default()
+        };
+        let (signatures, results, summary) = count(&testdata("ex2_qual.vcf"), 0, &options);
+        let total: u64 = (0..signatures.len()).map(|v| results.get(v, 0)).sum();
+        assert_eq!(total, 3);
+        assert_eq!(summary.skipped(summary::Reason::LowQual), 2);
+        assert_eq!(summary.skipped(summary::Reason::MissingQual), 0);
+
+        let options = Options {
+            min_qual: Some(30.0),
+            require_qual: true,
+            ..Options::default()
+        };
+        let (signatures, results, summary) = count(&testdata("ex2_qual.vcf"), 0, &options);
+        let total: u64 = (0..signatures.len()).map(|v| results.get(v, 0)).sum();
+        assert_eq!(total, 2);
+        assert_eq!(summary.skipped(summary::Reason::LowQual), 2);
+        assert_eq!(summary.skipped(summary::Reason::MissingQual), 1);
+    }
+
+    #[test]
+    fn test_info_filter() {
+        let total = |options: &Options| -> (u64, summary::Summary) {
+            let (signatures, results, summary) = count(&testdata("ex2_info.vcf"), 0, options);
+            let t = (0..signatures.len()).map(|v| results.get(v, 0)).sum();
+            (t, summary)
+        };
+        let options = Options {
+            info_filter: Some(info_filter::Expression::parse("TLOD>=6.3 & STR!=1").unwrap()),
+            ..Options::default()
+        };
+        let (counted, summary) = total(&options);
+        assert_eq!(counted, 2);
+        assert_eq!(summary.skipped(summary::Reason::InfoFilter), 3);
+
+        // The record without TLOD is kept if missing fields compare as true
+        let options = Options {
+            info_missing: true,
+            ..options
+        };
+        assert_eq!(total(&options).0, 3);
+
+        let options = Options {
+            info_filter: Some(info_filter::Expression::parse("TLOD<5 | STR").unwrap()),
+            ..Options::default()
+        };
+        assert_eq!(total(&options).0, 2);
+    }
+
+    #[test]
+    fn test_somatic_only() {
+        let total = |vcf: &str, tag: &str| -> (u64, u64) {
+            let options = Options {
+                somatic: Some(filter::SomaticTag::parse(tag).unwrap()),
+                ..Options::default()
+            };
+            let (signatures, results, summary) = count(&testdata(vcf), 0, &options);
+            (
+                (0..signatures.len()).map(|v| results.get(v, 0)).sum(),
+                summary.skipped(summary::Reason::NotSomatic),
+            )
+        };
+        assert_eq!(total("ex2_somatic_flag.vcf", "SOMATIC"), (2, 1));
+        assert_eq!(total("ex2_somatic_status.vcf", "STATUS=2"), (2, 2));
+        assert_eq!(total("ex2_somatic_status.vcf", "SS=2"), (2, 2));
+    }
+
+    #[test]
+    fn test_max_pop_af() {
+        let signatures = signature::Signatures::new(0, signature::DEFAULT_MAX_CHANNELS).unwrap();
+        let index = |alternative| {
+            signatures
+                .index_of(&signature::Signature::new("C", 'C', alternative))
+                .unwrap()
+        };
+        let total = |results: &result::ResultMatrix| -> u64 {
+            (0..signatures.len()).map(|v| results.get(v, 0)).sum()
+        };
+
+        // The common C>A allele of the multi-allelic record is dropped, C>G is kept
+        let options = Options {
+            max_pop_af: Some(("AF".to_owned(), 0.01)),
+            ..Options::default()
+        };
+        let (_, results, summary) = count(&testdata("ex2_pop_af.vcf"), 0, &options);
+        assert_eq!(results.get(index('A'), 0), 0);
+        assert_eq!(results.get(index('G'), 0), 1);
+        assert_eq!(total(&results), 4);
+        assert_eq!(summary.skipped(summary::Reason::PopulationAf), 1);
+
+        let options = Options {
+            require_pop_af: true,
+            ..options
+        };
+        let (_, results, summary) = count(&testdata("ex2_pop_af.vcf"), 0, &options);
+        assert_eq!(total(&results), 2);
+        assert_eq!(summary.skipped(summary::Reason::MissingPopulationAf), 2);
+    }
+
+    #[test]
+    fn test_min_dp() {
+        let (signatures, mut results, _) =
+            count(&testdata("ex2_depth.vcf"), 0, &Options::default());
+        let total = |results: &mut result::ResultMatrix, sample| -> u64 {
+            (0..signatures.len()).map(|v| results.get(v, sample)).sum()
+        };
+        assert_eq!(total(&mut results, 0), 3);
+        assert_eq!(total(&mut results, 1), 4);
+
+        // Only the low-depth (or missing depth) genotypes are not counted
+        let options = Options {
+            min_dp: Some(10),
+            ..Options::default()
+        };
+        let (_, mut results, summary) = count(&testdata("ex2_depth.vcf"), 0, &options);
+        assert_eq!(total(&mut results, 0), 2);
+        assert_eq!(total(&mut results, 1), 3);
+        assert_eq!(summary.masked(summary::Reason::LowDepth), 2);
+    }
+
+    #[test]
+    fn test_sample_pass_only() {
+        let path = testdata("ex2_sample_filter.vcf");
+        let totals = |options: &Options| {
+            let (signatures, results, summary) = count(&path, 0, options);
+            let total =
+                |sample| -> u64 { (0..signatures.len()).map(|v| results.get(v, sample)).sum() };
+            (
+                total(0),
+                total(1),
+                summary.masked(summary::Reason::SampleFiltered),
+            )
+        };
+        assert_eq!(totals(&Options::default()), (3, 4, 0));
+        // 1:2 fails for Example2 only, 1:5 for Example1 only, 1:6 lacks FT
+        let options = Options {
+            sample_pass_only: true,
+            ..Options::default()
+        };
+        assert_eq!(totals(&options), (2, 2, 2));
+    }
+
+    #[test]
+    fn test_min_gq() {
+        let total = |results: &mut result::ResultMatrix, sample| -> u64 {
+            (0..signature::Signatures::new(0, signature::DEFAULT_MAX_CHANNELS)
+                .unwrap()
+                .len())
+                .map(|v| results.get(v, sample))
+                .sum()
+        };
+        let options = Options {
+            min_gq: Some(20),
+            ..Options::default()
+        };
+        let (_, mut results, summary) = count(&testdata("ex2_gq.vcf"), 0, &options);
+        assert_eq!(total(&mut results, 0), 1);
+        assert_eq!(total(&mut results, 1), 1);
+        assert_eq!(summary.masked(summary::Reason::LowGq), 2);
+
+        // Genotypes without GQ can be kept, the one failing GQ is still masked
+        let options = Options {
+            min_gq: Some(20),
+            keep_missing_gq: true,
+            ..Options::default()
+        };
+        let (_, mut results, summary) = count(&testdata("ex2_gq.vcf"), 0, &options);
+        assert_eq!(total(&mut results, 0), 2);
+        assert_eq!(total(&mut results, 1), 1);
+        assert_eq!(summary.masked(summary::Reason::LowGq), 1);
+        assert_eq!(summary.masked(summary::Reason::LowDepth), 0);
+    }
+
+    #[test]
+    fn test_min_vaf() {
+        let (signatures, _, _) = count(&testdata("ex2_vaf.vcf"), 0, &Options::default());
+        let index = |alternative| {
+            signatures
+                .index_of(&signature::Signature::new("C", 'C', alternative))
+                .unwrap()
+        };
+        let total = |results: &mut result::ResultMatrix, sample| -> u64 {
+            (0..signatures.len()).map(|v| results.get(v, sample)).sum()
+        };
+
+        // In the tri-allelic record, A passes (8/19) while G fails (1/19)
+        let options = Options {
+            min_vaf: Some(0.1),
+            keep_missing_ad: true,
+            ..Options::default()
+        };
+        let (_, mut results, summary) = count(&testdata("ex2_vaf.vcf"), 0, &options);
+        assert_eq!(results.get(index('A'), 0), 1);
+        assert_eq!(results.get(index('G'), 0), 0);
+        assert_eq!(total(&mut results, 0), 2);
+        assert_eq!(total(&mut results, 1), 2);
+        assert_eq!(summary.masked(summary::Reason::LowVaf), 1);
+
+        // Missing AD and zero depth are dropped on request
+        let options = Options {
+            min_vaf: Some(0.1),
+            ..Options::default()
+        };
+        let (_, mut results, summary) = count(&testdata("ex2_vaf.vcf"), 0, &options);
+        assert_eq!(total(&mut results, 0), 1);
+        assert_eq!(total(&mut results, 1), 1);
+        assert_eq!(summary.masked(summary::Reason::LowVaf), 1);
+        assert_eq!(summary.masked(summary::Reason::MissingAd), 2);
+    }
+
+    #[test]
+    fn test_weight_by_vaf() {
+        let (signatures, _, _) = count(&testdata("ex2_vaf_weight.vcf"), 0, &Options::default());
+        let index = |reference: char, alternative: char| {
+            signatures
+                .index_of(&signature::Signature::new(
+                    reference.to_string(),
+                    reference,
+                    alternative,
+                ))
+                .unwrap()
+        };
+        // The C>A het at 1:2 has a VAF of 0.5, the T>C hom at 1:4 of 1.0 and the G>T het
+        // at 1:5 (a C>A) has no AD
+        let options = Options {
+            missing_vaf_weight: Some(1.0),
+            ..Options::default()
+        };
+        let (_, results, _) = count(&testdata("ex2_vaf_weight.vcf"), 0, &options);
+        assert!(results.is_weighted());
+        assert_eq!(results.weight(index('C', 'A'), 0), 1.5);
+        assert_eq!(results.weight(index('T', 'C'), 0), 2.0);
+        assert_eq!(results.get(index('C', 'A'), 0), 2);
+
+        let options = Options {
+            missing_vaf_weight: Some(0.0),
+            count_mode: CountMode::Site,
+            ..Options::default()
+        };
+        let (_, results, _) = count(&testdata("ex2_vaf_weight.vcf"), 0, &options);
+        assert_eq!(results.weight(index('C', 'A'), 0), 0.5);
+        assert_eq!(results.weight(index('T', 'C'), 0), 1.0);
+    }
+
+    #[test]
+    fn test_vaf_window() {
+        let total = |results: &mut result::ResultMatrix, sample| -> u64 {
+            (0..signature::Signatures::new(0, signature::DEFAULT_MAX_CHANNELS)
+                .unwrap()
+                .len())
+                .map(|v| results.get(v, sample))
+                .sum()
+        };
+        // The samples have a VAF of 0.1, 0.4 and 0.9
+        let options = Options {
+            min_vaf: Some(0.2),
+            max_vaf: Some(0.5),
+            ..Options::default()
+        };
+        let (_, mut results, summary) = count(&testdata("ex2_vaf_window.vcf"), 0, &options);
+        assert_eq!(total(&mut results, 0), 0);
+        assert_eq!(total(&mut results, 1), 1);
+        assert_eq!(total(&mut results, 2), 0);
+        assert_eq!(summary.masked(summary::Reason::LowVaf), 1);
+        assert_eq!(summary.masked(summary::Reason::HighVaf), 1);
+
+        let options = Options {
+            max_vaf: Some(0.5),
+            ..Options::default()
+        };
+        let (_, mut results, summary) = count(&testdata("ex2_vaf_window.vcf"), 0, &options);
+        assert_eq!(total(&mut results, 0), 1);
+        assert_eq!(total(&mut results, 1), 1);
+        assert_eq!(total(&mut results, 2), 0);
+        assert_eq!(summary.masked(summary::Reason::HighVaf), 1);
+    }
+
+    #[test]
+    fn test_biallelic_only() {
+        let options = Options {
+            max_alt_alleles: Some(1),
+            ..Options::default()
+        };
+        let (signatures, results, summary) = count(&testdata("ex2_vaf.vcf"), 0, &options);
+        // Neither alternative allele of the tri-allelic record is counted
+        let total: u64 = (0..signatures.len()).map(|v| results.get(v, 0)).sum();
+        assert_eq!(total, 1);
+        assert_eq!(summary.skipped(summary::Reason::Multiallelic), 1);
+
+        let options = Options {
+            max_alt_alleles: Some(2),
+            ..Options::default()
+        };
+        let (_, _, summary) = count(&testdata("ex2_vaf.vcf"), 0, &options);
+        assert_eq!(summary.skipped(summary::Reason::Multiallelic), 0);
+    }
+
+    #[test]
+    fn test_require_complete_genotypes() {
+        let options = Options {
+            require_complete_genotypes: true,
+            ..Options::default()
+        };
+        let (signatures, results, summary) = count(&testdata("ex2_missing_gt.vcf"), 0, &options);
+        let total: u64 = (0..signatures.len()).map(|v| results.get(v, 0)).sum();
+        assert_eq!(total, 1);
+        assert_eq!(summary.skipped(summary::Reason::IncompleteGenotypes), 2);
+
+        // Missing genotypes of samples that are not selected do not matter
+        let reference = reference::Reference::new(testdata("ex2.fa"), 0).unwrap();
+        let mut variants = open_vcf(&testdata("ex2_missing_gt.vcf"), 1).unwrap();
+        let mut summary = summary::Summary::new();
+        let results = count_variants(
+            &mut variants,
+            &reference,
+            &signatures,
+            &[0, 2],
+            &options,
+            1,
+            &mut summary,
+            None,
+        )
+        .unwrap();
+        let total: u64 = (0..signatures.len()).map(|v| results.get(v, 0)).sum();
+        assert_eq!(total, 3);
+        assert_eq!(summary.skipped(summary::Reason::IncompleteGenotypes), 0);
+    }
+
+    #[test]
+    fn test_skip_missing_genotypes() {
+        let total = |results: &mut result::ResultMatrix, sample| -> u64 {
+            (0..signature::Signatures::new(0, signature::DEFAULT_MAX_CHANNELS)
+                .unwrap()
+                .len())
+                .map(|v| results.get(v, sample))
+                .sum()
+        };
+        // The samples are genotyped ./., ./1 and 1/1
+        let (_, mut results, _) = count(&testdata("ex2_half_calls.vcf"), 0, &Options::default());
+        assert_eq!(total(&mut results, 0), 0);
+        assert_eq!(total(&mut results, 1), 1);
+        assert_eq!(total(&mut results, 2), 2);
+
+        let options = Options {
+            skip_missing_genotypes: true,
+            ..Options::default()
+        };
+        let (_, mut results, summary) = count(&testdata("ex2_half_calls.vcf"), 0, &options);
+        assert_eq!(total(&mut results, 0), 0);
+        assert_eq!(total(&mut results, 1), 0);
+        assert_eq!(total(&mut results, 2), 2);
+        assert_eq!(summary.masked(summary::Reason::MissingGenotype), 2);
+    }
+
+    #[test]
+    fn test_min_alt_reads() {
+        let total = |results: &mut result::ResultMatrix, sample| -> u64 {
+            (0..signature::Signatures::new(0, signature::DEFAULT_MAX_CHANNELS)
+                .unwrap()
+                .len())
+                .map(|v| results.get(v, sample))
+                .sum()
+        };
+        // The C>A allele is supported by 2 reads in Example1 and 10 in Example2
+        let options = Options {
+            min_alt_reads: Some(4),
+            keep_missing_ad: true,
+            ..Options::default()
+        };
+        let (_, mut results, summary) = count(&testdata("ex2_alt_reads.vcf"), 0, &options);
+        assert_eq!(total(&mut results, 0), 1);
+        assert_eq!(total(&mut results, 1), 2);
+        assert_eq!(summary.masked(summary::Reason::LowAltReads), 1);
+
+        let options = Options {
+            keep_missing_ad: false,
+            ..options
+        };
+        let (_, mut results, summary) = count(&testdata("ex2_alt_reads.vcf"), 0, &options);
+        assert_eq!(total(&mut results, 0), 0);
+        assert_eq!(summary.masked(summary::Reason::MissingAd), 1);
+
+        // Combined with the VAF filter, both must pass (Example2 has a VAF of 1/3 at 1:2)
+        let options = Options {
+            min_alt_reads: Some(4),
+            min_vaf: Some(0.4),
+            keep_missing_ad: true,
+            ..Options::default()
+        };
+        let (_, mut results, summary) = count(&testdata("ex2_alt_reads.vcf"), 0, &options);
+        assert_eq!(total(&mut results, 0), 1);
+        assert_eq!(total(&mut results, 1), 1);
+        assert_eq!(summary.masked(summary::Reason::LowVaf), 2);
+        assert_eq!(summary.masked(summary::Reason::LowAltReads), 0);
+    }
+
+    #[test]
+    fn test_count_mode() {
+        let signatures = signature::Signatures::new(0, signature::DEFAULT_MAX_CHANNELS).unwrap();
+        let index = |alternative| {
+            signatures
+                .index_of(&signature::Signature::new("C", 'C', alternative))
+                .unwrap()
+        };
+        // The samples are genotyped 1/2, 1/1 and 0/1 for the alleles C>A and C>G
+        let (_, results, _) = count(&testdata("ex2_zygosity.vcf"), 0, &Options::default());
+        assert_eq!(results.get(index('A'), 0), 1);
+        assert_eq!(results.get(index('G'), 0), 1);
+        assert_eq!(results.get(index('A'), 1), 2);
+        assert_eq!(results.get(index('A'), 2), 1);
+
+        let options = Options {
+            count_mode: CountMode::Site,
+            ..Options::default()
+        };
+        let (_, results, _) = count(&testdata("ex2_zygosity.vcf"), 0, &options);
+        assert_eq!(results.get(index('A'), 0), 1);
+        assert_eq!(results.get(index('G'), 0), 1);
+        assert_eq!(results.get(index('A'), 1), 1);
+        assert_eq!(results.get(index('G'), 1), 0);
+        assert_eq!(results.get(index('A'), 2), 1);
+    }
+
+    #[test]
+    fn test_metrics_of_counts() {
+        let reference = reference::Reference::new(testdata("ex2.fa"), 1).unwrap();
+        let signatures = signature::Signatures::new(1, signature::DEFAULT_MAX_CHANNELS).unwrap();
+        let mut variants = open_vcf(&testdata("ex2.vcf"), 1).unwrap();
+        let results = count_variants(
+            &mut variants,
+            &reference,
+            &signatures,
+            &[0, 1],
+            &Options::default(),
+            1,
+            &mut summary::Summary::new(),
+            None,
+        )
+        .unwrap();
+        let names = vec!["S1".to_owned(), "S2".to_owned()];
+        // The counts are read back in both label styles
+        for cosmic_order in &[false, true] {
+            let rows = sbs_rows(
+                &signatures,
+                *cosmic_order,
+                signature::Orientation::Pyrimidine,
+            );
+            let mut out = Vec::new();
+            write_counts(&mut out, &names, &rows, &results).unwrap();
+            let counts = metrics::read_counts_from(out.as_slice(), "test").unwrap();
+            let metrics = metrics::metrics(&counts).unwrap();
+            for (s, m) in metrics.iter().enumerate() {
+                let total: u64 = rows.iter().map(|(_, i)| results.get(*i, s)).sum();
+                assert_eq!(m.total, total as f64);
+                assert_eq!(m.transitions + m.transversions, m.total);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparse_counts() {
+        let write = |vcf: &str, threads: usize, count_mode: CountMode, sparse: bool| {
+            let mut options = CountOptions::new(testdata(vcf), testdata("ex2.fa"));
+            options.window = 1;
+            options.threads = threads;
+            options.classification.count_mode = count_mode;
+            options.classification.sparse = sparse;
+            let counts = count_vcf(&options).unwrap();
+            assert_eq!(counts.results.is_sparse(), sparse);
+            let rows = sbs_rows(
+                &counts.signatures,
+                false,
+                signature::Orientation::Pyrimidine,
+            );
+            let mut out = Vec::new();
+            write_counts(&mut out, &counts.samples, &rows, &counts.results).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        // Counted in order, weighted, and by contig in parallel
+        for (vcf, threads, count_mode) in [
+            ("ex2.vcf", 1, CountMode::Haplotype),
+            ("ex2.vcf", 2, CountMode::GenotypeDosage),
+            ("ex2_indexed.vcf.gz", 3, CountMode::Haplotype),
+        ] {
+            let dense = write(vcf, threads, count_mode, false);
+            assert_eq!(write(vcf, threads, count_mode, true), dense);
+            assert!(dense
+                .lines()
+                .skip(1)
+                .any(|l| l.split('\t').skip(1).any(|c| c != "0")));
+        }
+    }
+
+    #[test]
+    fn test_mito_mode() {
+        let options = Options {
+            mito: Some(mito::Mito::new(
+                vec!["2".to_owned()].into_iter().collect(),
+                Some((1, 1)),
+                0,
+            )),
+            ..Options::default()
+        };
+        let (signatures, results, summary) = count(&testdata("ex2_mito.vcf"), 0, &options);
+        let offset = signatures.len();
+        let mito_index = |r: char, a: char| {
+            let mito = options.mito.as_ref().unwrap();
+            offset
+                + mito
+                    .signatures()
+                    .index_of(&signature::Signature::new(r.to_string(), r, a))
+                    .unwrap()
+        };
+        let c_t = signatures
+            .index_of(&signature::Signature::new("C", 'C', 'T'))
+            .unwrap();
+        // The G>A at 1:5 is folded into C>T, the one at 2:5 is not
+        assert_eq!(results.get(c_t, 0), 1);
+        assert_eq!(results.get(mito_index('G', 'A'), 0), 1);
+        assert_eq!(results.get(mito_index('C', 'T'), 0), 0);
+        // The T>C at 2:1 is in the control region
+        assert_eq!(results.get(mito_index('T', 'C'), 0), 0);
+        assert_eq!(summary.skipped(summary::Reason::ExcludedRegions), 1);
+    }
+
+    #[test]
+    fn test_mito_partial_context() {
+        // The C>A at 1:2 has an N in its window, which has no mitochondrial channel
+        let mut options = CountOptions::new(testdata("ex2.vcf"), testdata("ex2_ambiguous.fa"));
+        options.window = 1;
+        options.classification.count_partial_context = true;
+        options.classification.mito = Some(mito::Mito::new(
+            vec!["1".to_owned()].into_iter().collect(),
+            None,
+            1,
+        ));
+        let e = count_vcf(&options).err().unwrap();
+        assert!(matches!(e, Error::InvalidOptions(_)), "{}", e);
+
+        // Counted anyway, the substitution is skipped instead of failing
+        let reference = reference::Reference::new(testdata("ex2_ambiguous.fa"), 1).unwrap();
+        let signatures = signature::Signatures::with_partial_context(1);
+        let mut variants = open_vcf(&testdata("ex2.vcf"), 1).unwrap();
+        let mut summary = summary::Summary::new();
+        let results = count_variants(
+            &mut variants,
+            &reference,
+            &signatures,
+            &[0, 1],
+            &options.classification,
+            1,
+            &mut summary,
+            None,
+        )
+        .unwrap();
+        assert_eq!(summary.ignored_records(), 1);
+        // The G>T at 1:5 is counted once for the 0/1 and twice for the 1/1 genotype
+        let total: u64 = results.iter_cells().map(|(_, _, n)| n).sum();
+        assert_eq!(total, 3);
+    }
+
+    #[test]
+    fn test_dosage() {
+        let signatures = signature::Signatures::new(0, signature::DEFAULT_MAX_CHANNELS).unwrap();
+        let c_a = signatures
+            .index_of(&signature::Signature::new("C", 'C', 'A'))
+            .unwrap();
+        let c_g = signatures
+            .index_of(&signature::Signature::new("C", 'C', 'G'))
+            .unwrap();
+        // The samples are genotyped 0/1, 1/1, 1/2 and ./1 for the alleles C>A and C>G
+        let weights = |count_mode: CountMode| {
+            let options = Options {
+                count_mode,
+                ..Options::default()
+            };
+            let (_, results, _) = count(&testdata("ex2_dosage.vcf"), 0, &options);
+            (0..4)
+                .map(|s| (results.weight(c_a, s), results.weight(c_g, s)))
+                .collect::<Vec<(f64, f64)>>()
+        };
+        assert_eq!(
+            weights(CountMode::Haplotype),
+            vec![(1.0, 0.0), (2.0, 0.0), (1.0, 1.0), (1.0, 0.0)]
+        );
+        assert_eq!(
+            weights(CountMode::Site),
+            vec![(1.0, 0.0), (1.0, 0.0), (1.0, 1.0), (1.0, 0.0)]
+        );
+        assert_eq!(
+            weights(CountMode::GenotypeDosage),
+            vec![(1.0, 0.0), (2.0, 0.0), (1.0, 1.0), (1.0, 0.0)]
+        );
+        // The dosage is a weight, the mutation of the 1/1 is counted once
+        let options = Options {
+            count_mode: CountMode::GenotypeDosage,
+            ..Options::default()
+        };
+        let (_, results, _) = count(&testdata("ex2_dosage.vcf"), 0, &options);
+        assert!(results.is_weighted());
+        assert_eq!(results.get(c_a, 1), 1);
+    }
+
+    #[test]
+    fn test_min_distance() {
+        let total = |options: &Options| -> (u64, u64, u64) {
+            let (signatures, results, summary) = count(&testdata("ex2_clustered.vcf"), 0, options);
+            (
+                (0..signatures.len()).map(|v| results.get(v, 0)).sum(),
+                (0..signatures.len()).map(|v| results.get(v, 1)).sum(),
+                summary.masked(summary::Reason::Clustered),
+            )
+        };
+        // Example1 has mutations at 1:2, 1:3 and 1:5, and 2:2
+        let options = Options {
+            min_distance: Some(2),
+            ..Options::default()
+        };
+        assert_eq!(total(&options), (3, 2, 1));
+        let options = Options {
+            drop_both: true,
+            ..options
+        };
+        assert_eq!(total(&options), (2, 2, 2));
+
+        // The trio spans the threshold: 1:5 is within 3 bases of 1:3, but not of 1:2
+        let options = Options {
+            min_distance: Some(3),
+            ..Options::default()
+        };
+        assert_eq!(total(&options), (3, 2, 1));
+        let options = Options {
+            drop_both: true,
+            ..options
+        };
+        assert_eq!(total(&options), (1, 2, 3));
+    }
+
+    #[test]
+    fn test_region_labels() {
+        let named = intervals::read_named_bed(testdata("ex2_regions.bed")).unwrap();
+        let options = Options {
+            region_labels: Some(
+                intervals::LabeledIntervals::new(named, &["promoter".to_owned()]).unwrap(),
+            ),
+            ..Options::default()
+        };
+        let (signatures, results, _) = count(&testdata("ex2_strand.vcf"), 0, &options);
+        let n_channels = signatures.len();
+        let total = |block: usize| -> u64 {
+            (0..n_channels)
+                .map(|v| results.get(block * n_channels + v, 0))
+                .sum()
+        };
+        // The promoter at 1:2 is nested in the exon at 1:1-4, 1:6 is unassigned
+        assert_eq!(total(0), 1);
+        assert_eq!(total(1), 1);
+        assert_eq!(total(2), 1);
+        assert_eq!(total(3), 1);
+    }
+
+    #[test]
+    fn test_clustered_channels() {
+        let options = Options {
+            clustered_cutoff: Some(2),
+            ..Options::default()
+        };
+        let (signatures, results, _) = count(&testdata("ex2_clustered.vcf"), 0, &options);
+        let (_, unsplit, _) = count(&testdata("ex2_clustered.vcf"), 0, &Options::default());
+        let n_channels = signatures.len();
+        for sample in 0..2 {
+            let clustered: u64 = (0..n_channels).map(|v| results.get(v, sample)).sum();
+            let nonclustered: u64 = (0..n_channels)
+                .map(|v| results.get(n_channels + v, sample))
+                .sum();
+            let total: u64 = (0..n_channels).map(|v| unsplit.get(v, sample)).sum();
+            assert_eq!(clustered + nonclustered, total);
+            // Example1 has the close pair 1:2 and 1:3 besides 1:5 and 2:2
+            if sample == 0 {
+                assert_eq!((clustered, nonclustered), (2, 2));
+            }
+            // The channels are preserved within the strata
+            for v in 0..n_channels {
+                assert_eq!(
+                    results.get(v, sample) + results.get(n_channels + v, sample),
+                    unsplit.get(v, sample)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_downsample() {
+        let matrix = |results: &mut result::ResultMatrix, sample| -> Vec<u64> {
+            (0..signature::Signatures::new(0, signature::DEFAULT_MAX_CHANNELS)
+                .unwrap()
+                .len())
+                .map(|v| results.get(v, sample))
+                .collect()
+        };
+        // Example1 carries 5 and Example2 6 mutations
+        let (_, mut original, _) = count(&testdata("ex2_filter.vcf"), 0, &Options::default());
+        let options = Options {
+            downsample: Some((5, 42)),
+            ..Options::default()
+        };
+        let (_, mut first, summary) = count(&testdata("ex2_filter.vcf"), 0, &options);
+        let (_, mut second, _) = count(&testdata("ex2_filter.vcf"), 0, &options);
+        assert_eq!(summary.downsampling(), &[(5, 5), (6, 5)]);
+
+        // The sample is reproducible for the same seed
+        assert_eq!(matrix(&mut first, 0), matrix(&mut second, 0));
+        assert_eq!(matrix(&mut first, 1), matrix(&mut second, 1));
+
+        // Samples not above the size are untouched
+        assert_eq!(matrix(&mut first, 0), matrix(&mut original, 0));
+        assert_eq!(matrix(&mut first, 1).iter().sum::<u64>(), 5);
+    }
+
+    #[test]
+    fn test_skip_softmasked() {
+        let total = |window_size: u8, options: &Options| -> (u64, u64) {
+            let reference =
+                reference::Reference::new(testdata("ex2_softmasked.fa"), window_size).unwrap();
+            let signatures =
+                signature::Signatures::new(window_size.into(), signature::DEFAULT_MAX_CHANNELS)
+                    .unwrap();
+            let mut variants = open_vcf(&testdata("ex2_filter.vcf"), 1).unwrap();
+            let mut summary = summary::Summary::new();
+            let results = count_variants(
+                &mut variants,
+                &reference,
+                &signatures,
+                &[0, 1],
+                options,
+                1,
+                &mut summary,
+                None,
+            )
+            .unwrap();
+            (
+                (0..signatures.len()).map(|v| results.get(v, 0)).sum(),
+                summary.skipped(summary::Reason::Softmasked),
+            )
+        };
+        // 1:3-4 are soft-masked, the classification is not affected by default
+        assert_eq!(total(0, &Options::default()), (5, 0));
+        let options = Options {
+            skip_softmasked: true,
+            ..Options::default()
+        };
+        assert_eq!(total(0, &options), (3, 2));
+        // With a window, the neighbors 1:2 and 1:5 are affected too
+        assert_eq!(total(1, &options), (1, 4));
+    }
+
+    #[test]
+    fn test_orientation() {
+        let (signatures, results, _) = count(&testdata("ex2.vcf"), 1, &Options::default());
+        let rows = |orientation: signature::Orientation| {
+            sbs_rows(&signatures, false, orientation)
+                .into_iter()
+                .map(|(label, index)| (label, index, results.get(index, 0)))
+                .collect::<Vec<(String, usize, u64)>>()
+        };
+        let pyrimidine = rows(signature::Orientation::Pyrimidine);
+        let purine = rows(signature::Orientation::Purine);
+        assert_eq!(pyrimidine.len(), 96);
+        assert_eq!(purine.len(), 96);
+        assert!(pyrimidine.iter().any(|(label, _, _)| label == "TCA>A"));
+        assert!(purine.iter().any(|(label, _, _)| label == "TGA>T"));
+        assert!(purine
+            .iter()
+            .all(|(label, _, _)| label.as_bytes()[1] == b'A' || label.as_bytes()[1] == b'G'));
+        // The counts are identical, only the labels differ
+        let counts = |rows: &[(String, usize, u64)]| {
+            let mut c: Vec<(usize, u64)> = rows.iter().map(|(_, i, n)| (*i, *n)).collect();
+            c.sort_unstable();
+            c
+        };
+        assert_eq!(counts(&pyrimidine), counts(&purine));
+        assert!(pyrimidine.iter().any(|(_, _, n)| *n > 0));
+    }
+
+    #[test]
+    fn test_stratify_cpg() {
+        let options = Options {
+            scheme: Scheme::SbsCpg,
+            ..Options::default()
+        };
+        for window_size in 0..2 {
+            let reference = reference::Reference::new(testdata("ex2_cpg.fa"), window_size).unwrap();
+            let signatures =
+                signature::Signatures::new(window_size.into(), signature::DEFAULT_MAX_CHANNELS)
+                    .unwrap();
+            let mut variants = open_vcf(&testdata("ex2_cpg.vcf"), 1).unwrap();
+            let mut summary = summary::Summary::new();
+            let results = count_variants(
+                &mut variants,
+                &reference,
+                &signatures,
+                &[0],
+                &options,
+                1,
+                &mut summary,
+                None,
+            )
+            .unwrap();
+            let n_signatures = signatures.channel_count();
+            let get = |block: usize, codon: &str| {
+                let s = signature::Signature::parse(codon).unwrap();
+                results.get(block * n_signatures + signatures.index_of(&s).unwrap(), 0)
+            };
+            if window_size == 0 {
+                // The C>T at the CpG on 1:3 and the G>A of the CpG on 1:4 (a C>T on the
+                // reverse strand) are separated from the C>T at the CpA on 1:5
+                assert_eq!(get(1, "C>T"), 2);
+                assert_eq!(get(0, "C>T"), 1);
+            } else {
+                assert_eq!(get(1, "ACG>T"), 1);
+                assert_eq!(get(1, "GCG>T"), 1);
+                assert_eq!(get(0, "GCA>T"), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_channels() {
+        let definition = signature::read_definition(testdata("ex2_channels.tsv")).unwrap();
+        assert!(signature::Signatures::from_definition(0, &definition, false).is_err());
+        let signatures = signature::Signatures::from_definition(0, &definition, true).unwrap();
+        let rows = sbs_rows(&signatures, false, signature::Orientation::Pyrimidine);
+        assert_eq!(
+            rows,
+            vec![
+                ("C>A".to_owned(), 0),
+                ("T>C".to_owned(), 1),
+                (signature::OTHER_CHANNEL.to_owned(), 2)
+            ]
+        );
+
+        let reference = reference::Reference::new(testdata("ex2.fa"), 0).unwrap();
+        let mut variants = open_vcf(&testdata("ex2_strand.vcf"), 1).unwrap();
+        let mut summary = summary::Summary::new();
+        let results = count_variants(
+            &mut variants,
+            &reference,
+            &signatures,
+            &[0],
+            &Options::default(),
+            1,
+            &mut summary,
+            None,
+        )
+        .unwrap();
+        // The G>T and A>G count as their reverse complements C>A and T>C
+        assert_eq!(results.get(0, 0), 2);
+        assert_eq!(results.get(1, 0), 2);
+        assert_eq!(results.get(2, 0), 0);
+    }
+
+    #[test]
+    fn test_ambiguous_context() {
+        let count = |window_size: u8, options: &Options| {
+            let reference =
+                reference::Reference::new(testdata("ex2_ambiguous.fa"), window_size).unwrap();
+            let signatures = if options.count_partial_context {
+                signature::Signatures::with_partial_context(window_size.into())
+            } else {
+                signature::Signatures::new(window_size.into(), signature::DEFAULT_MAX_CHANNELS)
+                    .unwrap()
+            };
+            let mut variants = open_vcf(&testdata("ex2.vcf"), 1).unwrap();
+            let mut summary = summary::Summary::new();
+            let results = count_variants(
+                &mut variants,
+                &reference,
+                &signatures,
+                &[0, 1],
+                options,
+                1,
+                &mut summary,
+                None,
+            )
+            .unwrap();
+            (signatures, results, summary)
+        };
+        // The N at 1:1 is adjacent to the C>A at 1:2, the G>T at 1:5 is unaffected
+        assert_eq!(
+            count(0, &Options::default())
+                .2
+                .skipped(summary::Reason::AmbiguousContext),
+            0
+        );
+        let (signatures, results, summary) = count(1, &Options::default());
+        assert_eq!(summary.skipped(summary::Reason::AmbiguousContext), 1);
+        let total: u64 = (0..signatures.len()).map(|v| results.get(v, 0)).sum();
+        assert_eq!(total, 1);
+
+        let options = Options {
+            count_partial_context: true,
+            ..Options::default()
+        };
+        let (signatures, results, summary) = count(1, &options);
+        assert_eq!(summary.skipped(summary::Reason::AmbiguousContext), 0);
+        assert_eq!(summary.partial_contexts(), 1);
+        let c_a = signatures
+            .index_of(&signature::Signature::new("C", 'C', 'A'))
+            .unwrap();
+        assert_eq!(results.get(c_a, 0), 1);
+        assert_eq!(results.get(c_a, 1), 2);
+        let t_g_a = signatures
+            .index_of(&signature::Signature::new("TGA", 'G', 'T'))
+            .unwrap();
+        assert_eq!(results.get(t_g_a, 0), 1);
+    }
+
+    #[test]
+    fn test_unused_allele_context() {
+        let reference = reference::Reference::new(testdata("ex2_ambiguous.fa"), 1).unwrap();
+        let signatures = signature::Signatures::new(1, signature::DEFAULT_MAX_CHANNELS).unwrap();
+        let options = Options {
+            decompose_mnv: true,
+            ..Options::default()
+        };
+        let mut variants = open_vcf(&testdata("ex2_unused_allele.vcf"), 1).unwrap();
+        let mut summary = summary::Summary::new();
+        let results = count_variants(
+            &mut variants,
+            &reference,
+            &signatures,
+            &[0, 1],
+            &options,
+            1,
+            &mut summary,
+            None,
+        )
+        .unwrap();
+        // The C>T next to the N at 1:1 is only carried by the second record
+        assert_eq!(summary.skipped(summary::Reason::AmbiguousContext), 1);
+        let c_a_t = signatures
+            .index_of(&signature::Signature::new("CAT", 'A', 'G'))
+            .unwrap();
+        assert_eq!(results.get(c_a_t, 0), 1);
+        assert_eq!(results.get(c_a_t, 1), 0);
+    }
+
+    #[test]
+    fn test_truncated_context() {
+        let count = |window_size: u8| {
+            let reference = reference::Reference::new(testdata("ex2.fa"), window_size).unwrap();
+            let signatures =
+                signature::Signatures::new(window_size.into(), signature::DEFAULT_MAX_CHANNELS)
+                    .unwrap();
+            let mut variants = open_vcf(&testdata("ex2_edges.vcf"), 1).unwrap();
+            let mut summary = summary::Summary::new();
+            let results = count_variants(
+                &mut variants,
+                &reference,
+                &signatures,
+                &[0, 1],
+                &Options::default(),
+                1,
+                &mut summary,
+                None,
+            )
+            .unwrap();
+            (results, summary)
+        };
+        // The variants at the first and last base of contig 1 lack a flanking base
+        let (results, summary) = count(1);
+        assert_eq!(summary.skipped(summary::Reason::TruncatedContext), 2);
+        assert_eq!(results.iter_cells().map(|(_, _, n)| n).sum::<u64>(), 2);
+        let (results, summary) = count(0);
+        assert_eq!(summary.skipped(summary::Reason::TruncatedContext), 0);
+        assert_eq!(results.iter_cells().map(|(_, _, n)| n).sum::<u64>(), 4);
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let path = testdata("ex2_duplicates.vcf");
+        let total = |options: &Options| {
+            let (signatures, results, summary) = count(&path, 0, options);
+            let total: u64 = (0..signatures.len())
+                .map(|v| results.get(v, 0) + results.get(v, 1))
+                .sum();
+            (total, summary.skipped(summary::Reason::Duplicate))
+        };
+        // Without deduplication, both duplicates are counted
+        assert_eq!(total(&Options::default()), (10, 0));
+        let options = Options {
+            dedupe: Some(DedupeMode::First),
+            ..Options::default()
+        };
+        assert_eq!(total(&options), (6, 2));
+
+        let reference = reference::Reference::new(testdata("ex2.fa"), 0).unwrap();
+        let signatures = signature::Signatures::new(0, signature::DEFAULT_MAX_CHANNELS).unwrap();
+        let mut variants = open_vcf(&path, 1).unwrap();
+        let options = Options {
+            dedupe: Some(DedupeMode::Error),
+            ..Options::default()
+        };
+        let result = count_variants(
+            &mut variants,
+            &reference,
+            &signatures,
+            &[0, 1],
+            &options,
+            1,
+            &mut summary::Summary::new(),
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(Error::ConflictingDuplicates { contig, position: 5 }) if contig == "1"
+        ));
+    }
+
+    #[test]
+    fn test_chromosomes() {
+        let path = testdata("ex2_contigs.vcf");
+        let total = |options: &Options| {
+            let (signatures, results, summary) = count(&path, 0, options);
+            let total: u64 = (0..signatures.len()).map(|v| results.get(v, 0)).sum();
+            (total, summary.skipped(summary::Reason::OtherContig))
+        };
+        let chromosomes = |c: &[&str]| Some(c.iter().map(|c| c.to_string()).collect());
+        // The decoy and MT contigs are not in the reference, but skipped before fetching
+        let options = Options {
+            chromosomes: chromosomes(&["1"]),
+            ..Options::default()
+        };
+        assert_eq!(total(&options), (1, 3));
+        let options = Options {
+            primary_only: true,
+            ..Options::default()
+        };
+        assert_eq!(total(&options), (2, 2));
+        let options = Options {
+            chromosomes: chromosomes(&["2", "MT"]),
+            primary_only: true,
+            ..Options::default()
+        };
+        assert_eq!(total(&options), (1, 3));
+    }
+
+    #[test]
+    fn test_unknown_contigs() {
+        // The decoy and MT contigs of the header are not in the reference
+        let mut options = CountOptions::new(testdata("ex2_contigs.vcf"), testdata("ex2.fa"));
+        let e = count_vcf(&options).err().unwrap();
+        assert_eq!(
+            e.to_string(),
+            "Can not find sequences 'chrUn_JTFH01000001v1_decoy', 'MT' in the reference"
+        );
+        assert_eq!(e.exit_code(), 4);
+        options.classification.chromosomes =
+            Some(["1", "MT"].iter().map(|c| c.to_string()).collect());
+        let e = count_vcf(&options).err().unwrap();
+        assert!(
+            matches!(&e, Error::UnknownContig { contig } if contig == "MT"),
+            "{}",
+            e
+        );
+        assert_eq!(e.exit_code(), 4);
+
+        // Unless the contigs are not selected
+        options.classification.chromosomes = None;
+        options.classification.primary_only = true;
+        let counts = count_vcf(&options).unwrap();
+        assert_eq!(counts.summary.records(), 4);
+        assert_eq!(counts.summary.skipped(summary::Reason::OtherContig), 2);
+
+        // Or their records are skipped
+        options.classification.primary_only = false;
+        options.classification.skip_unknown_contigs = true;
+        let counts = count_vcf(&options).unwrap();
+        assert_eq!(counts.summary.records(), 4);
+        assert_eq!(counts.summary.skipped(summary::Reason::UnknownContig), 2);
+        assert_eq!(counts.summary.skipped(summary::Reason::OtherContig), 0);
+        let total: u64 = counts.results.iter_cells().map(|(_, _, n)| n).sum();
+        assert_eq!(total, 2);
+
+        let reference = reference::Reference::new(testdata("ex2.fa"), 0).unwrap();
+        let contigs: Vec<Arc<str>> = vec![Arc::from("1"), Arc::from("X")];
+        let lengths = HashMap::new();
+        let options = Options::default();
+        assert!(check_reference_contigs(&contigs[..1], &lengths, &reference, &options).is_ok());
+        assert!(check_reference_contigs(&contigs, &lengths, &reference, &options).is_err());
+    }
+
+    #[test]
+    fn test_ref_mismatch() {
+        // The reference has an A instead of the G at 2:4
+        let mut options = CountOptions::new(testdata("ex2_ref_mismatch.vcf"), testdata("ex2.fa"));
+        for policy in [RefMismatch::Warn, RefMismatch::Skip] {
+            options.classification.on_ref_mismatch = policy;
+            let counts = count_vcf(&options).unwrap();
+            assert_eq!(counts.summary.records(), 4);
+            assert_eq!(counts.summary.skipped(summary::Reason::RefMismatch), 1);
+            let total: u64 = counts.results.iter_cells().map(|(_, _, n)| n).sum();
+            assert_eq!(total, 3);
+        }
+        options.classification.on_ref_mismatch = RefMismatch::Error;
+        let e = count_vcf(&options).err().unwrap();
+        assert_eq!(
+            e.to_string(),
+            "Can not match the reference allele, the VCF file expects G at position 2:4 but \
+             the reference has A"
+        );
+        assert!(matches!(
+            &e,
+            Error::ReferenceMismatch { contig, pos: 4, .. } if contig == "2"
+        ));
+        assert_eq!(e.exit_code(), 4);
+
+        // Too many mismatches fail even if they are skipped
+        options.classification.on_ref_mismatch = RefMismatch::Warn;
+        options.classification.max_ref_mismatch_fraction = Some(0.25);
+        assert!(count_vcf(&options).is_ok());
+        options.classification.max_ref_mismatch_fraction = Some(0.2);
+        let e = count_vcf(&options).err().unwrap();
+        assert!(
+            matches!(
+                e,
+                Error::ReferenceMismatches {
+                    mismatches: 1,
+                    records: 4,
+                    ..
+                }
+            ),
+            "{}",
+            e
+        );
+        assert_eq!(e.exit_code(), 4);
+    }
+
+    #[test]
+    fn test_uncarried_ref_mismatch() {
+        /// A reference in memory that records the contigs whose bases are fetched.
+        struct FetchLog {
+            inner: reference::MemoryReference,
+            contigs: Arc<std::sync::Mutex<Vec<String>>>,
+        }
+
+        impl ReferenceProvider for FetchLog {
+            fn window_size(&self) -> u8 {
+                self.inner.window_size()
+            }
+
+            fn sequence_names(&self) -> Result<Vec<String>, Error> {
+                self.inner.sequence_names()
+            }
+
+            fn sequence_lengths(&self) -> Result<Vec<(String, u64)>, Error> {
+                self.inner.sequence_lengths()
+            }
+
+            fn fetch_bases(&self, name: &str, start: u64, end: u64) -> Result<String, Error> {
+                self.contigs.lock().unwrap().push(name.to_owned());
+                self.inner.fetch_bases(name, start, end)
+            }
+
+            fn try_clone(&self) -> Result<Self, Error> {
+                Ok(FetchLog {
+                    inner: self.inner.try_clone()?,
+                    contigs: Arc::clone(&self.contigs),
+                })
+            }
+        }
+
+        // The G>A at 2:4, where the reference has an A, is only carried by Example2
+        let count = |samples: &[usize]| {
+            let reference = FetchLog {
+                inner: reference::MemoryReference::new([("1", "TCATGA"), ("2", "TCTAGA")], 0),
+                contigs: Arc::default(),
+            };
+            let signatures =
+                signature::Signatures::new(0, signature::DEFAULT_MAX_CHANNELS).unwrap();
+            let mut variants = open_vcf(&testdata("ex2_uncarried_mismatch.vcf"), 1).unwrap();
+            let mut summary = summary::Summary::new();
+            let results = count_variants(
+                &mut variants,
+                &reference,
+                &signatures,
+                samples,
+                &Options::default(),
+                1,
+                &mut summary,
+                None,
+            )
+            .unwrap();
+            let fetched = reference.contigs.lock().unwrap().clone();
+            (results, summary, fetched)
+        };
+        let (results, summary, fetched) = count(&[0]);
+        assert_eq!(fetched, vec!["1"]);
+        assert_eq!(summary.skipped(summary::Reason::RefMismatch), 0);
+        let total: u64 = results.iter_cells().map(|(_, _, n)| n).sum();
+        assert_eq!(total, 1);
+
+        let (_, summary, fetched) = count(&[0, 1]);
+        assert_eq!(fetched, vec!["1", "2"]);
+        assert_eq!(summary.skipped(summary::Reason::RefMismatch), 1);
+    }
+
+    #[test]
+    fn test_ignored_records() {
+        // Each record but 1:5 and 2:5 is skipped for another reason
+        let mut options =
+            CountOptions::new(testdata("ex2_skipped.vcf"), testdata("ex2_ambiguous.fa"));
+        options.window = 1;
+        options.classification.pass_only = true;
+        options.classification.skip_unknown_contigs = true;
+        let counts = count_vcf(&options).unwrap();
+        let summary = &counts.summary;
+        assert_eq!(summary.records(), 9);
+        assert_eq!(summary.ignored_records(), 7);
+        for reason in [
+            summary::Reason::AmbiguousContext,
+            summary::Reason::NonSnv,
+            summary::Reason::Symbolic,
+            summary::Reason::Filtered,
+            summary::Reason::RefMismatch,
+            summary::Reason::TruncatedContext,
+            summary::Reason::UnknownContig,
+        ] {
+            assert_eq!(summary.skipped(reason), 1, "{}", reason);
+        }
+        assert_eq!(summary.skipped_total(), 7);
+        let total: u64 = counts.results.iter_cells().map(|(_, _, n)| n).sum();
+        assert_eq!(total, 2);
+
+        options.classification.max_ignored_fraction = Some(0.8);
+        assert!(count_vcf(&options).is_ok());
+        options.classification.max_ignored_fraction = Some(0.5);
+        let e = count_vcf(&options).err().unwrap();
+        assert!(
+            matches!(
+                e,
+                Error::TooManyIgnored {
+                    ignored: 7,
+                    records: 9,
+                    ..
+                }
+            ),
+            "{}",
+            e
+        );
+        assert_eq!(e.exit_code(), 5);
+    }
+
+    #[test]
+    fn test_contig_lengths() {
+        // Contig 1 is declared with 7 instead of 6 bases, MT is not in the reference
+        let variants =
+            rust_htslib::bcf::Reader::from_path(testdata("ex2_wrong_length.vcf")).unwrap();
+        let lengths = contig_lengths(variants.header());
+        assert_eq!(lengths.get("1"), Some(&7));
+        assert_eq!(lengths.get("2"), Some(&6));
+        assert_eq!(lengths.get("MT"), None);
+
+        let mut options = CountOptions::new(testdata("ex2_wrong_length.vcf"), testdata("ex2.fa"));
+        let e = count_vcf(&options).err().unwrap();
+        assert_eq!(
+            e.to_string(),
+            "Can not match the contigs of the VCF header to the reference: \
+             '1' has 7 bases in the VCF header but 6 in the reference; \
+             'MT' is not in the reference"
+        );
+        assert_eq!(e.exit_code(), 4);
+        options.classification.skip_unknown_contigs = true;
+        let e = count_vcf(&options).err().unwrap();
+        assert!(
+            matches!(&e, Error::InconsistentContigs { discrepancies } if discrepancies.len() == 1),
+            "{}",
+            e
+        );
+        // Unless the contig is not selected or the check is disabled
+        options.classification.chromosomes = Some(["2"].iter().map(|c| c.to_string()).collect());
+        assert_eq!(count_vcf(&options).unwrap().summary.records(), 3);
+        options.classification.chromosomes = None;
+        options.classification.skip_unknown_contigs = false;
+        options.classification.no_validate_contigs = true;
+        let counts = count_vcf(&options).unwrap();
+        assert_eq!(counts.summary.records(), 3);
+        let total: u64 = counts.results.iter_cells().map(|(_, _, n)| n).sum();
+        assert_eq!(total, 3);
+    }
+
+    #[test]
+    fn test_mixed_ploidy() {
+        let path = testdata("ex2_ploidy.vcf");
+        let totals = |options: &Options| {
+            let (signatures, results, _) = count(&path, 0, options);
+            let total =
+                |sample| -> u64 { (0..signatures.len()).map(|v| results.get(v, sample)).sum() };
+            (total(0), total(1))
+        };
+        // A haploid alternative allele counts once, a diploid homozygous one twice
+        assert_eq!(totals(&Options::default()), (1, 3));
+        let options = Options {
+            count_mode: CountMode::Site,
+            ..Options::default()
+        };
+        assert_eq!(totals(&options), (1, 2));
+        // The haploid 1 and diploid 1/1 at 1:2 differ, unless compared by allele content
+        let options = Options {
+            ignore_homogeneous_sites: true,
+            ..Options::default()
+        };
+        assert_eq!(totals(&options), (1, 3));
+        let options = Options {
+            ignore_homogeneous_sites: true,
+            compare_allele_content: true,
+            ..Options::default()
+        };
+        assert_eq!(totals(&options), (0, 1));
+    }
+
+    #[test]
+    fn test_varying_position() {
+        let gt = |alleles: &[Option<u32>]| genotype::Genotype::from_allele_indices(alleles);
+        let hom_alt = gt(&[Some(1), Some(1)]);
+        let uncalled = genotype::Genotype::missing(2);
+        // An uncalled sample varies from the called ones, regardless of its order
+        for gts in [
+            [uncalled.clone(), hom_alt.clone(), hom_alt.clone()],
+            [hom_alt.clone(), uncalled.clone(), hom_alt.clone()],
+            [hom_alt.clone(), hom_alt.clone(), uncalled.clone()],
+        ] {
+            for allele_content in [false, true] {
+                let varying = |missing| is_varying_position(&gts, allele_content, missing);
+                assert!(varying(HomogeneousMissing::TreatAsDistinct));
+                assert!(varying(HomogeneousMissing::TreatAsRef));
+                assert!(!varying(HomogeneousMissing::Ignore));
+            }
+        }
+        // The phase and position of a missing allele does not matter
+        let gts = [gt(&[None, Some(1)]), gt(&[Some(1), None])];
+        assert!(!is_varying_position(
+            &gts,
+            false,
+            HomogeneousMissing::TreatAsDistinct
+        ));
+        let gts = [gt(&[Some(1)]), gt(&[Some(1), Some(1)])];
+        assert!(is_varying_position(
+            &gts,
+            false,
+            HomogeneousMissing::TreatAsDistinct
+        ));
+        assert!(!is_varying_position(
+            &gts,
+            true,
+            HomogeneousMissing::TreatAsDistinct
+        ));
+    }
+
+    #[test]
+    fn test_homogeneous_missing() {
+        use HomogeneousMissing::*;
+        let gt = |alleles: &[Option<u32>]| genotype::Genotype::from_allele_indices(alleles);
+        let (hom_ref, het, hom_alt) = (
+            gt(&[Some(0), Some(0)]),
+            gt(&[Some(0), Some(1)]),
+            gt(&[Some(1), Some(1)]),
+        );
+        let (uncalled, half) = (genotype::Genotype::missing(2), gt(&[None, Some(1)]));
+        // The genotypes with whether they vary when ignoring, treating as reference and
+        // treating as distinct the missing alleles
+        let sites = [
+            (vec![&hom_ref, &het, &het], [true, true, true]),
+            (vec![&het, &het, &het], [false, false, false]),
+            (vec![&uncalled, &uncalled], [false, false, false]),
+            (vec![&uncalled, &hom_ref, &hom_ref], [false, false, true]),
+            (vec![&hom_ref, &uncalled, &het], [true, true, true]),
+            (vec![&half, &het], [true, false, true]),
+            (vec![&half, &hom_alt], [true, true, true]),
+            (vec![&half, &half, &uncalled], [false, true, true]),
+            (vec![&het, &uncalled, &half], [true, true, true]),
+        ];
+        for (gts, varying) in sites.iter() {
+            let gts: Vec<genotype::Genotype> = gts.iter().map(|gt| (*gt).clone()).collect();
+            for (missing, varying) in [Ignore, TreatAsRef, TreatAsDistinct].iter().zip(varying) {
+                assert_eq!(
+                    is_varying_position(&gts, false, *missing),
+                    *varying,
+                    "{:?} {:?}",
+                    gts,
+                    missing
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_genotypes() {
+        let total = |path: &str, no_genotypes: bool| {
+            let mut options = CountOptions::new(testdata(path), testdata("ex2.fa"));
+            options.classification.no_genotypes = no_genotypes;
+            let counts = count_vcf(&options)?;
+            let total = |sample| -> u64 {
+                (0..counts.signatures.len())
+                    .map(|v| counts.results.get(v, sample))
+                    .sum()
+            };
+            let skipped = counts.summary.skipped(summary::Reason::NoGenotypes);
+            Ok::<_, Error>((total(0), total(1), skipped))
+        };
+        // Without GT in the header, the genotypes are only ignored if requested
+        let e = total("ex2_no_gt.vcf", false).err().unwrap();
+        assert!(matches!(e, Error::InvalidOptions(_)), "{}", e);
+        assert!(e.to_string().contains("--no-genotypes"), "{}", e);
+        // Each sample carries the C>A and both alleles of the G>T,C once
+        assert_eq!(total("ex2_no_gt.vcf", true).unwrap(), (3, 3, 0));
+        // With GT, the records without it are skipped
+        assert_eq!(total("ex2.vcf", false).unwrap(), (2, 4, 0));
+        assert_eq!(total("ex2.vcf", true).unwrap(), (2, 2, 0));
+        assert_eq!(total("ex2_mixed_gt.vcf", false).unwrap(), (1, 2, 1));
+        assert_eq!(total("ex2_mixed_gt.vcf", true).unwrap(), (2, 2, 0));
+    }
+
+    #[test]
+    fn test_many_alleles() {
+        // The allele 300 is the A>G, the allele 44 an insertion
+        let (signatures, results, summary) =
+            count(&testdata("ex2_many_alleles.vcf"), 0, &Options::default());
+        let t_c = signatures
+            .index_of(&signature::Signature::new("T", 'T', 'C'))
+            .unwrap();
+        assert_eq!(results.get(t_c, 0), 1);
+        let total = |sample| -> u64 { (0..signatures.len()).map(|v| results.get(v, sample)).sum() };
+        assert_eq!((total(0), total(1)), (1, 0));
+        assert_eq!(summary.skipped(summary::Reason::NonSnv), 299);
+
+        let options = CountOptions::new(testdata("ex2_corrupt_allele.vcf"), testdata("ex2.fa"));
+        let e = count_vcf(&options).err().unwrap();
+        assert_eq!(
+            e.to_string(),
+            "Can not find allele 2 of a genotype at position 1:3, the record has 2 alleles"
+        );
+        assert!(matches!(
+            e,
+            Error::UnknownAllele {
+                allele: 2,
+                alleles: 2,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_doublets() {
+        let options = Options {
+            scheme: Scheme::Dbs(dbs::Doublets::new()),
+            ..Options::default()
+        };
+        let (_, results, summary) = count(&testdata("ex2_dbs.vcf"), 0, &options);
+        let doublets = dbs::Doublets::new();
+        let index = |label: &str| doublets.labels().iter().position(|l| l == label).unwrap();
+        // CA>TT is counted as its reverse complement TG>AA
+        assert_eq!(results.get(index("TG>AA"), 0), 1);
+        assert_eq!(results.get(index("TG>AA"), 1), 2);
+        assert_eq!(results.get(index("TG>CA"), 0), 1);
+        assert_eq!(results.get(index("CT>AA"), 0), 1);
+        let total: u64 = (0..doublets.len()).map(|v| results.get(v, 0)).sum();
+        assert_eq!(total, 3);
+        // The SNV and the CT>AT substitution of a single base are ignored
+        assert_eq!(summary.skipped(summary::Reason::NonDbs), 2);
+    }
+
+    #[test]
+    fn test_indels() {
+        let options = Options {
+            scheme: Scheme::Id(indel::Indels::new()),
+            ..Options::default()
+        };
+        let (_, results, summary) = count(&testdata("ex2_indels.vcf"), 0, &options);
+        let indels = indel::Indels::new();
+        let index = |label: &str| indels.index_of(label).unwrap();
+        assert_eq!(results.get(index("1:Ins:C:0"), 0), 1);
+        assert_eq!(results.get(index("1:Del:T:0"), 0), 1);
+        // The inserted A is followed by another A in the reference
+        assert_eq!(results.get(index("1:Ins:T:1"), 0), 1);
+        // The deleted CT ends with the T before it
+        assert_eq!(results.get(index("2:Del:M:1"), 1), 2);
+        let total: u64 = (0..indels.len()).map(|v| results.get(v, 0)).sum();
+        assert_eq!(total, 3);
+        assert_eq!(summary.skipped(summary::Reason::NonIndel), 1);
+    }
+
+    #[test]
+    fn test_transcription_strands() {
+        let options = Options {
+            scheme: Scheme::SbsTs,
+            annotation: Some(
+                annotation::Annotation::from_path(testdata("ex2.gtf"), "gene").unwrap(),
+            ),
+            ..Options::default()
+        };
+        let (signatures, results, _) = count(&testdata("ex2_strand.vcf"), 0, &options);
+        let n_signatures = signatures.channel_count();
+        let get = |block: usize, reference: char, alternative: char| {
+            let s = signature::Signature::new(reference.to_string(), reference, alternative);
+            results.get(block * n_signatures + signatures.index_of(&s).unwrap(), 0)
+        };
+        // The C>A at 1:2 in the plus gene has its pyrimidine on the coding strand, and the
+        // G>T at 1:5 in the minus gene is counted as C>A whose C is on the coding strand too
+        assert_eq!(get(1, 'C', 'A'), 2);
+        assert_eq!(get(0, 'C', 'A'), 0);
+        // The T>C at 1:4 in the minus gene has its pyrimidine on the template strand
+        assert_eq!(get(0, 'T', 'C'), 1);
+        // The A>G at 1:6 is outside of the genes
+        assert_eq!(get(2, 'T', 'C'), 1);
+    }
+
+    #[test]
+    fn test_sbs288() {
+        let gtf = annotation::Annotation::from_path(testdata("ex2.gtf"), "gene").unwrap();
+        let options = Options {
+            scheme: Scheme::Sbs288(annotation::TranscriptionStrand::Transcribed),
+            annotation: Some(gtf),
+            ..Options::default()
+        };
+        let (signatures, results, _) = count(&testdata("ex2_sbs288.vcf"), 1, &options);
+        let rows = stratified_rows(
+            &sbs_rows(&signatures, true, signature::Orientation::Pyrimidine),
+            &TRANSCRIPTION_STRAND_PREFIXES,
+            signatures.channel_count(),
+        );
+        assert_eq!(rows.len(), 288);
+        assert_eq!(rows[0].0, "T:A[C>A]A");
+        assert_eq!(rows[96].0, "U:A[C>A]A");
+        assert_eq!(rows[287].0, "N:T[T>G]T");
+        let get = |label: &str| {
+            let (_, index) = rows.iter().find(|(l, _)| l == label).unwrap();
+            results.get(*index, 0)
+        };
+        // The C>A at 1:2 in the plus gene and the G>T at 1:5 in the minus gene
+        assert_eq!(get("U:T[C>A]A"), 2);
+        // The T>C at 1:4 in the minus gene and the A>G at 1:3 in both genes
+        assert_eq!(get("T:A[T>C]G"), 2);
+        // The C>T at 2:2 is in an unstranded gene only
+        assert_eq!(get("N:T[C>T]T"), 1);
+        let total: u64 = rows.iter().map(|(_, i)| results.get(*i, 0)).sum();
+        assert_eq!(total, 5);
+
+        let options = Options {
+            scheme: Scheme::Sbs288(annotation::TranscriptionStrand::Intergenic),
+            ..options
+        };
+        let (_, results, _) = count(&testdata("ex2_sbs288.vcf"), 1, &options);
+        let get = |label: &str| {
+            let (_, index) = rows.iter().find(|(l, _)| l == label).unwrap();
+            results.get(*index, 0)
+        };
+        assert_eq!(get("T:A[T>C]G"), 1);
+        assert_eq!(get("N:A[T>C]G"), 1);
+    }
+
+    #[test]
+    fn test_replication_strands() {
+        let options = Options {
+            scheme: Scheme::SbsRs(
+                replication::Replication::from_path(testdata("ex2_replication.bed")).unwrap(),
+            ),
+            ..Options::default()
+        };
+        let (signatures, results, _) = count(&testdata("ex2_strand.vcf"), 0, &options);
+        let n_signatures = signatures.channel_count();
+        let get = |block: usize, reference: char, alternative: char| {
+            let s = signature::Signature::new(reference.to_string(), reference, alternative);
+            results.get(block * n_signatures + signatures.index_of(&s).unwrap(), 0)
+        };
+        // The C>A at 1:2 is right-replicating with the C on the lagging strand template, the
+        // G>T at 1:5 is left-replicating and counted as C>A on the reverse strand
+        assert_eq!(get(1, 'C', 'A'), 2);
+        assert_eq!(get(0, 'C', 'A'), 0);
+        // The T>C at 1:4 is left-replicating with the T on the leading strand template
+        assert_eq!(get(0, 'T', 'C'), 1);
+        // The A>G at 1:6 is outside of the annotated regions
+        assert_eq!(get(2, 'T', 'C'), 1);
+    }
+
+    #[test]
+    fn test_decompose_mnv() {
+        let options = Options {
+            decompose_mnv: true,
+            ..Options::default()
+        };
+        let (signatures, results, summary) = count(&testdata("ex2_mnv.vcf"), 1, &options);
+        let index = |codon: &str, alternative: char| {
+            let reference = codon.as_bytes()[1] as char;
+            signatures
+                .index_of(&signature::Signature::new(codon, reference, alternative))
+                .unwrap()
+        };
+
+        // CA>TG decomposes into TCA>T and CAT>G, CAT>TAG into TCA>T and ATG>G
+        assert_eq!(results.get(index("TCA", 'T'), 0), 2);
+        assert_eq!(results.get(index("TCA", 'T'), 1), 2);
+        assert_eq!(results.get(index("CAT", 'G'), 0), 1);
+        assert_eq!(results.get(index("CAT", 'G'), 1), 2);
+        assert_eq!(results.get(index("ATG", 'G'), 0), 1);
+        assert_eq!(results.get(index("ATG", 'G'), 1), 0);
+
+        // CT>TG does not match the reference and must not be counted at all
+        let totals: Vec<u64> = (0..2)
+            .map(|s| (0..signatures.len()).map(|v| results.get(v, s)).sum())
+            .collect();
+        assert_eq!(totals, vec![4, 4]);
+        assert_eq!(summary.skipped(summary::Reason::NonSnv), 0);
+    }
+
+    #[test]
+    fn test_indexed_record_count() {
+        assert_eq!(indexed_record_count("testdata/ex2_indexed.vcf.gz"), Some(9));
+        assert_eq!(indexed_record_count("testdata/ex2.vcf"), None);
+        // The third contig of the header has no records
+        let records = indexed_contig_records("testdata/ex2_indexed.vcf.gz").unwrap();
+        assert_eq!(records.iter().sum::<u64>(), 9);
+        assert!(records.get(2).is_none_or(|n| *n == 0));
+    }
+
+    #[test]
+    fn test_mnv_not_decomposed_by_default() {
+        let (signatures, results, summary) =
+            count(&testdata("ex2_mnv.vcf"), 1, &Options::default());
+        for v in 0..signatures.len() {
+            assert_eq!(results.get(v, 0), 0);
+            assert_eq!(results.get(v, 1), 0);
+        }
+        assert_eq!(summary.skipped(summary::Reason::NonSnv), 3);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::signature::rev_comp;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The reference dinucleotides of the doublet base substitutions in canonical order. The
+/// other six dinucleotides are their reverse complements.
+const REFERENCES: [&str; 10] = ["AC", "AT", "CC", "CG", "CT", "GC", "TA", "TC", "TG", "TT"];
+
+/// The 78 doublet base substitution (DBS) channels, i.e., substitutions of two adjacent
+/// bases such as `CC>TT`. A substitution and its reverse complement share one channel,
+/// which is labeled by the canonical reference dinucleotide. For the palindromic reference
+/// dinucleotides (e.g., `CG`), the smaller of the two equivalent alternatives is used.
+pub struct Doublets {
+    labels: Vec<String>,
+    db: BTreeMap<(String, String), usize>,
+}
+
+impl Doublets {
+    /// Build the 78 channels in the canonical (COSMIC) order.
+    pub fn new() -> Self {
+        let mut labels = Vec::new();
+        let mut db = BTreeMap::new();
+        for reference in REFERENCES.iter() {
+            let palindromic = rev_comp(reference.chars()) == *reference;
+            for alternative in dinucleotides() {
+                let differs = alternative
+                    .chars()
+                    .zip(reference.chars())
+                    .all(|(a, r)| a != r);
+                if !differs {
+                    continue;
+                }
+                let alternative_rc = rev_comp(alternative.chars());
+                if palindromic && alternative_rc < alternative {
+                    continue;
+                }
+                let idx = labels.len();
+                labels.push(format!("{}>{}", reference, alternative));
+                db.insert((reference.to_string(), alternative.clone()), idx);
+                db.insert((rev_comp(reference.chars()), alternative_rc), idx);
+            }
+        }
+        let d = Doublets { labels, db };
+        trace!("Built doublet channels: {:?}", d);
+        d
+    }
+
+    /// Return the index of the channel of the substitution of the `reference` by the
+    /// `alternative` dinucleotide (both uppercase), if it is a doublet substitution.
+    pub fn index_of(&self, reference: &str, alternative: &str) -> Option<usize> {
+        self.db
+            .get(&(reference.to_owned(), alternative.to_owned()))
+            .cloned()
+    }
+
+    /// Retrieve the number of channels
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Return the labels of the channels (e.g., `CC>TT`) in the order of their indizes.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+}
+
+impl fmt::Debug for Doublets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Doublets {{")?;
+        for ((r, a), v) in &self.db {
+            write!(f, "{}>{} = {}, ", r, a, v)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Return all 16 dinucleotides in lexicographic order.
+fn dinucleotides() -> Vec<String> {
+    let nucleotides = ['A', 'C', 'G', 'T'];
+    nucleotides
+        .iter()
+        .flat_map(|a| nucleotides.iter().map(move |b| format!("{}{}", a, b)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channels() {
+        let d = Doublets::new();
+        assert_eq!(d.len(), 78);
+        assert_eq!(d.labels()[0], "AC>CA");
+        assert_eq!(d.labels()[9], "AT>CA");
+        assert_eq!(d.labels()[77], "TT>GG");
+        // 9 channels for each non-palindromic and 6 for each palindromic reference
+        for (reference, n) in &[("AC", 9), ("AT", 6), ("CG", 6), ("GC", 6), ("TA", 6)] {
+            let prefix = format!("{}>", reference);
+            assert_eq!(
+                d.labels().iter().filter(|l| l.starts_with(&prefix)).count(),
+                *n
+            );
+        }
+    }
+
+    #[test]
+    fn test_reverse_complement() {
+        let d = Doublets::new();
+        assert_eq!(d.index_of("AC", "CA"), Some(0));
+        assert_eq!(d.index_of("GT", "TG"), Some(0));
+        assert_eq!(d.index_of("AT", "TG"), d.index_of("AT", "CA"));
+        // Each of the 144 doublet substitutions has a channel shared with its reverse
+        // complement, which is the substitution itself for e.g. AT>CG
+        let mut hits = vec![0; d.len()];
+        for reference in dinucleotides() {
+            for alternative in dinucleotides() {
+                let differs = alternative
+                    .chars()
+                    .zip(reference.chars())
+                    .all(|(a, r)| a != r);
+                let idx = d.index_of(&reference, &alternative);
+                assert_eq!(idx.is_some(), differs, "{}>{}", reference, alternative);
+                if let Some(idx) = idx {
+                    let rc =
+                        d.index_of(&rev_comp(reference.chars()), &rev_comp(alternative.chars()));
+                    assert_eq!(rc, Some(idx), "{}>{}", reference, alternative);
+                    hits[idx] += 1;
+                }
+            }
+        }
+        assert!(hits.iter().all(|h| *h == 1 || *h == 2));
+        assert_eq!(hits.iter().sum::<usize>(), 144);
+        // Substitutions of a single base are no doublets
+        assert_eq!(d.index_of("AC", "AA"), None);
+    }
+}
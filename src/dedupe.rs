@@ -0,0 +1,87 @@
+/// How a record is related to the earlier records at the same site.
+#[derive(Debug, PartialEq)]
+pub enum Occurrence {
+    /// No earlier record has the same alleles
+    First,
+    /// An earlier record has the same alleles and genotypes
+    Duplicate,
+    /// An earlier record has the same alleles, but different genotypes
+    Conflicting,
+}
+
+/// Recognizes records repeating the alleles of an earlier record at the same site, as found
+/// in carelessly merged VCFs. As the input is sorted by coordinate, only the records at the
+/// current site need to be remembered.
+pub struct Deduplicator<G> {
+    site: Option<(u32, i64)>,
+    seen: Vec<(Vec<Vec<u8>>, Vec<G>)>,
+}
+
+impl<G: PartialEq> Deduplicator<G> {
+    /// Create a deduplicator that has not seen any record.
+    pub fn new() -> Self {
+        Deduplicator {
+            site: None,
+            seen: Vec::new(),
+        }
+    }
+
+    /// Offer the `alleles` and `genotypes` of a record at the 0-based `position` of contig
+    /// `rid`. Records must be offered in sorted order.
+    pub fn offer(
+        &mut self,
+        rid: u32,
+        position: i64,
+        alleles: Vec<Vec<u8>>,
+        genotypes: Vec<G>,
+    ) -> Occurrence {
+        if self.site != Some((rid, position)) {
+            self.site = Some((rid, position));
+            self.seen.clear();
+        }
+        match self.seen.iter().find(|(a, _)| *a == alleles) {
+            Some((_, g)) if *g == genotypes => Occurrence::Duplicate,
+            Some(_) => Occurrence::Conflicting,
+            None => {
+                self.seen.push((alleles, genotypes));
+                Occurrence::First
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offer() {
+        let alleles = |a: &[&[u8]]| a.iter().map(|a| a.to_vec()).collect::<Vec<_>>();
+        let mut d = Deduplicator::new();
+        assert_eq!(
+            d.offer(0, 1, alleles(&[b"C", b"A"]), vec![1, 2]),
+            Occurrence::First
+        );
+        assert_eq!(
+            d.offer(0, 1, alleles(&[b"C", b"T"]), vec![1, 2]),
+            Occurrence::First
+        );
+        assert_eq!(
+            d.offer(0, 1, alleles(&[b"C", b"A"]), vec![1, 2]),
+            Occurrence::Duplicate
+        );
+        assert_eq!(
+            d.offer(0, 1, alleles(&[b"C", b"T"]), vec![1, 1]),
+            Occurrence::Conflicting
+        );
+        // Records at other sites are never duplicates
+        assert_eq!(
+            d.offer(1, 1, alleles(&[b"C", b"A"]), vec![1, 2]),
+            Occurrence::First
+        );
+        assert_eq!(
+            d.offer(1, 2, alleles(&[b"C", b"A"]), vec![1, 2]),
+            Occurrence::First
+        );
+    }
+}
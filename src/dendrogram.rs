@@ -0,0 +1,211 @@
+use crate::similarity;
+
+/// The distance between the spectra of two samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Distance {
+    /// One minus the cosine similarity of the counts, or one if any sample has no counts
+    Cosine,
+    /// The euclidean distance of the fractions of the counts
+    Euclidean,
+}
+
+/// A binary tree of samples as merged by agglomerative clustering.
+#[derive(Debug, PartialEq)]
+pub enum Tree {
+    /// The sample with this index
+    Leaf(usize),
+    /// The merge of two subtrees at this height, i.e., the distance of their samples
+    Node(Box<Tree>, Box<Tree>, f64),
+}
+
+impl Tree {
+    /// Return the height of the tree, which is zero for a sample.
+    pub fn height(&self) -> f64 {
+        match self {
+            Tree::Leaf(_) => 0.0,
+            Tree::Node(_, _, h) => *h,
+        }
+    }
+
+    /// Return the tree in the Newick format, with the sample `names` as leaves and the
+    /// difference of the heights of a subtree and its parent as branch lengths.
+    pub fn to_newick(&self, names: &[String]) -> String {
+        format!("{};", self.newick_subtree(names))
+    }
+
+    fn newick_subtree(&self, names: &[String]) -> String {
+        match self {
+            Tree::Leaf(i) => newick_label(&names[*i]),
+            Tree::Node(left, right, height) => {
+                // Round off the summation errors, which may also yield negative branch lengths
+                let length = |t: &Tree| ((height - t.height()).max(0.0) * 1e6).round() / 1e6;
+                format!(
+                    "({}:{},{}:{})",
+                    left.newick_subtree(names),
+                    length(left),
+                    right.newick_subtree(names),
+                    length(right)
+                )
+            }
+        }
+    }
+}
+
+/// Return the `name` as Newick label, quoted if it contains any reserved character.
+fn newick_label(name: &str) -> String {
+    if name
+        .chars()
+        .any(|c| c.is_whitespace() || "()[]',:;".contains(c))
+    {
+        format!("'{}'", name.replace('\'', "''"))
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Return the pairwise `distance` of the `profiles` of the samples.
+pub fn distance_matrix(profiles: &[Vec<f64>], distance: Distance) -> Vec<Vec<f64>> {
+    let fractions: Vec<Vec<f64>> = profiles
+        .iter()
+        .map(|p| {
+            let total: f64 = p.iter().sum();
+            p.iter()
+                .map(|v| if total > 0.0 { v / total } else { 0.0 })
+                .collect()
+        })
+        .collect();
+    profiles
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            profiles
+                .iter()
+                .enumerate()
+                .map(|(j, b)| match distance {
+                    _ if i == j => 0.0,
+                    Distance::Cosine => 1.0 - similarity::cosine_similarity(a, b).unwrap_or(0.0),
+                    Distance::Euclidean => fractions[i]
+                        .iter()
+                        .zip(&fractions[j])
+                        .map(|(a, b)| (a - b).powi(2))
+                        .sum::<f64>()
+                        .sqrt(),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Cluster the samples by their pairwise `distances` with average linkage, i.e., merge the
+/// two closest clusters until a single one remains, where the distance of two clusters is the
+/// mean distance of their samples. Ties are merged in the order of the samples.
+pub fn average_linkage(distances: &[Vec<f64>]) -> Result<Tree, String> {
+    if distances.is_empty() {
+        return Err("Can not cluster the samples, there are none".to_owned());
+    }
+    let mut clusters: Vec<(Tree, usize)> =
+        (0..distances.len()).map(|i| (Tree::Leaf(i), 1)).collect();
+    let mut d: Vec<Vec<f64>> = distances.to_vec();
+    while clusters.len() > 1 {
+        let mut closest = (0, 1);
+        for i in 0..clusters.len() {
+            for j in (i + 1)..clusters.len() {
+                if d[i][j] < d[closest.0][closest.1] {
+                    closest = (i, j);
+                }
+            }
+        }
+        let (i, j) = closest;
+        let height = d[i][j];
+        // Update the distances of the merged cluster, which replaces the cluster i
+        let (n_i, n_j) = (clusters[i].1, clusters[j].1);
+        let merged: Vec<f64> = d[i]
+            .iter()
+            .zip(&d[j])
+            .map(|(a, b)| (n_i as f64 * a + n_j as f64 * b) / (n_i + n_j) as f64)
+            .collect();
+        for (k, distance) in merged.into_iter().enumerate() {
+            d[i][k] = distance;
+            d[k][i] = distance;
+        }
+        d[i][i] = 0.0;
+        d.remove(j);
+        for row in d.iter_mut() {
+            row.remove(j);
+        }
+        let (right, _) = clusters.remove(j);
+        let (left, _) = std::mem::replace(&mut clusters[i], (Tree::Leaf(0), 0));
+        clusters[i] = (
+            Tree::Node(Box::new(left), Box::new(right), height),
+            n_i + n_j,
+        );
+    }
+    Ok(clusters.remove(0).0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(n: usize) -> Vec<String> {
+        ["A", "B", "C", "D"][..n]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_average_linkage() {
+        let distances = vec![
+            vec![0.0, 1.0, 4.0, 5.0],
+            vec![1.0, 0.0, 3.0, 6.0],
+            vec![4.0, 3.0, 0.0, 2.0],
+            vec![5.0, 6.0, 2.0, 0.0],
+        ];
+        // A and B merge at 1, C and D at 2, and both at (4 + 5 + 3 + 6) / 4 = 4.5
+        let tree = average_linkage(&distances).unwrap();
+        assert_eq!(tree.height(), 4.5);
+        assert_eq!(tree.to_newick(&names(4)), "((A:1,B:1):3.5,(C:2,D:2):2.5);");
+    }
+
+    #[test]
+    fn test_average_linkage_trivial() {
+        let tree = average_linkage(&[vec![0.0]]).unwrap();
+        assert_eq!(tree.to_newick(&names(1)), "A;");
+        let tree = average_linkage(&[vec![0.0, 0.5], vec![0.5, 0.0]]).unwrap();
+        assert_eq!(tree.to_newick(&names(2)), "(A:0.5,B:0.5);");
+        assert!(average_linkage(&[]).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_profiles() {
+        let profiles = vec![vec![1.0, 2.0], vec![2.0, 4.0], vec![3.0, 0.0]];
+        let distances = distance_matrix(&profiles, Distance::Euclidean);
+        assert_eq!(distances[0][1], 0.0);
+        let tree = average_linkage(&distances).unwrap();
+        assert_eq!(
+            tree.to_newick(&names(3)),
+            "((A:0,B:0):0.942809,C:0.942809);"
+        );
+    }
+
+    #[test]
+    fn test_distance_matrix() {
+        let profiles = vec![vec![1.0, 0.0], vec![0.0, 2.0], vec![0.0, 0.0]];
+        let distances = distance_matrix(&profiles, Distance::Cosine);
+        assert_eq!(distances[0][1], 1.0);
+        assert_eq!(distances[1][0], 1.0);
+        assert_eq!(distances[0][2], 1.0);
+        assert_eq!(distances[2][2], 0.0);
+        let distances = distance_matrix(&profiles, Distance::Euclidean);
+        assert_eq!(distances[0][1], 2f64.sqrt());
+        assert_eq!(distances[0][2], 1.0);
+    }
+
+    #[test]
+    fn test_newick_label() {
+        assert_eq!(newick_label("S1"), "S1");
+        assert_eq!(newick_label("my sample"), "'my sample'");
+        assert_eq!(newick_label("O'Brien"), "'O''Brien'");
+    }
+}
@@ -0,0 +1,199 @@
+/// Drops mutations of a sample that are closer than a minimum distance to the previous
+/// mutation of that sample on the same contig, as dense clusters of mutations are often
+/// alignment artifacts. Either only the later mutation of a close pair is dropped, or, with
+/// `drop_both`, all mutations of a cluster. The latter requires to defer counting a
+/// mutation until the next mutation of the sample confirms that it is isolated.
+pub struct DistanceFilter {
+    min_distance: u64,
+    drop_both: bool,
+    last: Vec<Option<Site>>,
+}
+
+/// The mutations of a sample at a site.
+struct Site {
+    rid: u32,
+    position: u64,
+    signatures: Vec<usize>,
+    clustered: bool,
+}
+
+impl DistanceFilter {
+    /// Create a filter requiring a distance of at least `min_distance` between the
+    /// mutations of each of `n_samples`.
+    pub fn new(min_distance: u64, drop_both: bool, n_samples: usize) -> Self {
+        DistanceFilter {
+            min_distance,
+            drop_both,
+            last: (0..n_samples).map(|_| None).collect(),
+        }
+    }
+
+    /// Offer the mutations (signature indizes) of sample `sidx` at the 0-based `position`
+    /// of contig `rid`. Sites must be offered in sorted order. Returns the mutations that
+    /// can be counted now and the number of dropped mutations.
+    pub fn offer(
+        &mut self,
+        sidx: usize,
+        rid: u32,
+        position: u64,
+        signatures: Vec<usize>,
+    ) -> (Vec<usize>, u64) {
+        let close = match &self.last[sidx] {
+            Some(last) => {
+                last.rid == rid && position.saturating_sub(last.position) < self.min_distance
+            }
+            None => false,
+        };
+        if !self.drop_both {
+            // Only the counted mutations are remembered
+            if close {
+                return (Vec::new(), signatures.len() as u64);
+            }
+            self.last[sidx] = Some(Site {
+                rid,
+                position,
+                signatures: Vec::new(),
+                clustered: false,
+            });
+            return (signatures, 0);
+        }
+
+        let site = Site {
+            rid,
+            position,
+            signatures,
+            clustered: close,
+        };
+        match self.last[sidx].replace(site) {
+            Some(mut last) => {
+                last.clustered |= close;
+                Self::release(last)
+            }
+            None => (Vec::new(), 0),
+        }
+    }
+
+    /// Return the deferred mutations of sample `sidx` that can be counted and the number of
+    /// dropped mutations once all sites have been offered.
+    pub fn finish(&mut self, sidx: usize) -> (Vec<usize>, u64) {
+        match self.last[sidx].take() {
+            Some(last) if self.drop_both => Self::release(last),
+            _ => (Vec::new(), 0),
+        }
+    }
+
+    fn release(site: Site) -> (Vec<usize>, u64) {
+        if site.clustered {
+            (Vec::new(), site.signatures.len() as u64)
+        } else {
+            (site.signatures, 0)
+        }
+    }
+}
+
+/// Splits the mutations of each sample into clustered and non-clustered ones. A mutation is
+/// clustered if it is closer than a cutoff to the previous or the next mutation of the sample
+/// on the same contig, so counting a mutation is deferred until the next mutation of the
+/// sample. The signature indizes of non-clustered mutations are offset by the number of
+/// channels, such that they are counted into a second block of channels.
+pub struct ClusterSplitter {
+    cutoff: u64,
+    n_channels: usize,
+    last: Vec<Option<Site>>,
+}
+
+impl ClusterSplitter {
+    /// Create a splitter for `n_samples` with `n_channels` per block that counts mutations
+    /// closer than `cutoff` as clustered.
+    pub fn new(cutoff: u64, n_channels: usize, n_samples: usize) -> Self {
+        ClusterSplitter {
+            cutoff,
+            n_channels,
+            last: (0..n_samples).map(|_| None).collect(),
+        }
+    }
+
+    /// Offer the mutations (signature indizes) of sample `sidx` at the 0-based `position`
+    /// of contig `rid`. Sites must be offered in sorted order. Returns the mutations of the
+    /// previous site with their final signature indizes.
+    pub fn offer(
+        &mut self,
+        sidx: usize,
+        rid: u32,
+        position: u64,
+        signatures: Vec<usize>,
+    ) -> Vec<usize> {
+        let close = match &self.last[sidx] {
+            Some(last) => last.rid == rid && position.saturating_sub(last.position) < self.cutoff,
+            None => false,
+        };
+        let site = Site {
+            rid,
+            position,
+            signatures,
+            clustered: close,
+        };
+        match self.last[sidx].replace(site) {
+            Some(mut last) => {
+                last.clustered |= close;
+                self.release(last)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Return the deferred mutations of sample `sidx` once all sites have been offered.
+    pub fn finish(&mut self, sidx: usize) -> Vec<usize> {
+        match self.last[sidx].take() {
+            Some(last) => self.release(last),
+            None => Vec::new(),
+        }
+    }
+
+    fn release(&self, site: Site) -> Vec<usize> {
+        if site.clustered {
+            site.signatures
+        } else {
+            site.signatures
+                .into_iter()
+                .map(|i| i + self.n_channels)
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_later() {
+        let mut f = DistanceFilter::new(2, false, 1);
+        assert_eq!(f.offer(0, 0, 1, vec![1]), (vec![1], 0));
+        assert_eq!(f.offer(0, 0, 2, vec![2]), (vec![], 1));
+        // The distance is measured to the last counted mutation
+        assert_eq!(f.offer(0, 0, 3, vec![3]), (vec![3], 0));
+        assert_eq!(f.offer(0, 1, 3, vec![4]), (vec![4], 0));
+        assert_eq!(f.finish(0), (vec![], 0));
+    }
+
+    #[test]
+    fn test_cluster_splitter() {
+        let mut s = ClusterSplitter::new(2, 10, 1);
+        assert_eq!(s.offer(0, 0, 1, vec![1]), Vec::<usize>::new());
+        assert_eq!(s.offer(0, 0, 2, vec![2]), vec![1]);
+        assert_eq!(s.offer(0, 0, 4, vec![3]), vec![2]);
+        assert_eq!(s.offer(0, 1, 4, vec![4, 5]), vec![13]);
+        assert_eq!(s.finish(0), vec![14, 15]);
+    }
+
+    #[test]
+    fn test_drop_both() {
+        let mut f = DistanceFilter::new(2, true, 1);
+        assert_eq!(f.offer(0, 0, 1, vec![1]), (vec![], 0));
+        assert_eq!(f.offer(0, 0, 2, vec![2]), (vec![], 1));
+        assert_eq!(f.offer(0, 0, 4, vec![3]), (vec![], 1));
+        assert_eq!(f.offer(0, 1, 4, vec![4, 5]), (vec![3], 0));
+        assert_eq!(f.finish(0), (vec![4, 5], 0));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::result::ResultMatrix;
+
+/// Draws a uniform random sample of at most `size` counted mutations per sample using
+/// reservoir sampling, such that the counts of samples with many mutations can be
+/// compared to those with few. Each counted mutation is one increment of a signature
+/// for a sample. The sample is reproducible for a given seed and input order.
+pub struct Downsampler {
+    size: usize,
+    rng: SplitMix64,
+    reservoirs: Vec<Vec<usize>>,
+    seen: Vec<u64>,
+}
+
+impl Downsampler {
+    /// Create a downsampler keeping at most `size` mutations for each of `n_samples`.
+    pub fn new(size: usize, n_samples: usize, seed: u64) -> Self {
+        Downsampler {
+            size,
+            rng: SplitMix64::new(seed),
+            reservoirs: vec![Vec::new(); n_samples],
+            seen: vec![0; n_samples],
+        }
+    }
+
+    /// Offer one mutation of signature `vidx` in sample `sidx` to the sample.
+    pub fn add(&mut self, vidx: usize, sidx: usize) {
+        self.seen[sidx] += 1;
+        let reservoir = &mut self.reservoirs[sidx];
+        if reservoir.len() < self.size {
+            reservoir.push(vidx);
+        } else {
+            let j = self.rng.below(self.seen[sidx]);
+            if j < self.size as u64 {
+                reservoir[j as usize] = vidx;
+            }
+        }
+    }
+
+    /// Return the number of mutations per sample before and after downsampling.
+    pub fn totals(&self) -> Vec<(u64, u64)> {
+        self.seen
+            .iter()
+            .zip(&self.reservoirs)
+            .map(|(seen, r)| (*seen, r.len() as u64))
+            .collect()
+    }
+
+    /// Count the sampled mutations into `results`.
+    pub fn count_into(&self, results: &mut ResultMatrix) {
+        for (sidx, reservoir) in self.reservoirs.iter().enumerate() {
+            for vidx in reservoir {
+                results.increment(*vidx, sidx);
+            }
+        }
+    }
+}
+
+/// The SplitMix64 pseudo random number generator. It is implemented here, rather than
+/// taken from a crate, so that a seed yields the same sample across versions.
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Return a random number in `[0, n)`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        // Reject the values of the incomplete last block to avoid a modulo bias
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let v = self.next();
+            if v < zone {
+                return v % n;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reservoir() {
+        let mut d = Downsampler::new(3, 2, 7);
+        for v in 0..10 {
+            d.add(v, 0);
+        }
+        d.add(4, 1);
+        d.add(5, 1);
+        assert_eq!(d.totals(), vec![(10, 3), (2, 2)]);
+        // Samples below the size are kept completely
+        assert_eq!(d.reservoirs[1], vec![4, 5]);
+        assert!(d.reservoirs[0].iter().all(|v| *v < 10));
+    }
+
+    #[test]
+    fn test_below() {
+        let mut rng = SplitMix64 { state: 1 };
+        assert!((0..1000).all(|_| rng.below(7) < 7));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::classify::ClassifyError;
+use crate::result::MergeError;
+use crate::signature::Signature;
+use std::io;
+use thiserror::Error;
+
+/// The errors of reading the inputs and counting their variants.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A file can not be read or written
+    #[error("Can not {action}: {source}")]
+    Io {
+        action: String,
+        #[source]
+        source: io::Error,
+    },
+    /// htslib can not read a file, e.g., as it is missing or not an indexed FASTA file
+    #[error("Can not {action}: {source}")]
+    Htslib {
+        action: String,
+        #[source]
+        source: rust_htslib::errors::Error,
+    },
+    /// The reference allele of a variant differs from the bases `found` in the reference at
+    /// its 1-based position `pos`
+    #[error("Can not match the reference allele, the VCF file expects {expected} at position {contig}:{pos} but the reference has {found}")]
+    ReferenceMismatch {
+        contig: String,
+        pos: u64,
+        expected: String,
+        found: String,
+    },
+    /// Too many records differ from the reference, which is likely the wrong one
+    #[error("Can not trust the counts, {mismatches} of {records} records do not match the reference (more than a fraction of {max_fraction})")]
+    ReferenceMismatches {
+        mismatches: u64,
+        records: u64,
+        max_fraction: f32,
+    },
+    /// Too many records were not counted, e.g., as they are filtered or can not be classified
+    #[error(
+        "Can not count {ignored} of {records} records, more than a fraction of {max_fraction}"
+    )]
+    TooManyIgnored {
+        ignored: u64,
+        records: u64,
+        max_fraction: f32,
+    },
+    /// The bases of a range exceed the sequence
+    #[error("Can not fetch {length} bases at {contig}:{position}")]
+    OutOfBounds {
+        contig: String,
+        position: i64,
+        length: usize,
+    },
+    /// The window around a position is cut off by the start or end of the sequence
+    #[error("Can not fetch the full window at {contig}:{position}, only '{context}'")]
+    TruncatedContext {
+        contig: String,
+        position: i64,
+        context: String,
+    },
+    /// The context of a substitution has other bases than A, C, G and T
+    #[error("Can not classify the codon '{context}' with non-standard nucleotide")]
+    AmbiguousContext { context: String },
+    /// The context of a substitution is soft-masked, with these substitutions skipped
+    #[error("Can not classify the codon '{context}' in a soft-masked region")]
+    SoftmaskedContext { context: String },
+    /// The substitution has no channel, e.g., as its alternative allele is not a base
+    #[error("Can not find the channel of signature '{signature}'")]
+    Unclassified { signature: Signature },
+    /// The sequence is not in the reference
+    #[error("Can not find sequence '{contig}' in the reference")]
+    UnknownContig { contig: String },
+    /// Several sequences of the VCF file are not in the reference
+    #[error("Can not find sequences {} in the reference", quote(.contigs))]
+    UnknownContigs { contigs: Vec<String> },
+    /// A record at the 1-based `position` has no contig
+    #[error("Can not find the contig of the record at position {position}")]
+    MissingContig { position: u64 },
+    /// The contig with template-id `rid` of a record at the 1-based `position` has no (UTF-8)
+    /// name in the VCF header
+    #[error("Can not find the name of contig {rid} of the record at position {position} in the VCF header")]
+    UnnamedContig { rid: u32, position: u64 },
+    /// Records at the same site differ in their genotypes, with duplicates failing
+    #[error("Found duplicate records with different genotypes at position {contig}:{position}")]
+    ConflictingDuplicates { contig: String, position: u64 },
+    /// A genotype of the record at the 1-based `position` calls an allele the record lacks
+    #[error("Can not find allele {allele} of a genotype at position {contig}:{position}, the record has {alleles} alleles")]
+    UnknownAllele {
+        contig: String,
+        position: u64,
+        allele: u32,
+        alleles: u32,
+    },
+    /// The contigs of the VCF header are missing from the reference or differ in length
+    #[error("Can not match the contigs of the VCF header to the reference: {}", .discrepancies.join("; "))]
+    InconsistentContigs { discrepancies: Vec<String> },
+    /// The requested sample is not in the VCF file
+    #[error("Can not find sample '{name}' in list of: {}", quote(.available))]
+    UnknownSample {
+        name: String,
+        available: Vec<String>,
+    },
+    /// The channels of the window can not be built
+    #[error("Can not count with window {window}, {reason}")]
+    InvalidWindow { window: usize, reason: String },
+    /// The label of a signature can not be parsed
+    #[error("Can not parse signature '{label}', {reason}")]
+    InvalidSignature { label: String, reason: String },
+    /// The definition of the channels by the user is invalid
+    #[error("{0}")]
+    ChannelDefinition(String),
+    /// The options contradict each other or the input
+    #[error("{0}")]
+    InvalidOptions(String),
+    /// The counts of several parts of the input can not be summed up
+    #[error("{0}")]
+    Merge(#[from] MergeError),
+    /// Any other failure, described by its message
+    #[error("{0}")]
+    Other(String),
+}
+
+/// Return the `names` quoted and separated by commas.
+fn quote(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|n| format!("'{}'", n))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl Error {
+    /// Return the exit code of the command line tool for the category of the error: 2 for
+    /// invalid options or definitions, 3 for unreadable inputs, 4 for inconsistent inputs, 5 if
+    /// too many records were not counted and 1 for anything else.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InvalidWindow { .. }
+            | Error::InvalidSignature { .. }
+            | Error::ChannelDefinition(_)
+            | Error::InvalidOptions(_)
+            | Error::UnknownSample { .. } => 2,
+            Error::Io { .. } | Error::Htslib { .. } => 3,
+            Error::ReferenceMismatch { .. }
+            | Error::ReferenceMismatches { .. }
+            | Error::OutOfBounds { .. }
+            | Error::TruncatedContext { .. }
+            | Error::AmbiguousContext { .. }
+            | Error::SoftmaskedContext { .. }
+            | Error::Unclassified { .. }
+            | Error::UnknownContig { .. }
+            | Error::UnknownContigs { .. }
+            | Error::MissingContig { .. }
+            | Error::UnnamedContig { .. }
+            | Error::ConflictingDuplicates { .. }
+            | Error::UnknownAllele { .. }
+            | Error::InconsistentContigs { .. } => 4,
+            Error::TooManyIgnored { .. } => 5,
+            Error::Merge(_) | Error::Other(_) => 1,
+        }
+    }
+}
+
+impl From<Error> for String {
+    fn from(error: Error) -> Self {
+        error.to_string()
+    }
+}
+
+impl From<ClassifyError> for Error {
+    fn from(error: ClassifyError) -> Self {
+        match error {
+            ClassifyError::Mismatch {
+                contig,
+                position,
+                context,
+                expected,
+            } => Error::ReferenceMismatch {
+                contig,
+                pos: position as u64 + 1,
+                expected: expected.to_string(),
+                // The base at the center of the window
+                found: context[context.len() / 2..context.len() / 2 + 1].to_owned(),
+            },
+            ClassifyError::AmbiguousContext { context } => Error::AmbiguousContext { context },
+            ClassifyError::TruncatedContext {
+                contig,
+                position,
+                context,
+            } => Error::TruncatedContext {
+                contig,
+                position,
+                context,
+            },
+            ClassifyError::Softmasked { context } => Error::SoftmaskedContext { context },
+            // The base at the position itself is outside of the sequence
+            ClassifyError::OutOfBounds {
+                contig, position, ..
+            } => Error::OutOfBounds {
+                contig,
+                position,
+                length: 1,
+            },
+            ClassifyError::Unclassified { signature } => Error::Unclassified { signature },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let e = Error::UnknownSample {
+            name: "S3".to_owned(),
+            available: vec!["S1".to_owned(), "S2".to_owned()],
+        };
+        assert_eq!(
+            e.to_string(),
+            "Can not find sample 'S3' in list of: 'S1', 'S2'"
+        );
+        assert_eq!(e.exit_code(), 2);
+        let e = Error::Io {
+            action: "read 'a.tsv'".to_owned(),
+            source: io::Error::new(io::ErrorKind::NotFound, "No such file"),
+        };
+        assert_eq!(e.to_string(), "Can not read 'a.tsv': No such file");
+        assert_eq!(e.exit_code(), 3);
+        assert_eq!(String::from(Error::Other("failed".to_owned())), "failed");
+    }
+
+    #[test]
+    fn test_from_classify_error() {
+        let e = Error::from(ClassifyError::Mismatch {
+            contig: "1".to_owned(),
+            position: 2,
+            context: "CAT".to_owned(),
+            expected: 'C',
+        });
+        assert!(matches!(
+            &e,
+            Error::ReferenceMismatch { contig, pos: 3, expected, found } if contig == "1" && expected == "C" && found == "A"
+        ));
+        assert_eq!(
+            e.to_string(),
+            "Can not match the reference allele, the VCF file expects C at position 1:3 but the reference has A"
+        );
+        assert_eq!(e.exit_code(), 4);
+        let e = Error::from(ClassifyError::TruncatedContext {
+            contig: "1".to_owned(),
+            position: 0,
+            context: "TC".to_owned(),
+        });
+        assert!(matches!(e, Error::TruncatedContext { position: 0, .. }));
+        let e = Error::from(ClassifyError::AmbiguousContext {
+            context: "TNA".to_owned(),
+        });
+        assert!(matches!(e, Error::AmbiguousContext { .. }));
+        assert_eq!(e.exit_code(), 4);
+        let e = Error::from(ClassifyError::OutOfBounds {
+            contig: "1".to_owned(),
+            position: -1,
+            message: "negative position".to_owned(),
+        });
+        assert!(matches!(e, Error::OutOfBounds { position: -1, .. }));
+        assert_eq!(e.exit_code(), 4);
+    }
+}
@@ -0,0 +1,337 @@
+//! The C interface of the counting, built with the cargo feature `ffi`.
+//!
+//! All strings passed to and returned by the functions are NUL-terminated UTF-8 strings. The
+//! strings returned are owned by the library: the labels of the channels and the names of the
+//! samples live as long as their result, which the caller releases with `mutsig_result_free`,
+//! and the message of the last error lives until the next call of `mutsig_count` on the same
+//! thread. Panics do not cross the boundary but are reported as `MUTSIG_STATUS_PANIC`.
+use crate::count::{count_vcf, CountOptions};
+use crate::result::ResultMatrix;
+use crate::signature::{self, Signatures};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// The outcome of a call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MutsigStatus {
+    /// The call succeeded
+    Ok = 0,
+    /// An argument is a null pointer, not UTF-8 or out of range
+    InvalidArgument = 1,
+    /// The counting failed, e.g., as a file can not be read
+    Error = 2,
+    /// The counting panicked
+    Panic = 3,
+}
+
+/// The parameters of the counting.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MutsigOptions {
+    /// The number of bases of the sequence context on each side of a substitution
+    pub window: u8,
+    /// The number of threads used for decompressing the VCF
+    pub threads: usize,
+    /// The largest number of channels to allocate for the window
+    pub max_channels: usize,
+}
+
+/// The counts of the variants of a VCF file per channel and sample, opaque to C.
+pub struct MutsigResult {
+    samples: Vec<CString>,
+    channels: Vec<CString>,
+    results: ResultMatrix,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Remember the `message` as the last error of the thread.
+fn set_last_error(message: &str) {
+    // A message can not contain NUL, which would end it early in C anyway
+    let message = CString::new(message.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Return the labels of the channels in the order of their index, i.e., the labels of the
+/// user or the signatures with a pyrimidine as reference base.
+fn channel_labels(signatures: &Signatures) -> Vec<String> {
+    if let Some(labels) = signatures.labels() {
+        return labels.to_vec();
+    }
+    let mut labels: Vec<Option<String>> = vec![None; signatures.channel_count()];
+    for (s, i) in signatures.iter() {
+        if labels[i].is_none() || s.is_forward_signature() {
+            labels[i] = Some(s.to_string());
+        }
+    }
+    labels.into_iter().map(Option::unwrap_or_default).collect()
+}
+
+/// Return the string at `s`, or `None` if it is null or not UTF-8.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string.
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        None
+    } else {
+        CStr::from_ptr(s).to_str().ok()
+    }
+}
+
+fn to_c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', " ")).unwrap()
+}
+
+/// Return the default parameters, i.e., substitutions without context counted by one thread.
+#[no_mangle]
+pub extern "C" fn mutsig_options_default() -> MutsigOptions {
+    MutsigOptions {
+        window: 0,
+        threads: 1,
+        max_channels: signature::DEFAULT_MAX_CHANNELS,
+    }
+}
+
+/// Count the variants of the VCF file at `vcf` against the reference sequences (FASTA) at
+/// `reference` with the `options`, or the default ones if null. On success, `*result` is set
+/// to the counts, which the caller releases with `mutsig_result_free`. Otherwise, `*result`
+/// is set to null and `mutsig_last_error` describes the failure.
+///
+/// # Safety
+///
+/// `vcf` and `reference` must be null or NUL-terminated strings, `options` must be null or
+/// point to valid options and `result` must be null or point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn mutsig_count(
+    vcf: *const c_char,
+    reference: *const c_char,
+    options: *const MutsigOptions,
+    result: *mut *mut MutsigResult,
+) -> MutsigStatus {
+    if result.is_null() {
+        set_last_error("Can not count without a result pointer");
+        return MutsigStatus::InvalidArgument;
+    }
+    *result = ptr::null_mut();
+    let (vcf, reference) = match (to_str(vcf), to_str(reference)) {
+        (Some(v), Some(r)) => (v, r),
+        _ => {
+            set_last_error("Can not count without the paths of the VCF and the reference as UTF-8");
+            return MutsigStatus::InvalidArgument;
+        }
+    };
+    let options = if options.is_null() {
+        mutsig_options_default()
+    } else {
+        *options
+    };
+
+    let counted = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut count_options = CountOptions::new(vcf, reference);
+        count_options.window = options.window;
+        count_options.threads = options.threads;
+        count_options.max_channels = options.max_channels;
+        count_vcf(&count_options)
+    }));
+    match counted {
+        Ok(Ok(counts)) => {
+            *result = Box::into_raw(Box::new(MutsigResult {
+                samples: counts.samples.iter().map(|s| to_c_string(s)).collect(),
+                channels: channel_labels(&counts.signatures)
+                    .iter()
+                    .map(|s| to_c_string(s))
+                    .collect(),
+                results: counts.results,
+            }));
+            MutsigStatus::Ok
+        }
+        Ok(Err(e)) => {
+            set_last_error(&e.to_string());
+            MutsigStatus::Error
+        }
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown cause".to_owned());
+            set_last_error(&format!(
+                "Can not count the variants: panicked: {}",
+                message
+            ));
+            MutsigStatus::Panic
+        }
+    }
+}
+
+/// Return the message of the last failed `mutsig_count` on this thread, or null if none
+/// failed. The message is valid until the next call of `mutsig_count` on this thread.
+#[no_mangle]
+pub extern "C" fn mutsig_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Return the number of counted samples, or 0 if `result` is null.
+///
+/// # Safety
+///
+/// `result` must be null or a result of `mutsig_count` that was not released.
+#[no_mangle]
+pub unsafe extern "C" fn mutsig_result_sample_count(result: *const MutsigResult) -> usize {
+    result.as_ref().map_or(0, |r| r.samples.len())
+}
+
+/// Return the name of the sample at `index`, or null if `result` is null or it has no such
+/// sample. The name is owned by the result.
+///
+/// # Safety
+///
+/// `result` must be null or a result of `mutsig_count` that was not released.
+#[no_mangle]
+pub unsafe extern "C" fn mutsig_result_sample(
+    result: *const MutsigResult,
+    index: usize,
+) -> *const c_char {
+    result
+        .as_ref()
+        .and_then(|r| r.samples.get(index))
+        .map_or(ptr::null(), |s| s.as_ptr())
+}
+
+/// Return the number of channels, or 0 if `result` is null.
+///
+/// # Safety
+///
+/// `result` must be null or a result of `mutsig_count` that was not released.
+#[no_mangle]
+pub unsafe extern "C" fn mutsig_result_channel_count(result: *const MutsigResult) -> usize {
+    result.as_ref().map_or(0, |r| r.channels.len())
+}
+
+/// Return the label of the channel at `index`, or null if `result` is null or it has no such
+/// channel. The label is owned by the result.
+///
+/// # Safety
+///
+/// `result` must be null or a result of `mutsig_count` that was not released.
+#[no_mangle]
+pub unsafe extern "C" fn mutsig_result_channel(
+    result: *const MutsigResult,
+    index: usize,
+) -> *const c_char {
+    result
+        .as_ref()
+        .and_then(|r| r.channels.get(index))
+        .map_or(ptr::null(), |s| s.as_ptr())
+}
+
+/// Set `*count` to the count of the channel at `channel` in the sample at `sample`.
+///
+/// # Safety
+///
+/// `result` must be null or a result of `mutsig_count` that was not released and `count`
+/// must be null or point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn mutsig_result_count(
+    result: *const MutsigResult,
+    channel: usize,
+    sample: usize,
+    count: *mut u64,
+) -> MutsigStatus {
+    match result.as_ref() {
+        Some(r) if !count.is_null() && channel < r.channels.len() && sample < r.samples.len() => {
+            *count = r.results.get(channel, sample);
+            MutsigStatus::Ok
+        }
+        _ => MutsigStatus::InvalidArgument,
+    }
+}
+
+/// Release a result of `mutsig_count`, including its strings. Null is ignored.
+///
+/// # Safety
+///
+/// `result` must be null or a result of `mutsig_count` that was not released.
+#[no_mangle]
+pub unsafe extern "C" fn mutsig_result_free(result: *mut MutsigResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testdata(name: &str) -> CString {
+        CString::new(format!("{}/testdata/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+    }
+
+    #[test]
+    fn test_count() {
+        let (vcf, reference) = (testdata("ex2.vcf"), testdata("ex2.fa"));
+        let mut result = ptr::null_mut();
+        unsafe {
+            let status = mutsig_count(vcf.as_ptr(), reference.as_ptr(), ptr::null(), &mut result);
+            assert_eq!(status, MutsigStatus::Ok);
+            assert_eq!(mutsig_result_sample_count(result), 2);
+            let sample = CStr::from_ptr(mutsig_result_sample(result, 1));
+            assert_eq!(sample.to_str(), Ok("Example2"));
+            assert!(mutsig_result_sample(result, 2).is_null());
+
+            assert_eq!(mutsig_result_channel_count(result), 6);
+            let labels: Vec<&str> = (0..6)
+                .map(|i| {
+                    CStr::from_ptr(mutsig_result_channel(result, i))
+                        .to_str()
+                        .unwrap()
+                })
+                .collect();
+            assert!(labels.contains(&"T>G"));
+            assert!(!labels.contains(&"A>C"));
+            let c_a = labels.iter().position(|l| *l == "C>A").unwrap();
+            let mut count = 0;
+            assert_eq!(
+                mutsig_result_count(result, c_a, 0, &mut count),
+                MutsigStatus::Ok
+            );
+            assert_eq!(count, 2);
+            assert_eq!(
+                mutsig_result_count(result, 6, 0, &mut count),
+                MutsigStatus::InvalidArgument
+            );
+            mutsig_result_free(result);
+        }
+    }
+
+    #[test]
+    fn test_count_errors() {
+        let reference = testdata("ex2.fa");
+        let missing = testdata("missing.vcf");
+        let mut result = ptr::null_mut();
+        unsafe {
+            let status = mutsig_count(ptr::null(), reference.as_ptr(), ptr::null(), &mut result);
+            assert_eq!(status, MutsigStatus::InvalidArgument);
+            assert!(result.is_null());
+
+            let options = MutsigOptions {
+                window: 1,
+                ..mutsig_options_default()
+            };
+            let status = mutsig_count(missing.as_ptr(), reference.as_ptr(), &options, &mut result);
+            assert_eq!(status, MutsigStatus::Error);
+            assert!(result.is_null());
+            let message = CStr::from_ptr(mutsig_last_error()).to_str().unwrap();
+            assert!(message.contains("missing.vcf"), "{}", message);
+            mutsig_result_free(result);
+        }
+    }
+}
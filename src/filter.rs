@@ -0,0 +1,735 @@
+use crate::error::Error;
+use crate::summary::Reason;
+use rust_htslib::bcf::header::{HeaderRecord, HeaderView, TagType};
+use rust_htslib::bcf::record::Numeric;
+use rust_htslib::bcf::Record;
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::path::Path;
+
+/// Read a set of variant IDs from the (optionally gzip compressed) file at `path`,
+/// one ID per line. Empty lines and lines starting with `#` are skipped.
+pub fn read_ids<P: AsRef<Path>>(path: P) -> Result<HashSet<String>, String> {
+    let name = path.as_ref().display().to_string();
+    let mut ids = HashSet::new();
+    for line in crate::input::open(path)?.lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => return Err(format!("Can not read '{}': {}", name, e)),
+        };
+        let id = line.trim();
+        if !id.is_empty() && !id.starts_with('#') {
+            ids.insert(id.to_owned());
+        }
+    }
+    Ok(ids)
+}
+
+/// Check the ID column `ids` of a record (possibly multiple IDs separated by `;`)
+/// against the `include` and `exclude` sets. The include set is applied first and
+/// requires at least one of the IDs to be listed; the exclude set rejects records
+/// with any listed ID. Missing IDs (`.`) never match. Returns the reason if the
+/// record should be skipped.
+pub fn check_ids(
+    ids: &str,
+    include: Option<&HashSet<String>>,
+    exclude: Option<&HashSet<String>>,
+) -> Option<Reason> {
+    let mut record_ids = ids.split(';').filter(|i| !i.is_empty() && *i != ".");
+    if let Some(include) = include {
+        if !record_ids.clone().any(|i| include.contains(i)) {
+            return Some(Reason::IdNotIncluded);
+        }
+    }
+    if let Some(exclude) = exclude {
+        if record_ids.any(|i| exclude.contains(i)) {
+            return Some(Reason::IdExcluded);
+        }
+    }
+    None
+}
+
+/// Check if `contig` is a primary human chromosome, i.e., 1-22, X or Y with or without a
+/// `chr` prefix. Mitochondria, unplaced, ALT and decoy contigs are not primary.
+pub fn is_primary_contig(contig: &str) -> bool {
+    let name = contig.strip_prefix("chr").unwrap_or(contig);
+    match name {
+        "X" | "Y" => true,
+        _ => {
+            !name.starts_with('0')
+                && name.bytes().all(|b| b.is_ascii_digit())
+                && matches!(name.parse::<u8>(), Ok(1..=22))
+        }
+    }
+}
+
+/// Check if `contig` is selected by the `chromosomes` to count and, if `primary_only`,
+/// a primary chromosome. Returns the reason if the record should be skipped.
+pub fn check_contig(
+    contig: &str,
+    chromosomes: Option<&HashSet<String>>,
+    primary_only: bool,
+) -> Option<Reason> {
+    if let Some(chromosomes) = chromosomes {
+        if !chromosomes.contains(contig) {
+            return Some(Reason::OtherContig);
+        }
+    }
+    if primary_only && !is_primary_contig(contig) {
+        return Some(Reason::OtherContig);
+    }
+    None
+}
+
+/// Check if the FILTER column of `record` is `PASS` or missing (`.`).
+pub fn is_pass(record: &Record) -> bool {
+    let header = record.header();
+    record.filters().all(|id| header.id_to_name(id) == b"PASS")
+}
+
+/// Return the names of the FILTER column of `record`. Records without any filter (`.`)
+/// are reported as `PASS`.
+pub fn filter_names(record: &Record) -> Vec<String> {
+    let header = record.header();
+    let names: Vec<String> = record
+        .filters()
+        .map(|id| String::from_utf8_lossy(&header.id_to_name(id)).into_owned())
+        .collect();
+    if names.is_empty() {
+        vec!["PASS".to_owned()]
+    } else {
+        names
+    }
+}
+
+/// Return the names of the filters declared in the `##FILTER` lines of `header`.
+pub fn declared_filters(header: &HeaderView) -> HashSet<String> {
+    header
+        .header_records()
+        .into_iter()
+        .filter_map(|r| match r {
+            HeaderRecord::Filter { values, .. } => values.get("ID").cloned(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Check the FILTER names `filters` of a record against the `include` and `exclude`
+/// sets. A record is skipped if any of its filters is excluded or, if an include set is
+/// given, none of its filters is included. Returns the reason if the record should be
+/// skipped.
+pub fn check_filters(
+    filters: &[String],
+    include: Option<&HashSet<String>>,
+    exclude: Option<&HashSet<String>>,
+) -> Option<Reason> {
+    if let Some(exclude) = exclude {
+        if filters.iter().any(|f| exclude.contains(f)) {
+            return Some(Reason::Filtered);
+        }
+    }
+    if let Some(include) = include {
+        if !filters.iter().any(|f| include.contains(f)) {
+            return Some(Reason::Filtered);
+        }
+    }
+    None
+}
+
+/// Check the QUAL column `qual` of a record against the threshold `min_qual`. Missing
+/// values (stored by htslib as a special NaN) are never below the threshold, but are
+/// rejected if `require_qual` is set. Returns the reason if the record should be skipped.
+pub fn check_qual(qual: f32, min_qual: Option<f32>, require_qual: bool) -> Option<Reason> {
+    if qual.is_missing() || qual.is_nan() {
+        if require_qual {
+            return Some(Reason::MissingQual);
+        }
+        return None;
+    }
+    match min_qual {
+        Some(m) if qual < m => Some(Reason::LowQual),
+        _ => None,
+    }
+}
+
+/// Read the string FORMAT field `tag` of `record` for the samples at `bcf_sample_indizes`,
+/// in the order of the result columns. Missing values (`.`) are `None`, and if the record
+/// does not carry the field at all, the values of all samples are missing.
+pub fn format_strings(
+    record: &Record,
+    tag: &[u8],
+    bcf_sample_indizes: &[usize],
+) -> Result<Vec<Option<String>>, Error> {
+    let values = match record.format(tag).string() {
+        Ok(v) => v,
+        Err(rust_htslib::errors::Error::BcfUndefinedTag { .. })
+        | Err(rust_htslib::errors::Error::BcfMissingTag { .. }) => {
+            return Ok(vec![None; bcf_sample_indizes.len()])
+        }
+        Err(e) => {
+            return Err(Error::Htslib {
+                action: format!(
+                    "read FORMAT/{} at position {}",
+                    String::from_utf8_lossy(tag),
+                    record.pos() + 1
+                ),
+                source: e,
+            })
+        }
+    };
+    Ok(bcf_sample_indizes
+        .iter()
+        .map(|i| match values.get(*i) {
+            Some(v) if !v.is_empty() && *v != b"." => Some(String::from_utf8_lossy(v).into_owned()),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Check if the per-sample filter `ft` (FORMAT/FT) is `PASS` or missing.
+pub fn is_sample_pass(ft: Option<&str>) -> bool {
+    match ft {
+        Some(ft) => ft == "PASS",
+        None => true,
+    }
+}
+
+/// Read the integer FORMAT field `tag` of `record` for the samples at `bcf_sample_indizes`,
+/// in the order of the result columns. Missing values are `None`, and if the record
+/// does not carry the field at all, the values of all samples are missing.
+pub fn format_integers(
+    record: &Record,
+    tag: &[u8],
+    bcf_sample_indizes: &[usize],
+) -> Result<Vec<Vec<Option<i32>>>, Error> {
+    let values = match record.format(tag).integer() {
+        Ok(v) => v,
+        Err(rust_htslib::errors::Error::BcfUndefinedTag { .. })
+        | Err(rust_htslib::errors::Error::BcfMissingTag { .. }) => {
+            return Ok(vec![Vec::new(); bcf_sample_indizes.len()])
+        }
+        Err(e) => {
+            return Err(Error::Htslib {
+                action: format!(
+                    "read FORMAT/{} at position {}",
+                    String::from_utf8_lossy(tag),
+                    record.pos() + 1
+                ),
+                source: e,
+            })
+        }
+    };
+    Ok(bcf_sample_indizes
+        .iter()
+        .map(|i| {
+            values[*i]
+                .iter()
+                .map(|v| if v.is_missing() { None } else { Some(*v) })
+                .collect()
+        })
+        .collect())
+}
+
+/// Read the float INFO field `tag` of `record`. Returns `None` if the record does not
+/// carry the field or it is not declared in the header.
+pub fn info_floats(record: &Record, tag: &str) -> Result<Option<Vec<f32>>, Error> {
+    match record.info(tag.as_bytes()).float() {
+        Ok(v) => Ok(v.map(|v| v.to_vec())),
+        Err(rust_htslib::errors::Error::BcfUndefinedTag { .. }) => Ok(None),
+        Err(e) => Err(Error::Htslib {
+            action: format!("read INFO/{} at position {}", tag, record.pos() + 1),
+            source: e,
+        }),
+    }
+}
+
+/// Check the population allele frequency of the alternative allele `allele` (1-based)
+/// against `max_af`, given the values `afs` of the INFO field of a record with
+/// `n_alternatives` alternative alleles. The field holds either one value per alternative
+/// allele (`Number=A`) or a single value for all of them. Alleles without frequency are
+/// rejected if `require` is set. Returns the reason if the allele should not be counted.
+pub fn check_pop_af(
+    afs: Option<&[f32]>,
+    allele: usize,
+    n_alternatives: usize,
+    max_af: f32,
+    require: bool,
+) -> Option<Reason> {
+    let af = match afs {
+        Some(v) if v.len() == n_alternatives => v.get(allele - 1),
+        Some(v) if v.len() == 1 => v.first(),
+        _ => None,
+    };
+    match af {
+        Some(af) if !af.is_missing() && !af.is_nan() => {
+            if *af > max_af {
+                Some(Reason::PopulationAf)
+            } else {
+                None
+            }
+        }
+        _ if require => Some(Reason::MissingPopulationAf),
+        _ => None,
+    }
+}
+
+/// The INFO field marking records as somatic, either as a flag (e.g., `SOMATIC`) or with
+/// a specific value (e.g., `STATUS=2`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct SomaticTag {
+    tag: String,
+    value: Option<String>,
+}
+
+impl SomaticTag {
+    /// Parse `TAG` or `TAG=VALUE`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (tag, value) = match s.find('=') {
+            Some(i) => (&s[..i], Some(s[i + 1..].to_owned())),
+            None => (s, None),
+        };
+        if tag.is_empty() || value.as_ref().is_some_and(|v| v.is_empty()) {
+            return Err(format!(
+                "Invalid somatic tag '{}', expected TAG or TAG=VALUE",
+                s
+            ));
+        }
+        Ok(SomaticTag {
+            tag: tag.to_owned(),
+            value,
+        })
+    }
+
+    /// Return the name of the INFO field.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Check if `record` is marked as somatic. Flags must be set, and fields of other types
+    /// must be present or, if a value is given, carry it.
+    pub fn matches(&self, record: &Record) -> Result<bool, Error> {
+        let tag = self.tag.as_bytes();
+        let tag_type = match record.header().info_type(tag) {
+            Ok((t, _)) => t,
+            Err(_) => return Ok(false),
+        };
+        let error = |e: rust_htslib::errors::Error| Error::Htslib {
+            action: format!("read INFO/{} at position {}", self.tag, record.pos() + 1),
+            source: e,
+        };
+        let values: Vec<String> = match tag_type {
+            TagType::Flag => {
+                if self.value.is_some() {
+                    return Err(Error::InvalidOptions(format!(
+                        "INFO/{} is a flag and can not be matched against a value",
+                        self.tag
+                    )));
+                }
+                return record.info(tag).flag().map_err(error);
+            }
+            TagType::Integer => match record.info(tag).integer().map_err(error)? {
+                None => Vec::new(),
+                Some(v) => v
+                    .iter()
+                    .filter(|i| !i.is_missing())
+                    .map(|i| i.to_string())
+                    .collect(),
+            },
+            TagType::Float => match record.info(tag).float().map_err(error)? {
+                None => Vec::new(),
+                Some(v) => v
+                    .iter()
+                    .filter(|f| !f.is_missing())
+                    .map(|f| f.to_string())
+                    .collect(),
+            },
+            TagType::String => match record.info(tag).string().map_err(error)? {
+                None => Vec::new(),
+                Some(v) => v
+                    .iter()
+                    .map(|s| String::from_utf8_lossy(s).into_owned())
+                    .collect(),
+            },
+        };
+        Ok(match &self.value {
+            None => !values.is_empty(),
+            Some(value) => match (tag_type, value.parse::<f64>()) {
+                (TagType::String, _) => values.iter().any(|v| v == value),
+                (_, Ok(n)) => values.iter().any(|v| v.parse::<f64>() == Ok(n)),
+                (_, Err(_)) => {
+                    return Err(Error::InvalidOptions(format!(
+                        "INFO/{} is numeric and can not be matched against '{}'",
+                        self.tag, value
+                    )))
+                }
+            },
+        })
+    }
+}
+
+/// Compute the variant allele fraction of `allele` given the allelic depths `ad` of a
+/// sample (one value per allele of the record, reference first). Returns `None` if the
+/// fraction can not be computed, i.e., if the depths are missing, do not match the
+/// alleles or sum up to zero.
+pub fn vaf(ad: &[Option<i32>], allele: usize) -> Option<f32> {
+    if allele >= ad.len() || ad.iter().any(|d| d.is_none()) {
+        return None;
+    }
+    let total: i64 = ad.iter().map(|d| d.unwrap() as i64).sum();
+    if total <= 0 {
+        return None;
+    }
+    Some(ad[allele].unwrap() as f32 / total as f32)
+}
+
+/// Check the variant allele fraction of `allele` given the allelic depths `ad` of a sample
+/// against the inclusive window `min_vaf` to `max_vaf`. Alleles whose fraction can not be
+/// computed are rejected unless `keep_missing` is set. Returns the reason if the allele
+/// should not be counted.
+pub fn check_vaf(
+    ad: &[Option<i32>],
+    allele: usize,
+    min_vaf: Option<f32>,
+    max_vaf: Option<f32>,
+    keep_missing: bool,
+) -> Option<Reason> {
+    match vaf(ad, allele) {
+        None if keep_missing => None,
+        None => Some(Reason::MissingAd),
+        Some(v) if min_vaf.is_some_and(|m| v < m) => Some(Reason::LowVaf),
+        Some(v) if max_vaf.is_some_and(|m| v > m) => Some(Reason::HighVaf),
+        Some(_) => None,
+    }
+}
+
+/// Check the number of reads supporting the alternative allele `allele` given the allelic
+/// depths `ad` of a sample against `min_reads`. Alleles without depth are rejected unless
+/// `keep_missing` is set. Returns the reason if the allele should not be counted.
+pub fn check_alt_reads(
+    ad: &[Option<i32>],
+    allele: usize,
+    min_reads: i32,
+    keep_missing: bool,
+) -> Option<Reason> {
+    match ad.get(allele) {
+        Some(Some(reads)) if *reads < min_reads => Some(Reason::LowAltReads),
+        Some(Some(_)) => None,
+        _ if keep_missing => None,
+        _ => Some(Reason::MissingAd),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ids: &[&str]) -> HashSet<String> {
+        ids.iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn test_primary_contigs() {
+        for c in &["1", "22", "X", "Y", "chr1", "chr22", "chrX"] {
+            assert!(is_primary_contig(c), "{}", c);
+        }
+        for c in &[
+            "0",
+            "23",
+            "01",
+            "+1",
+            "M",
+            "MT",
+            "chrM",
+            "chr1_KI270706v1_random",
+            "chrUn_JTFH01000001v1_decoy",
+            "GL000192.1",
+            "chr",
+            "chrchr1",
+        ] {
+            assert!(!is_primary_contig(c), "{}", c);
+        }
+    }
+
+    #[test]
+    fn test_check_contig() {
+        let chromosomes = set(&["1", "MT"]);
+        assert_eq!(check_contig("1", Some(&chromosomes), false), None);
+        assert_eq!(check_contig("MT", Some(&chromosomes), false), None);
+        assert_eq!(
+            check_contig("2", Some(&chromosomes), false),
+            Some(Reason::OtherContig)
+        );
+        assert_eq!(check_contig("2", None, true), None);
+        // Both restrictions apply
+        assert_eq!(
+            check_contig("MT", Some(&chromosomes), true),
+            Some(Reason::OtherContig)
+        );
+        assert_eq!(check_contig("MT", None, false), None);
+    }
+
+    #[test]
+    fn test_sample_pass() {
+        assert!(is_sample_pass(None));
+        assert!(is_sample_pass(Some("PASS")));
+        assert!(!is_sample_pass(Some("LowGQ")));
+        assert!(!is_sample_pass(Some("LowGQ;LowDP")));
+    }
+
+    #[test]
+    fn test_include_ids() {
+        let include = set(&["rs1", "rs2"]);
+        assert_eq!(check_ids("rs1", Some(&include), None), None);
+        assert_eq!(
+            check_ids("rs3", Some(&include), None),
+            Some(Reason::IdNotIncluded)
+        );
+        assert_eq!(
+            check_ids(".", Some(&include), None),
+            Some(Reason::IdNotIncluded)
+        );
+        assert_eq!(check_ids(".", None, None), None);
+    }
+
+    #[test]
+    fn test_multiple_ids() {
+        let include = set(&["rs2"]);
+        let exclude = set(&["COSV1"]);
+        assert_eq!(check_ids("rs1;rs2", Some(&include), None), None);
+        assert_eq!(
+            check_ids("rs1;rs3", Some(&include), None),
+            Some(Reason::IdNotIncluded)
+        );
+        assert_eq!(
+            check_ids("rs1;COSV1", None, Some(&exclude)),
+            Some(Reason::IdExcluded)
+        );
+    }
+
+    #[test]
+    fn test_include_before_exclude() {
+        let include = set(&["rs1", "rs2"]);
+        let exclude = set(&["rs2", "rs3"]);
+        assert_eq!(check_ids("rs1", Some(&include), Some(&exclude)), None);
+        assert_eq!(
+            check_ids("rs2", Some(&include), Some(&exclude)),
+            Some(Reason::IdExcluded)
+        );
+        assert_eq!(
+            check_ids("rs3", Some(&include), Some(&exclude)),
+            Some(Reason::IdNotIncluded)
+        );
+        assert_eq!(
+            check_ids("rs1;rs3", Some(&include), Some(&exclude)),
+            Some(Reason::IdExcluded)
+        );
+    }
+
+    #[test]
+    fn test_is_pass() {
+        use rust_htslib::bcf::Read;
+        let mut variants = rust_htslib::bcf::Reader::from_path(format!(
+            "{}/testdata/ex2_filter.vcf",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let pass: Vec<bool> = variants.records().map(|r| is_pass(&r.unwrap())).collect();
+        assert_eq!(pass, vec![true, true, false, false, false]);
+    }
+
+    #[test]
+    fn test_check_filters() {
+        let filters = |f: &[&str]| -> Vec<String> { f.iter().map(|f| f.to_string()).collect() };
+        let include = set(&["PASS", "slippage"]);
+        let exclude = set(&["germline_risk"]);
+        assert_eq!(
+            check_filters(&filters(&["PASS"]), Some(&include), None),
+            None
+        );
+        assert_eq!(
+            check_filters(&filters(&["LowQual"]), Some(&include), None),
+            Some(Reason::Filtered)
+        );
+        // Records with multiple filters are kept if any of them is included ...
+        assert_eq!(
+            check_filters(&filters(&["LowQual", "slippage"]), Some(&include), None),
+            None
+        );
+        // ... but skipped if any of them is excluded
+        assert_eq!(
+            check_filters(
+                &filters(&["germline_risk", "slippage"]),
+                Some(&include),
+                Some(&exclude)
+            ),
+            Some(Reason::Filtered)
+        );
+        assert_eq!(
+            check_filters(&filters(&["slippage"]), None, Some(&exclude)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_filter_names() {
+        use rust_htslib::bcf::Read;
+        let mut variants = rust_htslib::bcf::Reader::from_path(format!(
+            "{}/testdata/ex2_filter.vcf",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let declared = declared_filters(variants.header());
+        assert_eq!(
+            declared,
+            set(&["PASS", "LowQual", "germline_risk", "slippage"])
+        );
+        let names: Vec<Vec<String>> = variants
+            .records()
+            .map(|r| filter_names(&r.unwrap()))
+            .collect();
+        assert_eq!(names[1], vec!["PASS".to_owned()]);
+        assert_eq!(
+            names[4],
+            vec!["germline_risk".to_owned(), "slippage".to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_check_qual() {
+        let missing = f32::missing();
+        assert_eq!(check_qual(30.0, Some(30.0), false), None);
+        assert_eq!(check_qual(29.9, Some(30.0), false), Some(Reason::LowQual));
+        assert_eq!(check_qual(missing, Some(30.0), false), None);
+        assert_eq!(
+            check_qual(missing, Some(30.0), true),
+            Some(Reason::MissingQual)
+        );
+        assert_eq!(check_qual(missing, None, true), Some(Reason::MissingQual));
+        assert_eq!(check_qual(0.0, None, true), None);
+    }
+
+    #[test]
+    fn test_vaf() {
+        let ad = [Some(10), Some(8), Some(2)];
+        assert_eq!(vaf(&ad, 1), Some(0.4));
+        assert_eq!(vaf(&ad, 2), Some(0.1));
+        assert_eq!(vaf(&ad, 3), None);
+        assert_eq!(vaf(&[Some(0), Some(0)], 1), None);
+        assert_eq!(vaf(&[Some(5), None], 1), None);
+        assert_eq!(vaf(&[], 1), None);
+    }
+
+    #[test]
+    fn test_check_vaf() {
+        let ad = [Some(10), Some(8), Some(2)];
+        assert_eq!(check_vaf(&ad, 2, Some(0.1), None, false), None);
+        assert_eq!(
+            check_vaf(&ad, 2, Some(0.11), None, false),
+            Some(Reason::LowVaf)
+        );
+        assert_eq!(
+            check_vaf(&ad, 1, Some(0.1), Some(0.3), false),
+            Some(Reason::HighVaf)
+        );
+        assert_eq!(check_vaf(&ad, 1, None, Some(0.4), false), None);
+        assert_eq!(
+            check_vaf(&[Some(0), Some(0)], 1, Some(0.1), None, false),
+            Some(Reason::MissingAd)
+        );
+        assert_eq!(
+            check_vaf(&[Some(0), Some(0)], 1, Some(0.1), None, true),
+            None
+        );
+    }
+
+    #[test]
+    fn test_check_pop_af() {
+        let afs = [0.2, 0.0001];
+        assert_eq!(
+            check_pop_af(Some(&afs), 1, 2, 0.01, false),
+            Some(Reason::PopulationAf)
+        );
+        assert_eq!(check_pop_af(Some(&afs), 2, 2, 0.01, false), None);
+        // A single value applies to all alternative alleles
+        assert_eq!(
+            check_pop_af(Some(&[0.2]), 2, 2, 0.01, false),
+            Some(Reason::PopulationAf)
+        );
+        assert_eq!(
+            check_pop_af(Some(&[f32::missing()]), 1, 1, 0.01, false),
+            None
+        );
+        assert_eq!(
+            check_pop_af(Some(&[f32::missing()]), 1, 1, 0.01, true),
+            Some(Reason::MissingPopulationAf)
+        );
+        assert_eq!(check_pop_af(None, 1, 1, 0.01, false), None);
+        assert_eq!(
+            check_pop_af(Some(&afs), 1, 3, 0.01, true),
+            Some(Reason::MissingPopulationAf)
+        );
+    }
+
+    #[test]
+    fn test_check_alt_reads() {
+        let ad = [Some(20), Some(2), Some(10)];
+        assert_eq!(check_alt_reads(&ad, 1, 4, false), Some(Reason::LowAltReads));
+        assert_eq!(check_alt_reads(&ad, 2, 4, false), None);
+        assert_eq!(check_alt_reads(&ad, 3, 4, true), None);
+        assert_eq!(check_alt_reads(&ad, 3, 4, false), Some(Reason::MissingAd));
+        assert_eq!(
+            check_alt_reads(&[Some(5), None], 1, 4, false),
+            Some(Reason::MissingAd)
+        );
+    }
+
+    #[test]
+    fn test_somatic_tag() {
+        use rust_htslib::bcf::Read;
+        let matches = |vcf: &str, tag: &str| -> Vec<bool> {
+            let tag = SomaticTag::parse(tag).unwrap();
+            let mut variants = rust_htslib::bcf::Reader::from_path(format!(
+                "{}/testdata/{}",
+                env!("CARGO_MANIFEST_DIR"),
+                vcf
+            ))
+            .unwrap();
+            variants
+                .records()
+                .map(|r| tag.matches(&r.unwrap()).unwrap())
+                .collect()
+        };
+        assert_eq!(
+            matches("ex2_somatic_flag.vcf", "SOMATIC"),
+            vec![true, false, true]
+        );
+        assert_eq!(
+            matches("ex2_somatic_status.vcf", "STATUS=2"),
+            vec![true, false, false, true]
+        );
+        assert_eq!(
+            matches("ex2_somatic_status.vcf", "SS=2"),
+            vec![true, false, true, false]
+        );
+        assert_eq!(
+            matches("ex2_somatic_status.vcf", "STATUS"),
+            vec![true, true, false, true]
+        );
+        assert_eq!(
+            matches("ex2_somatic_status.vcf", "SOMATIC"),
+            vec![false, false, false, false]
+        );
+        assert!(SomaticTag::parse("STATUS=").is_err());
+        assert!(SomaticTag::parse("=2").is_err());
+    }
+
+    #[test]
+    fn test_read_ids() {
+        let ids = read_ids(format!(
+            "{}/testdata/ex2_ids.txt",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        assert_eq!(ids, set(&["rs2", "COSV5"]));
+    }
+}
@@ -0,0 +1,727 @@
+use crate::signature::Signature;
+use crate::similarity;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+
+/// The maximum number of iterations of the NNLS solver per signature of the catalog.
+const MAX_ITERATIONS_PER_SIGNATURE: usize = 30;
+
+/// A matrix of values labeled by rows and columns, e.g., the counts of the channels (rows)
+/// per sample (columns) or the probabilities of the channels per reference signature.
+#[derive(Debug, PartialEq)]
+pub struct Matrix {
+    rows: Vec<String>,
+    columns: Vec<String>,
+    values: Vec<Vec<f64>>,
+}
+
+impl Matrix {
+    /// Create a matrix of the `values` per row with the labels `rows` and `columns`.
+    pub fn new(rows: Vec<String>, columns: Vec<String>, values: Vec<Vec<f64>>) -> Matrix {
+        Matrix {
+            rows,
+            columns,
+            values,
+        }
+    }
+
+    /// Return the labels of the rows.
+    pub fn rows(&self) -> &Vec<String> {
+        &self.rows
+    }
+
+    /// Return the labels of the columns.
+    pub fn columns(&self) -> &Vec<String> {
+        &self.columns
+    }
+
+    /// Return the value of the `row` and `column`.
+    pub fn value(&self, row: usize, column: usize) -> f64 {
+        self.values[row][column]
+    }
+
+    /// Return the values of the `column`.
+    pub fn column(&self, column: usize) -> Vec<f64> {
+        self.values.iter().map(|row| row[column]).collect()
+    }
+}
+
+/// The estimated exposures of a sample to the reference signatures.
+#[derive(Debug)]
+pub struct Fit {
+    /// The exposure per signature in the order of the catalog
+    pub exposures: Vec<f64>,
+    /// The euclidean norm of the difference between the counts and their reconstruction
+    pub residual: f64,
+    /// The cosine similarity of the counts and their reconstruction, or `None` if any of them
+    /// is zero
+    pub similarity: Option<f64>,
+    /// The indizes of the signatures in the order of their selection, if selected
+    pub selected: Option<Vec<usize>>,
+}
+
+/// The criterion of the improvement of the reconstruction by a signature in the forward
+/// selection of the signatures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Criterion {
+    /// The increase of the cosine similarity of the counts and their reconstruction
+    Cosine,
+    /// The decrease of the euclidean norm of the residual relative to the norm of the counts
+    L2,
+}
+
+/// The limits of the forward selection of the signatures of a sample.
+#[derive(Clone, Copy, Debug)]
+pub struct Selection {
+    /// The maximum number of selected signatures
+    pub max_signatures: usize,
+    /// The smallest improvement of the reconstruction to select another signature
+    pub min_improvement: f64,
+    /// The measure of the improvement
+    pub criterion: Criterion,
+}
+
+impl Fit {
+    /// Check if the sample is poorly explained by the catalog, i.e., if the similarity of its
+    /// counts and their reconstruction is below the `threshold`. Samples without counts are not.
+    pub fn is_poor(&self, threshold: f64) -> bool {
+        matches!(self.similarity, Some(s) if s < threshold)
+    }
+}
+
+/// Read a matrix from the (optionally gzip compressed) tab-separated file at `path`.
+pub fn read_matrix<P: AsRef<Path>>(path: P) -> Result<Matrix, String> {
+    let name = path.as_ref().display().to_string();
+    parse_matrix(crate::input::open(path)?, &name)
+}
+
+/// Parse a matrix from `reader`. The header names the columns after a first field naming
+/// the rows (e.g., `Type` or `Variant`), and each line holds the label of a row followed by
+/// its values. Empty and comment lines are skipped.
+pub fn parse_matrix<R: BufRead>(reader: R, name: &str) -> Result<Matrix, String> {
+    let mut columns: Option<Vec<String>> = None;
+    let mut rows = Vec::new();
+    let mut values = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => return Err(format!("Can not read '{}': {}", name, e)),
+        };
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.trim_end().split('\t');
+        let label = fields.next().unwrap().trim();
+        let n_columns = match &columns {
+            None => {
+                columns = Some(fields.map(|f| f.trim().to_owned()).collect());
+                continue;
+            }
+            Some(c) => c.len(),
+        };
+        let row = fields
+            .map(|f| f.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|e| format!("Can not parse value in line {} of '{}': {}", i + 1, name, e))?;
+        if row.len() != n_columns {
+            return Err(format!(
+                "Can not parse line {} of '{}', expected {} values but found {}",
+                i + 1,
+                name,
+                n_columns,
+                row.len()
+            ));
+        }
+        rows.push(label.to_owned());
+        values.push(row);
+    }
+    match columns {
+        Some(columns) if !columns.is_empty() => Ok(Matrix {
+            rows,
+            columns,
+            values,
+        }),
+        _ => Err(format!("Can not find any column in '{}'", name)),
+    }
+}
+
+/// Return the label of a channel independent of its style, i.e., substitutions labeled by
+/// their context (e.g., `TCA>T`) or as in COSMIC (e.g., `T[C>T]A`) are both labeled in the
+/// COSMIC style. Prefixes of stratified channels (e.g., `T:`) and other labels are kept.
+pub fn channel_key(label: &str) -> String {
+    let (prefix, channel) = match label.rfind(':') {
+        Some(i) => label.split_at(i + 1),
+        None => ("", label),
+    };
+    match Signature::parse(channel) {
+        Ok(s) => format!("{}{}", prefix, s.cosmic_label()),
+        Err(_) => label.to_owned(),
+    }
+}
+
+/// Return the index of each channel of the `catalog` in the `labels` of the counted channels.
+/// The channels must match, independent of their order and style.
+pub fn align_channels(catalog: &Matrix, labels: &[String]) -> Result<Vec<usize>, String> {
+    let keys: HashMap<String, usize> = labels
+        .iter()
+        .enumerate()
+        .map(|(i, l)| (channel_key(l), i))
+        .collect();
+    if keys.len() != labels.len() || keys.len() != catalog.rows.len() {
+        return Err(format!(
+            "Can not match {} channels to the {} channels of the signatures",
+            labels.len(),
+            catalog.rows.len()
+        ));
+    }
+    catalog
+        .rows
+        .iter()
+        .map(|label| match keys.get(&channel_key(label)) {
+            Some(i) => Ok(*i),
+            None => Err(format!(
+                "Can not match the signatures, channel '{}' is not counted",
+                label
+            )),
+        })
+        .collect()
+}
+
+/// Rescale the signatures of the `catalog` from the context frequencies of the `source` to
+/// those of the `target` territory (e.g., from the genome to the exome), i.e., multiply each
+/// channel by the ratio of its opportunities in the target and the source, and renormalize
+/// each signature to a sum of one. The opportunities are given by the first column.
+pub fn rescale_catalog(
+    catalog: &Matrix,
+    source: &Matrix,
+    target: &Matrix,
+) -> Result<Matrix, String> {
+    let source_order = align_channels(catalog, &source.rows)?;
+    let target_order = align_channels(catalog, &target.rows)?;
+    let mut values = catalog.values.clone();
+    for (r, row) in values.iter_mut().enumerate() {
+        let (s, t) = (
+            source.values[source_order[r]][0],
+            target.values[target_order[r]][0],
+        );
+        // A channel without opportunities in the source is only valid if it is not used
+        if s == 0.0 && row.iter().any(|v| *v != 0.0) {
+            return Err(format!(
+                "Can not rescale channel '{}' without opportunities",
+                catalog.rows[r]
+            ));
+        }
+        let factor = if s == 0.0 { 0.0 } else { t / s };
+        row.iter_mut().for_each(|v| *v *= factor);
+    }
+    for (c, signature) in catalog.columns.iter().enumerate() {
+        let total: f64 = values.iter().map(|row| row[c]).sum();
+        if total <= 0.0 {
+            return Err(format!(
+                "Can not rescale signature '{}', it has no opportunities in the target",
+                signature
+            ));
+        }
+        values.iter_mut().for_each(|row| row[c] /= total);
+    }
+    Ok(Matrix {
+        rows: catalog.rows.clone(),
+        columns: catalog.columns.clone(),
+        values,
+    })
+}
+
+/// Fit the `counts` of each sample to the signatures of the `catalog` by non-negative least
+/// squares. The channels of both matrices must match, independent of their order and style.
+pub fn fit(catalog: &Matrix, counts: &Matrix) -> Result<Vec<Fit>, String> {
+    let order = align_channels(catalog, &counts.rows)?;
+    (0..counts.columns.len())
+        .map(|sample| {
+            let b: Vec<f64> = order.iter().map(|i| counts.values[*i][sample]).collect();
+            let exposures = nnls(&catalog.values, &b)?;
+            Ok(evaluate(&catalog.values, &b, exposures, None))
+        })
+        .collect()
+}
+
+/// Fit the `counts` of each sample, indexed by channel and sample like the view of a
+/// `ResultMatrix`, to the signatures of the `catalog` without copying them into a `Matrix`.
+/// The channels of the counts are labeled by `labels`, which must match the channels of the
+/// catalog as for `fit`. Requires the cargo feature `ndarray`.
+#[cfg(feature = "ndarray")]
+pub fn fit_array<T>(
+    catalog: &Matrix,
+    labels: &[String],
+    counts: ndarray::ArrayView2<'_, T>,
+) -> Result<Vec<Fit>, String>
+where
+    T: crate::result::AsF64,
+{
+    if labels.len() != counts.nrows() {
+        return Err(format!(
+            "Can not fit counts of {} channels labeled by {} labels",
+            counts.nrows(),
+            labels.len()
+        ));
+    }
+    let order = align_channels(catalog, labels)?;
+    counts
+        .columns()
+        .into_iter()
+        .map(|column| {
+            let b: Vec<f64> = order.iter().map(|i| column[*i].as_f64()).collect();
+            let exposures = nnls(&catalog.values, &b)?;
+            Ok(evaluate(&catalog.values, &b, exposures, None))
+        })
+        .collect()
+}
+
+/// Fit the `counts` of each sample to a subset of the signatures of the `catalog`, selected
+/// forward: starting without any, the signature that most improves the reconstruction by the
+/// criterion of the `selection` is added, until the maximum number of signatures is reached or
+/// the improvement falls below the minimum. The exposures are then fitted to the selected
+/// signatures by non-negative least squares. Ties are selected in the order of the catalog.
+pub fn fit_sparse(
+    catalog: &Matrix,
+    counts: &Matrix,
+    selection: Selection,
+) -> Result<Vec<Fit>, String> {
+    let order = align_channels(catalog, &counts.rows)?;
+    (0..counts.columns.len())
+        .map(|sample| {
+            let b: Vec<f64> = order.iter().map(|i| counts.values[*i][sample]).collect();
+            let norm = b.iter().map(|v| v * v).sum::<f64>().sqrt();
+            let mut selected: Vec<usize> = Vec::new();
+            // The score of the reconstruction, which is to be maximized
+            let score = |exposures: &[f64], columns: &[usize]| {
+                let reconstruction = reconstruct(&subset(&catalog.values, columns), exposures);
+                match selection.criterion {
+                    Criterion::Cosine => {
+                        similarity::cosine_similarity(&b, &reconstruction).unwrap_or(0.0)
+                    }
+                    Criterion::L2 => {
+                        let residual = b
+                            .iter()
+                            .zip(&reconstruction)
+                            .map(|(v, r)| (v - r).powi(2))
+                            .sum::<f64>()
+                            .sqrt();
+                        -residual / norm
+                    }
+                }
+            };
+            let mut current = match selection.criterion {
+                Criterion::Cosine => 0.0,
+                Criterion::L2 => -1.0,
+            };
+            while norm > 0.0 && selected.len() < selection.max_signatures {
+                let mut best: Option<(usize, f64)> = None;
+                for j in (0..catalog.columns.len()).filter(|j| !selected.contains(j)) {
+                    let mut columns = selected.clone();
+                    columns.push(j);
+                    let exposures = nnls(&subset(&catalog.values, &columns), &b)?;
+                    let s = score(&exposures, &columns);
+                    if !matches!(best, Some((_, b)) if s <= b) {
+                        best = Some((j, s));
+                    }
+                }
+                match best {
+                    Some((j, s)) if s - current >= selection.min_improvement => {
+                        selected.push(j);
+                        current = s;
+                    }
+                    _ => break,
+                }
+            }
+            let mut exposures = vec![0.0; catalog.columns.len()];
+            if !selected.is_empty() {
+                let fitted = nnls(&subset(&catalog.values, &selected), &b)?;
+                for (j, x) in selected.iter().zip(fitted) {
+                    exposures[*j] = x;
+                }
+            }
+            Ok(evaluate(&catalog.values, &b, exposures, Some(selected)))
+        })
+        .collect()
+}
+
+/// Return the `columns` of the `catalog` given by its rows.
+fn subset(catalog: &[Vec<f64>], columns: &[usize]) -> Vec<Vec<f64>> {
+    catalog
+        .iter()
+        .map(|row| columns.iter().map(|j| row[*j]).collect())
+        .collect()
+}
+
+/// Return the fit of the counts `b` by the `exposures` to the signatures of the `catalog`.
+fn evaluate(
+    catalog: &[Vec<f64>],
+    b: &[f64],
+    exposures: Vec<f64>,
+    selected: Option<Vec<usize>>,
+) -> Fit {
+    let reconstruction = reconstruct(catalog, &exposures);
+    let residual = b
+        .iter()
+        .zip(&reconstruction)
+        .map(|(v, r)| (v - r).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    Fit {
+        similarity: similarity::cosine_similarity(b, &reconstruction),
+        exposures,
+        residual,
+        selected,
+    }
+}
+
+/// Return the reconstruction of the counts by the mixture of the signatures of the `catalog`,
+/// given by its rows, with the `exposures`.
+pub fn reconstruct(catalog: &[Vec<f64>], exposures: &[f64]) -> Vec<f64> {
+    catalog
+        .iter()
+        .map(|row| row.iter().zip(exposures).map(|(a, x)| a * x).sum())
+        .collect()
+}
+
+/// Solve `min ||a·x - b||` subject to `x >= 0` by the active set method of Lawson and Hanson.
+/// The matrix `a` is given by its rows.
+pub fn nnls(a: &[Vec<f64>], b: &[f64]) -> Result<Vec<f64>, String> {
+    let n = a.first().map_or(0, |r| r.len());
+    let scale = b.iter().map(|v| v.abs()).fold(1.0, f64::max);
+    let tolerance = 1e-10 * scale;
+    let mut x = vec![0.0; n];
+    let mut passive = vec![false; n];
+    for _ in 0..(MAX_ITERATIONS_PER_SIGNATURE * n.max(1)) {
+        // Move the variable with the steepest descent of the residual into the passive set
+        let w = gradient(a, b, &x);
+        let next = (0..n)
+            .filter(|j| !passive[*j] && w[*j] > tolerance)
+            .max_by(|i, j| w[*i].partial_cmp(&w[*j]).unwrap());
+        let next = match next {
+            Some(j) => j,
+            None => return Ok(x),
+        };
+        passive[next] = true;
+        loop {
+            let s = passive_least_squares(a, b, &passive)?;
+            if (0..n).all(|j| !passive[j] || s[j] > 0.0) {
+                x = s;
+                break;
+            }
+            // Step towards the solution until a variable hits zero and release it
+            let alpha = (0..n)
+                .filter(|j| passive[*j] && s[*j] <= 0.0)
+                .map(|j| x[j] / (x[j] - s[j]))
+                .fold(f64::INFINITY, f64::min);
+            for j in 0..n {
+                x[j] += alpha * (s[j] - x[j]);
+                if passive[j] && x[j] <= tolerance {
+                    passive[j] = false;
+                    x[j] = 0.0;
+                }
+            }
+        }
+    }
+    Err("Can not fit the signatures, the solver did not converge".to_owned())
+}
+
+/// Return the negative gradient of the squared residual, i.e., `aᵀ·(b - a·x)`.
+fn gradient(a: &[Vec<f64>], b: &[f64], x: &[f64]) -> Vec<f64> {
+    let mut w = vec![0.0; x.len()];
+    for (row, v) in a.iter().zip(b) {
+        let r = v - row.iter().zip(x).map(|(a, x)| a * x).sum::<f64>();
+        for (w, a) in w.iter_mut().zip(row) {
+            *w += a * r;
+        }
+    }
+    w
+}
+
+/// Solve the unconstrained least squares problem on the `passive` variables by the normal
+/// equations, fixing the other variables at zero.
+fn passive_least_squares(a: &[Vec<f64>], b: &[f64], passive: &[bool]) -> Result<Vec<f64>, String> {
+    let p: Vec<usize> = (0..passive.len()).filter(|j| passive[*j]).collect();
+    let k = p.len();
+    // The augmented matrix [aᵀa | aᵀb] of the passive columns
+    let mut m = vec![vec![0.0; k + 1]; k];
+    for (row, v) in a.iter().zip(b) {
+        for (i, pi) in p.iter().enumerate() {
+            for (j, pj) in p.iter().enumerate() {
+                m[i][j] += row[*pi] * row[*pj];
+            }
+            m[i][k] += row[*pi] * v;
+        }
+    }
+    // Gaussian elimination with partial pivoting
+    for c in 0..k {
+        let pivot = (c..k)
+            .max_by(|i, j| m[*i][c].abs().partial_cmp(&m[*j][c].abs()).unwrap())
+            .unwrap();
+        if m[pivot][c].abs() < 1e-12 {
+            return Err("Can not fit the signatures, they are linearly dependent".to_owned());
+        }
+        m.swap(c, pivot);
+        let (upper, lower) = m.split_at_mut(c + 1);
+        let pivot_row = &upper[c];
+        for row in lower.iter_mut() {
+            let f = row[c] / pivot_row[c];
+            for (v, p) in row[c..].iter_mut().zip(&pivot_row[c..]) {
+                *v -= f * p;
+            }
+        }
+    }
+    let mut s = vec![0.0; passive.len()];
+    for c in (0..k).rev() {
+        let sum: f64 = ((c + 1)..k).map(|j| m[c][j] * s[p[j]]).sum();
+        s[p[c]] = (m[c][k] - sum) / m[c][c];
+    }
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> Vec<Vec<f64>> {
+        vec![
+            vec![0.5, 0.1, 0.2],
+            vec![0.3, 0.1, 0.2],
+            vec![0.1, 0.4, 0.2],
+            vec![0.1, 0.3, 0.1],
+            vec![0.0, 0.1, 0.3],
+        ]
+    }
+
+    #[test]
+    fn test_nnls() {
+        let a = catalog();
+        for exposures in &[
+            vec![3.0, 5.0, 0.0],
+            vec![100.0, 20.0, 7.0],
+            vec![0.0, 0.0, 0.0],
+        ] {
+            let x = nnls(&a, &reconstruct(&a, exposures)).unwrap();
+            for (x, e) in x.iter().zip(exposures) {
+                assert!((x - e).abs() < 1e-6, "{:?} != {:?}", x, exposures);
+            }
+        }
+    }
+
+    #[test]
+    fn test_nnls_non_negative() {
+        // The unconstrained solution has a negative exposure to the second signature
+        let a = vec![vec![1.0, 1.0], vec![0.0, 1.0]];
+        let x = nnls(&a, &[1.0, -1.0]).unwrap();
+        assert_eq!(x, vec![1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_nnls_collinear() {
+        let a = vec![vec![1.0, 2.0], vec![1.0, 2.0]];
+        assert!(nnls(&a, &[1.0, 1.0]).is_ok());
+        let a = vec![vec![1.0, 1.0], vec![1.0, 1.0]];
+        assert!(nnls(&a, &[1.0, 1.0]).is_ok());
+    }
+
+    #[test]
+    fn test_parse_matrix() {
+        let m = parse_matrix(
+            "Type\tS1\tS2\n# comment\nA[C>A]A\t0.5\t1\n".as_bytes(),
+            "test",
+        )
+        .unwrap();
+        assert_eq!(m.columns(), &vec!["S1".to_owned(), "S2".to_owned()]);
+        assert_eq!(m.column(1), vec![1.0]);
+        assert_eq!(m.rows, vec!["A[C>A]A".to_owned()]);
+        assert_eq!(m.values, vec![vec![0.5, 1.0]]);
+        assert!(parse_matrix("Type\tS1\nA[C>A]A\t0.5\t1\n".as_bytes(), "test").is_err());
+        assert!(parse_matrix("Type\tS1\nA[C>A]A\tx\n".as_bytes(), "test").is_err());
+        assert!(parse_matrix("".as_bytes(), "test").is_err());
+    }
+
+    #[test]
+    fn test_channel_key() {
+        assert_eq!(channel_key("TCA>T"), "T[C>T]A");
+        assert_eq!(channel_key("T[C>T]A"), "T[C>T]A");
+        assert_eq!(channel_key("T:TCA>T"), "T:T[C>T]A");
+        assert_eq!(channel_key("mt:G>A"), "mt:G>A");
+        assert_eq!(channel_key("OTHER"), "OTHER");
+    }
+
+    #[test]
+    fn test_fit() {
+        let catalog = read_matrix("testdata/ex2_signatures.tsv").unwrap();
+        let counts = read_matrix("testdata/ex2_counts.txt").unwrap();
+        let fits = fit(&catalog, &counts).unwrap();
+        assert_eq!(fits.len(), 2);
+        for (f, expected) in fits.iter().zip(&[[10.0, 0.0], [10.0, 10.0]]) {
+            assert!((f.exposures[0] - expected[0]).abs() < 1e-6);
+            assert!((f.exposures[1] - expected[1]).abs() < 1e-6);
+            assert!(f.residual < 1e-6);
+            assert!((f.similarity.unwrap() - 1.0).abs() < 1e-9);
+            assert!(!f.is_poor(0.99));
+        }
+
+        let other = parse_matrix(
+            "Type\tS\nC>A\t1\nC>G\t0\nC>T\t0\nT>A\t0\nT>C\t0\nOTHER\t0\n".as_bytes(),
+            "test",
+        )
+        .unwrap();
+        assert!(fit(&other, &counts).is_err());
+        assert_eq!(
+            align_channels(&catalog, &counts.rows).unwrap(),
+            vec![3, 4, 5, 2, 0, 1]
+        );
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_fit_array() {
+        let catalog = read_matrix("testdata/ex2_signatures.tsv").unwrap();
+        let counts = read_matrix("testdata/ex2_counts.txt").unwrap();
+        let array =
+            ndarray::Array2::from_shape_fn((counts.rows.len(), counts.columns.len()), |(r, c)| {
+                counts.value(r, c) as u32
+            });
+        let fits = fit_array(&catalog, &counts.rows, array.view()).unwrap();
+        let expected = fit(&catalog, &counts).unwrap();
+        assert_eq!(fits.len(), 2);
+        for (f, e) in fits.iter().zip(&expected) {
+            assert_eq!(f.exposures, e.exposures);
+            assert_eq!(f.similarity, e.similarity);
+        }
+        assert!(fit_array(&catalog, &counts.rows[1..], array.view()).is_err());
+    }
+
+    #[test]
+    fn test_poorly_explained() {
+        let catalog = read_matrix("testdata/ex2_signatures.tsv").unwrap();
+        // A sample of C>T and T>G only, as by a signature absent from the catalog, and a
+        // sample without any counts
+        let counts = parse_matrix(
+            "Variant\tAbsent\tMixed\tEmpty\n\
+             ACA>A\t0\t5\t0\nACA>G\t0\t2\t0\nACA>T\t10\t1\t0\n\
+             ATA>A\t0\t1\t0\nATA>C\t0\t1\t0\nATA>G\t10\t0\t0\n"
+                .as_bytes(),
+            "test",
+        )
+        .unwrap();
+        let fits = fit(&catalog, &counts).unwrap();
+        assert!(fits[0].similarity.unwrap() < 0.9);
+        assert!(fits[0].residual > 5.0);
+        assert!(fits[0].is_poor(0.9));
+        assert!(!fits[1].is_poor(0.9));
+        assert_eq!(fits[2].similarity, None);
+        assert!(!fits[2].is_poor(0.9));
+    }
+
+    #[test]
+    fn test_fit_sparse() {
+        let catalog = parse_matrix(
+            "Type\tS1\tS2\tS3\tS4\tS5\n\
+             C>A\t0.5\t0\t0.1\t0.2\t0\n\
+             C>G\t0.2\t0.1\t0.1\t0.2\t0\n\
+             C>T\t0.1\t0.6\t0.1\t0.2\t0.1\n\
+             T>A\t0.1\t0.1\t0.5\t0.2\t0.1\n\
+             T>C\t0.1\t0.1\t0.1\t0.1\t0.3\n\
+             T>G\t0\t0.1\t0.1\t0.1\t0.5\n"
+                .as_bytes(),
+            "test",
+        )
+        .unwrap();
+        // The mixtures 100 · S1 + 50 · S5 and 30 · S2 + 60 · S3, and a sample without counts
+        let counts = parse_matrix(
+            "Variant\tA\tB\tEmpty\n\
+             T>G\t25\t9\t0\nT>C\t25\t9\t0\nT>A\t15\t33\t0\n\
+             C>T\t15\t24\t0\nC>G\t20\t9\t0\nC>A\t50\t6\t0\n"
+                .as_bytes(),
+            "test",
+        )
+        .unwrap();
+        for criterion in &[Criterion::Cosine, Criterion::L2] {
+            let selection = Selection {
+                max_signatures: 5,
+                min_improvement: 0.01,
+                criterion: *criterion,
+            };
+            let fits = fit_sparse(&catalog, &counts, selection).unwrap();
+            assert_eq!(fits[0].selected, Some(vec![0, 4]), "{:?}", criterion);
+            assert!((fits[0].exposures[0] - 100.0).abs() < 1e-6);
+            assert!((fits[0].exposures[4] - 50.0).abs() < 1e-6);
+            assert_eq!(fits[0].exposures[1..4], [0.0; 3]);
+            let mut selected = fits[1].selected.clone().unwrap();
+            selected.sort_unstable();
+            assert_eq!(selected, vec![1, 2]);
+            assert!(fits[1].residual < 1e-6);
+            assert_eq!(fits[2].selected, Some(vec![]));
+            assert_eq!(fits[2].exposures, vec![0.0; 5]);
+        }
+
+        // At most one signature is selected
+        let selection = Selection {
+            max_signatures: 1,
+            min_improvement: 0.0,
+            criterion: Criterion::Cosine,
+        };
+        let fits = fit_sparse(&catalog, &counts, selection).unwrap();
+        assert_eq!(fits[0].selected, Some(vec![0]));
+        assert!(fits[0].exposures[1..].iter().all(|x| *x == 0.0));
+    }
+
+    #[test]
+    fn test_rescale_catalog() {
+        let catalog = parse_matrix(
+            "Type\tA\tB\nC>A\t0.4\t0.1\nC>G\t0.3\t0.2\nC>T\t0.2\t0.3\nT>A\t0.1\t0.4\n".as_bytes(),
+            "test",
+        )
+        .unwrap();
+        let genome = parse_matrix(
+            "Variant\tgenome\nC>A\t100\nC>G\t100\nC>T\t100\nT>A\t300\n".as_bytes(),
+            "test",
+        )
+        .unwrap();
+        let exome = parse_matrix(
+            "Variant\texome\nT>A\t10\nC>A\t40\nC>G\t20\nC>T\t30\n".as_bytes(),
+            "test",
+        )
+        .unwrap();
+        let rescaled = rescale_catalog(&catalog, &genome, &exome).unwrap();
+        // The signature A is scaled by 0.4, 0.2, 0.3 and 1/30 and renormalized
+        let total = 0.16 + 0.06 + 0.06 + 0.1 / 30.0;
+        assert!((rescaled.values[0][0] - 0.16 / total).abs() < 1e-12);
+        assert!((rescaled.column(1).iter().sum::<f64>() - 1.0).abs() < 1e-12);
+
+        // Synthetic exome counts of the rescaled signatures are fitted better after rescaling
+        let exposures = [300.0, 100.0];
+        let counts: Vec<f64> = (0..4)
+            .map(|r| (0..2).map(|c| rescaled.values[r][c] * exposures[c]).sum())
+            .collect();
+        let error = |m: &Matrix| {
+            let x = nnls(&m.values, &counts).unwrap();
+            x.iter()
+                .zip(&exposures)
+                .map(|(x, e)| (x - e).abs())
+                .sum::<f64>()
+        };
+        assert!(error(&rescaled) < 1e-6);
+        assert!(error(&catalog) > 10.0);
+
+        // The rescaling back to the genome restores the catalog
+        let restored = rescale_catalog(&rescaled, &exome, &genome).unwrap();
+        for (r, e) in restored.values.iter().zip(&catalog.values) {
+            for (r, e) in r.iter().zip(e) {
+                assert!((r - e).abs() < 1e-12);
+            }
+        }
+
+        // Channels used by the signatures need opportunities in the source
+        let empty = parse_matrix(
+            "Variant\tgenome\nC>A\t0\nC>G\t100\nC>T\t100\nT>A\t300\n".as_bytes(),
+            "test",
+        )
+        .unwrap();
+        assert!(rescale_catalog(&catalog, &empty, &exome).is_err());
+    }
+}
@@ -4,16 +4,151 @@
 ///
 /// Since we are only interested in the number of different alleles,
 /// and not the haplotype, they are stored in a sorted vector.
+///
+/// Two genotypes are equal if they call the same alleles with the same dosage and miss the
+/// same number of alleles, e.g., `./1` equals `1/.` but neither `1/1` nor `./.`.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Genotype {
-    inner: Vec<Option<u8>>,
+    inner: Vec<Option<u32>>,
 }
 
 impl Genotype {
-    /// Iterate the genotypes.
-    pub fn iter(&self) -> GenotypeAlleleIterator {
+    /// Create the genotype of the allele indizes `alleles`, where `None` is a missing allele,
+    /// e.g., `[Some(0), Some(1)]` for `0/1` and `[None, Some(1)]` for `./1`. The alleles are
+    /// sorted, such that the phase is lost and the missing alleles come first.
+    pub fn from_allele_indices(alleles: &[Option<u32>]) -> Genotype {
+        let mut inner = alleles.to_vec();
+        inner.sort();
+        Genotype { inner }
+    }
+
+    /// Create the genotype of `ploidy` missing alleles, e.g., `./.` for a ploidy of 2.
+    pub fn missing(ploidy: usize) -> Genotype {
+        Genotype {
+            inner: vec![None; ploidy],
+        }
+    }
+
+    /// Return the number of alleles of the genotype, including the missing ones.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Check if the genotype has no alleles at all, not even missing ones.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Check if all alleles of the genotype are missing, e.g., `./.`.
+    pub fn is_missing(&self) -> bool {
+        self.inner.iter().all(|a| a.is_none())
+    }
+
+    /// Check if the allele `allele_index` is called in the genotype.
+    pub fn contains(&self, allele_index: u32) -> bool {
+        self.inner.contains(&Some(allele_index))
+    }
+
+    /// Return the indizes of the called alternative alleles in ascending order, each once
+    /// regardless of its dosage, e.g., `[1]` for `1/1` and `[1, 2]` for `1/2`.
+    pub fn alt_allele_indices(&self) -> Vec<u32> {
+        self.alt_alleles().collect()
+    }
+
+    /// Iterate the indizes of the called alternative alleles like `alt_allele_indices()`
+    /// without collecting them.
+    pub fn alt_alleles(&self) -> impl Iterator<Item = u32> + '_ {
+        self.distinct_alleles().filter(|a| *a > 0)
+    }
+
+    /// Iterate the called alleles of the genotype in ascending order, skipping the missing ones.
+    pub fn iter(&self) -> GenotypeAlleleIterator<'_> {
         GenotypeAlleleIterator {
-            inner: self.inner.iter().filter_map(|v| v.clone()).collect(),
-            pos: 0,
+            inner: self.inner.iter(),
+        }
+    }
+
+    /// Iterate the distinct called alleles of the genotype in ascending order.
+    fn distinct_alleles(&self) -> impl Iterator<Item = u32> + '_ {
+        distinct(self.iter())
+    }
+
+    /// Iterate all alleles of the genotype in ascending order, counting the missing ones as
+    /// the reference allele, e.g., `0, 1` for `./1` and `0, 0` for `./.`.
+    pub fn iter_missing_as_reference(&self) -> impl Iterator<Item = u32> + '_ {
+        // The missing alleles sort first, like the reference allele
+        self.inner.iter().map(|a| a.unwrap_or(0))
+    }
+
+    /// Replace the alleles of the genotype by the rust-htslib `alleles`, reusing the
+    /// allocation of the genotype. This is used to refill the genotypes of the samples
+    /// record by record.
+    pub fn assign<I>(&mut self, alleles: I)
+    where
+        I: IntoIterator<Item = rust_htslib::bcf::record::GenotypeAllele>,
+    {
+        // Reformat the rust-htslib allele indizes
+        self.inner.clear();
+        self.inner.extend(alleles.into_iter().map(|gta| match gta {
+            rust_htslib::bcf::record::GenotypeAllele::Unphased(i) => Some(i as u32),
+            rust_htslib::bcf::record::GenotypeAllele::Phased(i) => Some(i as u32),
+            _ => None,
+        }));
+
+        // Sort the vector (remember, we are only interested in the counts and not the haplotypes)
+        self.inner.sort_unstable();
+    }
+
+    /// Return the indizes of the called alternative alleles, one per haplotype. Missing
+    /// alleles and the reference are left out, such that a half-call like `./1`
+    /// returns the called alternative allele only.
+    pub fn called_alt_indices(&self) -> Vec<u32> {
+        self.called_alts().collect()
+    }
+
+    /// Iterate the indizes of the called alternative alleles like `called_alt_indices()`
+    /// without collecting them.
+    pub fn called_alts(&self) -> impl Iterator<Item = u32> + '_ {
+        self.iter().filter(|a| *a > 0)
+    }
+
+    /// Return the dosage of the allele `allele_index`, i.e., the number of its called copies
+    /// in the genotype (e.g., 2 for allele 1 of a 1/1 genotype and 1 of a `./1`).
+    pub fn dosage_of(&self, allele_index: u32) -> usize {
+        self.iter().filter(|a| *a == allele_index).count()
+    }
+
+    /// Return the number of alleles of the genotype, e.g., 1 for the haploid calls of male
+    /// sex chromosomes and 2 for diploid calls. Missing alleles are included.
+    pub fn ploidy(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Check if the genotype calls the same alleles with the same dosage as `other`, ignoring
+    /// the missing alleles, e.g., `./1` and a haploid `1`, but not `./1` and `1/1`. This is
+    /// how `--ignore-homogeneous` compares the genotypes of a site, such that an uncalled
+    /// `./.` differs from any called genotype.
+    pub fn same_called_alleles(&self, other: &Genotype) -> bool {
+        self.iter().eq(other.iter())
+    }
+
+    /// Check if the genotype carries the same alleles as `other` regardless of the ploidy,
+    /// e.g., a haploid `1` and a diploid `1/1`. Like `same_called_alleles()`, the missing
+    /// alleles are ignored, such that `./.` only has the same allele content as other
+    /// genotypes without called alleles.
+    pub fn has_same_allele_content(&self, other: &Genotype) -> bool {
+        self.distinct_alleles().eq(other.distinct_alleles())
+    }
+
+    /// Check if all alleles of the genotype are called, i.e., none is missing.
+    pub fn is_fully_called(&self) -> bool {
+        !self.inner.is_empty() && self.inner.iter().all(|a| a.is_some())
+    }
+
+    /// Treat all alleles of the genotype as missing, such that it is not counted.
+    pub fn mask(&mut self) {
+        for allele in self.inner.iter_mut() {
+            *allele = None;
         }
     }
 }
@@ -21,14 +156,14 @@ impl Genotype {
 use std::fmt;
 impl fmt::Debug for Genotype {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.inner[0] {
-            Some(i) => write!(f, "Genotype({}", i)?,
-            None => write!(f, "Genotype(-")?,
-        }
-        for i in 1..self.inner.len() {
-            match self.inner[i] {
-                Some(g) => write!(f, "/{}", g)?,
-                None => write!(f, "/-")?,
+        write!(f, "Genotype(")?;
+        for (i, allele) in self.inner.iter().enumerate() {
+            if i > 0 {
+                write!(f, "/")?;
+            }
+            match allele {
+                Some(a) => write!(f, "{}", a)?,
+                None => write!(f, "-")?,
             }
         }
         write!(f, ")")
@@ -37,57 +172,249 @@ impl fmt::Debug for Genotype {
 
 impl From<rust_htslib::bcf::record::Genotype> for Genotype {
     fn from(gt: rust_htslib::bcf::record::Genotype) -> Genotype {
-        // Reformat the rust-htslib allele indizes
-        let mut inner: Vec<Option<u8>> = gt
-            .iter()
-            .map(|gta| match gta {
-                rust_htslib::bcf::record::GenotypeAllele::Unphased(i) => Some(*i as u8),
-                rust_htslib::bcf::record::GenotypeAllele::Phased(i) => Some(*i as u8),
-                _ => None,
-            })
-            .collect();
+        let mut genotype = Genotype::missing(0);
+        genotype.assign(gt.iter().copied());
+        genotype
+    }
+}
 
-        // Sort the vector (remember, we are only interested in the counts and not the haplotypes)
-        inner.sort();
-        Genotype { inner: inner }
+/// Skip the repeated `alleles` of an iterator in ascending order, keeping each allele once.
+pub fn distinct<I: Iterator<Item = u32>>(alleles: I) -> impl Iterator<Item = u32> {
+    let mut previous = None;
+    alleles.filter(move |a| previous.replace(*a) != Some(*a))
+}
+
+/// Iterator of the called alleles of the genotype of one sample, borrowing its alleles
+#[derive(Clone)]
+pub struct GenotypeAlleleIterator<'a> {
+    inner: std::slice::Iter<'a, Option<u32>>,
+}
+
+impl<'a> Iterator for GenotypeAlleleIterator<'a> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Skip the missing alleles, which come first in the sorted genotype
+        self.inner.by_ref().find_map(|a| *a)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
     }
 }
 
-use std::cmp::*;
-impl PartialEq<Genotype> for Genotype {
-    fn eq(&self, other: &Genotype) -> bool {
-        if self.inner.len() != other.inner.len() {
-            return false;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        for i in 0..self.inner.len() {
-            if self.inner[i].is_none() || other.inner[i].is_none() {
-                return true;
-            }
-            if self.inner[i] != other.inner[i] {
-                return false;
+    fn genotype(alleles: &[Option<u32>]) -> Genotype {
+        Genotype::from_allele_indices(alleles)
+    }
+
+    #[test]
+    fn test_from_allele_indices() {
+        // The alleles are sorted, with the missing ones first
+        let gt = genotype(&[Some(2), None, Some(0)]);
+        assert_eq!(format!("{:?}", gt), "Genotype(-/0/2)");
+        assert_eq!(gt.iter().collect::<Vec<u32>>(), vec![0, 2]);
+        assert_eq!(gt.len(), 3);
+        assert!(!gt.is_empty());
+        assert!(!gt.is_fully_called());
+        assert!(!gt.is_missing());
+        assert!(gt.contains(0) && gt.contains(2));
+        assert!(!gt.contains(1));
+        assert_eq!(
+            format!("{:?}", genotype(&[Some(1), Some(0)])),
+            format!("{:?}", genotype(&[Some(0), Some(1)]))
+        );
+    }
+
+    #[test]
+    fn test_missing() {
+        let missing = Genotype::missing(2);
+        assert_eq!(format!("{:?}", missing), "Genotype(-/-)");
+        assert_eq!(format!("{:?}", Genotype::missing(0)), "Genotype()");
+        assert_eq!(format!("{:?}", genotype(&[])), "Genotype()");
+        assert_eq!(missing.len(), 2);
+        assert!(missing.is_missing());
+        assert!(!missing.is_fully_called());
+        assert!(missing.alt_allele_indices().is_empty());
+        assert!(!missing.contains(0));
+        assert_eq!(missing.iter().count(), 0);
+
+        let mut gt = genotype(&[Some(0), Some(1)]);
+        assert!(!gt.is_missing());
+        gt.mask();
+        assert!(gt.is_missing());
+        assert_eq!(gt.len(), 2);
+        // A genotype without any allele is missing, too
+        assert!(Genotype::missing(0).is_empty());
+        assert!(Genotype::missing(0).is_missing());
+        assert!(genotype(&[None, Some(1)]).contains(1));
+    }
+
+    #[test]
+    fn test_alt_allele_indices() {
+        assert_eq!(genotype(&[Some(1), Some(1)]).alt_allele_indices(), vec![1]);
+        assert_eq!(
+            genotype(&[Some(1), Some(1)]).called_alt_indices(),
+            vec![1, 1]
+        );
+        assert_eq!(
+            genotype(&[Some(2), Some(1)]).alt_allele_indices(),
+            vec![1, 2]
+        );
+        assert!(genotype(&[Some(0), Some(0)])
+            .alt_allele_indices()
+            .is_empty());
+        assert_eq!(
+            genotype(&[Some(3), None, Some(3), Some(0)]).alt_allele_indices(),
+            vec![3]
+        );
+    }
+
+    #[test]
+    fn test_haploid() {
+        let haploid = genotype(&[Some(1)]);
+        assert_eq!(haploid.ploidy(), 1);
+        assert_eq!(haploid.called_alt_indices(), vec![1]);
+        assert!(haploid.is_fully_called());
+    }
+
+    #[test]
+    fn test_allele_content() {
+        let haploid = genotype(&[Some(1)]);
+        assert!(haploid != genotype(&[Some(1), Some(1)]));
+        assert!(haploid.has_same_allele_content(&genotype(&[Some(1), Some(1)])));
+        assert!(!haploid.has_same_allele_content(&genotype(&[Some(0), Some(1)])));
+        assert!(genotype(&[Some(0)]).has_same_allele_content(&genotype(&[Some(0), Some(0)])));
+        assert!(!haploid.has_same_allele_content(&genotype(&[None, Some(0)])));
+        assert!(haploid.has_same_allele_content(&genotype(&[None, Some(1)])));
+    }
+
+    #[test]
+    fn test_equality() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        let hash = |gt: &Genotype| {
+            let mut hasher = DefaultHasher::new();
+            gt.hash(&mut hasher);
+            hasher.finish()
+        };
+        // The pairs with whether they are equal and have the same called alleles
+        let pairs = [
+            (vec![None, None], vec![Some(0), Some(0)], false, false),
+            (vec![None, None], vec![Some(1), Some(1)], false, false),
+            (vec![None, None], vec![None, None], true, true),
+            (vec![None, Some(1)], vec![Some(1), Some(1)], false, false),
+            (vec![Some(1), None], vec![Some(1), Some(1)], false, false),
+            (vec![Some(1), None], vec![None, Some(1)], true, true),
+            (vec![None, Some(1)], vec![Some(1)], false, true),
+            (vec![Some(1), Some(0)], vec![Some(0), Some(1)], true, true),
+            (vec![Some(1), Some(1)], vec![Some(1)], false, false),
+            (vec![None], vec![None, None], false, true),
+        ];
+        for (a, b, equal, same_called) in pairs.iter() {
+            let (a, b) = (genotype(a), genotype(b));
+            assert_eq!(a == b, *equal, "{:?} == {:?}", a, b);
+            assert_eq!(b == a, *equal, "{:?} == {:?}", b, a);
+            assert_eq!(a.same_called_alleles(&b), *same_called, "{:?}, {:?}", a, b);
+            assert_eq!(b.same_called_alleles(&a), *same_called, "{:?}, {:?}", b, a);
+            if *equal {
+                assert_eq!(hash(&a), hash(&b));
             }
         }
+    }
 
-        return true;
+    #[test]
+    fn test_wide_allele_indices() {
+        // An index above 255 must not wrap around, e.g., to 44
+        let gt = genotype(&[Some(0), Some(300)]);
+        assert_eq!(gt.alt_allele_indices(), vec![300]);
+        assert!(gt.contains(300) && !gt.contains(44));
+        assert_eq!((gt.dosage_of(300), gt.dosage_of(44)), (1, 0));
+        let mut assigned = Genotype::missing(0);
+        assigned.assign(vec![
+            rust_htslib::bcf::record::GenotypeAllele::Unphased(300),
+            rust_htslib::bcf::record::GenotypeAllele::Phased(0),
+        ]);
+        assert_eq!(assigned, gt);
+        assert_eq!(format!("{:?}", assigned), "Genotype(0/300)");
     }
-}
 
-/// Simple iterator of the genotypes of one sample
-pub struct GenotypeAlleleIterator {
-    inner: Vec<u8>,
-    pos: usize,
-}
+    #[test]
+    fn test_iter_missing_as_reference() {
+        let alleles = |gt: &Genotype| gt.iter_missing_as_reference().collect::<Vec<u32>>();
+        assert_eq!(alleles(&genotype(&[Some(1), None])), vec![0, 1]);
+        assert_eq!(alleles(&Genotype::missing(2)), vec![0, 0]);
+        assert_eq!(alleles(&genotype(&[Some(2), Some(1)])), vec![1, 2]);
+        assert_eq!(
+            distinct(alleles(&genotype(&[None, Some(0), Some(1)])).into_iter())
+                .collect::<Vec<u32>>(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn test_dosage_of() {
+        assert_eq!(genotype(&[Some(0), Some(1)]).dosage_of(1), 1);
+        assert_eq!(genotype(&[Some(1), Some(1)]).dosage_of(1), 2);
+        let multi = genotype(&[Some(1), Some(2)]);
+        assert_eq!((multi.dosage_of(1), multi.dosage_of(2)), (1, 1));
+        assert_eq!(genotype(&[None, Some(1)]).dosage_of(1), 1);
+        assert_eq!(genotype(&[Some(0), Some(1)]).dosage_of(2), 0);
+    }
 
-impl Iterator for GenotypeAlleleIterator {
-    type Item = u8;
+    #[test]
+    fn test_iter_leading_missing() {
+        // The missing alleles sort first and are skipped
+        let gt = genotype(&[Some(1), None, Some(0), None]);
+        assert_eq!(gt.iter().collect::<Vec<u32>>(), vec![0, 1]);
+        assert_eq!(gt.called_alt_indices(), vec![1]);
+        assert_eq!(gt.alt_allele_indices(), vec![1]);
+        assert_eq!((gt.dosage_of(0), gt.dosage_of(1)), (1, 1));
+        let gt = genotype(&[None, Some(2), Some(2)]);
+        assert_eq!(gt.iter().collect::<Vec<u32>>(), vec![2, 2]);
+        assert_eq!(gt.called_alt_indices(), vec![2, 2]);
+        assert_eq!(gt.alt_allele_indices(), vec![2]);
+        assert_eq!(gt.dosage_of(2), 2);
+        // The iterator can be cloned mid-way
+        let mut iter = gt.iter();
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.clone().collect::<Vec<u32>>(), vec![2]);
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.next(), None);
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.inner.len() {
-            None
-        } else {
-            self.pos += 1;
-            Some(self.inner[self.pos - 1])
+    #[test]
+    fn test_iter_all_missing() {
+        for gt in [Genotype::missing(0), Genotype::missing(3), {
+            let mut gt = genotype(&[Some(0), Some(1)]);
+            gt.mask();
+            gt
+        }] {
+            assert_eq!(gt.iter().next(), None);
+            assert!(gt.called_alt_indices().is_empty());
+            assert!(gt.alt_allele_indices().is_empty());
+            assert_eq!(gt.dosage_of(0), 0);
+            assert!(!gt.has_same_allele_content(&genotype(&[Some(1), Some(1)])));
+            assert!(gt.has_same_allele_content(&Genotype::missing(2)));
         }
     }
+
+    #[test]
+    fn test_distinct_alleles() {
+        let gt = genotype(&[Some(2), Some(0), Some(2), None, Some(0)]);
+        assert_eq!(gt.distinct_alleles().collect::<Vec<u32>>(), vec![0, 2]);
+        assert!(
+            genotype(&[Some(1), Some(2)]).has_same_allele_content(&genotype(&[
+                Some(2),
+                Some(1),
+                Some(1)
+            ]))
+        );
+        assert!(
+            !genotype(&[Some(1), Some(1)]).has_same_allele_content(&genotype(&[Some(1), Some(2)]))
+        );
+    }
 }
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+/// The longest repeat or microhomology that is distinguished, longer ones share the channel.
+const MAX_COUNT: usize = 5;
+
+/// The 83 small insertion and deletion (ID) channels as defined by COSMIC. Indels are
+/// classified by their type, their length (1 to 5+ bases) and the repeats of the indel
+/// sequence in the reference. Deletions of at least two bases that are not repeated are
+/// further classified by the microhomology at their breakpoints.
+pub struct Indels {
+    labels: Vec<String>,
+    db: HashMap<String, usize>,
+}
+
+impl Indels {
+    /// Build the 83 channels in the canonical (COSMIC) order.
+    pub fn new() -> Self {
+        let mut labels = Vec::new();
+        for kind in &["Del", "Ins"] {
+            for base in &['C', 'T'] {
+                for repeats in 0..=MAX_COUNT {
+                    labels.push(format!("1:{}:{}:{}", kind, base, repeats));
+                }
+            }
+        }
+        for kind in &["Del", "Ins"] {
+            for length in 2..=MAX_COUNT {
+                for repeats in 0..=MAX_COUNT {
+                    labels.push(format!("{}:{}:R:{}", length, kind, repeats));
+                }
+            }
+        }
+        for length in 2..=MAX_COUNT {
+            // The microhomology is shorter than the deletion, except for the 5+ class
+            let max = if length < MAX_COUNT {
+                length - 1
+            } else {
+                MAX_COUNT
+            };
+            for microhomology in 1..=max {
+                labels.push(format!("{}:Del:M:{}", length, microhomology));
+            }
+        }
+        let db = labels
+            .iter()
+            .enumerate()
+            .map(|(i, l)| (l.clone(), i))
+            .collect();
+        Indels { labels, db }
+    }
+
+    /// Return the index of the channel with `label`, e.g., `1:Del:C:0`.
+    pub fn index_of(&self, label: &str) -> Option<usize> {
+        self.db.get(label).cloned()
+    }
+
+    /// Retrieve the number of channels
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Return the labels of the channels in the order of their indizes.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+}
+
+/// Return the label of the channel of the insertion (if `inserted`) or deletion of the
+/// uppercase bases `sequence`. `before` is the reference sequence up to and including the
+/// base before the indel, and `after` the reference sequence following the deleted bases
+/// respectively the insertion point. The indel is expected to be left-aligned, such that
+/// its repeats are found in `after` only. At least `MAX_COUNT` times the length of
+/// `sequence` bases of `after` are needed to distinguish all repeat counts.
+pub fn classify(inserted: bool, sequence: &str, before: &str, after: &str) -> String {
+    let kind = if inserted { "Ins" } else { "Del" };
+    let length = sequence.len();
+    let repeats = count_repeats(sequence, after).min(MAX_COUNT);
+    if length == 1 {
+        // Count deletions and insertions of purines as those of the complementary pyrimidines
+        let base = match sequence {
+            "A" | "T" => 'T',
+            _ => 'C',
+        };
+        return format!("1:{}:{}:{}", kind, base, repeats);
+    }
+
+    let length_class = length.min(MAX_COUNT);
+    if !inserted && repeats == 0 {
+        let microhomology = microhomology(sequence, before, after).min(MAX_COUNT);
+        if microhomology > 0 {
+            return format!("{}:Del:M:{}", length_class, microhomology);
+        }
+    }
+    format!("{}:{}:R:{}", length_class, kind, repeats)
+}
+
+/// Count the number of times `unit` is repeated at the start of `sequence`.
+fn count_repeats(unit: &str, sequence: &str) -> usize {
+    let mut count = 0;
+    let mut rest = sequence;
+    while let Some(r) = rest.strip_prefix(unit) {
+        count += 1;
+        rest = r;
+    }
+    count
+}
+
+/// Return the length of the longest partial copy of the `deleted` bases at either
+/// breakpoint of the deletion, i.e., the start of `deleted` found at the start of `after`
+/// or the end of `deleted` found at the end of `before`.
+fn microhomology(deleted: &str, before: &str, after: &str) -> usize {
+    let right = (1..deleted.len())
+        .rev()
+        .find(|k| after.starts_with(&deleted[..*k]))
+        .unwrap_or(0);
+    let left = (1..deleted.len())
+        .rev()
+        .find(|k| before.ends_with(&deleted[deleted.len() - k..]))
+        .unwrap_or(0);
+    right.max(left)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channels() {
+        let indels = Indels::new();
+        assert_eq!(indels.len(), 83);
+        assert_eq!(indels.labels()[0], "1:Del:C:0");
+        assert_eq!(indels.labels()[23], "1:Ins:T:5");
+        assert_eq!(indels.labels()[24], "2:Del:R:0");
+        assert_eq!(indels.labels()[71], "5:Ins:R:5");
+        assert_eq!(indels.labels()[72], "2:Del:M:1");
+        assert_eq!(indels.labels()[82], "5:Del:M:5");
+        assert_eq!(indels.index_of("3:Del:M:2"), Some(74));
+        assert_eq!(indels.index_of("3:Del:M:3"), None);
+    }
+
+    #[test]
+    fn test_homopolymer() {
+        assert_eq!(classify(false, "T", "GA", "TTTG"), "1:Del:T:3");
+        assert_eq!(classify(false, "A", "GC", "AAC"), "1:Del:T:2");
+        assert_eq!(classify(false, "C", "GA", "AC"), "1:Del:C:0");
+        assert_eq!(classify(false, "G", "GA", "GGGGGGGGA"), "1:Del:C:5");
+        assert_eq!(classify(true, "C", "GA", "CCA"), "1:Ins:C:2");
+        assert_eq!(classify(true, "G", "GA", "TCA"), "1:Ins:C:0");
+    }
+
+    #[test]
+    fn test_repeats() {
+        assert_eq!(classify(false, "AT", "GC", "ATATG"), "2:Del:R:2");
+        assert_eq!(classify(true, "AG", "GC", "AGAGC"), "2:Ins:R:2");
+        assert_eq!(classify(true, "ACGTACG", "GC", "TT"), "5:Ins:R:0");
+        assert_eq!(classify(false, "AC", "GT", "GG"), "2:Del:R:0");
+    }
+
+    #[test]
+    fn test_microhomology() {
+        // The start of the deletion is repeated after it
+        assert_eq!(classify(false, "ACG", "GT", "ACT"), "3:Del:M:2");
+        // The end of the deletion is repeated before it
+        assert_eq!(classify(false, "TGCA", "GTCA", "GG"), "4:Del:M:2");
+        assert_eq!(classify(false, "ACGTAC", "GT", "ACGTAT"), "5:Del:M:5");
+        // Repeats take precedence
+        assert_eq!(classify(false, "ACG", "GT", "ACGT"), "3:Del:R:1");
+    }
+}
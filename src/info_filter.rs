@@ -0,0 +1,378 @@
+use crate::error::Error;
+use rust_htslib::bcf::header::TagType;
+use rust_htslib::bcf::record::Numeric;
+use rust_htslib::bcf::Record;
+use std::collections::BTreeSet;
+
+/// A comparison operator of an INFO expression.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Operator {
+    /// Apply the operator to `value` and the threshold `other`.
+    fn apply(self, value: f64, other: f64) -> bool {
+        match self {
+            Operator::Lt => value < other,
+            Operator::Le => value <= other,
+            Operator::Gt => value > other,
+            Operator::Ge => value >= other,
+            Operator::Eq => value == other,
+            Operator::Ne => value != other,
+        }
+    }
+}
+
+/// A boolean expression on the INFO fields of a record, e.g., `TLOD>=6.3 & STR!=1`.
+///
+/// Numeric fields are compared against numbers, flags compare as `1` if set and `0`
+/// otherwise. A key without comparison tests for a set flag or a non-zero value. Fields
+/// with multiple values match if any of their values matches. `&` binds stronger than
+/// `|`, `!` negates and parentheses group.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression {
+    Test(String),
+    Compare(String, Operator, f64),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /// Parse the expression `s`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+        };
+        let expression = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expression),
+            Some(t) => Err(format!(
+                "Unexpected '{}' in INFO expression '{}'",
+                t.text(),
+                s
+            )),
+        }
+    }
+
+    /// Return the INFO keys referenced by the expression.
+    pub fn keys(&self) -> BTreeSet<&str> {
+        let mut keys = BTreeSet::new();
+        self.collect_keys(&mut keys);
+        keys
+    }
+
+    fn collect_keys<'a>(&'a self, keys: &mut BTreeSet<&'a str>) {
+        match self {
+            Expression::Test(k) | Expression::Compare(k, _, _) => {
+                keys.insert(k.as_str());
+            }
+            Expression::Not(e) => e.collect_keys(keys),
+            Expression::And(a, b) | Expression::Or(a, b) => {
+                a.collect_keys(keys);
+                b.collect_keys(keys);
+            }
+        }
+    }
+
+    /// Evaluate the expression given the values of the INFO fields returned by `lookup`.
+    /// Tests and comparisons on missing fields evaluate to `missing`, and the first error of
+    /// `lookup` is returned.
+    pub fn evaluate<F, E>(&self, lookup: &F, missing: bool) -> Result<bool, E>
+    where
+        F: Fn(&str) -> Result<Option<Vec<f64>>, E>,
+    {
+        Ok(match self {
+            Expression::Test(k) => match lookup(k)? {
+                None => missing,
+                Some(values) => values.iter().any(|v| *v != 0.0),
+            },
+            Expression::Compare(k, op, other) => match lookup(k)? {
+                None => missing,
+                Some(values) => values.iter().any(|v| op.apply(*v, *other)),
+            },
+            Expression::Not(e) => !e.evaluate(lookup, missing)?,
+            Expression::And(a, b) => a.evaluate(lookup, missing)? && b.evaluate(lookup, missing)?,
+            Expression::Or(a, b) => a.evaluate(lookup, missing)? || b.evaluate(lookup, missing)?,
+        })
+    }
+}
+
+/// Return the numeric values of the INFO field `key` of `record`, or `None` if the record
+/// does not carry the field. Flags are returned as `1` if set and `0` otherwise.
+pub fn record_values(record: &Record, key: &str) -> Result<Option<Vec<f64>>, Error> {
+    let tag = key.as_bytes();
+    let tag_type = match record.header().info_type(tag) {
+        Ok((t, _)) => t,
+        Err(_) => return Ok(None),
+    };
+    let error = |e| Error::Htslib {
+        action: format!("read INFO/{} at position {}", key, record.pos() + 1),
+        source: e,
+    };
+    let values: Vec<f64> = match tag_type {
+        TagType::Flag => match record.info(tag).flag() {
+            Ok(set) => return Ok(Some(vec![if set { 1.0 } else { 0.0 }])),
+            Err(e) => return Err(error(e)),
+        },
+        TagType::Integer => match record.info(tag).integer() {
+            Ok(None) => return Ok(None),
+            Ok(Some(v)) => v
+                .iter()
+                .filter(|i| !i.is_missing())
+                .map(|i| *i as f64)
+                .collect(),
+            Err(e) => return Err(error(e)),
+        },
+        TagType::Float => match record.info(tag).float() {
+            Ok(None) => return Ok(None),
+            Ok(Some(v)) => v
+                .iter()
+                .filter(|f| !f.is_missing() && !f.is_nan())
+                .map(|f| *f as f64)
+                .collect(),
+            Err(e) => return Err(error(e)),
+        },
+        TagType::String => {
+            return Err(Error::InvalidOptions(format!(
+                "INFO/{} is a string field, only numeric and flag fields can be filtered",
+                key
+            )))
+        }
+    };
+    if values.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(values))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Operator(Operator),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Token {
+    fn text(&self) -> String {
+        match self {
+            Token::Word(w) => w.clone(),
+            Token::Operator(o) => match o {
+                Operator::Lt => "<",
+                Operator::Le => "<=",
+                Operator::Gt => ">",
+                Operator::Ge => ">=",
+                Operator::Eq => "==",
+                Operator::Ne => "!=",
+            }
+            .to_owned(),
+            Token::And => "&".to_owned(),
+            Token::Or => "|".to_owned(),
+            Token::Not => "!".to_owned(),
+            Token::Open => "(".to_owned(),
+            Token::Close => ")".to_owned(),
+        }
+    }
+}
+
+/// Split the expression `s` into tokens. `&&` and `||` are accepted as well.
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let next = chars.get(i + 1).cloned();
+        let (token, len) = match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => (Token::Open, 1),
+            ')' => (Token::Close, 1),
+            '&' => (Token::And, if next == Some('&') { 2 } else { 1 }),
+            '|' => (Token::Or, if next == Some('|') { 2 } else { 1 }),
+            '!' if next == Some('=') => (Token::Operator(Operator::Ne), 2),
+            '!' => (Token::Not, 1),
+            '<' if next == Some('=') => (Token::Operator(Operator::Le), 2),
+            '<' => (Token::Operator(Operator::Lt), 1),
+            '>' if next == Some('=') => (Token::Operator(Operator::Ge), 2),
+            '>' => (Token::Operator(Operator::Gt), 1),
+            '=' if next == Some('=') => (Token::Operator(Operator::Eq), 2),
+            '=' => (Token::Operator(Operator::Eq), 1),
+            _ => {
+                let end = (i..chars.len())
+                    .find(|j| chars[*j].is_whitespace() || "()&|!<>=".contains(chars[*j]))
+                    .unwrap_or(chars.len());
+                let word: String = chars[i..end].iter().collect();
+                (Token::Word(word), end - i)
+            }
+        };
+        tokens.push(token);
+        i += len;
+    }
+    if tokens.is_empty() {
+        return Err("Empty INFO expression".to_owned());
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser of the expression tokens.
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<&'a Token> {
+        let t = self.tokens.get(self.pos);
+        self.pos += 1;
+        t
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or(&mut self) -> Result<Expression, String> {
+        let mut e = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            e = Expression::Or(Box::new(e), Box::new(self.and()?));
+        }
+        Ok(e)
+    }
+
+    fn and(&mut self) -> Result<Expression, String> {
+        let mut e = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            e = Expression::And(Box::new(e), Box::new(self.unary()?));
+        }
+        Ok(e)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expression::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let e = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(e),
+                    _ => Err("Missing ')' in INFO expression".to_owned()),
+                }
+            }
+            Some(Token::Word(key)) => {
+                if !key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                    return Err(format!("Invalid INFO key '{}'", key));
+                }
+                match self.peek() {
+                    Some(Token::Operator(op)) => {
+                        self.pos += 1;
+                        match self.next() {
+                            Some(Token::Word(v)) => match v.parse::<f64>() {
+                                Ok(n) => Ok(Expression::Compare(key.clone(), *op, n)),
+                                Err(_) => {
+                                    Err(format!("Invalid number '{}' compared to INFO/{}", v, key))
+                                }
+                            },
+                            _ => Err(format!("Missing value compared to INFO/{}", key)),
+                        }
+                    }
+                    _ => Ok(Expression::Test(key.clone())),
+                }
+            }
+            Some(t) => Err(format!("Unexpected '{}' in INFO expression", t.text())),
+            None => Err("Unexpected end of INFO expression".to_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(key: &str) -> Result<Option<Vec<f64>>, String> {
+        Ok(match key {
+            "TLOD" => Some(vec![7.5]),
+            "STR" => Some(vec![1.0]),
+            "NALOD" => Some(vec![1.0, -2.0]),
+            "PON" => Some(vec![0.0]),
+            _ => None,
+        })
+    }
+
+    fn eval(s: &str) -> bool {
+        Expression::parse(s)
+            .unwrap()
+            .evaluate(&lookup, false)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Expression::parse("TLOD>=6.3").unwrap(),
+            Expression::Compare("TLOD".to_owned(), Operator::Ge, 6.3)
+        );
+        assert_eq!(
+            Expression::parse("A | B & !C").unwrap(),
+            Expression::Or(
+                Box::new(Expression::Test("A".to_owned())),
+                Box::new(Expression::And(
+                    Box::new(Expression::Test("B".to_owned())),
+                    Box::new(Expression::Not(Box::new(Expression::Test("C".to_owned()))))
+                ))
+            )
+        );
+        assert_eq!(
+            Expression::parse("(A || B) && C").unwrap().keys(),
+            ["A", "B", "C"].iter().cloned().collect()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Expression::parse("").is_err());
+        assert!(Expression::parse("TLOD>=").is_err());
+        assert!(Expression::parse("TLOD>=abc").is_err());
+        assert!(Expression::parse("(TLOD>1").is_err());
+        assert!(Expression::parse("TLOD>1)").is_err());
+        assert!(Expression::parse("TLOD>1 &").is_err());
+        assert!(Expression::parse("6.3<TLOD").is_err());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert!(eval("TLOD>=6.3"));
+        assert!(!eval("TLOD<6.3"));
+        assert!(eval("TLOD>=6.3 & STR"));
+        assert!(!eval("TLOD>=6.3 & STR!=1"));
+        assert!(eval("TLOD>=10 | STR==1"));
+        assert!(!eval("PON"));
+        assert!(eval("!PON"));
+        assert!(eval("NALOD<0"));
+        assert!(eval("!(TLOD<6.3 | PON)"));
+    }
+
+    #[test]
+    fn test_evaluate_missing() {
+        let e = Expression::parse("DP>10").unwrap();
+        assert!(!e.evaluate(&lookup, false).unwrap());
+        assert!(e.evaluate(&lookup, true).unwrap());
+        let e = Expression::parse("!(DP>10)").unwrap();
+        assert!(e.evaluate(&lookup, false).unwrap());
+    }
+}
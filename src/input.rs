@@ -0,0 +1,38 @@
+use crate::error::Error;
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// The magic bytes at the start of gzip (and thus bgzip) compressed files.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Open the text file at `path` for reading. Gzip and bgzip compressed files are
+/// recognized by their magic bytes and decompressed transparently.
+pub fn open<P: AsRef<Path>>(path: P) -> Result<Box<dyn BufRead>, Error> {
+    let path = path.as_ref();
+    let mut file = match File::open(path) {
+        Ok(f) => BufReader::new(f),
+        Err(e) => {
+            return Err(Error::Io {
+                action: format!("open '{}'", path.display()),
+                source: e,
+            })
+        }
+    };
+    let is_compressed = match file.fill_buf() {
+        Ok(buffer) => buffer.starts_with(&GZIP_MAGIC),
+        Err(e) => {
+            return Err(Error::Io {
+                action: format!("read '{}'", path.display()),
+                source: e,
+            })
+        }
+    };
+
+    if is_compressed {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+    } else {
+        Ok(Box::new(file))
+    }
+}
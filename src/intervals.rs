@@ -0,0 +1,406 @@
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::path::Path;
+
+/// A set of genomic intervals per contig. Intervals are stored 0-based and half-open,
+/// sorted by their start and with overlapping intervals merged, such that
+/// lookups are a binary search.
+#[derive(Default, Debug, PartialEq)]
+pub struct Intervals {
+    inner: BTreeMap<String, Vec<(u64, u64)>>,
+}
+
+impl Intervals {
+    /// Create a new empty interval set.
+    pub fn new() -> Self {
+        Intervals::default()
+    }
+
+    /// Add the 0-based half-open interval `[start, end)` on `contig`.
+    pub fn insert(&mut self, contig: &str, mut start: u64, mut end: u64) {
+        if start >= end {
+            return;
+        }
+        let v = self.inner.entry(contig.to_owned()).or_default();
+        // Find all intervals overlapping or adjacent to the new one and merge them
+        let lo = v.partition_point(|&(_, e)| e < start);
+        let hi = v.partition_point(|&(s, _)| s <= end);
+        if lo < hi {
+            start = start.min(v[lo].0);
+            end = end.max(v[hi - 1].1);
+        }
+        v.splice(lo..hi, std::iter::once((start, end)));
+    }
+
+    /// Check if the 0-based `position` on `contig` is covered by any interval.
+    pub fn contains(&self, contig: &str, position: u64) -> bool {
+        match self.inner.get(contig) {
+            None => false,
+            Some(v) => {
+                let i = v.partition_point(|&(s, _)| s <= position);
+                i > 0 && v[i - 1].1 > position
+            }
+        }
+    }
+
+    /// Return the number of (merged) intervals.
+    pub fn len(&self) -> usize {
+        self.inner.values().map(|v| v.len()).sum()
+    }
+
+    /// Check if the set does not contain any interval.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the contig, start and end of all (merged) intervals, ordered by contig
+    /// and start.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64, u64)> {
+        self.inner
+            .iter()
+            .flat_map(|(contig, v)| v.iter().map(move |(s, e)| (contig.as_str(), *s, *e)))
+    }
+
+    /// Add all intervals of `other` to this set.
+    pub fn extend(&mut self, other: &Intervals) {
+        for (contig, v) in &other.inner {
+            for (start, end) in v {
+                self.insert(contig, *start, *end);
+            }
+        }
+    }
+}
+
+/// Intervals labeled by their name, e.g., functional classes like exons or promoters. The
+/// labels are ordered by their priority, which resolves positions covered by several labels.
+#[derive(Debug, PartialEq)]
+pub struct LabeledIntervals {
+    labels: Vec<String>,
+    intervals: Vec<Intervals>,
+}
+
+impl LabeledIntervals {
+    /// Order the `named` intervals by the labels in `priority`, followed by the labels
+    /// missing in `priority` in lexicographic order. Every label in `priority` must be present.
+    pub fn new(
+        mut named: BTreeMap<String, Intervals>,
+        priority: &[String],
+    ) -> Result<LabeledIntervals, String> {
+        let mut labels = Vec::new();
+        let mut intervals = Vec::new();
+        for label in priority {
+            match named.remove(label) {
+                Some(i) => {
+                    labels.push(label.clone());
+                    intervals.push(i);
+                }
+                None => {
+                    return Err(format!(
+                        "Can not prioritize label '{}', no interval is labeled with it",
+                        label
+                    ))
+                }
+            }
+        }
+        for (label, i) in named {
+            labels.push(label);
+            intervals.push(i);
+        }
+        Ok(LabeledIntervals { labels, intervals })
+    }
+
+    /// Return the index of the label with the highest priority covering the 0-based
+    /// `position` on `contig`, if any.
+    pub fn label_at(&self, contig: &str, position: u64) -> Option<usize> {
+        self.intervals
+            .iter()
+            .position(|i| i.contains(contig, position))
+    }
+
+    /// Return the labels in the order of their priority.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+}
+
+/// Read the intervals of the (optionally gzip or bgzip compressed) BED file at `path`.
+pub fn read_bed<P: AsRef<Path>>(path: P) -> Result<Intervals, String> {
+    let name = path.as_ref().display().to_string();
+    parse_bed(crate::input::open(path)?, &name)
+}
+
+/// Read the intervals of the (optionally gzip or bgzip compressed) BED file at `path`,
+/// grouped by their name (fourth column).
+pub fn read_named_bed<P: AsRef<Path>>(path: P) -> Result<BTreeMap<String, Intervals>, String> {
+    let name = path.as_ref().display().to_string();
+    parse_named_bed(crate::input::open(path)?, &name)
+}
+
+/// Read the intervals of the (optionally gzip or bgzip compressed) Picard interval list
+/// at `path`. Returns the intervals and the names of the sequences declared in the
+/// `@SQ` header lines.
+pub fn read_interval_list<P: AsRef<Path>>(path: P) -> Result<(Intervals, Vec<String>), String> {
+    let name = path.as_ref().display().to_string();
+    parse_interval_list(crate::input::open(path)?, &name)
+}
+
+/// Parse a coordinate in column `column` of line `line_no` of file `name`.
+fn parse_coordinate(
+    value: Option<&str>,
+    column: &str,
+    name: &str,
+    line_no: usize,
+) -> Result<u64, String> {
+    match value.map(|v| v.parse::<u64>()) {
+        Some(Ok(v)) => Ok(v),
+        Some(Err(e)) => Err(format!(
+            "Invalid {} in line {} of '{}': {}",
+            column, line_no, name, e
+        )),
+        None => Err(format!(
+            "Missing {} in line {} of '{}'",
+            column, line_no, name
+        )),
+    }
+}
+
+/// Parse BED formatted intervals (0-based, half-open) from `reader`. Track, browser
+/// and comment lines are skipped, columns after the end coordinate are ignored.
+fn parse_bed<R: BufRead>(reader: R, name: &str) -> Result<Intervals, String> {
+    let mut intervals = Intervals::new();
+    parse_bed_with(reader, name, |contig, start, end, _| {
+        intervals.insert(contig, start, end);
+        Ok(())
+    })?;
+    Ok(intervals)
+}
+
+/// Parse BED formatted intervals from `reader` into one set per name (fourth column).
+/// Every interval must be named.
+fn parse_named_bed<R: BufRead>(
+    reader: R,
+    name: &str,
+) -> Result<BTreeMap<String, Intervals>, String> {
+    let mut named: BTreeMap<String, Intervals> = BTreeMap::new();
+    parse_bed_with(reader, name, |contig, start, end, fields| {
+        match fields.next() {
+            Some(n) if !n.is_empty() => {
+                named
+                    .entry(n.to_owned())
+                    .or_default()
+                    .insert(contig, start, end);
+                Ok(())
+            }
+            _ => Err("Missing name".to_owned()),
+        }
+    })?;
+    Ok(named)
+}
+
+/// Parse the lines of BED formatted `reader` and pass the contig, start, end and the
+/// remaining columns of each interval to `f`. Errors of `f` are reported with the line.
+fn parse_bed_with<R, F>(reader: R, name: &str, mut f: F) -> Result<(), String>
+where
+    R: BufRead,
+    F: FnMut(&str, u64, u64, &mut std::str::Split<char>) -> Result<(), String>,
+{
+    for (i, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => return Err(format!("Can not read '{}': {}", name, e)),
+        };
+        if line.trim().is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let mut fields = line.split('\t');
+        let contig = fields.next().unwrap();
+        let start = parse_coordinate(fields.next(), "start", name, i + 1)?;
+        let end = parse_coordinate(fields.next(), "end", name, i + 1)?;
+        if end < start {
+            return Err(format!(
+                "Invalid interval {}:{}-{} in line {} of '{}'",
+                contig,
+                start,
+                end,
+                i + 1,
+                name
+            ));
+        }
+        if let Err(e) = f(contig, start, end, &mut fields) {
+            return Err(format!("{} in line {} of '{}'", e, i + 1, name));
+        }
+    }
+    Ok(())
+}
+
+/// Parse Picard interval list formatted intervals (1-based, inclusive) from `reader` and
+/// convert them into 0-based half-open intervals. The strand and name columns are ignored.
+/// Every interval must be located on a sequence declared in the `@SQ` header.
+fn parse_interval_list<R: BufRead>(
+    reader: R,
+    name: &str,
+) -> Result<(Intervals, Vec<String>), String> {
+    let mut intervals = Intervals::new();
+    let mut sequences = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => return Err(format!("Can not read '{}': {}", name, e)),
+        };
+        if line.starts_with("@SQ") {
+            match line.split('\t').find(|f| f.starts_with("SN:")) {
+                Some(sn) => sequences.push(sn[3..].to_owned()),
+                None => {
+                    return Err(format!(
+                        "Missing SN in @SQ header line {} of '{}'",
+                        i + 1,
+                        name
+                    ))
+                }
+            }
+            continue;
+        }
+        if line.starts_with('@') || line.trim().is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split('\t');
+        let contig = fields.next().unwrap();
+        let start = parse_coordinate(fields.next(), "start", name, i + 1)?;
+        let end = parse_coordinate(fields.next(), "end", name, i + 1)?;
+        if start < 1 || end < start {
+            return Err(format!(
+                "Invalid interval {}:{}-{} in line {} of '{}'",
+                contig,
+                start,
+                end,
+                i + 1,
+                name
+            ));
+        }
+        if !sequences.iter().any(|s| s == contig) {
+            return Err(format!(
+                "Contig '{}' in line {} of '{}' is not declared in the @SQ header",
+                contig,
+                i + 1,
+                name
+            ));
+        }
+        intervals.insert(contig, start - 1, end);
+    }
+    Ok((intervals, sequences))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_merges_overlaps() {
+        let mut i = Intervals::new();
+        i.insert("1", 10, 20);
+        i.insert("1", 30, 40);
+        i.insert("1", 15, 32);
+        i.insert("1", 50, 50);
+        assert_eq!(i.len(), 1);
+        assert!(!i.contains("1", 9));
+        assert!(i.contains("1", 10));
+        assert!(i.contains("1", 39));
+        assert!(!i.contains("1", 40));
+        assert!(!i.contains("2", 15));
+        i.insert("2", 5, 8);
+        assert_eq!(
+            i.iter().collect::<Vec<_>>(),
+            vec![("1", 10, 40), ("2", 5, 8)]
+        );
+    }
+
+    #[test]
+    fn test_parse_bed() {
+        let bed = "track name=test\n1\t1\t3\tname\n2\t0\t1\n";
+        let i = parse_bed(bed.as_bytes(), "test.bed").unwrap();
+        assert!(!i.contains("1", 0));
+        assert!(i.contains("1", 1));
+        assert!(i.contains("1", 2));
+        assert!(!i.contains("1", 3));
+        assert!(i.contains("2", 0));
+        assert!(parse_bed("1\t5\t3\n".as_bytes(), "test.bed").is_err());
+    }
+
+    #[test]
+    fn test_parse_named_bed() {
+        let bed = "1\t0\t3\tright\n1\t3\t5\tleft\t0\n2\t0\t2\tright\n";
+        let named = parse_named_bed(bed.as_bytes(), "test.bed").unwrap();
+        assert_eq!(named.len(), 2);
+        assert!(named["right"].contains("1", 2));
+        assert!(named["right"].contains("2", 1));
+        assert!(!named["right"].contains("1", 3));
+        assert!(named["left"].contains("1", 3));
+        let e = parse_named_bed("1\t0\t3\n".as_bytes(), "test.bed").unwrap_err();
+        assert_eq!(e, "Missing name in line 1 of 'test.bed'");
+    }
+
+    #[test]
+    fn test_labeled_intervals() {
+        // An exon nested in a gene with a promoter overlapping the first exon
+        let bed = "1\t0\t100\tintron\n1\t10\t20\texon\n1\t50\t60\texon\n1\t5\t15\tpromoter\n";
+        let named = parse_named_bed(bed.as_bytes(), "test.bed").unwrap();
+        let labeled = LabeledIntervals::new(named, &[]).unwrap();
+        assert_eq!(labeled.labels(), &["exon", "intron", "promoter"]);
+        assert_eq!(labeled.label_at("1", 12), Some(0));
+        assert_eq!(labeled.label_at("1", 7), Some(1));
+        assert_eq!(labeled.label_at("1", 100), None);
+
+        let named = parse_named_bed(bed.as_bytes(), "test.bed").unwrap();
+        let priority = vec!["promoter".to_owned(), "exon".to_owned()];
+        let labeled = LabeledIntervals::new(named, &priority).unwrap();
+        assert_eq!(labeled.labels(), &["promoter", "exon", "intron"]);
+        assert_eq!(labeled.label_at("1", 12), Some(0));
+        assert_eq!(labeled.label_at("1", 15), Some(1));
+        assert_eq!(labeled.label_at("1", 55), Some(1));
+        assert_eq!(labeled.label_at("1", 30), Some(2));
+
+        let named = parse_named_bed(bed.as_bytes(), "test.bed").unwrap();
+        assert!(LabeledIntervals::new(named, &["utr".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn test_read_compressed_bed() {
+        let testdata = |name: &str| format!("{}/testdata/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let plain = read_bed(testdata("ex2.bed")).unwrap();
+        assert_eq!(plain.len(), 2);
+        assert_eq!(read_bed(testdata("ex2.bed.gz")).unwrap(), plain);
+        assert_eq!(read_bed(testdata("ex2_bgzip.bed.gz")).unwrap(), plain);
+
+        let e = read_bed(testdata("ex2_corrupt.bed.gz")).unwrap_err();
+        assert!(e.contains("ex2_corrupt.bed.gz"), "{}", e);
+    }
+
+    #[test]
+    fn test_interval_list_coordinate_conversion() {
+        let (intervals, sequences) = read_interval_list(format!(
+            "{}/testdata/ex2.interval_list",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        assert_eq!(sequences, vec!["1".to_owned(), "2".to_owned()]);
+
+        // 1:2-3 (1-based, inclusive) is [1, 3) in 0-based half-open coordinates
+        let mut expected = Intervals::new();
+        expected.insert("1", 1, 3);
+        expected.insert("2", 5, 6);
+        assert_eq!(intervals, expected);
+    }
+
+    #[test]
+    fn test_interval_list_requires_declared_contigs() {
+        let list = "@HD\tVN:1.0\n@SQ\tSN:1\tLN:6\n2\t1\t2\t+\tx\n";
+        assert!(parse_interval_list(list.as_bytes(), "test.interval_list").is_err());
+        let list = "@SQ\tSN:1\tLN:6\n1\t0\t2\t+\tx\n";
+        assert!(parse_interval_list(list.as_bytes(), "test.interval_list").is_err());
+    }
+}
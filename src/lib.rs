@@ -0,0 +1,73 @@
+//! Count the mutational signatures, i.e., the substitutions in their sequence context, of the
+//! samples of a VCF file.
+//!
+//! The command line tool `mutsig` is a thin wrapper around this library. The counting is
+//! configured by [`CountOptions`] and run by [`count_vcf`], which returns the counts per
+//! channel and sample together with the summary of the processed records:
+//!
+//! ```
+//! let options = mutsig::CountOptions::new("testdata/ex2.vcf", "testdata/ex2.fa");
+//! let counts = mutsig::count_vcf(&options).unwrap();
+//! assert_eq!(counts.samples, vec!["Example1", "Example2"]);
+//! let c_to_a = mutsig::signature::Signature::parse("C>A").unwrap();
+//! let index = counts.signatures.index_of(&c_to_a).unwrap();
+//! assert_eq!(counts.results.get(index, 0), 2);
+//! ```
+extern crate console;
+extern crate flate2;
+extern crate indicatif;
+extern crate indicatif_log_bridge;
+#[macro_use]
+extern crate log;
+#[cfg(feature = "ndarray")]
+extern crate ndarray;
+extern crate pretty_env_logger;
+extern crate rust_htslib;
+#[cfg(feature = "serde")]
+extern crate serde;
+extern crate thiserror;
+extern crate url;
+mod aggregate;
+mod annotation;
+pub mod classify;
+mod compare;
+mod count;
+mod dbs;
+mod dedupe;
+mod dendrogram;
+mod distance;
+mod downsample;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod filter;
+pub mod fit;
+pub mod genotype;
+mod indel;
+mod info_filter;
+mod input;
+mod intervals;
+mod metrics;
+mod mito;
+mod opportunity;
+mod plot;
+pub mod profile;
+pub mod reference;
+mod remote;
+mod replication;
+pub mod result;
+pub mod runner;
+pub mod signature;
+pub mod similarity;
+mod stats;
+pub mod summary;
+mod svg;
+
+#[doc(hidden)]
+pub mod cli;
+#[doc(hidden)]
+pub mod progress;
+
+pub use count::{count_vcf, CountOptions, CountResult, Progress};
+pub use error::Error;
+pub use runner::MutsigRunner;
@@ -0,0 +1,409 @@
+use crate::fit::{self, Matrix};
+use crate::signature::Signature;
+use crate::similarity::NA;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// The six classes of single base substitutions with the pyrimidine as reference base.
+pub const CLASSES: [(char, char); 6] = [
+    ('C', 'A'),
+    ('C', 'G'),
+    ('C', 'T'),
+    ('T', 'A'),
+    ('T', 'C'),
+    ('T', 'G'),
+];
+
+/// The number of channels with the largest counts reported per sample.
+const TOP_CHANNELS: usize = 5;
+
+/// The summary metrics of the counts of a sample.
+#[derive(Debug)]
+pub struct Metrics {
+    /// The name of the sample
+    pub sample: String,
+    /// The total count of all channels
+    pub total: f64,
+    /// The count of the transitions, i.e., C>T and T>C
+    pub transitions: f64,
+    /// The count of the transversions, i.e., all other substitutions
+    pub transversions: f64,
+    /// The count per substitution class in the order of `CLASSES`
+    pub classes: [f64; 6],
+    /// The labels and counts of the channels with the largest non-zero counts
+    pub top: Vec<(String, f64)>,
+    /// The Shannon entropy of the fractions of the channels, or `None` without counts
+    pub entropy: Option<f64>,
+}
+
+impl Metrics {
+    /// Return the ratio of transitions to transversions, or `None` without transversions.
+    pub fn ts_tv(&self) -> Option<f64> {
+        if self.transversions > 0.0 {
+            Some(self.transitions / self.transversions)
+        } else {
+            None
+        }
+    }
+
+    /// Return the effective number of channels, i.e., the exponential of the entropy.
+    pub fn effective_channels(&self) -> Option<f64> {
+        self.entropy.map(f64::exp)
+    }
+}
+
+/// Return the Shannon entropy (in nats) of the fractions of the `profile`, where channels
+/// without counts contribute zero, or `None` if the profile has no counts.
+pub fn shannon_entropy(profile: &[f64]) -> Option<f64> {
+    let total: f64 = profile.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let entropy = profile
+        .iter()
+        .filter(|v| **v > 0.0)
+        .map(|v| {
+            let p = v / total;
+            -p * p.ln()
+        })
+        .sum::<f64>();
+    // A single channel yields -0
+    Some(if entropy > 0.0 { entropy } else { 0.0 })
+}
+
+/// Write the Shannon entropy and the effective number of channels of the `profiles` of the
+/// samples `names` to `writer` as a tab-separated table, with `NA` for samples without counts.
+pub fn write_diversity<W: Write>(
+    mut writer: W,
+    names: &[String],
+    profiles: &[Vec<f64>],
+) -> Result<(), String> {
+    let write_error = |e: std::io::Error| format!("Can not write diversity: {}", e);
+    writeln!(writer, "Sample\tEntropy\tEffectiveChannels").map_err(write_error)?;
+    for (name, profile) in names.iter().zip(profiles) {
+        let (entropy, effective) = match shannon_entropy(profile) {
+            Some(h) => (round(h), round(h.exp())),
+            None => (NA.to_owned(), NA.to_owned()),
+        };
+        writeln!(writer, "{}\t{}\t{}", name, entropy, effective).map_err(write_error)?;
+    }
+    writer.flush().map_err(write_error)
+}
+
+/// Return the `value` rounded off the summation errors.
+fn round(value: f64) -> String {
+    ((value * 1e6).round() / 1e6).to_string()
+}
+
+/// Read the counts from the (optionally gzip compressed) file at `path`, either as matrix of
+/// the channels (rows) per sample (columns) as reported by mutsig, or in the long format with
+/// the header `Sample`, `Channel` and `Count`.
+pub fn read_counts<P: AsRef<Path>>(path: P) -> Result<Matrix, String> {
+    let name = path.as_ref().display().to_string();
+    read_counts_from(crate::input::open(path)?, &name)
+}
+
+/// Read the counts in the wide or long format from `reader`.
+pub fn read_counts_from<R: BufRead>(reader: R, name: &str) -> Result<Matrix, String> {
+    let lines = reader
+        .lines()
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(|e| format!("Can not read '{}': {}", name, e))?;
+    let header = lines
+        .iter()
+        .find(|l| !l.starts_with('#') && !l.trim().is_empty());
+    let is_long = match header {
+        Some(h) => {
+            let fields: Vec<String> = h.split('\t').map(|f| f.trim().to_lowercase()).collect();
+            fields == ["sample", "channel", "count"]
+        }
+        None => false,
+    };
+    if is_long {
+        parse_long(&lines, name)
+    } else {
+        fit::parse_matrix(lines.join("\n").as_bytes(), name)
+    }
+}
+
+/// Parse the counts in the long format from the `lines`, i.e., a sample, channel and count per
+/// line after the header. Samples and channels are ordered by their first occurrence, and
+/// missing combinations are counted as zero.
+fn parse_long(lines: &[String], name: &str) -> Result<Matrix, String> {
+    let mut samples: Vec<String> = Vec::new();
+    let mut channels: Vec<String> = Vec::new();
+    let mut counts: HashMap<(usize, usize), f64> = HashMap::new();
+    let mut header = true;
+    for (i, line) in lines.iter().enumerate() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        if header {
+            header = false;
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').map(|f| f.trim()).collect();
+        let (sample, channel, count) = match fields[..] {
+            [sample, channel, count] if !sample.is_empty() && !channel.is_empty() => {
+                (sample, channel, count)
+            }
+            _ => {
+                return Err(format!(
+                    "Can not parse line {} of '{}', expected a sample, channel and count",
+                    i + 1,
+                    name
+                ))
+            }
+        };
+        let count: f64 = count
+            .parse()
+            .map_err(|e| format!("Can not parse value in line {} of '{}': {}", i + 1, name, e))?;
+        let s = position_or_insert(&mut samples, sample);
+        let c = position_or_insert(&mut channels, channel);
+        if counts.insert((c, s), count).is_some() {
+            return Err(format!(
+                "Can not parse line {} of '{}', channel '{}' of sample '{}' is repeated",
+                i + 1,
+                name,
+                channel,
+                sample
+            ));
+        }
+    }
+    if samples.is_empty() {
+        return Err(format!("Can not find any sample in '{}'", name));
+    }
+    let values = (0..channels.len())
+        .map(|c| {
+            (0..samples.len())
+                .map(|s| counts.get(&(c, s)).cloned().unwrap_or(0.0))
+                .collect()
+        })
+        .collect();
+    Ok(Matrix::new(channels, samples, values))
+}
+
+/// Return the index of the `label` in `labels`, appending it if missing.
+fn position_or_insert(labels: &mut Vec<String>, label: &str) -> usize {
+    match labels.iter().position(|l| l == label) {
+        Some(i) => i,
+        None => {
+            labels.push(label.to_owned());
+            labels.len() - 1
+        }
+    }
+}
+
+/// Return the index of the substitution class in `CLASSES` of the channel `label`, labeled by
+/// its context (e.g., `TCA>T`) or as in COSMIC (e.g., `T[C>T]A`), optionally with the prefix
+/// of a stratified channel (e.g., `T:`).
+fn substitution_class(label: &str) -> Result<usize, String> {
+    let channel = match label.rfind(':') {
+        Some(i) => &label[i + 1..],
+        None => label,
+    };
+    let invalid = || {
+        format!(
+            "Can not compute metrics of channel '{}', expected a single base substitution",
+            label
+        )
+    };
+    if channel.is_empty() || !channel.chars().all(|c| "ACGT[]>".contains(c)) {
+        return Err(invalid());
+    }
+    let substitution = Signature::parse(channel)
+        .map_err(|_| invalid())?
+        .substitution();
+    CLASSES
+        .iter()
+        .position(|c| *c == substitution)
+        .ok_or_else(invalid)
+}
+
+/// Compute the metrics of each sample of the `counts`. Ties of the top channels are ranked in
+/// the order of the channels.
+pub fn metrics(counts: &Matrix) -> Result<Vec<Metrics>, String> {
+    let classes = counts
+        .rows()
+        .iter()
+        .map(|l| substitution_class(l))
+        .collect::<Result<Vec<usize>, String>>()?;
+    let mut metrics = Vec::new();
+    for (column, sample) in counts.columns().iter().enumerate() {
+        let values = counts.column(column);
+        let mut class_counts = [0.0; 6];
+        for (value, class) in values.iter().zip(&classes) {
+            if *value < 0.0 {
+                return Err(format!(
+                    "Can not compute metrics of count {} of sample '{}', expected a non-negative value",
+                    value, sample
+                ));
+            }
+            class_counts[*class] += value;
+        }
+        let transitions = class_counts[2] + class_counts[4];
+        let total: f64 = class_counts.iter().sum();
+        let entropy = shannon_entropy(&values);
+        let mut top: Vec<(String, f64)> = counts
+            .rows()
+            .iter()
+            .zip(values)
+            .filter(|(_, v)| *v > 0.0)
+            .map(|(l, v)| (l.clone(), v))
+            .collect();
+        // The stable sort keeps ties in the order of the channels
+        top.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        top.truncate(TOP_CHANNELS);
+        metrics.push(Metrics {
+            sample: sample.clone(),
+            total,
+            transitions,
+            transversions: total - transitions,
+            classes: class_counts,
+            top,
+            entropy,
+        });
+    }
+    Ok(metrics)
+}
+
+/// Write the `metrics` to `writer` as a tidy tab-separated table of sample, metric, channel
+/// and value, where the channel is `NA` for metrics of all channels.
+pub fn write_metrics<W: Write>(mut writer: W, metrics: &[Metrics]) -> Result<(), String> {
+    let write_error = |e: std::io::Error| format!("Can not write metrics: {}", e);
+    writeln!(writer, "Sample\tMetric\tChannel\tValue").map_err(write_error)?;
+    for m in metrics {
+        let mut rows = vec![
+            ("total", NA.to_owned(), round(m.total)),
+            ("transitions", NA.to_owned(), round(m.transitions)),
+            ("transversions", NA.to_owned(), round(m.transversions)),
+            (
+                "ts_tv",
+                NA.to_owned(),
+                m.ts_tv().map(round).unwrap_or_else(|| NA.to_owned()),
+            ),
+            (
+                "entropy",
+                NA.to_owned(),
+                m.entropy.map(round).unwrap_or_else(|| NA.to_owned()),
+            ),
+            (
+                "effective_channels",
+                NA.to_owned(),
+                m.effective_channels()
+                    .map(round)
+                    .unwrap_or_else(|| NA.to_owned()),
+            ),
+        ];
+        for ((reference, alternative), count) in CLASSES.iter().zip(&m.classes) {
+            let fraction = if m.total > 0.0 {
+                round(count / m.total)
+            } else {
+                NA.to_owned()
+            };
+            rows.push((
+                "class_fraction",
+                format!("{}>{}", reference, alternative),
+                fraction,
+            ));
+        }
+        let ranks = ["top_1", "top_2", "top_3", "top_4", "top_5"];
+        for (rank, (label, count)) in ranks.iter().zip(&m.top) {
+            rows.push((rank, label.clone(), round(*count)));
+        }
+        for (metric, channel, value) in rows {
+            writeln!(writer, "{}\t{}\t{}\t{}", m.sample, metric, channel, value)
+                .map_err(write_error)?;
+        }
+    }
+    writer.flush().map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_long() {
+        let long = "sample\tchannel\tcount\nS1\tC>T\t3\nS2\tT>C\t1\nS1\tT>C\t2\n";
+        let counts = read_counts_from(long.as_bytes(), "test").unwrap();
+        assert_eq!(counts.rows(), &vec!["C>T".to_owned(), "T>C".to_owned()]);
+        assert_eq!(counts.columns(), &vec!["S1".to_owned(), "S2".to_owned()]);
+        assert_eq!(counts.column(0), vec![3.0, 2.0]);
+        assert_eq!(counts.column(1), vec![0.0, 1.0]);
+        let repeated = "Sample\tChannel\tCount\nS1\tC>T\t3\nS1\tC>T\t2\n";
+        assert!(read_counts_from(repeated.as_bytes(), "test").is_err());
+        assert!(read_counts_from("Sample\tChannel\tCount\nS1\tC>T\n".as_bytes(), "test").is_err());
+    }
+
+    #[test]
+    fn test_substitution_class() {
+        assert_eq!(substitution_class("C>A"), Ok(0));
+        assert_eq!(substitution_class("TCA>T"), Ok(2));
+        assert_eq!(substitution_class("T[C>T]A"), Ok(2));
+        // Purine substitutions are counted as their reverse complement
+        assert_eq!(substitution_class("CAG>T"), Ok(3));
+        assert_eq!(substitution_class("T:A[T>C]G"), Ok(4));
+        assert!(substitution_class("CC>TT").is_err());
+        assert!(substitution_class("ACA>C").is_err());
+        assert!(substitution_class("NCN>T").is_err());
+        assert!(substitution_class("residual").is_err());
+    }
+
+    #[test]
+    fn test_metrics() {
+        let wide = "Variant\tS1\tS2\nA[C>T]G\t4\t0\nT[T>C]A\t2\t0\nC>A\t3\t0\nT>G\t1\t0\n";
+        let counts = read_counts_from(wide.as_bytes(), "test").unwrap();
+        let metrics = metrics(&counts).unwrap();
+        assert_eq!(metrics[0].total, 10.0);
+        assert_eq!(metrics[0].transitions, 6.0);
+        assert_eq!(metrics[0].ts_tv(), Some(1.5));
+        assert_eq!(metrics[0].classes, [3.0, 0.0, 4.0, 0.0, 2.0, 1.0][..]);
+        assert_eq!(metrics[0].top[0], ("A[C>T]G".to_owned(), 4.0));
+        assert_eq!(metrics[0].top.len(), 4);
+        // Samples without counts have neither a ratio nor top channels
+        assert_eq!(metrics[1].ts_tv(), None);
+        assert!(metrics[1].top.is_empty());
+
+        let mut out = Vec::new();
+        write_metrics(&mut out, &metrics).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "Sample\tMetric\tChannel\tValue");
+        assert!(lines.contains(&"S1\tts_tv\tNA\t1.5"));
+        assert!(lines.contains(&"S1\tclass_fraction\tC>T\t0.4"));
+        assert!(lines.contains(&"S1\ttop_2\tC>A\t3"));
+        assert!(lines.contains(&"S2\tts_tv\tNA\tNA"));
+        assert!(lines.contains(&"S2\tclass_fraction\tC>A\tNA"));
+        assert!(lines.contains(&"S2\tentropy\tNA\tNA"));
+        assert_eq!(lines.len(), 1 + 16 + 12);
+    }
+
+    #[test]
+    fn test_shannon_entropy() {
+        // A uniform spectrum of four channels has the entropy ln(4) and four effective channels
+        let uniform = shannon_entropy(&[2.0, 2.0, 2.0, 2.0]).unwrap();
+        assert!((uniform - 4f64.ln()).abs() < 1e-12);
+        assert!((uniform.exp() - 4.0).abs() < 1e-12);
+        // A single channel has no entropy and one effective channel
+        assert_eq!(shannon_entropy(&[0.0, 7.0, 0.0]), Some(0.0));
+        // -(0.75 ln 0.75 + 0.25 ln 0.25)
+        let skewed = shannon_entropy(&[3.0, 1.0, 0.0]).unwrap();
+        assert!((skewed - 0.5623351446188083).abs() < 1e-12);
+        assert_eq!(shannon_entropy(&[0.0, 0.0]), None);
+        assert_eq!(shannon_entropy(&[]), None);
+    }
+
+    #[test]
+    fn test_write_diversity() {
+        let names = vec!["S1".to_owned(), "S2".to_owned(), "S3".to_owned()];
+        let profiles = vec![vec![1.0, 1.0], vec![0.0, 5.0], vec![0.0, 0.0]];
+        let mut out = Vec::new();
+        write_diversity(&mut out, &names, &profiles).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Sample\tEntropy\tEffectiveChannels\nS1\t0.693147\t2\nS2\t0\t1\nS3\tNA\tNA\n"
+        );
+    }
+}
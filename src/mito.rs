@@ -0,0 +1,108 @@
+use crate::signature::Signatures;
+use std::collections::HashSet;
+
+/// The default names of the mitochondrial contig.
+pub const DEFAULT_CONTIGS: [&str; 2] = ["chrM", "MT"];
+
+/// Counting of the substitutions on the mitochondrial contig relative to the reference
+/// (heavy) strand, i.e., without collapsing them to the pyrimidine of the base pair.
+pub struct Mito {
+    contigs: HashSet<String>,
+    /// The 1-based inclusive control region, which wraps around the end of the circular
+    /// contig if its start is after its end
+    control_region: Option<(u64, u64)>,
+    signatures: Signatures,
+}
+
+impl Mito {
+    /// Count the substitutions on any of the `contigs` into the uncollapsed signatures of
+    /// `window`, skipping those in the `control_region`.
+    pub fn new(
+        contigs: HashSet<String>,
+        control_region: Option<(u64, u64)>,
+        window: usize,
+    ) -> Mito {
+        Mito {
+            contigs,
+            control_region,
+            signatures: Signatures::uncollapsed(window),
+        }
+    }
+
+    /// Check if `contig` is the mitochondrial contig.
+    pub fn is_mito(&self, contig: &str) -> bool {
+        self.contigs.contains(contig)
+    }
+
+    /// Check if the 0-based `position` on `contig` is in the control region.
+    pub fn is_control_region(&self, contig: &str, position: u64) -> bool {
+        let position = position + 1;
+        match self.control_region {
+            Some((start, end)) if self.is_mito(contig) => {
+                if start <= end {
+                    start <= position && position <= end
+                } else {
+                    position >= start || position <= end
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Return the uncollapsed signatures of the mitochondrial contig.
+    pub fn signatures(&self) -> &Signatures {
+        &self.signatures
+    }
+}
+
+/// Parse a 1-based inclusive region like `16024-576`, whose start may be after its end if
+/// it wraps around the end of a circular contig.
+pub fn parse_region(region: &str) -> Result<(u64, u64), String> {
+    let invalid = || format!("Can not parse region '{}', expected START-END", region);
+    let mut parts = region.splitn(2, '-');
+    let start = parts.next().and_then(|s| s.trim().parse::<u64>().ok());
+    let end = parts.next().and_then(|s| s.trim().parse::<u64>().ok());
+    match (start, end) {
+        (Some(start), Some(end)) if start >= 1 && end >= 1 => Ok((start, end)),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mito(control_region: Option<(u64, u64)>) -> Mito {
+        let contigs = DEFAULT_CONTIGS.iter().map(|c| c.to_string()).collect();
+        Mito::new(contigs, control_region, 0)
+    }
+
+    #[test]
+    fn test_control_region() {
+        let m = mito(Some(parse_region("16024-576").unwrap()));
+        assert!(m.is_mito("chrM"));
+        assert!(!m.is_mito("chr1"));
+        // 0-based positions
+        assert!(m.is_control_region("chrM", 0));
+        assert!(m.is_control_region("chrM", 575));
+        assert!(!m.is_control_region("chrM", 576));
+        assert!(!m.is_control_region("chrM", 16022));
+        assert!(m.is_control_region("chrM", 16023));
+        assert!(!m.is_control_region("chr1", 0));
+
+        let m = mito(Some(parse_region("10-20").unwrap()));
+        assert!(!m.is_control_region("MT", 8));
+        assert!(m.is_control_region("MT", 9));
+        assert!(m.is_control_region("MT", 19));
+        assert!(!m.is_control_region("MT", 20));
+        assert!(!mito(None).is_control_region("MT", 0));
+    }
+
+    #[test]
+    fn test_parse_region() {
+        assert_eq!(parse_region("16024-576"), Ok((16024, 576)));
+        assert!(parse_region("16024").is_err());
+        assert!(parse_region("0-5").is_err());
+        assert!(parse_region("a-5").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::intervals::{self, Intervals};
+use crate::reference::ReferenceProvider;
+use crate::signature::{Signature, Signatures};
+use std::collections::{BTreeSet, HashSet};
+use std::io::BufRead;
+use std::path::Path;
+
+/// The number of bases fetched from the reference at once.
+const CHUNK_SIZE: u64 = 1 << 20;
+
+/// Count the opportunities of each channel of the `signatures` in the `intervals`, i.e., the
+/// number of substitutions possible at the positions with the context of the channel. Each
+/// position counts once into the channels of each of its three possible substitutions.
+/// Positions whose window extends beyond the sequence or includes an ambiguous base are not
+/// counted, and intervals on contigs missing from the `reference` are skipped.
+pub fn count_opportunities<R: ReferenceProvider>(
+    reference: &R,
+    intervals: &Intervals,
+    signatures: &Signatures,
+) -> Result<Vec<u64>, String> {
+    let window = reference.window_size() as u64;
+    let width = 2 * window as usize + 1;
+    let contigs: HashSet<String> = reference.sequence_names()?.into_iter().collect();
+    let mut missing: BTreeSet<&str> = BTreeSet::new();
+    let mut counts = vec![0u64; signatures.channel_count()];
+    for (contig, start, end) in intervals.iter() {
+        if !contigs.contains(contig) {
+            missing.insert(contig);
+            continue;
+        }
+        // The positions of each chunk and the windows around them
+        let first = start.max(window);
+        for chunk in (first..end).step_by(CHUNK_SIZE as usize) {
+            let chunk_end = (chunk + CHUNK_SIZE).min(end);
+            let from = chunk - window;
+            let sequence = reference.fetch_sequence(contig, from, chunk_end + window)?;
+            for codon in sequence.as_bytes().windows(width) {
+                let codon = match std::str::from_utf8(codon) {
+                    Ok(c) => c,
+                    Err(_) => continue,
+                };
+                let center = codon.as_bytes()[window as usize] as char;
+                for alternative in ['A', 'C', 'G', 'T'].iter().filter(|a| **a != center) {
+                    let s = Signature::from_codon(codon, *alternative);
+                    if let Some(i) = signatures.index_of(&s) {
+                        counts[i] += 1;
+                    }
+                }
+            }
+        }
+    }
+    for contig in missing {
+        warn!(
+            "Skipping callable regions on '{}' missing from the reference",
+            contig
+        );
+    }
+    Ok(counts)
+}
+
+/// Read the callable regions of each sample from the file at `path`, whose lines hold the
+/// name of a sample and the path of its BED file, separated by a tab. Relative paths are
+/// resolved against the directory of the file. Empty and comment lines are skipped.
+pub fn read_sample_beds<P: AsRef<Path>>(path: P) -> Result<Vec<(String, Intervals)>, String> {
+    let name = path.as_ref().display().to_string();
+    let directory = path
+        .as_ref()
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_default();
+    let reader = crate::input::open(&path)?;
+    let mut samples: Vec<(String, Intervals)> = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => return Err(format!("Can not read '{}': {}", name, e)),
+        };
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').map(|f| f.trim()).collect();
+        let (sample, bed) = match fields[..] {
+            [sample, bed] if !sample.is_empty() && !bed.is_empty() => (sample, bed),
+            _ => {
+                return Err(format!(
+                    "Can not parse line {} of '{}', expected a sample and its BED file",
+                    i + 1,
+                    name
+                ))
+            }
+        };
+        if samples.iter().any(|(s, _)| s == sample) {
+            return Err(format!(
+                "Can not assign two BED files to sample '{}' in '{}'",
+                sample, name
+            ));
+        }
+        let intervals = intervals::read_bed(directory.join(bed))?;
+        if intervals.is_empty() {
+            warn!("No callable regions for sample '{}' in '{}'", sample, bed);
+        }
+        samples.push((sample.to_owned(), intervals));
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::MemoryReference;
+
+    fn testdata(name: &str) -> String {
+        format!("{}/testdata/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn count(window: u8, bed: &[(&str, u64, u64)]) -> (Signatures, Vec<u64>) {
+        // The sequences of ex2.fa
+        let reference = MemoryReference::new(vec![("1", "TCATGA"), ("2", "TCTAGA")], window);
+        let signatures =
+            Signatures::new(window.into(), crate::signature::DEFAULT_MAX_CHANNELS).unwrap();
+        let mut intervals = Intervals::new();
+        for (contig, start, end) in bed {
+            intervals.insert(contig, *start, *end);
+        }
+        let counts = count_opportunities(&reference, &intervals, &signatures).unwrap();
+        (signatures, counts)
+    }
+
+    fn index(signatures: &Signatures, label: &str) -> usize {
+        signatures
+            .index_of(&Signature::parse(label).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_count_opportunities() {
+        // The bases CATG of 1:2-5 (TCATGA), as the overlapping intervals are merged
+        let (signatures, counts) = count(0, &[("1", 1, 4), ("1", 3, 5)]);
+        assert_eq!(counts.iter().sum::<u64>(), 4 * 3);
+        // The C and the G counted as C, as well as the A counted as T and the T
+        assert_eq!(counts[index(&signatures, "C>A")], 2);
+        assert_eq!(counts[index(&signatures, "C>T")], 2);
+        assert_eq!(counts[index(&signatures, "T>A")], 2);
+
+        // The windows of the first and last bases extend beyond the sequence, and each of
+        // the contexts CAT and TCA is counted with its reverse complement ATG and TGA
+        let (signatures, counts) = count(1, &[("1", 0, 6)]);
+        assert_eq!(counts.iter().sum::<u64>(), 4 * 3);
+        assert_eq!(counts[index(&signatures, "TCA>T")], 2);
+        assert_eq!(counts[index(&signatures, "ATG>C")], 2);
+        assert_eq!(counts[index(&signatures, "ATG>A")], 2);
+        assert_eq!(counts[index(&signatures, "TCT>A")], 0);
+
+        // Contigs missing from the reference and empty regions count nothing
+        let (_, counts) = count(0, &[("X", 0, 6)]);
+        assert_eq!(counts.iter().sum::<u64>(), 0);
+        let (_, counts) = count(0, &[]);
+        assert_eq!(counts.iter().sum::<u64>(), 0);
+    }
+
+    #[test]
+    fn test_read_sample_beds() {
+        let samples = read_sample_beds(testdata("ex2_callable.tsv")).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].0, "Example1");
+        assert!(samples[0].1.contains("1", 1));
+        assert_eq!(samples[1].0, "Example2");
+        assert!(samples[1].1.is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+use crate::svg::{Anchor, Svg};
+
+/// The width of the bar of a sample, and the gap to the next one.
+const BAR_WIDTH: f64 = 20.0;
+const BAR_GAP: f64 = 6.0;
+
+/// The height of the plotting area of the bars.
+const PLOT_HEIGHT: f64 = 240.0;
+
+/// The margins around the plotting area, which hold the axes, their labels and the legend.
+const MARGIN_LEFT: f64 = 70.0;
+const MARGIN_TOP: f64 = 20.0;
+const MARGIN_BOTTOM: f64 = 110.0;
+const LEGEND_WIDTH: f64 = 160.0;
+
+/// The height of an entry of the legend.
+const LEGEND_LINE: f64 = 18.0;
+
+/// The order of the samples in the plot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// The order of the samples in the input
+    Input,
+    /// Decreasing total exposure
+    Burden,
+    /// Grouped by the signature with the largest exposure, in the order of the signatures, and
+    /// by its decreasing proportion within each group
+    Dominant,
+}
+
+/// Return the color of the signature `name`, which is derived from a hash of the name so
+/// that a signature has the same color in every plot.
+pub fn color_of(name: &str) -> String {
+    // The FNV-1a hash, which is stable across versions unlike the hasher of the standard library
+    let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    let hue = hash % 360;
+    let saturation = 55 + (hash >> 16) % 30;
+    let lightness = 45 + (hash >> 32) % 20;
+    format!("hsl({},{}%,{}%)", hue, saturation, lightness)
+}
+
+/// Return the indizes of the samples with the `exposures` to the signatures in the `order`.
+/// Ties keep the order of the input.
+pub fn order_samples(exposures: &[Vec<f64>], order: Order) -> Vec<usize> {
+    let mut indizes: Vec<usize> = (0..exposures.len()).collect();
+    let total = |s: usize| exposures[s].iter().sum::<f64>();
+    match order {
+        Order::Input => {}
+        Order::Burden => indizes.sort_by(|a, b| total(*b).partial_cmp(&total(*a)).unwrap()),
+        Order::Dominant => {
+            // The dominant signature and its proportion, with samples without exposure last
+            let dominant = |s: usize| {
+                let largest = exposures[s].iter().cloned().fold(0.0, f64::max);
+                match exposures[s]
+                    .iter()
+                    .position(|x| largest > 0.0 && *x == largest)
+                {
+                    Some(j) => (j, largest / total(s)),
+                    None => (usize::MAX, 0.0),
+                }
+            };
+            indizes.sort_by(|a, b| {
+                let (a, b) = (dominant(*a), dominant(*b));
+                a.0.cmp(&b.0).then(b.1.partial_cmp(&a.1).unwrap())
+            });
+        }
+    }
+    indizes
+}
+
+/// Return an SVG document with a bar per sample `names`, stacked by the proportions of its
+/// `exposures` to the `signatures`, in the `order`. Samples without any exposure have an
+/// empty bar.
+pub fn plot_exposures(
+    names: &[String],
+    signatures: &[String],
+    exposures: &[Vec<f64>],
+    order: Order,
+) -> String {
+    let plot_width = names.len() as f64 * (BAR_WIDTH + BAR_GAP) + BAR_GAP;
+    let legend_height = signatures.len() as f64 * LEGEND_LINE;
+    let width = MARGIN_LEFT + plot_width + LEGEND_WIDTH;
+    let height = MARGIN_TOP + PLOT_HEIGHT.max(legend_height) + MARGIN_BOTTOM;
+    let mut svg = Svg::new(width, height);
+    let bottom = MARGIN_TOP + PLOT_HEIGHT;
+    let colors: Vec<String> = signatures.iter().map(|s| color_of(s)).collect();
+
+    for (position, s) in order_samples(exposures, order).into_iter().enumerate() {
+        let x = MARGIN_LEFT + BAR_GAP + position as f64 * (BAR_WIDTH + BAR_GAP);
+        let total: f64 = exposures[s].iter().sum();
+        let mut y = bottom;
+        if total > 0.0 {
+            for (exposure, color) in exposures[s].iter().zip(&colors) {
+                if *exposure <= 0.0 {
+                    continue;
+                }
+                let h = exposure / total * PLOT_HEIGHT;
+                y -= h;
+                svg.rect(x, y, BAR_WIDTH, h, color);
+            }
+        }
+        svg.text(
+            x + BAR_WIDTH / 2.0,
+            bottom + 8.0,
+            &names[s],
+            Anchor::End,
+            -90.0,
+        );
+    }
+
+    // The axes with ticks at every quarter
+    svg.line(MARGIN_LEFT, MARGIN_TOP, MARGIN_LEFT, bottom);
+    svg.line(MARGIN_LEFT, bottom, MARGIN_LEFT + plot_width, bottom);
+    for tick in 0..=4 {
+        let y = bottom - tick as f64 / 4.0 * PLOT_HEIGHT;
+        svg.line(MARGIN_LEFT - 4.0, y, MARGIN_LEFT, y);
+        svg.text(
+            MARGIN_LEFT - 6.0,
+            y + 4.0,
+            &(tick as f64 / 4.0).to_string(),
+            Anchor::End,
+            0.0,
+        );
+    }
+    svg.text(
+        MARGIN_LEFT - 45.0,
+        MARGIN_TOP + PLOT_HEIGHT / 2.0,
+        "Proportion of exposure",
+        Anchor::Middle,
+        -90.0,
+    );
+    svg.text(
+        MARGIN_LEFT + plot_width / 2.0,
+        height - 8.0,
+        "Sample",
+        Anchor::Middle,
+        0.0,
+    );
+
+    // The legend
+    let x = MARGIN_LEFT + plot_width + 20.0;
+    for (i, (signature, color)) in signatures.iter().zip(&colors).enumerate() {
+        let y = MARGIN_TOP + i as f64 * LEGEND_LINE;
+        svg.rect(x, y, 12.0, 12.0, color);
+        svg.text(x + 18.0, y + 11.0, signature, Anchor::Start, 0.0);
+    }
+    svg.render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(s: &[&str]) -> Vec<String> {
+        s.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_color_of() {
+        assert_eq!(color_of("SBS1"), color_of("SBS1"));
+        assert_ne!(color_of("SBS1"), color_of("SBS5"));
+        assert!(color_of("SBS1").starts_with("hsl("));
+    }
+
+    #[test]
+    fn test_order_samples() {
+        let exposures = vec![
+            vec![1.0, 9.0],
+            vec![30.0, 0.0],
+            vec![0.0, 0.0],
+            vec![6.0, 4.0],
+            vec![2.0, 18.0],
+        ];
+        assert_eq!(order_samples(&exposures, Order::Input), vec![0, 1, 2, 3, 4]);
+        assert_eq!(
+            order_samples(&exposures, Order::Burden),
+            vec![1, 4, 0, 3, 2]
+        );
+        assert_eq!(
+            order_samples(&exposures, Order::Dominant),
+            vec![1, 3, 0, 4, 2]
+        );
+    }
+
+    #[test]
+    fn test_plot_exposures() {
+        let names = strings(&["S1", "S2", "S3"]);
+        let signatures = strings(&["SBS1", "SBS5", "SBS40"]);
+        let exposures = vec![
+            vec![10.0, 5.0, 0.0],
+            vec![0.0, 0.0, 0.0],
+            vec![1.0, 1.0, 1.0],
+        ];
+        let svg = plot_exposures(&names, &signatures, &exposures, Order::Input);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        // A segment per exposure and a square per signature in the legend
+        assert_eq!(svg.matches("<rect ").count(), 5 + 3);
+        // The samples, the ticks, the axis labels and the legend
+        assert_eq!(svg.matches("<text ").count(), 3 + 5 + 2 + 3);
+        // The axes and the ticks
+        assert_eq!(svg.matches("<line ").count(), 2 + 5);
+        // Each signature has the same color in the bars and the legend
+        assert_eq!(svg.matches(&color_of("SBS1")).count(), 3);
+        assert_eq!(svg.matches(&color_of("SBS40")).count(), 2);
+    }
+}
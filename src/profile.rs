@@ -0,0 +1,242 @@
+use crate::result::ResultMatrix;
+use crate::signature::{Signature, Signatures};
+use crate::similarity;
+use std::cmp;
+
+/// The counts of a sample per channel of the signatures, borrowed from the result matrix.
+///
+/// The channels are represented by their signatures in the canonical order of the counts as
+/// reported, i.e., by the signatures with a pyrimidine as reference base, those with context
+/// before those without. The channels of a definition by the user have several members, each
+/// of which is represented with the count of its channel.
+///
+/// ```
+/// use mutsig::profile::SampleProfile;
+/// use mutsig::result::ResultMatrix;
+/// use mutsig::signature::{Signature, Signatures, DEFAULT_MAX_CHANNELS};
+///
+/// let signatures = Signatures::new(0, DEFAULT_MAX_CHANNELS).unwrap();
+/// let c_t = Signature::parse("C>T").unwrap();
+/// let mut results = ResultMatrix::new(signatures.channel_count(), 1);
+/// results.increment(signatures.index_of(&c_t).unwrap(), 0);
+/// let profile = SampleProfile::new("S1", &signatures, &results, 0);
+/// assert_eq!(profile.total(), 1);
+/// assert_eq!(profile.fraction(&c_t), Some(1.0));
+/// ```
+pub struct SampleProfile<'a> {
+    name: &'a str,
+    signatures: &'a Signatures,
+    results: &'a ResultMatrix,
+    sidx: usize,
+    entries: Vec<(&'a Signature, usize)>,
+}
+
+impl<'a> SampleProfile<'a> {
+    /// Return the profile of the sample `name` at `sidx` in the `results` of the `signatures`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `results` have no sample `sidx` or fewer variants than channels.
+    pub fn new(
+        name: &'a str,
+        signatures: &'a Signatures,
+        results: &'a ResultMatrix,
+        sidx: usize,
+    ) -> Self {
+        let (n_variants, n_samples) = results.dimensions();
+        assert!(
+            sidx < n_samples && signatures.channel_count() <= n_variants,
+            "Can not build the profile of sample {} from a result matrix of {} variants and {} samples",
+            sidx,
+            n_variants,
+            n_samples
+        );
+        // Represent each channel by its signatures with a pyrimidine as reference base, unless
+        // a purine substitution has a channel of its own
+        let mut entries: Vec<(&Signature, usize)> = signatures
+            .iter()
+            .filter(|(s, i)| {
+                s.is_forward_signature() || signatures.index_of(&s.reverse_complement()) != Some(*i)
+            })
+            .collect();
+        entries.sort_by_key(|(s, _)| cmp::Reverse(s.window()));
+        SampleProfile {
+            name,
+            signatures,
+            results,
+            sidx,
+            entries,
+        }
+    }
+
+    /// Return the name of the sample.
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// Return the count of the channel of the `signature`, or `None` if it is not counted.
+    pub fn count(&self, signature: &Signature) -> Option<u64> {
+        self.signatures
+            .index_of(signature)
+            .map(|i| self.results.get(i, self.sidx))
+    }
+
+    /// Return the total count of all channels.
+    pub fn total(&self) -> u64 {
+        self.channel_counts().sum()
+    }
+
+    /// Return the fraction of the total count in the channel of the `signature`, or `None`
+    /// if it is not counted or the sample has no count at all.
+    pub fn fraction(&self, signature: &Signature) -> Option<f64> {
+        let total = self.total();
+        match self.count(signature) {
+            Some(count) if total > 0 => Some(count as f64 / total as f64),
+            _ => None,
+        }
+    }
+
+    /// Iterate over the signatures and the counts of their channels in the canonical order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a Signature, u64)> + '_ {
+        self.entries
+            .iter()
+            .map(move |(s, i)| (*s, self.results.get(*i, self.sidx)))
+    }
+
+    /// Return at most `k` signatures with the largest non-zero counts, ties in the canonical
+    /// order.
+    pub fn top_n(&self, k: usize) -> Vec<(&'a Signature, u64)> {
+        let mut top: Vec<(&Signature, u64)> = self.iter().filter(|(_, c)| *c > 0).collect();
+        // The stable sort keeps ties in the canonical order
+        top.sort_by_key(|(_, c)| cmp::Reverse(*c));
+        top.truncate(k);
+        top
+    }
+
+    /// Return the cosine similarity of the counts of the channels to those of `other`, or
+    /// `None` if any of them has no count or their channels differ.
+    pub fn cosine(&self, other: &SampleProfile) -> Option<f64> {
+        if self.signatures != other.signatures {
+            return None;
+        }
+        let a: Vec<f64> = self.channel_counts().map(|c| c as f64).collect();
+        let b: Vec<f64> = other.channel_counts().map(|c| c as f64).collect();
+        similarity::cosine_similarity(&a, &b)
+    }
+
+    /// Iterate over the counts of the channels in the order of their indizes.
+    fn channel_counts(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.signatures.channel_count()).map(move |i| self.results.get(i, self.sidx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::DEFAULT_MAX_CHANNELS;
+
+    fn signature(label: &str) -> Signature {
+        Signature::parse(label).unwrap()
+    }
+
+    /// Return the signatures without context and a matrix of two samples with the `counts`
+    /// of their channels as labeled.
+    fn counts(counts: &[(&str, u64, u64)]) -> (Signatures, ResultMatrix) {
+        let signatures = Signatures::new(0, DEFAULT_MAX_CHANNELS).unwrap();
+        let mut values = vec![0; signatures.channel_count() * 2];
+        for (label, a, b) in counts {
+            let i = signatures.index_of(&signature(label)).unwrap();
+            values[2 * i] = *a;
+            values[2 * i + 1] = *b;
+        }
+        let results =
+            ResultMatrix::from_raw_parts(signatures.channel_count(), 2, values, None).unwrap();
+        (signatures, results)
+    }
+
+    #[test]
+    fn test_counts() {
+        let (signatures, results) = counts(&[("C>T", 6, 0), ("T>A", 2, 0)]);
+        let profile = SampleProfile::new("S1", &signatures, &results, 0);
+        assert_eq!(profile.name(), "S1");
+        assert_eq!(profile.total(), 8);
+        // A substitution and its reverse complement share their channel
+        assert_eq!(profile.count(&signature("G>A")), Some(6));
+        assert_eq!(profile.fraction(&signature("C>T")), Some(0.75));
+        assert_eq!(profile.fraction(&signature("T>G")), Some(0.0));
+        assert_eq!(profile.count(&signature("ACG>T")), None);
+        assert_eq!(profile.fraction(&signature("ACG>T")), None);
+
+        let empty = SampleProfile::new("S2", &signatures, &results, 1);
+        assert_eq!(empty.total(), 0);
+        assert_eq!(empty.fraction(&signature("C>T")), None);
+    }
+
+    #[test]
+    fn test_iter() {
+        let (signatures, results) = counts(&[("C>A", 1, 0), ("T>G", 3, 0)]);
+        let profile = SampleProfile::new("S1", &signatures, &results, 0);
+        let labels: Vec<String> = profile.iter().map(|(s, _)| s.to_string()).collect();
+        assert_eq!(labels, ["C>A", "C>G", "C>T", "T>A", "T>C", "T>G"]);
+        let counts: Vec<u64> = profile.iter().map(|(_, c)| c).collect();
+        assert_eq!(counts, [1, 0, 0, 0, 0, 3]);
+
+        // The channels with context before those without
+        let signatures = Signatures::with_partial_context(1);
+        let results = ResultMatrix::new(signatures.channel_count(), 1);
+        let profile = SampleProfile::new("S1", &signatures, &results, 0);
+        let windows: Vec<usize> = profile.iter().map(|(s, _)| s.window()).collect();
+        assert_eq!(windows.len(), 96 + 6);
+        assert!(windows[..96].iter().all(|w| *w == 1));
+        assert!(windows[96..].iter().all(|w| *w == 0));
+
+        // Purine substitutions of their own channel are represented as well
+        let signatures = Signatures::uncollapsed(0);
+        let results = ResultMatrix::new(signatures.channel_count(), 1);
+        let profile = SampleProfile::new("S1", &signatures, &results, 0);
+        assert_eq!(profile.iter().count(), 12);
+    }
+
+    #[test]
+    fn test_top_n() {
+        let (signatures, results) = counts(&[("C>A", 2, 0), ("T>C", 5, 0), ("T>G", 2, 0)]);
+        let profile = SampleProfile::new("S1", &signatures, &results, 0);
+        let top: Vec<(String, u64)> = profile
+            .top_n(2)
+            .into_iter()
+            .map(|(s, c)| (s.to_string(), c))
+            .collect();
+        assert_eq!(top, [("T>C".to_owned(), 5), ("C>A".to_owned(), 2)]);
+        // Channels without counts are not reported
+        assert_eq!(profile.top_n(10).len(), 3);
+        assert!(profile.top_n(0).is_empty());
+    }
+
+    #[test]
+    fn test_cosine() {
+        let (signatures, results) = counts(&[("C>A", 1, 2), ("T>C", 1, 2), ("T>G", 0, 1)]);
+        let a = SampleProfile::new("S1", &signatures, &results, 0);
+        let b = SampleProfile::new("S2", &signatures, &results, 1);
+        assert!((a.cosine(&a).unwrap() - 1.0).abs() < 1e-12);
+        let expected = 4.0 / (2f64.sqrt() * 3.0);
+        assert!((a.cosine(&b).unwrap() - expected).abs() < 1e-12);
+        assert_eq!(a.cosine(&b), b.cosine(&a));
+
+        let (_, empty) = counts(&[]);
+        let c = SampleProfile::new("S3", &signatures, &empty, 0);
+        assert_eq!(a.cosine(&c), None);
+
+        // Profiles of different channels are not comparable
+        let other = Signatures::new(1, DEFAULT_MAX_CHANNELS).unwrap();
+        let results = ResultMatrix::new(other.channel_count(), 1);
+        let d = SampleProfile::new("S4", &other, &results, 0);
+        assert_eq!(a.cosine(&d), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can not build the profile")]
+    fn test_missing_sample() {
+        let (signatures, results) = counts(&[]);
+        SampleProfile::new("S3", &signatures, &results, 2);
+    }
+}
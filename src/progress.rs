@@ -0,0 +1,150 @@
+//! Draw the progress of the counting on the terminal, above which the log lines are printed.
+use crate::count::Progress;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// The progress bars drawn on the standard error, shared with the logger.
+static BARS: OnceLock<MultiProgress> = OnceLock::new();
+
+/// How often the message of the progress bar is updated at most.
+const MESSAGE_INTERVAL: Duration = Duration::from_millis(100);
+
+fn bars() -> &'static MultiProgress {
+    BARS.get_or_init(MultiProgress::new)
+}
+
+/// Initialize the logger configured by the environment variable `RUST_LOG` to print its lines
+/// above the progress bars.
+pub fn init_logger() {
+    let mut builder = pretty_env_logger::formatted_builder();
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    let logger = builder.build();
+    let level = logger.filter();
+    if indicatif_log_bridge::LogWrapper::new(bars().clone(), logger)
+        .try_init()
+        .is_ok()
+    {
+        log::set_max_level(level);
+    }
+}
+
+/// When to draw the progress bar.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ProgressMode {
+    /// Only if the standard error is a terminal
+    Auto,
+    /// Even if the standard error is redirected
+    Always,
+    /// Never
+    Never,
+}
+
+/// The progress bar of the records counted, or a spinner if their total is unknown.
+pub(crate) struct CountProgress {
+    bar: ProgressBar,
+    updated: Option<Instant>,
+}
+
+impl CountProgress {
+    /// Start drawing the progress in `mode`, or return `None` for `ProgressMode::Never`.
+    pub(crate) fn new(mode: ProgressMode) -> Option<Self> {
+        match mode {
+            ProgressMode::Never => return None,
+            ProgressMode::Always => bars().set_draw_target(ProgressDrawTarget::term_like_with_hz(
+                Box::new(console::Term::buffered_stderr()),
+                20,
+            )),
+            ProgressMode::Auto => {}
+        }
+        let bar = bars().add(ProgressBar::no_length());
+        bar.set_style(
+            ProgressStyle::with_template("{spinner} {human_pos} records ({per_sec}) {msg}")
+                .unwrap(),
+        );
+        bar.enable_steady_tick(MESSAGE_INTERVAL);
+        Some(CountProgress { bar, updated: None })
+    }
+
+    /// Return the bar to finish once the counting is done.
+    pub(crate) fn bar(&self) -> ProgressBar {
+        self.bar.clone()
+    }
+
+    /// Draw the `progress`, turning the spinner into a bar once the total is known.
+    pub(crate) fn update(&mut self, progress: &Progress) {
+        if let (Some(total), None) = (progress.total, self.bar.length()) {
+            self.bar.set_length(total);
+            self.bar.set_style(
+                ProgressStyle::with_template(
+                    "{bar:40} {human_pos}/{human_len} records ({per_sec}, ETA {eta}) {msg}",
+                )
+                .unwrap(),
+            );
+        }
+        self.bar.set_position(progress.records);
+        if self
+            .updated
+            .is_none_or(|updated| updated.elapsed() >= MESSAGE_INTERVAL)
+        {
+            self.bar.set_message(message(progress));
+            self.updated = Some(Instant::now());
+        }
+    }
+}
+
+/// Format the position of the last record and the number of skipped records of `progress`.
+fn message(progress: &Progress) -> String {
+    match (progress.contig, progress.position) {
+        (Some(contig), Some(position)) => {
+            format!("{}:{} | {} skipped", contig, position, progress.skipped)
+        }
+        (Some(contig), None) => format!("{} | {} skipped", contig, progress.skipped),
+        _ => format!("{} skipped", progress.skipped),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let mut progress = Progress {
+            records: 10,
+            total: None,
+            contig: Some("chr1"),
+            position: Some(12345),
+            skipped: 3,
+        };
+        assert_eq!(message(&progress), "chr1:12345 | 3 skipped");
+        progress.position = None;
+        assert_eq!(message(&progress), "chr1 | 3 skipped");
+        progress.contig = None;
+        assert_eq!(message(&progress), "3 skipped");
+    }
+
+    #[test]
+    fn test_update_total() {
+        let mut progress = CountProgress {
+            bar: ProgressBar::hidden(),
+            updated: None,
+        };
+        let mut state = Progress {
+            records: 1,
+            total: None,
+            contig: None,
+            position: None,
+            skipped: 0,
+        };
+        progress.update(&state);
+        assert_eq!(progress.bar.length(), None);
+        state.records = 2;
+        state.total = Some(9);
+        progress.update(&state);
+        assert_eq!(progress.bar.length(), Some(9));
+        assert_eq!(progress.bar.position(), 2);
+    }
+}
@@ -1,61 +1,378 @@
+use crate::error::Error;
 use rust_htslib::faidx;
-use std::path::Path;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 /// Struct for fetching data from a faidx'ed FASTQ file. Automatically retrieves
 /// also the surrounding bases as requested per `window_size`.
 pub struct Reference {
     inner: faidx::Reader,
+    path: PathBuf,
     window: u8,
+    cache_contigs: bool,
+    /// The name and whole sequence of the contig fetched last, if contigs are cached
+    cached: RefCell<Option<(String, String)>>,
 }
 
+// SAFETY: The faidx handle is not tied to the thread that opened it, it must only not be used
+// by several threads at once, which `Reference` not being `Sync` already rules out.
+unsafe impl Send for Reference {}
+
 impl Reference {
     /// Create a new reference setup using the FASTA file located at
     /// `path` and a window size of `window_size` bases.
     ///
     /// # Arguments
     /// * `window_size` the number of bases to retrieve up- and down-stream of the requested position. A `window_size` of 1 will return triplets in `fetch()`.
-    pub fn new<P: AsRef<Path>>(path: P, window_size: u8) -> Result<Self, String> {
+    pub fn new<P: AsRef<Path>>(path: P, window_size: u8) -> Result<Self, Error> {
         let inner = match faidx::Reader::from_path(path.as_ref()) {
             Err(e) => {
-                return Err(format!(
-                    "Can not open '{}': {}",
-                    path.as_ref().to_str().unwrap(),
-                    e
-                ))
+                return Err(Error::Htslib {
+                    action: format!("open reference '{}'", path.as_ref().display()),
+                    source: e,
+                })
             }
             Ok(i) => i,
         };
 
         Ok(Reference {
             inner: inner,
+            path: path.as_ref().to_owned(),
             window: window_size,
+            cache_contigs: false,
+            cached: RefCell::new(None),
         })
     }
 
+    /// Load the whole sequence of a contig the first time it is fetched from and slice the
+    /// windows from memory, until bases of another contig are fetched. This saves a faidx
+    /// fetch per variant of a coordinate-sorted VCF file, but holds the largest contig in
+    /// memory and reloads a contig each time the input returns to it.
+    pub fn cache_contigs(mut self, cache: bool) -> Self {
+        self.cache_contigs = cache;
+        self
+    }
+
+    /// Return the length of the sequence `name`, which must be in the index.
+    fn sequence_length(&self, name: &str) -> Result<u64, Error> {
+        // faidx reports a missing sequence as a length of -1
+        match self.inner.fetch_seq_len(name) {
+            u64::MAX => Err(Error::UnknownContig {
+                contig: name.to_owned(),
+            }),
+            length => Ok(length),
+        }
+    }
+
+    /// Get the bases from `start` to `end` of the contig `name` from the cache, loading the
+    /// contig first unless it is the cached one.
+    fn fetch_cached_bases(&self, name: &str, start: u64, end: u64) -> Result<String, Error> {
+        let mut cached = self.cached.borrow_mut();
+        if !matches!(&*cached, Some((n, _)) if n == name) {
+            // Drop the previous contig before loading the next one
+            *cached = None;
+            let length = self.sequence_length(name)?;
+            let sequence = if length == 0 {
+                String::new()
+            } else {
+                match self.inner.fetch_seq_string(name, 0, (length - 1) as usize) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        return Err(Error::Htslib {
+                            action: format!("fetch bases {}-{} of {}", start, end, name),
+                            source: e,
+                        })
+                    }
+                }
+            };
+            *cached = Some((name.to_owned(), sequence));
+        }
+        let sequence = cached.as_ref().map_or(&b""[..], |(_, s)| s.as_bytes());
+        let end = (end as usize).min(sequence.len());
+        let start = (start as usize).min(end);
+        Ok(String::from_utf8_lossy(&sequence[start..end]).into_owned())
+    }
+}
+
+/// Provides the bases of the reference sequences, e.g., from an indexed FASTA file by
+/// `Reference` or from memory by `MemoryReference`. An implementation fetches the bases of a
+/// range and lists the sequences; the windows around a position and the CpG context are
+/// derived from them.
+pub trait ReferenceProvider {
+    /// Retrieve the window size
+    fn window_size(&self) -> u8;
+
+    /// Retrieve the names of all sequences in the order of the reference.
+    fn sequence_names(&self) -> Result<Vec<String>, Error>;
+
+    /// Retrieve the names and lengths of all sequences in the order of `sequence_names()`.
+    fn sequence_lengths(&self) -> Result<Vec<(String, u64)>, Error>;
+
+    /// Get the bases from `start` to `end` (0-based, exclusive) as stored, i.e., soft-masked
+    /// bases in lowercase. The range is clipped at the end of the sequence, such that fewer
+    /// bases may be returned.
+    fn fetch_bases(&self, name: &str, start: u64, end: u64) -> Result<String, Error>;
+
+    /// Open another handle on the same sequences, e.g., for another thread.
+    fn try_clone(&self) -> Result<Self, Error>
+    where
+        Self: Sized;
+
     /// Get the reference sequence at a given position (0-based offset). If a window-size was given during
     /// creation of the reference, then that number of bases before and after position are extracted too.
-    pub fn fetch<N: AsRef<str>>(&self, name: N, position: i64) -> Result<String, String> {
-        if self.window as i64 > position {
-            return Err(format!(
-                "Can not fetch window {} before {}",
-                self.window, position
-            ));
+    /// If the window exceeds the start or end of the sequence, `Error::TruncatedContext` is returned
+    /// with the bases available.
+    fn fetch<N: AsRef<str>>(&self, name: N, position: i64) -> Result<String, Error> {
+        Ok(self.fetch_with_case(name, position)?.0)
+    }
+
+    /// Like `fetch()`, but also report whether any of the bases is soft-masked, i.e., lowercase
+    /// in the FASTA file (e.g., repeats masked by RepeatMasker).
+    fn fetch_with_case<N: AsRef<str>>(
+        &self,
+        name: N,
+        position: i64,
+    ) -> Result<(String, bool), Error> {
+        let window = self.window_size() as i64;
+        let length = 2 * window as usize + 1;
+        if position < 0 {
+            return Err(Error::OutOfBounds {
+                contig: name.as_ref().to_owned(),
+                position: position - window,
+                length,
+            });
+        }
+        let start = (position - window).max(0) as u64;
+        let end = (position + window + 1) as u64;
+        let mut sequence = self.fetch_bases(name.as_ref(), start, end)?;
+        if sequence.len() != length {
+            // Without the base at the position itself, there is nothing to classify
+            if sequence.len() as u64 <= position as u64 - start {
+                return Err(Error::OutOfBounds {
+                    contig: name.as_ref().to_owned(),
+                    position: position - window,
+                    length,
+                });
+            }
+            return Err(Error::TruncatedContext {
+                contig: name.as_ref().to_owned(),
+                position,
+                context: sequence.to_uppercase(),
+            });
+        }
+        // Only soft-masked bases have to be uppercased
+        let softmasked = sequence.bytes().any(|b| b.is_ascii_lowercase());
+        if softmasked {
+            sequence.make_ascii_uppercase();
+        }
+        Ok((sequence, softmasked))
+    }
+
+    /// Get the `length` reference bases starting at a given position (0-based offset),
+    /// regardless of the window size, and report whether any of them is soft-masked.
+    fn fetch_range_with_case<N: AsRef<str>>(
+        &self,
+        name: N,
+        position: i64,
+        length: usize,
+    ) -> Result<(String, bool), Error> {
+        let out_of_bounds = || Error::OutOfBounds {
+            contig: name.as_ref().to_owned(),
+            position,
+            length,
+        };
+        if position < 0 || length == 0 {
+            return Err(out_of_bounds());
+        }
+        let mut sequence = self.fetch_bases(
+            name.as_ref(),
+            position as u64,
+            position as u64 + length as u64,
+        )?;
+        if sequence.len() != length {
+            return Err(out_of_bounds());
         }
-        let start = position - self.window as i64;
-        let end = position + self.window as i64;
+        let softmasked = sequence.bytes().any(|b| b.is_ascii_lowercase());
+        if softmasked {
+            sequence.make_ascii_uppercase();
+        }
+        Ok((sequence, softmasked))
+    }
 
-        Ok(self
+    /// Get the reference bases from `start` to `end` (0-based, exclusive) regardless of the
+    /// window size. Unlike `fetch_range_with_case()`, the range is clipped at the end of the
+    /// sequence, such that fewer bases may be returned.
+    fn fetch_sequence<N: AsRef<str>>(
+        &self,
+        name: N,
+        start: u64,
+        end: u64,
+    ) -> Result<String, Error> {
+        Ok(self.fetch_bases(name.as_ref(), start, end)?.to_uppercase())
+    }
+
+    /// Check if the base at a given position (0-based offset) is part of a CpG dinucleotide,
+    /// i.e., a C followed by a G or a G preceded by a C, regardless of the window size.
+    fn is_cpg<N: AsRef<str>>(&self, name: N, position: u64) -> Result<bool, Error> {
+        let start = position.saturating_sub(1);
+        let bases = self.fetch_sequence(name, start, position + 2)?;
+        let bases = bases.as_bytes();
+        let center = (position - start) as usize;
+        Ok(match bases.get(center) {
+            Some(b'C') => bases.get(center + 1) == Some(&b'G'),
+            Some(b'G') => center > 0 && bases[center - 1] == b'C',
+            _ => false,
+        })
+    }
+}
+
+impl ReferenceProvider for Reference {
+    fn window_size(&self) -> u8 {
+        self.window
+    }
+
+    /// Retrieve the names of all sequences listed in the faidx index, in index order.
+    fn sequence_names(&self) -> Result<Vec<String>, Error> {
+        match self.inner.seq_names() {
+            Ok(n) => Ok(n),
+            Err(e) => Err(Error::Htslib {
+                action: "read sequence names".to_owned(),
+                source: e,
+            }),
+        }
+    }
+
+    /// Retrieve the lengths of all sequences from the faidx index, in index order.
+    fn sequence_lengths(&self) -> Result<Vec<(String, u64)>, Error> {
+        self.sequence_names()?
+            .into_iter()
+            .map(|name| {
+                let length = self.sequence_length(&name)?;
+                Ok((name, length))
+            })
+            .collect()
+    }
+
+    fn fetch_bases(&self, name: &str, start: u64, end: u64) -> Result<String, Error> {
+        if end <= start {
+            return Ok(String::new());
+        }
+        if self.cache_contigs {
+            return self.fetch_cached_bases(name, start, end);
+        }
+        let length = self.sequence_length(name)?;
+        if start >= length {
+            return Ok(String::new());
+        }
+        match self
             .inner
-            .fetch_seq_string(name, start as usize, end as usize)
-            .unwrap()
-            .to_uppercase()
-            .to_string())
+            .fetch_seq_string(name, start as usize, (end.min(length) - 1) as usize)
+        {
+            Ok(s) => Ok(s),
+            Err(e) => Err(Error::Htslib {
+                action: format!("fetch bases {}-{} of {}", start, end, name),
+                source: e,
+            }),
+        }
     }
 
-    /// Retrieve the window size
-    pub fn window_size(&self) -> u8 {
+    /// Open the FASTA file again, with a cache of its own if contigs are cached.
+    fn try_clone(&self) -> Result<Self, Error> {
+        Ok(Reference::new(&self.path, self.window)?.cache_contigs(self.cache_contigs))
+    }
+}
+
+/// Reference sequences held in memory, e.g., for small genomes or to test the classification
+/// without a FASTA file.
+///
+/// ```
+/// use mutsig::reference::{MemoryReference, ReferenceProvider};
+///
+/// let reference = MemoryReference::new(vec![("1", "TCATGA")], 1);
+/// assert_eq!(reference.fetch("1", 1).unwrap(), "TCA");
+/// ```
+pub struct MemoryReference {
+    sequences: Arc<HashMap<String, String>>,
+    window: u8,
+}
+
+impl MemoryReference {
+    /// Create a reference of the `sequences` by their name and a window size of
+    /// `window_size` bases.
+    pub fn new<I, N, S>(sequences: I, window_size: u8) -> Self
+    where
+        I: IntoIterator<Item = (N, S)>,
+        N: Into<String>,
+        S: Into<String>,
+    {
+        MemoryReference {
+            sequences: Arc::new(
+                sequences
+                    .into_iter()
+                    .map(|(n, s)| (n.into(), s.into()))
+                    .collect(),
+            ),
+            window: window_size,
+        }
+    }
+}
+
+impl From<HashMap<String, String>> for MemoryReference {
+    /// Create a reference of the `sequences` without context, i.e., a window size of 0.
+    fn from(sequences: HashMap<String, String>) -> Self {
+        MemoryReference {
+            sequences: Arc::new(sequences),
+            window: 0,
+        }
+    }
+}
+
+impl ReferenceProvider for MemoryReference {
+    fn window_size(&self) -> u8 {
         self.window
     }
+
+    /// Retrieve the names of all sequences in lexicographic order.
+    fn sequence_names(&self) -> Result<Vec<String>, Error> {
+        let mut names: Vec<String> = self.sequences.keys().cloned().collect();
+        names.sort();
+        Ok(names)
+    }
+
+    fn sequence_lengths(&self) -> Result<Vec<(String, u64)>, Error> {
+        Ok(self
+            .sequence_names()?
+            .into_iter()
+            .map(|name| {
+                let length = self.sequences[&name].len() as u64;
+                (name, length)
+            })
+            .collect())
+    }
+
+    fn fetch_bases(&self, name: &str, start: u64, end: u64) -> Result<String, Error> {
+        let sequence = match self.sequences.get(name) {
+            Some(s) => s.as_bytes(),
+            None => {
+                return Err(Error::UnknownContig {
+                    contig: name.to_owned(),
+                })
+            }
+        };
+        let end = (end as usize).min(sequence.len());
+        let start = (start as usize).min(end);
+        Ok(String::from_utf8_lossy(&sequence[start..end]).into_owned())
+    }
+
+    /// Share the sequences with the new handle.
+    fn try_clone(&self) -> Result<Self, Error> {
+        Ok(MemoryReference {
+            sequences: Arc::clone(&self.sequences),
+            window: self.window,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -74,18 +391,246 @@ mod tests {
     #[test]
     fn test_singlet_start_of_chromosome() {
         let r = build(0).fetch("1", 0);
-        assert_eq!(r, Ok("T".to_owned()))
+        assert_eq!(r.unwrap(), "T".to_owned())
     }
 
     #[test]
     fn test_singlet_end_of_chromosome() {
         let r = build(0).fetch("1", 5);
-        assert_eq!(r, Ok("A".to_owned()))
+        assert_eq!(r.unwrap(), "A".to_owned())
     }
 
     #[test]
     fn test_triplet_end_of_chromosome() {
         let r = build(1).fetch("1", 5);
-        assert_eq!(r, Ok("GA".to_owned()))
+        assert!(matches!(
+            r,
+            Err(Error::TruncatedContext { position: 5, context, .. }) if context == "GA"
+        ));
+    }
+
+    #[test]
+    fn test_triplet_start_of_chromosome() {
+        let r = build(1).fetch("1", 0);
+        assert!(matches!(
+            r,
+            Err(Error::TruncatedContext { position: 0, context, .. }) if context == "TC"
+        ));
+        let e = build(2).fetch("1", 1).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Can not fetch the full window at 1:1, only 'TCAT'"
+        );
+        assert_eq!(e.exit_code(), 4);
+    }
+
+    #[test]
+    fn test_softmasked() {
+        let r = Reference::new(
+            format!("{}/testdata/ex2_softmasked.fa", env!("CARGO_MANIFEST_DIR")),
+            1,
+        )
+        .unwrap();
+        assert_eq!(r.fetch_with_case("1", 1).unwrap(), ("TCA".to_owned(), true));
+        assert_eq!(r.fetch_with_case("1", 4).unwrap(), ("TGA".to_owned(), true));
+        assert_eq!(
+            r.fetch_with_case("2", 1).unwrap(),
+            ("TCT".to_owned(), false)
+        );
+        assert_eq!(r.fetch("1", 2).unwrap(), "CAT".to_owned());
+    }
+
+    #[test]
+    fn test_fetch_range() {
+        // The range is independent of the window
+        let r = build(1);
+        assert_eq!(
+            r.fetch_range_with_case("1", 0, 2).unwrap(),
+            ("TC".to_owned(), false)
+        );
+        assert_eq!(
+            r.fetch_range_with_case("1", 4, 2).unwrap(),
+            ("GA".to_owned(), false)
+        );
+        assert!(matches!(
+            r.fetch_range_with_case("1", 5, 2),
+            Err(Error::OutOfBounds {
+                position: 5,
+                length: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_fetch_sequence() {
+        let r = build(0);
+        assert_eq!(r.fetch_sequence("1", 1, 4).unwrap(), "CAT".to_owned());
+        assert_eq!(r.fetch_sequence("1", 4, 10).unwrap(), "GA".to_owned());
+        assert_eq!(r.fetch_sequence("1", 6, 10).unwrap(), "".to_owned());
+    }
+
+    #[test]
+    fn test_is_cpg() {
+        let r = Reference::new(
+            format!("{}/testdata/ex2_cpg.fa", env!("CARGO_MANIFEST_DIR")),
+            0,
+        )
+        .unwrap();
+        // TACGCAT: the C and G at 2 and 3 form a CpG, the C at 4 is followed by an A
+        let cpg: Vec<bool> = (0..7).map(|p| r.is_cpg("1", p).unwrap()).collect();
+        assert_eq!(cpg, vec![false, false, true, true, false, false, false]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let missing = format!("{}/testdata/missing.fa", env!("CARGO_MANIFEST_DIR"));
+        assert!(matches!(
+            Reference::new(missing, 1),
+            Err(Error::Htslib { .. })
+        ));
+        let e = build(1).fetch("1", -1).unwrap_err();
+        assert!(matches!(
+            &e,
+            Error::OutOfBounds { contig, position: -2, length: 3 } if contig == "1"
+        ));
+        assert_eq!(e.to_string(), "Can not fetch 3 bases at 1:-2");
+        assert!(matches!(
+            build(1).fetch("1", 6),
+            Err(Error::OutOfBounds { position: 5, .. })
+        ));
+        let e = build(1).fetch("X", 1).unwrap_err();
+        assert!(matches!(&e, Error::UnknownContig { contig } if contig == "X"));
+    }
+
+    #[test]
+    fn test_memory_reference() {
+        // The sequences of ex2_softmasked.fa, fetched like from the FASTA file
+        let fasta = Reference::new(
+            format!("{}/testdata/ex2_softmasked.fa", env!("CARGO_MANIFEST_DIR")),
+            1,
+        )
+        .unwrap();
+        let memory = MemoryReference::new(vec![("1", "TCatGA"), ("2", "TCTAGA")], 1);
+        assert_eq!(memory.window_size(), 1);
+        assert_eq!(
+            memory.sequence_names().unwrap(),
+            fasta.sequence_names().unwrap()
+        );
+        for position in 1..6 {
+            assert_eq!(
+                memory
+                    .fetch_with_case("1", position)
+                    .map_err(|e| e.to_string()),
+                fasta
+                    .fetch_with_case("1", position)
+                    .map_err(|e| e.to_string())
+            );
+            assert_eq!(
+                memory.is_cpg("1", position as u64).unwrap(),
+                fasta.is_cpg("1", position as u64).unwrap()
+            );
+        }
+        assert!(matches!(
+            memory.fetch("1", 5),
+            Err(Error::TruncatedContext { context, .. }) if context == "GA"
+        ));
+        assert_eq!(memory.fetch_sequence("1", 4, 10).unwrap(), "GA".to_owned());
+        assert_eq!(memory.fetch_sequence("1", 6, 10).unwrap(), "".to_owned());
+        assert!(matches!(
+            memory.fetch_range_with_case("1", 5, 2),
+            Err(Error::OutOfBounds { .. })
+        ));
+        assert!(matches!(
+            memory.fetch("1", 0),
+            Err(Error::TruncatedContext { position: 0, .. })
+        ));
+        let e = memory.fetch("X", 1).unwrap_err();
+        assert!(matches!(&e, Error::UnknownContig { contig } if contig == "X"));
+        assert_eq!(e.to_string(), "Can not find sequence 'X' in the reference");
+        assert_eq!(e.exit_code(), 4);
+
+        let mut sequences = HashMap::new();
+        sequences.insert("1".to_owned(), "TCATGA".to_owned());
+        let memory = MemoryReference::from(sequences);
+        assert_eq!(memory.window_size(), 0);
+        assert_eq!(memory.fetch("1", 0).unwrap(), "T".to_owned());
+    }
+
+    #[test]
+    fn test_cached_contigs() {
+        let path = format!("{}/testdata/ex2_softmasked.fa", env!("CARGO_MANIFEST_DIR"));
+        let text = |r: Result<String, Error>| r.map_err(|e| e.to_string());
+        for window in 0..3 {
+            let uncached = Reference::new(&path, window).unwrap();
+            let cached = Reference::new(&path, window).unwrap().cache_contigs(true);
+            // Alternate the contigs to evict the cached one, and fetch across both ends
+            for position in 0..8 {
+                for name in ["1", "2", "1", "X"] {
+                    assert_eq!(
+                        cached
+                            .fetch_with_case(name, position)
+                            .map_err(|e| e.to_string()),
+                        uncached
+                            .fetch_with_case(name, position)
+                            .map_err(|e| e.to_string())
+                    );
+                    assert_eq!(
+                        cached
+                            .fetch_range_with_case(name, position, 2)
+                            .map_err(|e| e.to_string()),
+                        uncached
+                            .fetch_range_with_case(name, position, 2)
+                            .map_err(|e| e.to_string())
+                    );
+                    let start = position as u64;
+                    for end in start..start + 8 {
+                        assert_eq!(
+                            text(cached.fetch_sequence(name, start, end)),
+                            text(uncached.fetch_sequence(name, start, end))
+                        );
+                    }
+                    assert_eq!(
+                        cached.is_cpg(name, start).map_err(|e| e.to_string()),
+                        uncached.is_cpg(name, start).map_err(|e| e.to_string())
+                    );
+                }
+            }
+        }
+        let cached = Reference::new(&path, 1).unwrap().cache_contigs(true);
+        assert_eq!(
+            cached.fetch_with_case("1", 2).unwrap(),
+            ("CAT".to_owned(), true)
+        );
+        // The handles of other threads cache their own contig
+        let clone = cached.try_clone().unwrap();
+        assert!(clone.cache_contigs);
+        assert!(clone.cached.borrow().is_none());
+        assert_eq!(clone.fetch("2", 4).unwrap(), "AGA".to_owned());
+    }
+
+    #[test]
+    fn test_sequence_names() {
+        let r = build(0).sequence_names();
+        assert_eq!(r.unwrap(), vec!["1".to_owned(), "2".to_owned()])
+    }
+
+    #[test]
+    fn test_sequence_lengths() {
+        assert_eq!(
+            build(1).sequence_lengths().unwrap(),
+            vec![("1".to_owned(), 6), ("2".to_owned(), 6)]
+        );
+        let r = Reference::new(
+            format!("{}/testdata/ex2_cpg.fa", env!("CARGO_MANIFEST_DIR")),
+            0,
+        )
+        .unwrap();
+        assert_eq!(r.sequence_lengths().unwrap(), vec![("1".to_owned(), 7)]);
+        let memory = MemoryReference::new(vec![("2", "TCTAGAA"), ("1", "TCATGA")], 1);
+        assert_eq!(
+            memory.sequence_lengths().unwrap(),
+            vec![("1".to_owned(), 6), ("2".to_owned(), 7)]
+        );
     }
 }
@@ -0,0 +1,96 @@
+use crate::error::Error;
+use url::Url;
+
+/// The URL schemes that are passed through to the htslib hFILE plugins.
+const SCHEMES: [&str; 3] = ["http", "https", "s3"];
+
+/// Check if `location` refers to a remote file rather than a local path.
+pub fn is_url(location: &str) -> bool {
+    SCHEMES.iter().any(|s| {
+        let prefix = format!("{}://", s);
+        location.len() > prefix.len()
+            && location
+                .get(..prefix.len())
+                .is_some_and(|p| p.eq_ignore_ascii_case(&prefix))
+    })
+}
+
+/// Parse the remote `location` and validate that htslib is able to access it.
+pub fn parse_url(location: &str) -> Result<Url, Error> {
+    let url = match Url::parse(location) {
+        Ok(u) => u,
+        Err(e) => {
+            return Err(Error::InvalidOptions(format!(
+                "Invalid URL '{}': {}",
+                location, e
+            )))
+        }
+    };
+    if !SCHEMES.contains(&url.scheme()) {
+        return Err(Error::InvalidOptions(format!(
+            "Unsupported URL scheme '{}' in '{}' (supported: {})",
+            url.scheme(),
+            location,
+            SCHEMES.join(", ")
+        )));
+    }
+    match url.host_str() {
+        Some(h) if !h.is_empty() => {}
+        _ => {
+            return Err(Error::InvalidOptions(format!(
+                "Invalid URL '{}': missing host or bucket name",
+                location
+            )))
+        }
+    }
+    if url.path().len() <= 1 {
+        return Err(Error::InvalidOptions(format!(
+            "Invalid URL '{}': missing file path",
+            location
+        )));
+    }
+    Ok(url)
+}
+
+/// Return what to check if the remote file at `url` can not be opened. htslib does not
+/// report whether the file is missing or the access denied, so both are named.
+pub fn access_hint(url: &Url) -> &'static str {
+    if url.scheme() == "s3" {
+        "check that it exists and that the AWS credentials (AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY or ~/.aws/credentials) grant access"
+    } else {
+        "check that the URL exists and, if it requires authentication, the credentials (e.g., HTS_AUTH_LOCATION)"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://example.org/my_sample.vcf.gz"));
+        assert!(is_url("HTTP://example.org/my_sample.vcf.gz"));
+        assert!(is_url("s3://bucket/my_sample.vcf.gz"));
+        assert!(!is_url("my_sample.vcf.gz"));
+        assert!(!is_url("/data/https/my_sample.vcf.gz"));
+        assert!(!is_url("ftp://example.org/my_sample.vcf.gz"));
+        assert!(!is_url("https://"));
+    }
+
+    #[test]
+    fn test_parse_url() {
+        assert!(parse_url("https://example.org/my_sample.vcf.gz").is_ok());
+        assert!(parse_url("s3://bucket/path/my_sample.vcf.gz").is_ok());
+        assert!(parse_url("s3://bucket").is_err());
+        assert!(parse_url("https:///my_sample.vcf.gz").is_err());
+        assert!(parse_url("ftp://example.org/my_sample.vcf.gz").is_err());
+    }
+
+    #[test]
+    fn test_access_hint() {
+        let hint = access_hint(&parse_url("s3://bucket/x.vcf").unwrap());
+        assert!(hint.contains("AWS"));
+        let hint = access_hint(&parse_url("https://x.org/x.vcf").unwrap());
+        assert!(hint.contains("HTS_AUTH_LOCATION"));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::intervals::{self, Intervals};
+use std::fmt;
+use std::path::Path;
+
+/// The replication strand of the reference (forward) strand at a genomic position, i.e.,
+/// whether it serves as template for the leading or the lagging strand synthesis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReplicationStrand {
+    /// In a left-replicating region, the reference strand is the leading strand template
+    Leading,
+    /// In a right-replicating region, the reference strand is the lagging strand template
+    Lagging,
+    /// Outside of the annotated regions or in regions annotated with both directions
+    Unknown,
+}
+
+impl ReplicationStrand {
+    /// Return the replication strand of the strand carrying the pyrimidine of a base pair,
+    /// which is the reference strand if `forward` and the reverse strand otherwise.
+    pub fn of_pyrimidine(self, forward: bool) -> ReplicationStrand {
+        match self {
+            ReplicationStrand::Leading if !forward => ReplicationStrand::Lagging,
+            ReplicationStrand::Lagging if !forward => ReplicationStrand::Leading,
+            s => s,
+        }
+    }
+}
+
+impl fmt::Display for ReplicationStrand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplicationStrand::Leading => write!(f, "leading"),
+            ReplicationStrand::Lagging => write!(f, "lagging"),
+            ReplicationStrand::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// The replication direction of genomic regions, i.e., whether the replication fork moves
+/// towards lower (left) or higher (right) coordinates.
+#[derive(Debug, PartialEq)]
+pub struct Replication {
+    left: Intervals,
+    right: Intervals,
+}
+
+impl Replication {
+    /// Read the replication directions from the (optionally gzip or bgzip compressed) BED
+    /// file at `path`, whose intervals are named either `left` or `right`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let name = path.as_ref().display().to_string();
+        let mut named = intervals::read_named_bed(path)?;
+        let replication = Replication {
+            left: named.remove("left").unwrap_or_default(),
+            right: named.remove("right").unwrap_or_default(),
+        };
+        if let Some(other) = named.keys().next() {
+            return Err(format!(
+                "Can not read replication direction '{}' of '{}', expected 'left' or 'right'",
+                other, name
+            ));
+        }
+        Ok(replication)
+    }
+
+    /// Return the replication strand of the reference strand at the 0-based `position`.
+    pub fn strand_at(&self, contig: &str, position: u64) -> ReplicationStrand {
+        match (
+            self.left.contains(contig, position),
+            self.right.contains(contig, position),
+        ) {
+            (true, false) => ReplicationStrand::Leading,
+            (false, true) => ReplicationStrand::Lagging,
+            _ => ReplicationStrand::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testdata(name: &str) -> String {
+        format!("{}/testdata/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn test_strand_at() {
+        let mut left = Intervals::new();
+        left.insert("1", 3, 5);
+        left.insert("1", 10, 20);
+        let mut right = Intervals::new();
+        right.insert("1", 0, 3);
+        right.insert("1", 15, 25);
+        let replication = Replication { left, right };
+        assert_eq!(replication.strand_at("1", 1), ReplicationStrand::Lagging);
+        assert_eq!(replication.strand_at("1", 3), ReplicationStrand::Leading);
+        assert_eq!(replication.strand_at("1", 5), ReplicationStrand::Unknown);
+        // Overlapping directions are ambiguous
+        assert_eq!(replication.strand_at("1", 17), ReplicationStrand::Unknown);
+        assert_eq!(replication.strand_at("2", 1), ReplicationStrand::Unknown);
+    }
+
+    #[test]
+    fn test_of_pyrimidine() {
+        let l = ReplicationStrand::Leading;
+        assert_eq!(l.of_pyrimidine(true), l);
+        assert_eq!(l.of_pyrimidine(false), ReplicationStrand::Lagging);
+        let u = ReplicationStrand::Unknown;
+        assert_eq!(u.of_pyrimidine(false), u);
+    }
+
+    #[test]
+    fn test_from_path() {
+        let replication = Replication::from_path(testdata("ex2_replication.bed")).unwrap();
+        assert_eq!(replication.strand_at("1", 1), ReplicationStrand::Lagging);
+        assert_eq!(replication.strand_at("1", 4), ReplicationStrand::Leading);
+        assert!(Replication::from_path(testdata("ex2.bed")).is_err());
+    }
+}
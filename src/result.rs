@@ -1,8 +1,151 @@
+use std::collections::BTreeMap;
+
+/// The number of cells from which on the result matrix of a run is stored sparsely, e.g., for
+/// the 24576 channels of a window of three bases and more than 2730 samples.
+pub const SPARSE_CELLS: usize = 1 << 26;
+
+/// The cells of a matrix, indexed in the order of the variants and then the samples.
+trait Cells<T> {
+    /// Return the value of the cell at `idx`.
+    fn get(&self, idx: usize) -> T;
+
+    /// Return the value of the cell at `idx` for changing it.
+    fn get_mut(&mut self, idx: usize) -> &mut T;
+
+    /// Iterate over the stored cells as `(idx, value)` in the order of their index. All cells
+    /// not listed are zero.
+    fn stored(&self) -> Box<dyn Iterator<Item = (usize, T)> + '_>;
+}
+
+impl<T: Copy> Cells<T> for Vec<T> {
+    fn get(&self, idx: usize) -> T {
+        self[idx]
+    }
+
+    fn get_mut(&mut self, idx: usize) -> &mut T {
+        &mut self[idx]
+    }
+
+    fn stored(&self) -> Box<dyn Iterator<Item = (usize, T)> + '_> {
+        Box::new(self.iter().copied().enumerate())
+    }
+}
+
+/// The cells of a sparse matrix, of which only those ever changed are stored.
+#[derive(Clone, Debug, Default)]
+struct SparseCells<T>(BTreeMap<usize, T>);
+
+impl<T: Copy + Default> Cells<T> for SparseCells<T> {
+    fn get(&self, idx: usize) -> T {
+        self.0.get(&idx).copied().unwrap_or_default()
+    }
+
+    fn get_mut(&mut self, idx: usize) -> &mut T {
+        self.0.entry(idx).or_default()
+    }
+
+    fn stored(&self) -> Box<dyn Iterator<Item = (usize, T)> + '_> {
+        Box::new(self.0.iter().map(|(idx, value)| (*idx, *value)))
+    }
+}
+
+/// The cells of a matrix, stored densely or sparsely.
+#[derive(Clone, Debug)]
+enum Storage<T> {
+    Dense(Vec<T>),
+    Sparse(SparseCells<T>),
+}
+
+impl<T: Copy + Default + PartialEq> Storage<T> {
+    fn get(&self, idx: usize) -> T {
+        match self {
+            Storage::Dense(c) => c.get(idx),
+            Storage::Sparse(c) => c.get(idx),
+        }
+    }
+
+    fn get_mut(&mut self, idx: usize) -> &mut T {
+        match self {
+            Storage::Dense(c) => c.get_mut(idx),
+            Storage::Sparse(c) => c.get_mut(idx),
+        }
+    }
+
+    fn stored(&self) -> Box<dyn Iterator<Item = (usize, T)> + '_> {
+        match self {
+            Storage::Dense(c) => c.stored(),
+            Storage::Sparse(c) => c.stored(),
+        }
+    }
+
+    /// Store the non-zero cells only.
+    fn into_sparse(self) -> Self {
+        match self {
+            Storage::Dense(c) => Storage::Sparse(SparseCells(
+                c.into_iter()
+                    .enumerate()
+                    .filter(|(_, value)| *value != T::default())
+                    .collect(),
+            )),
+            sparse => sparse,
+        }
+    }
+
+    /// Return all `n_cells` cells.
+    fn into_vec(self, n_cells: usize) -> Vec<T> {
+        match self {
+            Storage::Dense(c) => c,
+            Storage::Sparse(c) => {
+                let mut cells = vec![T::default(); n_cells];
+                for (idx, value) in c.0 {
+                    cells[idx] = value;
+                }
+                cells
+            }
+        }
+    }
+}
+
+/// The storages are equal if their cells are, no matter how they are stored.
+impl<T: Copy + Default + PartialEq> PartialEq for Storage<T> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Storage::Dense(a), Storage::Dense(b)) => a == b,
+            _ => {
+                let non_zero = |s: &Self| {
+                    s.stored()
+                        .filter(|(_, value)| *value != T::default())
+                        .collect::<Vec<(usize, T)>>()
+                };
+                non_zero(self) == non_zero(other)
+            }
+        }
+    }
+}
+
 /// The result matrix that contains the results
-/// by means of counts of variants per sample.
+/// by means of counts of variants per sample. A weighted matrix additionally sums up
+/// a weight per counted variant.
+///
+/// The counts saturate at the largest count rather than wrapping around. This is checked
+/// in debug builds, while release builds warn and flag the matrix as `overflowed()`.
+///
+/// A matrix of many variants and samples with few counts, e.g., of many channels, can be
+/// stored sparsely by `into_sparse()`, which only changes its memory footprint.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(try_from = "StoredResultMatrix", into = "StoredResultMatrix")
+)]
 pub struct ResultMatrix {
+    n_variants: usize,
     n_samples: usize,
-    inner: Vec<u32>,
+    inner: Storage<u64>,
+    weights: Option<Storage<f64>>,
+    /// The largest count, which is only lowered to test the saturation
+    max_count: u64,
+    overflowed: bool,
 }
 
 impl ResultMatrix {
@@ -11,24 +154,774 @@ impl ResultMatrix {
         let n_total = n_variants * n_samples;
         let v = (0..n_total).map(|_| 0).collect();
         ResultMatrix {
-            n_samples: n_samples,
-            inner: v,
+            n_variants,
+            n_samples,
+            inner: Storage::Dense(v),
+            weights: None,
+            max_count: u64::MAX,
+            overflowed: false,
         }
     }
 
+    /// Create a new weighted matrix containing data for `n_variants` and `n_samples`.
+    pub fn new_weighted(n_variants: usize, n_samples: usize) -> Self {
+        let mut m = ResultMatrix::new(n_variants, n_samples);
+        m.weights = Some(Storage::Dense(vec![0.0; n_variants * n_samples]));
+        m
+    }
+
+    /// Create a new matrix for `n_variants` and `n_samples`, which only stores the cells
+    /// with counts.
+    pub fn new_sparse(n_variants: usize, n_samples: usize) -> Self {
+        ResultMatrix {
+            n_variants,
+            n_samples,
+            inner: Storage::Sparse(SparseCells::default()),
+            weights: None,
+            max_count: u64::MAX,
+            overflowed: false,
+        }
+    }
+
+    /// Create a new weighted matrix for `n_variants` and `n_samples`, which only stores the
+    /// cells with counts.
+    pub fn new_weighted_sparse(n_variants: usize, n_samples: usize) -> Self {
+        let mut m = ResultMatrix::new_sparse(n_variants, n_samples);
+        m.weights = Some(Storage::Sparse(SparseCells::default()));
+        m
+    }
+
+    /// Rebuild a matrix of `n_variants` and `n_samples` from its `counts` and, if it is
+    /// weighted, its `weights`, both in the order of the variants and then the samples.
+    pub fn from_raw_parts(
+        n_variants: usize,
+        n_samples: usize,
+        counts: Vec<u64>,
+        weights: Option<Vec<f64>>,
+    ) -> Result<Self, String> {
+        let n_cells = n_variants.checked_mul(n_samples);
+        let lengths = std::iter::once(counts.len()).chain(weights.as_ref().map(Vec::len));
+        for length in lengths {
+            if Some(length) != n_cells {
+                return Err(format!(
+                    "Can not build the result matrix, found {} values for {} variants and {} samples",
+                    length, n_variants, n_samples
+                ));
+            }
+        }
+        Ok(ResultMatrix {
+            n_variants,
+            n_samples,
+            inner: Storage::Dense(counts),
+            weights: weights.map(Storage::Dense),
+            max_count: u64::MAX,
+            overflowed: false,
+        })
+    }
+
+    /// Store only the cells of the matrix with counts, which saves memory if most of the
+    /// cells are zero, at the cost of slower access.
+    pub fn into_sparse(self) -> Self {
+        ResultMatrix {
+            inner: self.inner.into_sparse(),
+            weights: self.weights.map(Storage::into_sparse),
+            ..self
+        }
+    }
+
+    /// Store all cells of the matrix, e.g., to view them as an array.
+    pub fn into_dense(self) -> Self {
+        let n_cells = self.n_variants * self.n_samples;
+        ResultMatrix {
+            inner: Storage::Dense(self.inner.into_vec(n_cells)),
+            weights: self.weights.map(|w| Storage::Dense(w.into_vec(n_cells))),
+            ..self
+        }
+    }
+
+    /// Check if only the cells with counts are stored.
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.inner, Storage::Sparse(_))
+    }
+
+    /// Lower the largest count of the matrix to `max_count`, such that the saturation of the
+    /// counts can be tested.
+    #[cfg(test)]
+    fn with_max_count(mut self, max_count: u64) -> Self {
+        self.max_count = max_count;
+        self
+    }
+
+    /// Check if the matrix sums up weights.
+    pub fn is_weighted(&self) -> bool {
+        self.weights.is_some()
+    }
+
     /// Calculate the index position in `inner` for given variant index `vidx and sample index `sidx`.
     fn index(&self, vidx: usize, sidx: usize) -> usize {
         vidx * self.n_samples + sidx
     }
 
+    /// Check if a count reached the largest count, such that it was saturated rather than
+    /// incremented.
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Increment the count at the index position `idx` by one, saturating at the largest count.
+    fn increment_count(&mut self, idx: usize) {
+        let max_count = self.max_count;
+        let count = self.inner.get_mut(idx);
+        match count.checked_add(1).filter(|c| *c <= max_count) {
+            Some(incremented) => *count = incremented,
+            None => {
+                *count = max_count;
+                // A lowered largest count saturates in debug builds, too
+                debug_assert!(
+                    self.max_count < u64::MAX,
+                    "the count of variant {} and sample {} overflows",
+                    idx / self.n_samples,
+                    idx % self.n_samples
+                );
+                if !self.overflowed {
+                    warn!(
+                        "The count of variant {} and sample {} overflows, counts are saturated at {}",
+                        idx / self.n_samples,
+                        idx % self.n_samples,
+                        self.max_count
+                    );
+                }
+                self.overflowed = true;
+            }
+        }
+    }
+
     /// Increment the count for variant at `vidx` and samples at `sidx` by one.
     pub fn increment(&mut self, vidx: usize, sidx: usize) {
         let idx = self.index(vidx, sidx);
-        self.inner[idx] += 1;
+        self.increment_count(idx);
+    }
+
+    /// Increment the count for variant at `vidx` and samples at `sidx` by one and its
+    /// weight by `weight`. The weight is ignored if the matrix is not weighted.
+    pub fn increment_by(&mut self, vidx: usize, sidx: usize, weight: f64) {
+        let idx = self.index(vidx, sidx);
+        self.increment_count(idx);
+        if let Some(w) = &mut self.weights {
+            *w.get_mut(idx) += weight;
+        }
+    }
+
+    /// Return the weight for variant at `vidx` and sample at `sidx`, which is the count
+    /// if the matrix is not weighted.
+    pub fn weight(&self, vidx: usize, sidx: usize) -> f64 {
+        let idx = self.index(vidx, sidx);
+        match &self.weights {
+            Some(w) => w.get(idx),
+            None => self.inner.get(idx) as f64,
+        }
     }
 
     /// Return the count for variant at `vidx` and sample at `sidx`.
-    pub fn get(&mut self, vidx: usize, sidx: usize) -> u32 {
-        self.inner[self.index(vidx, sidx)]
+    pub fn get(&self, vidx: usize, sidx: usize) -> u64 {
+        self.inner.get(self.index(vidx, sidx))
+    }
+
+    /// Return the dimensions of the matrix, i.e., the number of variants and of samples.
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.n_variants, self.n_samples)
+    }
+
+    /// Iterate over the counts of the variant at `vidx` for all samples.
+    pub fn row(&self, vidx: usize) -> impl Iterator<Item = u64> + '_ {
+        let start = self.index(vidx, 0);
+        (start..start + self.n_samples).map(move |idx| self.inner.get(idx))
+    }
+
+    /// Iterate over the counts of the sample at `sidx` for all variants.
+    pub fn column_iter(&self, sidx: usize) -> impl Iterator<Item = u64> + '_ {
+        assert!(
+            sidx < self.n_samples,
+            "sample index {} out of range for {} samples",
+            sidx,
+            self.n_samples
+        );
+        (sidx..self.n_variants * self.n_samples)
+            .step_by(self.n_samples)
+            .map(move |idx| self.inner.get(idx))
+    }
+
+    /// Iterate over the counts of all variants and samples as `(vidx, sidx, count)`, by
+    /// variant and then by sample.
+    pub fn iter_cells(&self) -> impl Iterator<Item = (usize, usize, u64)> + '_ {
+        let n_samples = self.n_samples;
+        (0..self.n_variants * n_samples)
+            .map(move |idx| (idx / n_samples, idx % n_samples, self.inner.get(idx)))
+    }
+
+    /// Add the counts and weights of `other` to this matrix, e.g., of the partial results of
+    /// a batch. Both matrices must have the same dimensions and both or neither be weighted,
+    /// but either may be sparse. The matrix is left unchanged if a count would overflow. The
+    /// merged matrix is `overflowed()` if either matrix is.
+    pub fn merge(&mut self, other: &ResultMatrix) -> Result<(), MergeError> {
+        if (self.n_variants, self.n_samples) != (other.n_variants, other.n_samples) {
+            return Err(MergeError::Dimensions {
+                expected: (self.n_variants, self.n_samples),
+                found: (other.n_variants, other.n_samples),
+            });
+        }
+        if self.is_weighted() != other.is_weighted() {
+            return Err(MergeError::Weighting);
+        }
+        let max_count = self.max_count;
+        if let Some((idx, _)) = other.inner.stored().find(|(idx, b)| {
+            self.inner
+                .get(*idx)
+                .checked_add(*b)
+                .filter(|c| *c <= max_count)
+                .is_none()
+        }) {
+            return Err(MergeError::Overflow {
+                vidx: idx / self.n_samples,
+                sidx: idx % self.n_samples,
+            });
+        }
+        for (idx, b) in other.inner.stored().filter(|(_, b)| *b > 0) {
+            *self.inner.get_mut(idx) += b;
+        }
+        self.overflowed |= other.overflowed;
+        if let (Some(weights), Some(other)) = (&mut self.weights, &other.weights) {
+            for (idx, b) in other.stored().filter(|(_, b)| *b != 0.0) {
+                *weights.get_mut(idx) += b;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The conversions from and to the arrays of `ndarray`, built with the cargo feature `ndarray`.
+/// The arrays are indexed like `get()`, i.e., by the channel (axis 0) and then by the sample
+/// (axis 1).
+#[cfg(feature = "ndarray")]
+impl ResultMatrix {
+    /// Create an unweighted matrix of the counts in `array`, indexed by channel and sample.
+    pub fn from_array2(array: ndarray::Array2<u64>) -> Self {
+        let (n_variants, n_samples) = array.dim();
+        let inner = if array.is_standard_layout() {
+            array.into_raw_vec_and_offset().0
+        } else {
+            array.iter().copied().collect()
+        };
+        ResultMatrix {
+            n_variants,
+            n_samples,
+            inner: Storage::Dense(inner),
+            weights: None,
+            max_count: u64::MAX,
+            overflowed: false,
+        }
+    }
+
+    /// Return a copy of the counts, indexed by channel and sample. The weights are not copied.
+    pub fn to_array2(&self) -> ndarray::Array2<u64> {
+        ndarray::Array2::from_shape_fn((self.n_variants, self.n_samples), |(v, s)| self.get(v, s))
+    }
+
+    /// Return a view of the counts, indexed by channel and sample, without copying them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the matrix is sparse, which can be viewed `into_dense()` or copied by
+    /// `to_array2()`.
+    pub fn view(&self) -> ndarray::ArrayView2<'_, u64> {
+        match &self.inner {
+            Storage::Dense(inner) => {
+                ndarray::ArrayView2::from_shape((self.n_variants, self.n_samples), inner).unwrap()
+            }
+            Storage::Sparse(_) => panic!("Can not view the counts of a sparse result matrix"),
+        }
+    }
+
+    /// Return a mutable view of the counts, indexed by channel and sample. The weights are
+    /// not changed along with the counts. A sparse matrix is stored densely first.
+    pub fn view_mut(&mut self) -> ndarray::ArrayViewMut2<'_, u64> {
+        if self.is_sparse() {
+            *self = std::mem::replace(self, ResultMatrix::new(0, 0)).into_dense();
+        }
+        match &mut self.inner {
+            Storage::Dense(inner) => {
+                ndarray::ArrayViewMut2::from_shape((self.n_variants, self.n_samples), inner)
+                    .unwrap()
+            }
+            Storage::Sparse(_) => unreachable!(),
+        }
+    }
+}
+
+/// Adds the counts of another matrix as by `ResultMatrix::merge`.
+///
+/// # Panics
+///
+/// Panics if the matrices can not be merged, e.g., if a count would overflow.
+impl std::ops::AddAssign<&ResultMatrix> for ResultMatrix {
+    fn add_assign(&mut self, other: &ResultMatrix) {
+        if let Err(e) = self.merge(other) {
+            panic!("{}", e);
+        }
+    }
+}
+
+/// The reason why two result matrices can not be merged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeError {
+    /// The numbers of variants and samples differ
+    Dimensions {
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+    /// Only one of the matrices is weighted
+    Weighting,
+    /// The count of the variant at `vidx` and the sample at `sidx` exceeds the largest count
+    Overflow { vidx: usize, sidx: usize },
+}
+
+impl std::fmt::Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeError::Dimensions { expected, found } => write!(
+                f,
+                "Can not merge a result matrix of {} variants and {} samples into one of {} variants and {} samples",
+                found.0, found.1, expected.0, expected.1
+            ),
+            MergeError::Weighting => write!(
+                f,
+                "Can not merge a weighted and an unweighted result matrix"
+            ),
+            MergeError::Overflow { vidx, sidx } => write!(
+                f,
+                "Can not merge the result matrices, the count of variant {} and sample {} overflows",
+                vidx, sidx
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// A count or weight that is converted to a float for comparing the counts, e.g., the `u64`
+/// counts of a `ResultMatrix`. Counts beyond 2^53 are rounded.
+pub trait AsF64: Copy {
+    /// Return the value as float.
+    fn as_f64(self) -> f64;
+}
+
+macro_rules! impl_as_f64 {
+    ($($t:ty),*) => {
+        $(impl AsF64 for $t {
+            fn as_f64(self) -> f64 {
+                self as f64
+            }
+        })*
+    };
+}
+
+impl_as_f64!(u8, u16, u32, u64, usize, i32, i64, f32, f64);
+
+/// The result matrix as stored, which is validated before it is restored. The cells are
+/// stored densely, no matter how the matrix stores them.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredResultMatrix {
+    n_variants: usize,
+    n_samples: usize,
+    counts: Vec<u64>,
+    weights: Option<Vec<f64>>,
+    #[serde(default)]
+    overflowed: bool,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<StoredResultMatrix> for ResultMatrix {
+    type Error = String;
+
+    fn try_from(stored: StoredResultMatrix) -> Result<Self, String> {
+        let mut matrix = ResultMatrix::from_raw_parts(
+            stored.n_variants,
+            stored.n_samples,
+            stored.counts,
+            stored.weights,
+        )?;
+        matrix.overflowed = stored.overflowed;
+        Ok(matrix)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<ResultMatrix> for StoredResultMatrix {
+    fn from(matrix: ResultMatrix) -> Self {
+        let n_cells = matrix.n_variants * matrix.n_samples;
+        StoredResultMatrix {
+            n_variants: matrix.n_variants,
+            n_samples: matrix.n_samples,
+            counts: matrix.inner.into_vec(n_cells),
+            weights: matrix.weights.map(|w| w.into_vec(n_cells)),
+            overflowed: matrix.overflowed,
+        }
+    }
+}
+
+/// A result matrix together with the names of its samples, e.g., for storing the results of
+/// a run.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "StoredNamedResultMatrix"))]
+pub struct NamedResultMatrix {
+    samples: Vec<String>,
+    matrix: ResultMatrix,
+}
+
+impl NamedResultMatrix {
+    /// Name the samples of the `matrix` by `samples`, one per sample in the order of the matrix.
+    pub fn new(samples: Vec<String>, matrix: ResultMatrix) -> Result<Self, String> {
+        if samples.len() != matrix.n_samples {
+            return Err(format!(
+                "Can not name the samples of the result matrix, found {} names for {} samples",
+                samples.len(),
+                matrix.n_samples
+            ));
+        }
+        Ok(NamedResultMatrix { samples, matrix })
+    }
+
+    /// Return the names of the samples.
+    pub fn samples(&self) -> &[String] {
+        &self.samples
+    }
+
+    /// Return the result matrix.
+    pub fn matrix(&self) -> &ResultMatrix {
+        &self.matrix
+    }
+
+    /// Return the names of the samples and the result matrix.
+    pub fn into_parts(self) -> (Vec<String>, ResultMatrix) {
+        (self.samples, self.matrix)
+    }
+}
+
+/// The named result matrix as stored, which is validated before it is restored.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct StoredNamedResultMatrix {
+    samples: Vec<String>,
+    matrix: ResultMatrix,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<StoredNamedResultMatrix> for NamedResultMatrix {
+    type Error = String;
+
+    fn try_from(stored: StoredNamedResultMatrix) -> Result<Self, String> {
+        NamedResultMatrix::new(stored.samples, stored.matrix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted() {
+        let mut m = ResultMatrix::new_weighted(2, 1);
+        m.increment_by(1, 0, 0.5);
+        m.increment_by(1, 0, 0.25);
+        assert!(m.is_weighted());
+        assert_eq!(m.get(1, 0), 2);
+        assert_eq!(m.weight(1, 0), 0.75);
+        assert_eq!(m.weight(0, 0), 0.0);
+
+        let mut m = ResultMatrix::new(2, 1);
+        m.increment_by(1, 0, 0.5);
+        assert!(!m.is_weighted());
+        assert_eq!(m.weight(1, 0), 1.0);
+    }
+
+    #[test]
+    fn test_layout() {
+        let m = ResultMatrix::from_raw_parts(3, 2, vec![1, 2, 3, 4, 5, 6], None).unwrap();
+        assert_eq!(m.dimensions(), (3, 2));
+        assert_eq!(m.get(1, 0), 3);
+        assert_eq!(m.row(0).collect::<Vec<u64>>(), [1, 2]);
+        assert_eq!(m.row(2).collect::<Vec<u64>>(), [5, 6]);
+        assert_eq!(m.column_iter(0).collect::<Vec<u64>>(), vec![1, 3, 5]);
+        assert_eq!(m.column_iter(1).collect::<Vec<u64>>(), vec![2, 4, 6]);
+        let cells: Vec<(usize, usize, u64)> = m.iter_cells().collect();
+        assert_eq!(cells.len(), 6);
+        assert_eq!(cells[3], (1, 1, 4));
+        assert!(cells.iter().all(|(v, s, c)| m.get(*v, *s) == *c));
+
+        let mut m = ResultMatrix::new(2, 3);
+        m.increment(1, 2);
+        assert_eq!(m.row(1).collect::<Vec<u64>>(), [0, 0, 1]);
+        assert_eq!(m.column_iter(2).collect::<Vec<u64>>(), vec![0, 1]);
+        assert_eq!(ResultMatrix::new(0, 2).iter_cells().count(), 0);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_array2() {
+        let m = ResultMatrix::from_raw_parts(3, 2, vec![1, 2, 3, 4, 5, 6], None).unwrap();
+        let a = m.to_array2();
+        assert_eq!(a.dim(), (3, 2));
+        for ((v, s), c) in a.indexed_iter() {
+            assert_eq!(*c, m.get(v, s));
+        }
+        assert_eq!(ResultMatrix::from_array2(a), m);
+
+        // The order of the elements in memory does not matter
+        let t = ndarray::Array2::from_shape_vec((2, 3), vec![1, 3, 5, 2, 4, 6])
+            .unwrap()
+            .reversed_axes();
+        assert!(!t.is_standard_layout());
+        assert_eq!(ResultMatrix::from_array2(t), m);
+        assert_eq!(
+            ResultMatrix::from_array2(ndarray::Array2::zeros((0, 2))).dimensions(),
+            (0, 2)
+        );
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_view_mut() {
+        let mut m = ResultMatrix::new_weighted(2, 3);
+        m.increment_by(1, 2, 0.5);
+        {
+            let mut view = m.view_mut();
+            view[[0, 1]] = 7;
+            view.column_mut(2).mapv_inplace(|c| c * 10);
+        }
+        assert_eq!(m.get(0, 1), 7);
+        assert_eq!(m.get(1, 2), 10);
+        assert_eq!(m.weight(1, 2), 0.5);
+        assert_eq!(m.view().sum(), 17);
+
+        // A sparse matrix is stored densely to be viewed
+        let mut m = ResultMatrix::new_sparse(2, 3);
+        m.increment(1, 2);
+        assert_eq!(m.to_array2().sum(), 1);
+        m.view_mut()[[0, 1]] = 7;
+        assert!(!m.is_sparse());
+        assert_eq!((m.get(0, 1), m.get(1, 2)), (7, 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_column_out_of_range() {
+        ResultMatrix::new(2, 2).column_iter(2).count();
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = ResultMatrix::new(2, 2);
+        a.increment(0, 1);
+        let mut b = ResultMatrix::new(2, 2);
+        b.increment(0, 1);
+        b.increment(1, 0);
+        a.merge(&b).unwrap();
+        assert_eq!((a.get(0, 1), a.get(1, 0), a.get(1, 1)), (2, 1, 0));
+        a += &b;
+        assert_eq!((a.get(0, 1), a.get(1, 0)), (3, 2));
+
+        let mut w = ResultMatrix::new_weighted(2, 2);
+        w.increment_by(1, 1, 0.5);
+        let mut v = ResultMatrix::new_weighted(2, 2);
+        v.increment_by(1, 1, 0.25);
+        w.merge(&v).unwrap();
+        assert_eq!((w.get(1, 1), w.weight(1, 1)), (2, 0.75));
+        assert_eq!(a.merge(&w), Err(MergeError::Weighting));
+    }
+
+    #[test]
+    fn test_merge_dimensions() {
+        let mut a = ResultMatrix::new(2, 2);
+        let b = ResultMatrix::new(2, 3);
+        let error = a.merge(&b).unwrap_err();
+        assert_eq!(
+            error,
+            MergeError::Dimensions {
+                expected: (2, 2),
+                found: (2, 3)
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Can not merge a result matrix of 2 variants and 3 samples into one of 2 variants and 2 samples"
+        );
+    }
+
+    #[test]
+    fn test_merge_overflow() {
+        let mut a = ResultMatrix::from_raw_parts(2, 2, vec![1, 0, 0, u64::MAX - 1], None).unwrap();
+        let b = ResultMatrix::from_raw_parts(2, 2, vec![1, 0, 0, 2], None).unwrap();
+        assert_eq!(a.merge(&b), Err(MergeError::Overflow { vidx: 1, sidx: 1 }));
+        // The matrix is unchanged
+        assert_eq!((a.get(0, 0), a.get(1, 1)), (1, u64::MAX - 1));
+    }
+
+    #[test]
+    fn test_saturation() {
+        let mut m = ResultMatrix::new_weighted(2, 2).with_max_count(2);
+        m.increment(1, 0);
+        m.increment_by(1, 0, 0.5);
+        assert!(!m.overflowed());
+        // The count saturates, while the weight is still summed up
+        m.increment_by(1, 0, 0.5);
+        m.increment(1, 0);
+        assert!(m.overflowed());
+        assert_eq!((m.get(1, 0), m.weight(1, 0)), (2, 1.0));
+        assert_eq!((m.get(0, 0), m.get(1, 1)), (0, 0));
+
+        // Merged matrices are overflowed if either is
+        let mut merged = ResultMatrix::new_weighted(2, 2);
+        merged.merge(&m).unwrap();
+        assert!(merged.overflowed());
+        let mut full = ResultMatrix::new(1, 1).with_max_count(1);
+        full.increment(0, 0);
+        let error = full.merge(&full.clone()).unwrap_err();
+        assert_eq!(error, MergeError::Overflow { vidx: 0, sidx: 0 });
+        assert!(!full.overflowed());
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "overflows"))]
+    fn test_increment_overflow() {
+        // Overflows are checked in debug builds and saturate in release builds
+        let mut m = ResultMatrix::from_raw_parts(1, 1, vec![u64::MAX], None).unwrap();
+        m.increment(0, 0);
+        assert_eq!(m.get(0, 0), u64::MAX);
+        assert!(m.overflowed());
+    }
+
+    #[test]
+    #[should_panic(expected = "overflows")]
+    fn test_add_assign_overflow() {
+        let mut a = ResultMatrix::from_raw_parts(1, 1, vec![u64::MAX], None).unwrap();
+        a += &ResultMatrix::from_raw_parts(1, 1, vec![1], None).unwrap();
+    }
+
+    #[test]
+    fn test_from_raw_parts() {
+        let m = ResultMatrix::from_raw_parts(2, 3, (0..6).collect(), None).unwrap();
+        assert_eq!((m.get(0, 2), m.get(1, 0)), (2, 3));
+        assert!(ResultMatrix::from_raw_parts(2, 3, vec![0; 5], None).is_err());
+        assert!(ResultMatrix::from_raw_parts(2, 3, vec![0; 6], Some(vec![0.0; 5])).is_err());
+        assert!(ResultMatrix::from_raw_parts(usize::MAX, 2, vec![], None).is_err());
+    }
+
+    #[test]
+    fn test_sparse() {
+        let dense = ResultMatrix::from_raw_parts(3, 2, vec![1, 0, 0, 4, 0, 6], None).unwrap();
+        let sparse = dense.clone().into_sparse();
+        assert!(sparse.is_sparse());
+        assert!(!dense.is_sparse());
+        assert_eq!(sparse, dense);
+        assert_eq!((sparse.get(1, 1), sparse.get(1, 0)), (4, 0));
+        assert_eq!(sparse.row(2).collect::<Vec<u64>>(), [0, 6]);
+        assert_eq!(sparse.column_iter(1).collect::<Vec<u64>>(), [0, 4, 6]);
+        assert!(sparse.iter_cells().eq(dense.iter_cells()));
+        assert_eq!(sparse.clone().into_dense(), dense);
+        assert!(!sparse.clone().into_dense().is_sparse());
+
+        // Dense and sparse matrices merge into each other
+        let mut merged = dense.clone();
+        merged.merge(&sparse).unwrap();
+        assert_eq!(merged.get(2, 1), 12);
+        let mut merged = sparse.clone();
+        merged.merge(&dense).unwrap();
+        assert!(merged.is_sparse());
+        assert_eq!((merged.get(2, 1), merged.get(0, 1)), (12, 0));
+        merged.increment(0, 1);
+        assert_ne!(merged, dense);
+
+        let mut weighted = ResultMatrix::new_weighted_sparse(3, 2);
+        weighted.increment_by(2, 1, 0.5);
+        let mut other = ResultMatrix::new_weighted(3, 2);
+        other.increment_by(2, 1, 0.25);
+        weighted.merge(&other).unwrap();
+        assert_eq!((weighted.get(2, 1), weighted.weight(2, 1)), (2, 0.75));
+        assert_eq!(weighted.weight(0, 0), 0.0);
+        assert_eq!(
+            ResultMatrix::new_sparse(3, 2).merge(&other),
+            Err(MergeError::Weighting)
+        );
+
+        let mut full = ResultMatrix::new_sparse(1, 1).with_max_count(1);
+        full.increment(0, 0);
+        let error = full.merge(&full.clone()).unwrap_err();
+        assert_eq!(error, MergeError::Overflow { vidx: 0, sidx: 0 });
+    }
+
+    #[test]
+    fn test_named() {
+        let m = ResultMatrix::new(2, 2);
+        assert!(NamedResultMatrix::new(vec!["S1".to_owned()], m.clone()).is_err());
+        let named = NamedResultMatrix::new(vec!["S1".to_owned(), "S2".to_owned()], m).unwrap();
+        assert_eq!(named.samples(), ["S1", "S2"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut m = ResultMatrix::new_weighted(3, 2);
+        m.increment_by(2, 1, 0.5);
+        m.increment_by(0, 0, 0.25);
+        let named = NamedResultMatrix::new(vec!["S1".to_owned(), "S2".to_owned()], m).unwrap();
+
+        let json = serde_json::to_string(&named).unwrap();
+        let restored: NamedResultMatrix = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, named);
+        assert_eq!(restored.matrix().weight(2, 1), 0.5);
+        assert_eq!(restored.matrix().weight(0, 0), 0.25);
+
+        let bytes = bincode::serialize(&named).unwrap();
+        let restored: NamedResultMatrix = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored, named);
+
+        let m = ResultMatrix::new(2, 1);
+        let bytes = bincode::serialize(&m).unwrap();
+        assert_eq!(bincode::deserialize::<ResultMatrix>(&bytes).unwrap(), m);
+
+        // Sparse matrices are stored like dense ones
+        let mut sparse = ResultMatrix::new_weighted_sparse(3, 2);
+        sparse.increment_by(2, 1, 0.5);
+        let mut dense = ResultMatrix::new_weighted(3, 2);
+        dense.increment_by(2, 1, 0.5);
+        let json = serde_json::to_string(&sparse).unwrap();
+        assert_eq!(json, serde_json::to_string(&dense).unwrap());
+        assert_eq!(serde_json::from_str::<ResultMatrix>(&json).unwrap(), sparse);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_dimensions() {
+        let error = serde_json::from_str::<ResultMatrix>(
+            r#"{"n_variants":2,"n_samples":2,"counts":[1,2,3],"weights":null}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("found 3 values"), "{}", error);
+        let error = serde_json::from_str::<ResultMatrix>(
+            r#"{"n_variants":1,"n_samples":2,"counts":[1,2],"weights":[0.5]}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("found 1 values"), "{}", error);
+        let error = serde_json::from_str::<NamedResultMatrix>(
+            r#"{"samples":["S1"],"matrix":{"n_variants":1,"n_samples":2,"counts":[1,2],"weights":null}}"#,
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("1 names for 2 samples"),
+            "{}",
+            error
+        );
     }
 }
@@ -0,0 +1,478 @@
+use crate::count::{
+    count_vcf_with_progress, CountOptions, CountResult, Options, Progress, RefMismatch, Scheme,
+};
+use crate::error::Error;
+use crate::signature;
+use std::collections::HashSet;
+
+/// The callback receiving the progress of a run.
+type ProgressCallback = Box<dyn FnMut(&Progress)>;
+
+/// Configures and runs the counting of the variants of a VCF file.
+///
+/// The options are validated as they are set, such that `error()` reports an invalid
+/// combination right away and `run()` fails before any file is opened. Whether the samples
+/// exist is only known when the VCF file is read by `run()`.
+///
+/// ```
+/// use mutsig::MutsigRunner;
+///
+/// let counts = MutsigRunner::new("testdata/ex2.vcf", "testdata/ex2.fa")
+///     .window(1)
+///     .samples(["Example1", "Example2"])
+///     .ignore_homogeneous(true)
+///     .pass_only(true)
+///     .run()
+///     .unwrap();
+/// assert_eq!(counts.samples, vec!["Example1", "Example2"]);
+/// ```
+pub struct MutsigRunner {
+    options: CountOptions,
+    progress: Option<ProgressCallback>,
+    invalid: Option<Error>,
+}
+
+impl MutsigRunner {
+    /// Count the single base substitutions without context of all samples of the VCF file at
+    /// `vcf` against the reference sequences at `reference`.
+    pub fn new<V: Into<String>, R: Into<String>>(vcf: V, reference: R) -> Self {
+        MutsigRunner::from_options(CountOptions::new(vcf, reference))
+    }
+
+    /// Run with the `options`.
+    pub fn from_options(options: CountOptions) -> Self {
+        let mut runner = MutsigRunner {
+            options,
+            progress: None,
+            invalid: None,
+        };
+        runner.validate();
+        runner
+    }
+
+    /// Validate the options as set so far.
+    fn validate(&mut self) {
+        self.invalid = validate(&self.options).err();
+    }
+
+    /// Set an option of the classification and validate it.
+    fn classify<F: FnOnce(&mut Options)>(mut self, set: F) -> Self {
+        set(&mut self.options.classification);
+        self.validate();
+        self
+    }
+
+    /// Set the number of bases of the sequence context on each side of a substitution.
+    pub fn window(mut self, window: u8) -> Self {
+        self.options.window = window;
+        self.validate();
+        self
+    }
+
+    /// Set the largest number of channels to allocate for the window.
+    pub fn max_channels(mut self, max_channels: usize) -> Self {
+        self.options.max_channels = max_channels;
+        self.validate();
+        self
+    }
+
+    /// Count only the `samples` in this order instead of all samples of the VCF file.
+    pub fn samples<I, S>(mut self, samples: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.options.samples = Some(samples.into_iter().map(|s| s.as_ref().to_owned()).collect());
+        self
+    }
+
+    /// Only read the records in the `regions`, given as `contig` or `contig:start-end` with
+    /// 1-based inclusive bounds, by the index of the VCF file, which may be remote.
+    pub fn regions<I, S>(mut self, regions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.options.regions = Some(regions.into_iter().map(|r| r.as_ref().to_owned()).collect());
+        self
+    }
+
+    /// Set the number of threads used for decompressing the VCF file and classifying its
+    /// records.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = threads;
+        self.validate();
+        self
+    }
+
+    /// Hold the sequence of the current contig in memory instead of fetching the window of
+    /// each variant from the reference, which is faster for coordinate-sorted VCF files.
+    pub fn cache_reference(mut self, cache: bool) -> Self {
+        self.options.cache_reference = cache;
+        self
+    }
+
+    /// Count into the custom channels defined in the file at `path`.
+    pub fn channels<P: Into<String>>(mut self, path: P) -> Self {
+        self.options.channels = Some(path.into());
+        self
+    }
+
+    /// Count the substitutions of no custom channel into an additional one.
+    pub fn channels_other(mut self, other: bool) -> Self {
+        self.options.channels_other = other;
+        self
+    }
+
+    /// Log the skipped records to the file at `path`.
+    pub fn skipped<P: Into<String>>(mut self, path: P) -> Self {
+        self.options.skipped = Some(path.into());
+        self
+    }
+
+    /// Ignore the sites where all samples have the same genotype, which requires at least two
+    /// samples.
+    pub fn ignore_homogeneous(self, ignore: bool) -> Self {
+        self.classify(|o| o.ignore_homogeneous_sites = ignore)
+    }
+
+    /// Count each alternative allele of a record once per sample instead of decoding the
+    /// genotypes, e.g., for VCF files without FORMAT/GT.
+    pub fn no_genotypes(self, no_genotypes: bool) -> Self {
+        self.classify(|o| o.no_genotypes = no_genotypes)
+    }
+
+    /// Compare the genotypes of homogeneous sites by their alleles regardless of the ploidy.
+    pub fn compare_allele_content(self, compare: bool) -> Self {
+        self.classify(|o| o.compare_allele_content = compare)
+    }
+
+    /// Ignore the sites where any of the samples has a missing genotype.
+    pub fn require_complete_genotypes(self, require: bool) -> Self {
+        self.classify(|o| o.require_complete_genotypes = require)
+    }
+
+    /// Do not count the samples with a (partially) missing genotype at a site.
+    pub fn skip_missing_genotypes(self, skip: bool) -> Self {
+        self.classify(|o| o.skip_missing_genotypes = skip)
+    }
+
+    /// Only count the records whose FILTER is `PASS` or missing.
+    pub fn pass_only(self, pass_only: bool) -> Self {
+        self.classify(|o| o.pass_only = pass_only)
+    }
+
+    /// Only count the records having one of the FILTER values.
+    pub fn include_filters<I, S>(self, filters: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let filters = strings(filters);
+        self.classify(|o| o.include_filters = Some(filters))
+    }
+
+    /// Do not count the records having one of the FILTER values.
+    pub fn exclude_filters<I, S>(self, filters: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let filters = strings(filters);
+        self.classify(|o| o.exclude_filters = Some(filters))
+    }
+
+    /// Only count the records with a QUAL of at least `min_qual`.
+    pub fn min_qual(self, min_qual: f32) -> Self {
+        self.classify(|o| o.min_qual = Some(min_qual))
+    }
+
+    /// Do not count the records with missing QUAL.
+    pub fn require_qual(self, require: bool) -> Self {
+        self.classify(|o| o.require_qual = require)
+    }
+
+    /// Only count the records on the `contigs`.
+    pub fn chromosomes<I, S>(self, contigs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let contigs = strings(contigs);
+        self.classify(|o| o.chromosomes = Some(contigs))
+    }
+
+    /// Only count the records on the primary chromosomes.
+    pub fn primary_only(self, primary_only: bool) -> Self {
+        self.classify(|o| o.primary_only = primary_only)
+    }
+
+    /// Skip the records on contigs missing from the reference instead of failing.
+    pub fn skip_unknown_contigs(self, skip: bool) -> Self {
+        self.classify(|o| o.skip_unknown_contigs = skip)
+    }
+
+    /// Fail on the first record whose reference allele differs from the reference, instead of
+    /// skipping it with a warning.
+    pub fn strict(self, strict: bool) -> Self {
+        self.classify(|o| {
+            o.on_ref_mismatch = if strict {
+                RefMismatch::Error
+            } else {
+                RefMismatch::Warn
+            }
+        })
+    }
+
+    /// Fail after counting if more than `fraction` of the records differ from the reference.
+    pub fn max_ref_mismatch_fraction(self, fraction: Option<f32>) -> Self {
+        self.classify(|o| o.max_ref_mismatch_fraction = fraction)
+    }
+
+    /// Fail after counting if more than `fraction` of the records was not counted, e.g., as
+    /// they are filtered or can not be classified.
+    pub fn max_ignored_fraction(self, fraction: Option<f32>) -> Self {
+        self.classify(|o| o.max_ignored_fraction = fraction)
+    }
+
+    /// Skip malformed records, e.g., without a contig, with a warning instead of failing.
+    pub fn skip_errors(self, skip: bool) -> Self {
+        self.classify(|o| o.skip_errors = skip)
+    }
+
+    /// Check the contigs and lengths of the VCF header against the reference before counting.
+    pub fn validate_contigs(self, validate: bool) -> Self {
+        self.classify(|o| o.no_validate_contigs = !validate)
+    }
+
+    /// Do not count the records with more than `max_alt_alleles` alternative alleles.
+    pub fn max_alt_alleles(self, max_alt_alleles: usize) -> Self {
+        self.classify(|o| o.max_alt_alleles = Some(max_alt_alleles))
+    }
+
+    /// Do not count the mutations of a sample closer than `distance` to the previous one.
+    pub fn min_distance(self, distance: u64) -> Self {
+        self.classify(|o| o.min_distance = Some(distance))
+    }
+
+    /// Store the counts sparsely, which saves memory for many channels and samples with few
+    /// mutations each. Result matrices of more than `result::SPARSE_CELLS` cells are always
+    /// stored sparsely.
+    pub fn sparse(self, sparse: bool) -> Self {
+        self.classify(|o| o.sparse = sparse)
+    }
+
+    /// Decompose the multi-nucleotide variants into their single base substitutions.
+    pub fn decompose_mnv(self, decompose: bool) -> Self {
+        self.classify(|o| o.decompose_mnv = decompose)
+    }
+
+    /// Do not count the records whose base or window is soft-masked in the reference.
+    pub fn skip_softmasked(self, skip: bool) -> Self {
+        self.classify(|o| o.skip_softmasked = skip)
+    }
+
+    /// Count the substitutions whose window is ambiguous without their context.
+    pub fn count_partial_context(self, count: bool) -> Self {
+        self.classify(|o| o.count_partial_context = count)
+    }
+
+    /// Do not count the genotypes with a FORMAT/DP below `min_dp` or missing.
+    pub fn min_dp(self, min_dp: i32) -> Self {
+        self.classify(|o| o.min_dp = Some(min_dp))
+    }
+
+    /// Do not count the genotypes with a FORMAT/GQ below `min_gq`.
+    pub fn min_gq(self, min_gq: i32) -> Self {
+        self.classify(|o| o.min_gq = Some(min_gq))
+    }
+
+    /// Do not count the genotypes whose FORMAT/FT is not `PASS`.
+    pub fn sample_pass_only(self, pass_only: bool) -> Self {
+        self.classify(|o| o.sample_pass_only = pass_only)
+    }
+
+    /// Do not count the alternative alleles with an allele fraction below `min_vaf`.
+    pub fn min_vaf(self, min_vaf: f32) -> Self {
+        self.classify(|o| o.min_vaf = Some(min_vaf))
+    }
+
+    /// Do not count the alternative alleles with an allele fraction above `max_vaf`.
+    pub fn max_vaf(self, max_vaf: f32) -> Self {
+        self.classify(|o| o.max_vaf = Some(max_vaf))
+    }
+
+    /// Do not count the alternative alleles supported by fewer than `min_alt_reads` reads.
+    pub fn min_alt_reads(self, min_alt_reads: i32) -> Self {
+        self.classify(|o| o.min_alt_reads = Some(min_alt_reads))
+    }
+
+    /// Report the number of records read so far to `progress` after each record.
+    pub fn progress<F: FnMut(u64) + 'static>(self, mut progress: F) -> Self {
+        self.report_progress(move |p: &Progress| progress(p.records))
+    }
+
+    /// Report the `Progress` of the count, i.e., the records read and skipped so far, the
+    /// position of the last record and the total number of records if known from the index of
+    /// the VCF file, to `progress` after each record.
+    pub fn report_progress<F: FnMut(&Progress) + 'static>(mut self, progress: F) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Replace the classification and filters of the variants, e.g., as configured by the
+    /// command line.
+    pub(crate) fn classification(mut self, classification: Options) -> Self {
+        self.options.classification = classification;
+        self.validate();
+        self
+    }
+
+    /// Set the sequences of the interval list restricting the counting.
+    pub(crate) fn interval_list_sequences(mut self, sequences: Vec<String>) -> Self {
+        self.options.interval_list_sequences = sequences;
+        self
+    }
+
+    /// Return the options of the run.
+    pub fn options(&self) -> &CountOptions {
+        &self.options
+    }
+
+    /// Return why the options as set so far are invalid, if they are.
+    pub fn error(&self) -> Option<&Error> {
+        self.invalid.as_ref()
+    }
+
+    /// Count the variants, unless the options are invalid.
+    pub fn run(&mut self) -> Result<CountResult, Error> {
+        validate(&self.options)?;
+        let n_channels =
+            signature::check_window(self.options.window.into(), self.options.max_channels)?;
+        info!(
+            "Counting into {} channels, requiring about {} MB for the signatures",
+            n_channels,
+            signature::memory_footprint(n_channels) / (1 << 20)
+        );
+        let progress = self
+            .progress
+            .as_mut()
+            .map(|p| p.as_mut() as &mut dyn FnMut(&Progress));
+        count_vcf_with_progress(&self.options, progress)
+    }
+}
+
+fn strings<I, S>(values: I) -> HashSet<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    values.into_iter().map(|v| v.as_ref().to_owned()).collect()
+}
+
+/// Check the options for values out of range and for contradictions between them.
+fn validate(options: &CountOptions) -> Result<(), Error> {
+    let invalid = |message: String| Err(Error::InvalidOptions(message));
+    let classification = &options.classification;
+    if options.threads == 0 {
+        return invalid("Can not count with 0 threads, require at least 1".to_owned());
+    }
+    signature::check_window(options.window.into(), options.max_channels)?;
+    if matches!(classification.scheme, Scheme::Sbs288(_)) && options.window != 1 {
+        return invalid(format!(
+            "Can not count SBS288 with window {}, it requires a window of 1",
+            options.window
+        ));
+    }
+    if classification.max_alt_alleles == Some(0) {
+        return invalid(
+            "Can not count records with at most 0 alternative alleles, require at least 1"
+                .to_owned(),
+        );
+    }
+    if classification.no_genotypes && classification.ignore_homogeneous_sites {
+        return invalid(
+            "Can not ignore homogeneous sites without genotypes, all sites are homogeneous"
+                .to_owned(),
+        );
+    }
+    if let (Some(i), Some(e)) = (
+        &classification.include_filters,
+        &classification.exclude_filters,
+    ) {
+        if let Some(f) = i.intersection(e).next() {
+            return invalid(format!(
+                "FILTER value '{}' can not be both included and excluded",
+                f
+            ));
+        }
+    }
+    for (name, vaf) in &[
+        ("min-vaf", classification.min_vaf),
+        ("max-vaf", classification.max_vaf),
+    ] {
+        if let Some(v) = vaf {
+            if !(0.0..=1.0).contains(v) {
+                return invalid(format!("Invalid {} {}: must be between 0 and 1", name, v));
+            }
+        }
+    }
+    if let (Some(min), Some(max)) = (classification.min_vaf, classification.max_vaf) {
+        if min > max {
+            return invalid(format!(
+                "Invalid VAF window: min-vaf {} is larger than max-vaf {}",
+                min, max
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runner() -> MutsigRunner {
+        MutsigRunner::new("missing.vcf", "missing.fa")
+    }
+
+    fn invalid(runner: MutsigRunner) -> Option<Error> {
+        runner.invalid
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(invalid(runner().window(1).threads(2)).is_none());
+        assert!(matches!(
+            invalid(runner().window(8)),
+            Some(Error::InvalidWindow { window: 8, .. })
+        ));
+        assert!(matches!(
+            invalid(runner().threads(0)),
+            Some(Error::InvalidOptions(_))
+        ));
+        assert!(invalid(runner().max_alt_alleles(0)).is_some());
+        assert!(invalid(runner().no_genotypes(true).ignore_homogeneous(true)).is_some());
+        assert!(invalid(runner().min_vaf(1.5)).is_some());
+        assert!(invalid(runner().min_vaf(0.5).max_vaf(0.2)).is_some());
+        assert!(invalid(
+            runner()
+                .include_filters(["LowQual"])
+                .exclude_filters(["LowQual"])
+        )
+        .is_some());
+        // The options are validated together, regardless of their order
+        assert!(invalid(runner().window(8).max_channels(usize::MAX)).is_none());
+        assert!(invalid(runner().threads(0).threads(1)).is_none());
+    }
+
+    #[test]
+    fn test_run_invalid() {
+        // The invalid option is reported before the missing files
+        let mut runner = runner().threads(0);
+        assert!(matches!(runner.error(), Some(Error::InvalidOptions(_))));
+        assert!(matches!(runner.run(), Err(Error::InvalidOptions(_))));
+        let mut runner = self::runner();
+        assert!(matches!(runner.run(), Err(Error::Htslib { .. })));
+    }
+}
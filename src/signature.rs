@@ -1,8 +1,18 @@
+use crate::error::Error;
 use std::cmp;
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt;
+use std::io::BufRead;
+use std::path::Path;
+use std::str::FromStr;
 
-#[derive(Clone)]
+/// The bases of the labels of the signatures.
+const NUCLEOTIDES: [char; 4] = ['A', 'C', 'G', 'T'];
+
+/// The substitution of the center base of a codon, which is its reference base. The
+/// signatures are ordered by their codon and then by their alternative base.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Signature {
     codon: String,
     reference: char,
@@ -15,17 +25,193 @@ impl fmt::Debug for Signature {
 }
 
 impl Signature {
+    /// Create the signature of the substitution of `reference` by `alternative` in the center
+    /// of `codon`, e.g., `ACG>T` for `("ACG", 'C', 'T')`.
+    ///
+    /// # Panics
+    ///
+    /// If the codon has no center base, i.e., an even number of bases, or `reference` is not
+    /// its center base.
     pub fn new<S: AsRef<str>>(codon: S, reference: char, alternative: char) -> Signature {
+        let codon = codon.as_ref();
+        assert!(
+            codon.len() % 2 == 1 && codon.chars().nth(codon.len() / 2) == Some(reference),
+            "Can not create signature of codon '{}' with reference base {}",
+            codon,
+            reference
+        );
         Signature {
-            codon: codon.as_ref().to_owned(),
-            reference: reference,
-            alternative: alternative,
+            codon: codon.to_owned(),
+            reference,
+            alternative,
+        }
+    }
+
+    /// Create the signature of the substitution of the center base of `codon` by
+    /// `alternative`, e.g., `C>T` for `("C", 'T')` without context.
+    ///
+    /// # Panics
+    ///
+    /// If the codon has no center base, i.e., an even number of bases.
+    pub fn from_codon<S: AsRef<str>>(codon: S, alternative: char) -> Signature {
+        let codon = codon.as_ref();
+        let window = codon.len() / 2;
+        match codon.chars().nth(window) {
+            Some(reference) => Signature::new(codon, reference, alternative),
+            None => panic!("Can not create signature of empty codon"),
         }
     }
 
     pub fn is_forward_signature(&self) -> bool {
         self.reference == 'C' || self.reference == 'T'
     }
+
+    /// Check if the substituted base is part of a CpG dinucleotide, i.e., a C followed by a G
+    /// or a G preceded by a C. Returns `None` if the context does not include the neighbour.
+    pub fn is_cpg(&self) -> Option<bool> {
+        let window = self.codon.len() / 2;
+        let codon = self.codon.as_bytes();
+        match self.reference {
+            'C' if window > 0 => Some(codon[window + 1] == b'G'),
+            'G' if window > 0 => Some(codon[window - 1] == b'C'),
+            'C' | 'G' => None,
+            _ => Some(false),
+        }
+    }
+
+    /// Check if the signature is labeled in the `orientation`, i.e., by the representation
+    /// of the substitution that has the pyrimidine or purine as reference base.
+    pub fn has_orientation(&self, orientation: Orientation) -> bool {
+        match orientation {
+            Orientation::Pyrimidine => self.is_forward_signature(),
+            Orientation::Purine => !self.is_forward_signature(),
+        }
+    }
+
+    /// Return the reference and alternative base of the substitution with the pyrimidine as
+    /// reference base, e.g., `('C', 'T')` for both `ACG>T` and `CGT>A`.
+    pub fn substitution(&self) -> (char, char) {
+        if self.is_forward_signature() {
+            (self.reference, self.alternative)
+        } else {
+            (rev_comp_c(self.reference), rev_comp_c(self.alternative))
+        }
+    }
+
+    /// Return the number of bases of the context on each side of the substituted base.
+    pub fn window(&self) -> usize {
+        self.codon.len() / 2
+    }
+
+    /// Return the encoding of the signature, see `encode()`.
+    pub fn code(&self) -> Option<usize> {
+        let alternative = u8::try_from(self.alternative).ok()?;
+        encode(self.codon.as_bytes(), alternative)
+    }
+
+    /// Return the signature on the reverse strand, e.g., `CGT>A` for `ACG>T`.
+    pub fn reverse_complement(&self) -> Signature {
+        Signature::new(
+            rev_comp(self.codon.chars()),
+            rev_comp_c(self.reference),
+            rev_comp_c(self.alternative),
+        )
+    }
+
+    /// Parse a signature in the format of its label, e.g., `ACG>T`, or of its COSMIC label,
+    /// e.g., `A[C>T]G`, detected by the brackets. The bases must be upper case nucleotides and
+    /// the context must have the same length on both sides of the substituted base.
+    pub fn parse(label: &str) -> Result<Signature, Error> {
+        let invalid = |reason: &str| Error::InvalidSignature {
+            label: label.to_owned(),
+            reason: reason.to_owned(),
+        };
+        let (codon, reference, alternative) = match (label.find('['), label.find(']')) {
+            (Some(open), Some(close)) if open < close => {
+                let substitution: Vec<char> = label[open + 1..close].chars().collect();
+                if substitution.len() != 3 || substitution[1] != '>' {
+                    return Err(invalid(
+                        "expected a substitution like [C>T] in the brackets",
+                    ));
+                }
+                if open != label.len() - close - 1 {
+                    return Err(invalid(
+                        "expected the same number of bases on both sides of the brackets",
+                    ));
+                }
+                let codon = format!(
+                    "{}{}{}",
+                    &label[..open],
+                    substitution[0],
+                    &label[close + 1..]
+                );
+                (codon, substitution[0], substitution[2])
+            }
+            (None, None) => {
+                let mut parts = label.split('>');
+                let codon = parts.next().unwrap_or_default();
+                let alternative: Vec<char> = parts.next().unwrap_or_default().chars().collect();
+                if alternative.len() != 1 || parts.next().is_some() {
+                    return Err(invalid("expected a label like ACG>T or A[C>T]G"));
+                }
+                let window = codon.len() / 2;
+                if codon.len() != 2 * window + 1 {
+                    return Err(invalid(
+                        "expected an odd number of bases with the substituted one in the middle",
+                    ));
+                }
+                match codon.chars().nth(window) {
+                    Some(reference) => (codon.to_owned(), reference, alternative[0]),
+                    None => return Err(invalid("expected a label like ACG>T or A[C>T]G")),
+                }
+            }
+            _ => return Err(invalid("expected a label like ACG>T or A[C>T]G")),
+        };
+        if let Some(c) = codon
+            .chars()
+            .chain(Some(alternative))
+            .find(|c| !NUCLEOTIDES.contains(c))
+        {
+            return Err(invalid(&format!(
+                "'{}' is not one of the bases A, C, G and T",
+                c
+            )));
+        }
+        if reference == alternative {
+            return Err(invalid(
+                "expected different reference and alternative bases",
+            ));
+        }
+        Ok(Signature::new(codon, reference, alternative))
+    }
+
+    /// Return the COSMIC label of the signature, e.g., `A[C>T]G` or `C>T` without context.
+    pub fn cosmic_label(&self) -> String {
+        let window = self.codon.len() / 2;
+        if window == 0 {
+            return format!("{}>{}", self.reference, self.alternative);
+        }
+        format!(
+            "{}[{}>{}]{}",
+            &self.codon[..window],
+            self.reference,
+            self.alternative,
+            &self.codon[window + 1..]
+        )
+    }
+
+    /// Compare the signatures in the COSMIC order, i.e., grouped by the substitution and
+    /// then by the context of the substituted base.
+    pub fn cosmic_cmp(&self, other: &Self) -> cmp::Ordering {
+        let window = self.codon.len() / 2;
+        let other_window = other.codon.len() / 2;
+        other_window
+            .cmp(&window)
+            .then(self.reference.cmp(&other.reference))
+            .then(self.alternative.cmp(&other.alternative))
+            .then(self.codon[..window].cmp(&other.codon[..other_window]))
+            .then(self.codon[window + 1..].cmp(&other.codon[other_window + 1..]))
+    }
 }
 
 impl fmt::Display for Signature {
@@ -34,44 +220,239 @@ impl fmt::Display for Signature {
     }
 }
 
-impl cmp::Ord for Signature {
-    fn cmp(&self, other: &Self) -> cmp::Ordering {
-        let c = self.codon.cmp(&other.codon);
-        if c != cmp::Ordering::Equal {
-            c
-        } else {
-            self.alternative.cmp(&other.alternative)
-        }
+/// Parses a signature as by `Signature::parse`, which the labels of `Display` and
+/// `Signature::cosmic_label` round-trip through.
+impl FromStr for Signature {
+    type Err = Error;
+
+    fn from_str(label: &str) -> Result<Self, Error> {
+        Signature::parse(label)
     }
 }
 
-impl cmp::PartialOrd for Signature {
-    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
-        Some(self.cmp(other))
+/// A signature is stored by its label, e.g., `ACG>T`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Signature {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
     }
 }
 
-impl cmp::PartialEq for Signature {
-    fn eq(&self, other: &Self) -> bool {
-        self.codon == other.codon && self.alternative == other.alternative
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Signature {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let label = String::deserialize(deserializer)?;
+        Signature::parse(&label).map_err(serde::de::Error::custom)
     }
 }
-impl cmp::Eq for Signature {}
 
-#[derive(Clone)]
+/// The reference base by which a substitution and its reverse complement are labeled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    /// Label by the representation with C or T as reference base
+    Pyrimidine,
+    /// Label by the representation with A or G as reference base
+    Purine,
+}
+
+/// The maximum number of channels built by default, e.g., window 5 has 6,291,456 channels.
+pub const DEFAULT_MAX_CHANNELS: usize = 1 << 24;
+
+/// The approximate number of bytes of a signature in the database, counted twice per channel.
+const SIGNATURE_BYTES: usize = 96;
+
+/// Return the number of collapsed substitution channels of `window`, i.e., 2·4^(2·window)·3,
+/// or `None` if it does not fit into `usize`.
+pub fn channel_count(window: usize) -> Option<usize> {
+    u32::try_from(window)
+        .ok()
+        .and_then(|w| 16usize.checked_pow(w))
+        .and_then(|n| n.checked_mul(6))
+}
+
+/// Return the number of channels of `window`, or an error if it exceeds `max_channels`.
+pub fn check_window(window: usize, max_channels: usize) -> Result<usize, Error> {
+    match channel_count(window) {
+        Some(n) if n <= max_channels => Ok(n),
+        Some(n) => Err(Error::InvalidWindow {
+            window,
+            reason: format!("its {} channels exceed the maximum of {}", n, max_channels),
+        }),
+        None => Err(Error::InvalidWindow {
+            window,
+            reason: format!("its channels exceed the maximum of {}", max_channels),
+        }),
+    }
+}
+
+/// Return the approximate number of bytes of the signature database of `n_channels`.
+pub fn memory_footprint(n_channels: usize) -> usize {
+    n_channels.saturating_mul(2 * SIGNATURE_BYTES)
+}
+
+/// Return the code of the substitution of the center of `codon` by `alternative`, i.e., the bases
+/// of the codon followed by the alternative base packed into 2 bits each (A, C, G, T as 0 to
+/// 3) after a marker bit telling codons of different lengths apart, e.g., `0b1_000110_11` for
+/// `ACG>T`. Returns `None` if a base is not an upper case nucleotide or the code does not fit
+/// into `usize`.
+pub fn encode(codon: &[u8], alternative: u8) -> Option<usize> {
+    if 2 * (codon.len() + 1) >= usize::BITS as usize {
+        return None;
+    }
+    let mut code = 1;
+    for base in codon.iter().chain(std::iter::once(&alternative)) {
+        let bits = match base {
+            b'A' => 0,
+            b'C' => 1,
+            b'G' => 2,
+            b'T' => 3,
+            _ => return None,
+        };
+        code = (code << 2) | bits;
+    }
+    Some(code)
+}
+
+/// The label of the channel of the signatures not assigned by a channel definition.
+pub const OTHER_CHANNEL: &str = "OTHER";
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "StoredSignatures"))]
 pub struct Signatures {
+    /// The index of the channel of each signature
+    #[cfg_attr(feature = "serde", serde(rename = "channels"))]
     db: BTreeMap<Signature, usize>,
+    /// The labels of the channels in the order of their index, if defined by the user
+    labels: Option<Vec<String>>,
+    /// The index of the channel of each signature by its encoding, see `encode()`, or
+    /// `NO_CHANNEL` for the codes of no signature
+    #[cfg_attr(feature = "serde", serde(skip))]
+    table: Vec<u32>,
 }
 
+/// The entry of the lookup table of the signatures for the codes of no signature.
+const NO_CHANNEL: u32 = u32::MAX;
+
 impl Signatures {
-    pub fn new(window: usize) -> Signatures {
-        let s = Signatures {
-            db: build_signatures(window),
+    /// Build the collapsed signatures of `window`, unless their channels exceed `max_channels`.
+    pub fn new(window: usize, max_channels: usize) -> Result<Signatures, Error> {
+        check_window(window, max_channels)?;
+        let s = Signatures::from_db(build_signatures(window), None);
+        trace!("Build signature database: {:?}", s);
+        Ok(s)
+    }
+
+    /// Build the signatures counting into the channels of the `db` and their lookup table.
+    fn from_db(db: BTreeMap<Signature, usize>, labels: Option<Vec<String>>) -> Signatures {
+        let max_window = db.keys().map(|s| s.window()).max();
+        let mut table = match max_window {
+            // The marker bit of the codes of the longest codons and the bases after it
+            Some(w) => vec![NO_CHANNEL; 1 << (2 * (2 * w + 2) + 1)],
+            None => Vec::new(),
         };
+        for (s, i) in &db {
+            if let Some(code) = s.code() {
+                table[code] = *i as u32;
+            }
+        }
+        Signatures { db, labels, table }
+    }
+
+    /// Build the signatures of `window` followed by the six substitution channels without
+    /// context (window 0). These count substitutions whose flanking bases are ambiguous.
+    pub fn with_partial_context(window: usize) -> Signatures {
+        let mut db = build_signatures(window);
+        if window > 0 {
+            let offset = db.values().max().map_or(0, |i| i + 1);
+            for (s, i) in build_signatures(0) {
+                db.insert(s, i + offset);
+            }
+        }
+        let s = Signatures::from_db(db, None);
         trace!("Build signature database: {:?}", s);
         s
     }
 
+    /// Build the signatures of `window` without collapsing the substitutions of purines into
+    /// their reverse complement, i.e., each signature is a channel of its own.
+    pub fn uncollapsed(window: usize) -> Signatures {
+        let db = build_signatures(window)
+            .into_keys()
+            .enumerate()
+            .map(|(i, s)| (s, i))
+            .collect();
+        let s = Signatures::from_db(db, None);
+        trace!("Build signature database: {:?}", s);
+        s
+    }
+
+    /// Build the signatures of `window` from the channel `definition`, i.e., the labels of
+    /// the channels and their member signatures. A member and its reverse complement count
+    /// into the channel, which are numbered in the order of the definition. Signatures not
+    /// assigned to any channel count into an additional last channel `OTHER` if `other` is set.
+    pub fn from_definition(
+        window: usize,
+        definition: &[(String, Vec<Signature>)],
+        other: bool,
+    ) -> Result<Signatures, Error> {
+        let invalid = |message: String| Err(Error::ChannelDefinition(message));
+        let all = build_signatures(window);
+        let mut db = BTreeMap::new();
+        let mut labels: Vec<String> = Vec::new();
+        for (label, members) in definition {
+            if labels.contains(label) || (other && label == OTHER_CHANNEL) {
+                return invalid(format!("Can not define channel '{}' twice", label));
+            }
+            let idx = labels.len();
+            labels.push(label.clone());
+            for member in members {
+                if member.codon.len() != 2 * window + 1 {
+                    return invalid(format!(
+                        "Can not assign '{}' to channel '{}' with window {}",
+                        member, label, window
+                    ));
+                }
+                if !all.contains_key(member) {
+                    return invalid(format!(
+                        "Can not assign '{}' to channel '{}', it is not a substitution",
+                        member, label
+                    ));
+                }
+                if let Some(i) = db.get(member) {
+                    return invalid(format!(
+                        "Can not assign '{}' to channel '{}', it is already assigned to channel '{}'",
+                        member, label, labels[*i]
+                    ));
+                }
+                db.insert(member.reverse_complement(), idx);
+                db.insert(member.clone(), idx);
+            }
+        }
+        let unassigned: Vec<Signature> = all.into_keys().filter(|s| !db.contains_key(s)).collect();
+        if let Some(s) = unassigned.iter().find(|s| s.is_forward_signature()) {
+            if !other {
+                return invalid(format!(
+                    "Can not classify '{}', it is not assigned to any channel",
+                    s
+                ));
+            }
+            labels.push(OTHER_CHANNEL.to_owned());
+            for s in unassigned {
+                db.insert(s, labels.len() - 1);
+            }
+        }
+        let s = Signatures::from_db(db, Some(labels));
+        trace!("Build signature database: {:?}", s);
+        Ok(s)
+    }
+
+    /// Returns the labels of the channels in the order of their index, if they were built
+    /// from a channel definition.
+    pub fn labels(&self) -> Option<&[String]> {
+        self.labels.as_deref()
+    }
+
     /// Returns the index of the signature
     pub fn index_of(&self, sig: &Signature) -> Option<usize> {
         match self.db.get(sig) {
@@ -80,13 +461,79 @@ impl Signatures {
         }
     }
 
+    /// Returns the index of the substitution of the center of `codon` by `alternative` like
+    /// `index_of()`, but without building its signature, e.g., `(b"ACG", b'T')` for `ACG>T`.
+    pub fn index_of_bases(&self, codon: &[u8], alternative: u8) -> Option<usize> {
+        match encode(codon, alternative).and_then(|code| self.table.get(code)) {
+            Some(&i) if i != NO_CHANNEL => Some(i as usize),
+            _ => None,
+        }
+    }
+
+    /// Retrieve the number of distinct channels, i.e., signatures and their reverse
+    /// complements count once
+    pub fn channel_count(&self) -> usize {
+        self.db.values().max().map_or(0, |i| i + 1)
+    }
+
     /// Retrieve the number of signatures
     pub fn len(&self) -> usize {
         self.db.len()
     }
 
+    /// Check whether there are no signatures
+    pub fn is_empty(&self) -> bool {
+        self.db.is_empty()
+    }
+
+    /// Check that all channels of the `window` were built, i.e., 6 times 16 for each base of
+    /// the window (e.g., 96 for a window of 1 and 1536 for a window of 2).
+    pub fn validate(&self, window: usize) -> Result<(), Error> {
+        let expected = 16usize
+            .checked_pow(window as u32)
+            .and_then(|n| n.checked_mul(6));
+        let found = self
+            .db
+            .keys()
+            .filter(|s| s.is_forward_signature() && s.codon.len() == 2 * window + 1)
+            .count();
+        if expected != Some(found) {
+            return Err(Error::InvalidWindow {
+                window,
+                reason: format!("only {} of its channels were built", found),
+            });
+        }
+        Ok(())
+    }
+
+    /// Iterate over the signatures and the indizes of their channels in the order of the
+    /// signatures.
+    pub fn iter(&self) -> impl Iterator<Item = (&Signature, usize)> {
+        self.db.iter().map(|(s, i)| (s, *i))
+    }
+
+    /// Return the signature on the reverse strand of `signature`, which counts into the same
+    /// channel unless the signatures are uncollapsed, e.g., `CGT>A` for `ACG>T` and vice versa.
+    pub fn reverse_complement_of(&self, signature: &Signature) -> Signature {
+        signature.reverse_complement()
+    }
+
+    /// Return the signatures counting into the channel at `index` in their order, e.g., the
+    /// signature with the pyrimidine as reference base and its reverse complement with the
+    /// purine. The list is empty if there is no such channel.
+    pub fn collapsed_members(&self, index: usize) -> Vec<Signature> {
+        self.db
+            .iter()
+            .filter(|(_, i)| **i == index)
+            .map(|(s, _)| s.clone())
+            .collect()
+    }
+
+    /// Returns all signatures, the channels without context after those with context
     pub fn signatures(&self) -> Vec<Signature> {
-        self.db.keys().map(|i| i.clone()).collect()
+        let mut signatures: Vec<Signature> = self.db.keys().cloned().collect();
+        signatures.sort_by_key(|s| cmp::Reverse(s.codon.len()));
+        signatures
     }
 }
 
@@ -100,6 +547,46 @@ impl fmt::Debug for Signatures {
     }
 }
 
+/// The signatures as stored, which are validated before they are restored.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct StoredSignatures {
+    channels: BTreeMap<Signature, usize>,
+    labels: Option<Vec<String>>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<StoredSignatures> for Signatures {
+    type Error = Error;
+
+    /// Restore the signatures, whose channels must be numbered without gaps and labeled, if
+    /// at all, once each.
+    fn try_from(stored: StoredSignatures) -> Result<Self, Error> {
+        let signatures = Signatures::from_db(stored.channels, stored.labels);
+        let n_channels = signatures.channel_count();
+        let mut used = vec![false; n_channels];
+        for i in signatures.db.values() {
+            used[*i] = true;
+        }
+        if let Some(i) = used.iter().position(|u| !u) {
+            return Err(Error::ChannelDefinition(format!(
+                "Can not restore the signatures, channel {} has no signature",
+                i
+            )));
+        }
+        if let Some(labels) = &signatures.labels {
+            if labels.len() != n_channels {
+                return Err(Error::ChannelDefinition(format!(
+                    "Can not restore the signatures, found {} labels for {} channels",
+                    labels.len(),
+                    n_channels
+                )));
+            }
+        }
+        Ok(signatures)
+    }
+}
+
 fn build_codons(is_forward: bool, mut prior: usize, mut after: usize) -> Vec<String> {
     let mut codons: Vec<String> = vec!["".to_owned()];
 
@@ -156,14 +643,7 @@ pub fn build_signatures(window: usize) -> BTreeMap<Signature, usize> {
         let cn = cod.as_bytes()[window] as char;
         for n in vec!['A', 'C', 'G', 'T'] {
             if n != cn {
-                signatures.insert(
-                    Signature {
-                        codon: cod.clone(),
-                        reference: cn,
-                        alternative: n,
-                    },
-                    idx,
-                );
+                signatures.insert(Signature::from_codon(&cod, n), idx);
                 idx += 1;
             }
         }
@@ -172,19 +652,12 @@ pub fn build_signatures(window: usize) -> BTreeMap<Signature, usize> {
 
     for cod in build_codons(false, window, window) {
         let cn = cod[window..(window + 1)].as_bytes()[0] as char;
-        let cod2 = rev_comp(cod.chars());
         for n in vec!['A', 'C', 'G', 'T'] {
             if n != cn {
-                let rev_sig = Signature::new(&cod2, cn, rev_comp_c(n));
-                let idx = signatures.get(&rev_sig).unwrap().clone();
-                signatures.insert(
-                    Signature {
-                        codon: cod.clone(),
-                        reference: cn,
-                        alternative: n,
-                    },
-                    idx,
-                );
+                // The reverse complement has the complementary reference base
+                let signature = Signature::from_codon(&cod, n);
+                let idx = signatures[&signature.reverse_complement()];
+                signatures.insert(signature, idx);
             }
         }
     }
@@ -192,11 +665,65 @@ pub fn build_signatures(window: usize) -> BTreeMap<Signature, usize> {
     signatures
 }
 
-fn rev_comp<I: DoubleEndedIterator<Item = char>>(chars: I) -> String {
+/// Read the channel definition from the (optionally gzip compressed) file at `path`, see
+/// `parse_definition`.
+pub fn read_definition<P: AsRef<Path>>(path: P) -> Result<Vec<(String, Vec<Signature>)>, Error> {
+    let name = path.as_ref().display().to_string();
+    parse_definition(crate::input::open(path)?, &name)
+}
+
+/// Parse a channel definition from `reader`. Each line holds the label of a channel and
+/// its comma separated member signatures, separated by a tab (e.g., `C>T at CpG` and
+/// `ACG>T,CCG>T,GCG>T,TCG>T`). Empty and comment lines are skipped.
+fn parse_definition<R: BufRead>(
+    reader: R,
+    name: &str,
+) -> Result<Vec<(String, Vec<Signature>)>, Error> {
+    let mut definition = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                return Err(Error::Io {
+                    action: format!("read '{}'", name),
+                    source: e,
+                })
+            }
+        };
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.splitn(2, '\t');
+        let label = fields.next().unwrap().trim();
+        let members = match fields.next() {
+            Some(m) if !m.trim().is_empty() => m,
+            _ => {
+                return Err(Error::ChannelDefinition(format!(
+                    "Missing members in line {} of '{}'",
+                    i + 1,
+                    name
+                )))
+            }
+        };
+        let members = members
+            .split(',')
+            .map(|m| Signature::parse(m.trim()))
+            .collect::<Result<Vec<Signature>, Error>>()
+            .map_err(|e| {
+                Error::ChannelDefinition(format!("{} in line {} of '{}'", e, i + 1, name))
+            })?;
+        definition.push((label.to_owned(), members));
+    }
+    Ok(definition)
+}
+
+/// Return the reverse complement of the nucleotides `chars`.
+pub fn rev_comp<I: DoubleEndedIterator<Item = char>>(chars: I) -> String {
     chars.rev().map(rev_comp_c).collect::<String>()
 }
 
-fn rev_comp_c(n: char) -> char {
+/// Return the complement of the nucleotide `n`.
+pub fn rev_comp_c(n: char) -> char {
     match n {
         'A' => 'T',
         'C' => 'G',
@@ -209,9 +736,476 @@ fn rev_comp_c(n: char) -> char {
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn test_rev_comp() {
+        assert_eq!(rev_comp("ACGTT".chars()), "AACGT");
+        assert_eq!(rev_comp("".chars()), "");
+        let bases: Vec<char> = NUCLEOTIDES.iter().map(|n| rev_comp_c(*n)).collect();
+        assert_eq!(bases, vec!['T', 'G', 'C', 'A']);
+        for n in &NUCLEOTIDES {
+            assert_eq!(rev_comp_c(rev_comp_c(*n)), *n);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Can not complement 'N'")]
+    fn test_rev_comp_ambiguous() {
+        rev_comp("ANG".chars());
+    }
+
+    #[test]
+    fn test_reverse_complement_of() {
+        let signatures = Signatures::new(1, DEFAULT_MAX_CHANNELS).unwrap();
+        let forward = Signature::parse("ACG>T").unwrap();
+        let reverse = signatures.reverse_complement_of(&forward);
+        assert_eq!(reverse, Signature::new("CGT", 'G', 'A'));
+        assert_eq!(reverse.cosmic_label(), "C[G>A]T");
+        assert_eq!(signatures.reverse_complement_of(&reverse), forward);
+
+        for window in 0..3 {
+            let signatures = Signatures::new(window, DEFAULT_MAX_CHANNELS).unwrap();
+            for (s, i) in signatures.iter() {
+                let reverse = signatures.reverse_complement_of(s);
+                assert_eq!(signatures.reverse_complement_of(&reverse), *s);
+                assert_ne!(reverse.is_forward_signature(), s.is_forward_signature());
+                assert_eq!(signatures.index_of(&reverse), Some(i));
+            }
+        }
+    }
+
+    #[test]
+    fn test_collapsed_members() {
+        let signatures = Signatures::new(1, DEFAULT_MAX_CHANNELS).unwrap();
+        let forward = Signature::parse("ACG>T").unwrap();
+        let index = signatures.index_of(&forward).unwrap();
+        assert_eq!(
+            signatures.collapsed_members(index),
+            vec![forward.clone(), Signature::new("CGT", 'G', 'A')]
+        );
+        assert!(signatures
+            .collapsed_members(signatures.channel_count())
+            .is_empty());
+        for i in 0..signatures.channel_count() {
+            let members = signatures.collapsed_members(i);
+            assert_eq!(members.len(), 2);
+            assert_eq!(members[0], signatures.reverse_complement_of(&members[1]));
+        }
+
+        // Each signature is a channel of its own
+        let uncollapsed = Signatures::uncollapsed(1);
+        let index = uncollapsed.index_of(&forward).unwrap();
+        assert_eq!(uncollapsed.collapsed_members(index), vec![forward.clone()]);
+
+        // The members of a channel definition include their reverse complements
+        let definition = vec![(
+            "CpG".to_owned(),
+            vec![forward.clone(), Signature::parse("CCG>T").unwrap()],
+        )];
+        let defined = Signatures::from_definition(1, &definition, true).unwrap();
+        assert_eq!(defined.collapsed_members(0).len(), 4);
+        assert_eq!(defined.collapsed_members(1).len(), 192 - 4);
+    }
+
+    #[test]
+    fn test_encode() {
+        // The marker bit, A, C, G and T
+        let acg_t = (1 << 8) | (0b01 << 4) | (0b10 << 2) | 0b11;
+        assert_eq!(encode(b"ACG", b'T'), Some(acg_t));
+        assert_eq!(encode(b"C", b'A'), Some((1 << 4) | (0b01 << 2)));
+        assert_eq!(Signature::parse("ACG>T").unwrap().code(), Some(acg_t));
+        // Codons of different lengths do not collide
+        assert_ne!(encode(b"AAC", b'A'), encode(b"C", b'A'));
+        assert_eq!(encode(b"ANG", b'T'), None);
+        assert_eq!(encode(b"acg", b'T'), None);
+        assert_eq!(encode(b"ACG", b'N'), None);
+        assert_eq!(encode(&[b'A'; 40], b'T'), None);
+    }
+
+    #[test]
+    fn test_index_of_bases() {
+        // Every codon and alternative base, including the unchanged ones
+        let codons = |length: usize| -> Vec<String> {
+            (0..4usize.pow(length as u32))
+                .map(|n| {
+                    (0..length)
+                        .map(|i| NUCLEOTIDES[(n >> (2 * i)) % 4])
+                        .collect()
+                })
+                .collect()
+        };
+        for window in 0..3 {
+            let definition = vec![(
+                "C>T".to_owned(),
+                build_signatures(window)
+                    .into_keys()
+                    .filter(|s| s.is_forward_signature() && s.alternative == 'T')
+                    .collect(),
+            )];
+            let all = [
+                Signatures::new(window, DEFAULT_MAX_CHANNELS).unwrap(),
+                Signatures::with_partial_context(window),
+                Signatures::uncollapsed(window),
+                Signatures::from_definition(window, &definition, true).unwrap(),
+            ];
+            for signatures in &all {
+                let mut found = 0;
+                let mut lengths = vec![1, 2 * window + 1];
+                lengths.dedup();
+                for length in lengths {
+                    for codon in codons(length) {
+                        let reference = codon.as_bytes()[length / 2] as char;
+                        for alternative in NUCLEOTIDES {
+                            let signature = Signature::new(&codon, reference, alternative);
+                            let index = signatures.index_of(&signature);
+                            assert_eq!(
+                                signatures.index_of_bases(codon.as_bytes(), alternative as u8),
+                                index,
+                                "{:?}",
+                                signature
+                            );
+                            found += usize::from(index.is_some());
+                        }
+                    }
+                }
+                assert_eq!(found, signatures.len());
+                assert_eq!(signatures.index_of_bases(b"ANA", b'T'), None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_partial_context() {
+        let signatures = Signatures::with_partial_context(1);
+        let full = Signatures::new(1, DEFAULT_MAX_CHANNELS).unwrap();
+        assert_eq!(signatures.len(), full.len() + 12);
+        let c_a = signatures.index_of(&Signature::new("C", 'C', 'A')).unwrap();
+        assert_eq!(c_a, 96);
+        assert_eq!(
+            signatures.index_of(&Signature::new("G", 'G', 'T')),
+            Some(c_a)
+        );
+        let forwards: Vec<Signature> = signatures
+            .signatures()
+            .into_iter()
+            .filter(|s| s.is_forward_signature())
+            .collect();
+        assert_eq!(forwards.len(), 102);
+        assert_eq!(forwards[96], Signature::new("C", 'C', 'A'));
+        // Without a window, there are no extra channels
+        assert_eq!(
+            Signatures::with_partial_context(0).len(),
+            Signatures::new(0, DEFAULT_MAX_CHANNELS).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_cosmic_order() {
+        let signatures = Signatures::new(2, DEFAULT_MAX_CHANNELS).unwrap();
+        assert!(signatures.validate(2).is_ok());
+        let mut forwards: Vec<Signature> = signatures
+            .signatures()
+            .into_iter()
+            .filter(|s| s.is_forward_signature())
+            .collect();
+        forwards.sort_by(|a, b| a.cosmic_cmp(b));
+        let labels: Vec<String> = forwards.iter().map(|s| s.cosmic_label()).collect();
+        assert_eq!(labels.len(), 1536);
+        assert_eq!(
+            &labels[..10],
+            &[
+                "AA[C>A]AA",
+                "AA[C>A]AC",
+                "AA[C>A]AG",
+                "AA[C>A]AT",
+                "AA[C>A]CA",
+                "AA[C>A]CC",
+                "AA[C>A]CG",
+                "AA[C>A]CT",
+                "AA[C>A]GA",
+                "AA[C>A]GC",
+            ]
+        );
+        assert_eq!(
+            &labels[1526..],
+            &[
+                "TT[T>G]CG",
+                "TT[T>G]CT",
+                "TT[T>G]GA",
+                "TT[T>G]GC",
+                "TT[T>G]GG",
+                "TT[T>G]GT",
+                "TT[T>G]TA",
+                "TT[T>G]TC",
+                "TT[T>G]TG",
+                "TT[T>G]TT",
+            ]
+        );
+        assert_eq!(labels[256], "AA[C>G]AA");
+    }
+
+    #[test]
+    fn test_cosmic_label() {
+        assert_eq!(Signature::new("ACG", 'C', 'T').cosmic_label(), "A[C>T]G");
+        assert_eq!(Signature::new("C", 'C', 'T').cosmic_label(), "C>T");
+        // The SBS96 order starts with the C>A substitutions
+        let mut forwards: Vec<Signature> = Signatures::with_partial_context(1)
+            .signatures()
+            .into_iter()
+            .filter(|s| s.is_forward_signature())
+            .collect();
+        forwards.sort_by(|a, b| a.cosmic_cmp(b));
+        assert_eq!(forwards[0].cosmic_label(), "A[C>A]A");
+        assert_eq!(forwards[16].cosmic_label(), "A[C>G]A");
+        assert_eq!(forwards[95].cosmic_label(), "T[T>G]T");
+        // The channels without context are last
+        assert_eq!(forwards[96].cosmic_label(), "C>A");
+    }
+
+    #[test]
+    fn test_parse_signature() {
+        let s = Signature::parse("ACG>T").unwrap();
+        assert_eq!(s, Signature::new("ACG", 'C', 'T'));
+        assert_eq!(Signature::parse("A[C>T]G").unwrap(), s);
+        assert_eq!(
+            Signature::parse("C>T").unwrap(),
+            Signature::new("C", 'C', 'T')
+        );
+        assert!(matches!(
+            Signature::parse("AC>T"),
+            Err(Error::InvalidSignature { label, .. }) if label == "AC>T"
+        ));
+        assert!(Signature::parse("A[C-T]G").is_err());
+        assert!(Signature::parse("ACG").is_err());
+        assert_eq!(
+            "T[T>G]T".parse::<Signature>().unwrap(),
+            Signature::new("TTT", 'T', 'G')
+        );
+
+        let reason = |label: &str| match label.parse::<Signature>() {
+            Err(Error::InvalidSignature { reason, .. }) => reason,
+            other => panic!("Expected an invalid signature, got {:?}", other),
+        };
+        assert!(reason("AC[C>T]G").contains("both sides"));
+        assert!(reason("ANG>T").contains("'N'"));
+        assert!(reason("acg>t").contains("'a'"));
+        assert!(reason("A[C>U]G").contains("'U'"));
+        assert!(reason("ACG>C").contains("different"));
+        assert!(reason("A[C>C]G").contains("different"));
+        assert!(reason(">T").contains("odd"));
+        assert_eq!(
+            "ANG>T".parse::<Signature>().unwrap_err().to_string(),
+            "Can not parse signature 'ANG>T', 'N' is not one of the bases A, C, G and T"
+        );
+    }
+
+    #[test]
+    fn test_reference_identity() {
+        let c_t = Signature::from_codon("ACG", 'T');
+        assert_eq!(c_t, Signature::new("ACG", 'C', 'T'));
+        assert_eq!(format!("{:?}", c_t), "ACG:C>T");
+        // Without context, the codon is the reference base
+        let c_t = Signature::from_codon("C", 'T');
+        assert_eq!(c_t.to_string(), "C>T");
+        assert_eq!(c_t.cosmic_label(), "C>T");
+        assert_eq!("C>T".parse::<Signature>().unwrap(), c_t);
+        assert_ne!(c_t, Signature::from_codon("G", 'A'));
+        assert!(Signature::from_codon("C", 'A') < c_t);
+        for window in 0..2 {
+            let signatures = build_signatures(window);
+            for (s, i) in &signatures {
+                assert_eq!(s.codon.chars().nth(window), Some(s.reference), "{:?}", s);
+                assert_eq!(signatures[&s.reverse_complement()], *i, "{:?}", s);
+            }
+            assert_eq!(signatures.len(), 12 * 16usize.pow(window as u32));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Can not create signature of codon 'ACG' with reference base A")]
+    fn test_inconsistent_reference() {
+        Signature::new("ACG", 'A', 'T');
+    }
+
+    #[test]
+    #[should_panic(expected = "Can not create signature of codon 'GA'")]
+    fn test_even_codon() {
+        Signature::from_codon("GA", 'T');
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        for window in 0..3 {
+            let signatures = Signatures::new(window, DEFAULT_MAX_CHANNELS).unwrap();
+            let mut n = 0;
+            for (s, _) in signatures.iter() {
+                assert_eq!(s.to_string().parse::<Signature>().unwrap(), *s);
+                assert_eq!(s.cosmic_label().parse::<Signature>().unwrap(), *s);
+                n += 1;
+            }
+            // Both strands of the 6 substitutions in all contexts
+            assert_eq!(n, 12 * 16usize.pow(window as u32));
+        }
+    }
+
+    #[test]
+    fn test_parse_definition() {
+        let text = "# CpG\nCpG\tACG>T, CCG>T,GCG>T,T[C>T]G\n\nC>T\tC>T\n";
+        let definition = parse_definition(text.as_bytes(), "channels.tsv").unwrap();
+        assert_eq!(definition.len(), 2);
+        assert_eq!(definition[0].0, "CpG");
+        assert_eq!(definition[0].1.len(), 4);
+        assert_eq!(definition[1].1, vec![Signature::new("C", 'C', 'T')]);
+        let e = parse_definition("CpG\n".as_bytes(), "channels.tsv").unwrap_err();
+        assert_eq!(e.to_string(), "Missing members in line 1 of 'channels.tsv'");
+        assert!(matches!(
+            parse_definition("CpG\tAC>T".as_bytes(), "channels.tsv"),
+            Err(Error::ChannelDefinition(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_definition() {
+        let member = |l: &str| Signature::parse(l).unwrap();
+        let definition = vec![
+            ("C>T".to_owned(), vec![member("C>T")]),
+            (
+                "T>N".to_owned(),
+                vec![member("T>A"), member("T>C"), member("T>G")],
+            ),
+        ];
+        // The C>A and C>G substitutions are not assigned
+        assert!(Signatures::from_definition(0, &definition, false).is_err());
+        let signatures = Signatures::from_definition(0, &definition, true).unwrap();
+        assert_eq!(
+            signatures.labels().unwrap(),
+            &["C>T".to_owned(), "T>N".to_owned(), OTHER_CHANNEL.to_owned()]
+        );
+        assert_eq!(signatures.channel_count(), 3);
+        // The reverse complements share the channel
+        assert_eq!(signatures.index_of(&member("G>A")), Some(0));
+        assert_eq!(signatures.index_of(&member("A>G")), Some(1));
+        assert_eq!(signatures.index_of(&member("C>A")), Some(2));
+        assert_eq!(signatures.index_of(&member("G>C")), Some(2));
+
+        // Members may only be assigned once, also by their reverse complement
+        let duplicate = vec![
+            ("C>T".to_owned(), vec![member("C>T")]),
+            ("G>A".to_owned(), vec![member("G>A")]),
+        ];
+        let e = Signatures::from_definition(0, &duplicate, true)
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("already assigned to channel 'C>T'"), "{}", e);
+        // Members must match the window
+        assert!(Signatures::from_definition(1, &definition, true).is_err());
+    }
+
+    #[test]
+    fn test_orientation() {
+        let signatures = Signatures::new(1, DEFAULT_MAX_CHANNELS).unwrap();
+        for s in signatures.signatures() {
+            let reverse = s.reverse_complement();
+            assert_ne!(
+                s.has_orientation(Orientation::Pyrimidine),
+                s.has_orientation(Orientation::Purine)
+            );
+            assert_eq!(
+                s.has_orientation(Orientation::Purine),
+                reverse.has_orientation(Orientation::Pyrimidine)
+            );
+            assert_eq!(signatures.index_of(&s), signatures.index_of(&reverse));
+        }
+    }
+
+    #[test]
+    fn test_is_cpg() {
+        assert_eq!(Signature::new("ACG", 'C', 'T').is_cpg(), Some(true));
+        assert_eq!(Signature::new("CGT", 'G', 'A').is_cpg(), Some(true));
+        assert_eq!(Signature::new("ACA", 'C', 'T').is_cpg(), Some(false));
+        assert_eq!(Signature::new("ATG", 'T', 'C').is_cpg(), Some(false));
+        assert_eq!(Signature::new("C", 'C', 'T').is_cpg(), None);
+        assert_eq!(Signature::new("T", 'T', 'C').is_cpg(), Some(false));
+    }
+
+    #[test]
+    fn test_uncollapsed() {
+        let signatures = Signatures::uncollapsed(1);
+        assert_eq!(signatures.len(), 192);
+        assert_eq!(signatures.channel_count(), 192);
+        let c_t = signatures.index_of(&Signature::new("ACG", 'C', 'T'));
+        let g_a = signatures.index_of(&Signature::new("CGT", 'G', 'A'));
+        assert!(c_t.is_some() && g_a.is_some());
+        assert_ne!(c_t, g_a);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let signature = Signature::parse("ACG>T").unwrap();
+        let json = serde_json::to_string(&signature).unwrap();
+        assert_eq!(json, "\"ACG>T\"");
+        let restored: Signature = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.cosmic_label(), "A[C>T]G");
+        assert!(serde_json::from_str::<Signature>("\"AC>T\"").is_err());
+
+        let definition =
+            parse_definition("C>T\tC>T\nother\tC>A,C>G,T>A,T>C,T>G\n".as_bytes(), "test").unwrap();
+        for signatures in [
+            Signatures::new(1, DEFAULT_MAX_CHANNELS).unwrap(),
+            Signatures::from_definition(0, &definition, false).unwrap(),
+        ] {
+            let json = serde_json::to_string(&signatures).unwrap();
+            assert_eq!(
+                serde_json::from_str::<Signatures>(&json).unwrap(),
+                signatures
+            );
+            let bytes = bincode::serialize(&signatures).unwrap();
+            assert_eq!(
+                bincode::deserialize::<Signatures>(&bytes).unwrap(),
+                signatures
+            );
+        }
+
+        let error =
+            serde_json::from_str::<Signatures>(r#"{"channels":{"C>A":0,"G>T":2},"labels":null}"#)
+                .unwrap_err();
+        assert!(
+            error.to_string().contains("channel 1 has no signature"),
+            "{}",
+            error
+        );
+        let error =
+            serde_json::from_str::<Signatures>(r#"{"channels":{"C>A":0},"labels":["a","b"]}"#)
+                .unwrap_err();
+        assert!(
+            error.to_string().contains("2 labels for 1 channels"),
+            "{}",
+            error
+        );
+    }
+
     #[test]
     fn test_rev_comp1() {
         assert_eq!(rev_comp("TGA".chars()), "TCA");
         assert_eq!(rev_comp("AGA".chars()), "TCT");
     }
+
+    #[test]
+    fn test_window_guard() {
+        assert_eq!(channel_count(0), Some(6));
+        assert_eq!(channel_count(1), Some(96));
+        assert_eq!(channel_count(255), None);
+        assert_eq!(
+            Signatures::new(3, DEFAULT_MAX_CHANNELS).unwrap().len(),
+            2 * 24576
+        );
+        assert!(Signatures::new(8, DEFAULT_MAX_CHANNELS).is_err());
+        assert!(Signatures::new(255, DEFAULT_MAX_CHANNELS).is_err());
+        assert!(matches!(
+            Signatures::new(1, 95),
+            Err(Error::InvalidWindow { window: 1, .. })
+        ));
+        assert!(check_window(3, DEFAULT_MAX_CHANNELS).is_ok());
+    }
 }
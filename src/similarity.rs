@@ -0,0 +1,356 @@
+use std::io::Write;
+
+/// The value reported for undefined similarities, e.g., of profiles without any mutation.
+pub const NA: &str = "NA";
+
+/// Return the cosine similarity of the profiles `a` and `b`, or `None` if any of them is
+/// the zero vector.
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> Option<f64> {
+    cosine(a.iter().copied(), b.iter().copied())
+}
+
+/// Return the cosine similarity of the profiles given by the values of `a` and `b`.
+fn cosine<A, B>(a: A, b: B) -> Option<f64>
+where
+    A: Iterator<Item = f64> + Clone,
+    B: Iterator<Item = f64> + Clone,
+{
+    let dot: f64 = a.clone().zip(b.clone()).map(|(a, b)| a * b).sum();
+    let norm_a = a.map(|v| v * v).sum::<f64>().sqrt();
+    let norm_b = b.map(|v| v * v).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return None;
+    }
+    Some(dot / (norm_a * norm_b))
+}
+
+/// Return the pairwise cosine similarities of the `profiles`.
+pub fn similarity_matrix(profiles: &[Vec<f64>]) -> Vec<Vec<Option<f64>>> {
+    profiles
+        .iter()
+        .map(|a| profiles.iter().map(|b| cosine_similarity(a, b)).collect())
+        .collect()
+}
+
+/// Return the Jensen-Shannon divergence (base 2) of the fractions of the profiles `a` and `b`,
+/// which is between zero for identical and one for disjoint spectra, or `None` if any of them
+/// is the zero vector.
+pub fn jensen_shannon_divergence(a: &[f64], b: &[f64]) -> Option<f64> {
+    divergence(a.iter().copied(), b.iter().copied())
+}
+
+/// Return the Jensen-Shannon divergence of the profiles given by the values of `a` and `b`.
+fn divergence<A, B>(a: A, b: B) -> Option<f64>
+where
+    A: Iterator<Item = f64> + Clone,
+    B: Iterator<Item = f64> + Clone,
+{
+    let (total_a, total_b) = (a.clone().sum::<f64>(), b.clone().sum::<f64>());
+    if total_a <= 0.0 || total_b <= 0.0 {
+        return None;
+    }
+    // The Kullback-Leibler divergence of p from the mixture m, where 0·log(0) = 0
+    let kl = |p: f64, m: f64| if p > 0.0 { p * (p / m).log2() } else { 0.0 };
+    let divergence: f64 = a
+        .zip(b)
+        .map(|(a, b)| {
+            let (p, q) = (a / total_a, b / total_b);
+            let m = (p + q) / 2.0;
+            (kl(p, m) + kl(q, m)) / 2.0
+        })
+        .sum();
+    // Round off the summation errors beyond the bounds
+    Some(divergence.clamp(0.0, 1.0))
+}
+
+/// Return the pairwise cosine similarities of the columns of `counts`, e.g., of the samples
+/// of the view of a `ResultMatrix`, without copying them. Requires the cargo feature `ndarray`.
+#[cfg(feature = "ndarray")]
+pub fn column_similarity_matrix<T>(counts: ndarray::ArrayView2<'_, T>) -> Vec<Vec<Option<f64>>>
+where
+    T: crate::result::AsF64,
+{
+    pairwise_columns(counts, |a, b| {
+        cosine(a.iter().map(|v| v.as_f64()), b.iter().map(|v| v.as_f64()))
+    })
+}
+
+/// Return the pairwise Jensen-Shannon divergences of the columns of `counts` without copying
+/// them. Requires the cargo feature `ndarray`.
+#[cfg(feature = "ndarray")]
+pub fn column_jsd_matrix<T>(counts: ndarray::ArrayView2<'_, T>) -> Vec<Vec<Option<f64>>>
+where
+    T: crate::result::AsF64,
+{
+    pairwise_columns(counts, |a, b| {
+        divergence(a.iter().map(|v| v.as_f64()), b.iter().map(|v| v.as_f64()))
+    })
+}
+
+#[cfg(feature = "ndarray")]
+fn pairwise_columns<T, F>(counts: ndarray::ArrayView2<'_, T>, measure: F) -> Vec<Vec<Option<f64>>>
+where
+    F: Fn(ndarray::ArrayView1<'_, T>, ndarray::ArrayView1<'_, T>) -> Option<f64>,
+{
+    counts
+        .columns()
+        .into_iter()
+        .map(|a| {
+            counts
+                .columns()
+                .into_iter()
+                .map(|b| measure(a, b))
+                .collect()
+        })
+        .collect()
+}
+
+/// Return the pairwise Jensen-Shannon divergences of the `profiles`.
+pub fn jsd_matrix(profiles: &[Vec<f64>]) -> Vec<Vec<Option<f64>>> {
+    profiles
+        .iter()
+        .map(|a| {
+            profiles
+                .iter()
+                .map(|b| jensen_shannon_divergence(a, b))
+                .collect()
+        })
+        .collect()
+}
+
+/// Write the pairwise cosine similarities of the `profiles` of the samples `names` to
+/// `writer` as a tab-separated table with the samples as rows and columns.
+pub fn write_similarity_matrix<W: Write>(
+    writer: W,
+    names: &[String],
+    profiles: &[Vec<f64>],
+) -> Result<(), String> {
+    write_pairwise(writer, names, similarity_matrix(profiles))
+        .map_err(|e| format!("Can not write sample similarities: {}", e))
+}
+
+/// Write the pairwise Jensen-Shannon divergences of the `profiles` of the samples `names` to
+/// `writer` as a tab-separated table with the samples as rows and columns.
+pub fn write_jsd_matrix<W: Write>(
+    writer: W,
+    names: &[String],
+    profiles: &[Vec<f64>],
+) -> Result<(), String> {
+    write_pairwise(writer, names, jsd_matrix(profiles))
+        .map_err(|e| format!("Can not write sample divergences: {}", e))
+}
+
+/// Write the pairwise `values` of the samples `names` to `writer`, with `NA` for undefined ones.
+fn write_pairwise<W: Write>(
+    mut writer: W,
+    names: &[String],
+    values: Vec<Vec<Option<f64>>>,
+) -> std::io::Result<()> {
+    writeln!(writer, "Sample\t{}", names.join("\t"))?;
+    for (name, row) in names.iter().zip(values) {
+        let row: Vec<String> = row
+            .iter()
+            .map(|v| match v {
+                // Round off the summation errors
+                Some(v) => ((v * 1e6).round() / 1e6).to_string(),
+                None => NA.to_owned(),
+            })
+            .collect();
+        writeln!(writer, "{}\t{}", name, row.join("\t"))?;
+    }
+    writer.flush()
+}
+
+/// Return the indizes and cosine similarities of the `k` signatures most similar to the
+/// `profile`, with ties in the order of the `signatures`. Signatures without any channel
+/// are never similar, and a profile without any mutation matches no signature.
+pub fn top_matches(profile: &[f64], signatures: &[Vec<f64>], k: usize) -> Vec<(usize, f64)> {
+    let mut matches: Vec<(usize, f64)> = signatures
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| cosine_similarity(profile, s).map(|v| (i, v)))
+        .collect();
+    // The stable sort keeps ties in the order of the signatures
+    matches.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    matches.truncate(k);
+    matches
+}
+
+/// Write the `k` signatures most similar to the `profiles` of the samples `names` to `writer`
+/// as a tab-separated table of sample, rank, signature and cosine similarity. Samples without
+/// any match are reported once with `NA` as signature and similarity.
+pub fn write_matches<W: Write>(
+    mut writer: W,
+    names: &[String],
+    profiles: &[Vec<f64>],
+    signature_names: &[String],
+    signatures: &[Vec<f64>],
+    k: usize,
+) -> Result<(), String> {
+    let write_error = |e: std::io::Error| format!("Can not write signature matches: {}", e);
+    writeln!(writer, "Sample\tRank\tSignature\tSimilarity").map_err(write_error)?;
+    for (name, profile) in names.iter().zip(profiles) {
+        let matches = top_matches(profile, signatures, k);
+        if matches.is_empty() {
+            writeln!(writer, "{}\t{}\t{}\t{}", name, NA, NA, NA).map_err(write_error)?;
+        }
+        for (rank, (i, v)) in matches.iter().enumerate() {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}",
+                name,
+                rank + 1,
+                signature_names[*i],
+                (v * 1e6).round() / 1e6
+            )
+            .map_err(write_error)?;
+        }
+    }
+    writer.flush().map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 2.0], &[2.0, 4.0]).unwrap() - 1.0).abs() < 1e-12);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 3.0]), Some(0.0));
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[0.0, 3.0]), None);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[0.0, 0.0]), None);
+    }
+
+    #[test]
+    fn test_write_similarity_matrix() {
+        let names = vec!["S1".to_owned(), "S2".to_owned(), "S3".to_owned()];
+        let profiles = vec![
+            vec![3.0, 1.0, 2.0],
+            vec![1.0, 1.0, 0.0],
+            vec![0.0, 0.0, 0.0],
+        ];
+        let mut out = Vec::new();
+        write_similarity_matrix(&mut out, &names, &profiles).unwrap();
+        let table: Vec<Vec<String>> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| l.split('\t').map(|f| f.to_owned()).collect())
+            .collect();
+        assert_eq!(table[0], vec!["Sample", "S1", "S2", "S3"]);
+        for i in 1..3 {
+            assert_eq!(table[i][0], names[i - 1]);
+            // Each sample is similar to itself and the table is symmetric
+            assert_eq!(table[i][i], "1");
+            for (j, row) in table.iter().enumerate().skip(1) {
+                assert_eq!(table[i][j], row[i]);
+            }
+        }
+        assert_eq!(table[1][2], "0.755929");
+        assert_eq!(table[3][1..], ["NA", "NA", "NA"]);
+    }
+
+    #[test]
+    fn test_jensen_shannon_divergence() {
+        // Identical distributions, independent of the total counts
+        assert_eq!(
+            jensen_shannon_divergence(&[1.0, 2.0], &[2.0, 4.0]),
+            Some(0.0)
+        );
+        // Disjoint single-channel distributions
+        assert_eq!(
+            jensen_shannon_divergence(&[5.0, 0.0], &[0.0, 3.0]),
+            Some(1.0)
+        );
+        // p = (1, 0), q = (1/2, 1/2), m = (3/4, 1/4): (log2(4/3) + (log2(2/3) + log2(2)) / 2) / 2
+        let expected = (2.0 * (4.0f64 / 3.0).log2() + (2.0f64 / 3.0).log2() + 1.0) / 4.0;
+        let jsd = jensen_shannon_divergence(&[1.0, 0.0], &[1.0, 1.0]).unwrap();
+        assert!((jsd - expected).abs() < 1e-12);
+        assert_eq!(jensen_shannon_divergence(&[0.0, 0.0], &[1.0, 1.0]), None);
+
+        let profiles = vec![
+            vec![3.0, 1.0, 0.0],
+            vec![0.0, 2.0, 5.0],
+            vec![1.0, 1.0, 1.0],
+        ];
+        let matrix = jsd_matrix(&profiles);
+        for (i, row) in matrix.iter().enumerate() {
+            assert_eq!(row[i], Some(0.0));
+            for (j, v) in row.iter().enumerate() {
+                assert_eq!(*v, matrix[j][i]);
+                assert!((0.0..=1.0).contains(&v.unwrap()));
+            }
+        }
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_column_matrices() {
+        let profiles = vec![
+            vec![3.0, 1.0, 0.0],
+            vec![0.0, 2.0, 5.0],
+            vec![1.0, 1.0, 1.0],
+        ];
+        // The profiles as columns, like the samples of a result matrix
+        let counts = ndarray::arr2(&[[3u32, 0, 1], [1, 2, 1], [0, 5, 1]]);
+        assert_eq!(
+            column_similarity_matrix(counts.view()),
+            similarity_matrix(&profiles)
+        );
+        assert_eq!(column_jsd_matrix(counts.view()), jsd_matrix(&profiles));
+    }
+
+    #[test]
+    fn test_write_jsd_matrix() {
+        let names = vec!["S1".to_owned(), "S2".to_owned(), "S3".to_owned()];
+        let profiles = vec![vec![2.0, 0.0], vec![0.0, 1.0], vec![0.0, 0.0]];
+        let mut out = Vec::new();
+        write_jsd_matrix(&mut out, &names, &profiles).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Sample\tS1\tS2\tS3\nS1\t0\t1\tNA\nS2\t1\t0\tNA\nS3\tNA\tNA\tNA\n"
+        );
+    }
+
+    #[test]
+    fn test_top_matches() {
+        let signatures = vec![
+            vec![0.7, 0.2, 0.1, 0.0],
+            vec![0.25, 0.25, 0.25, 0.25],
+            vec![0.1, 0.1, 0.1, 0.7],
+            vec![0.0, 0.0, 0.0, 0.0],
+            vec![0.1, 0.1, 0.1, 0.7],
+        ];
+        // A pure mixture of the first signature matches it first
+        let matches = top_matches(&[14.0, 4.0, 2.0, 0.0], &signatures, 3);
+        assert_eq!(matches.len(), 3);
+        assert_eq!(matches[0].0, 0);
+        assert!((matches[0].1 - 1.0).abs() < 1e-12);
+        assert_eq!(matches[1].0, 1);
+        // Ties are ranked in the order of the signatures
+        let matches = top_matches(&[1.0, 1.0, 1.0, 7.0], &signatures, 2);
+        assert_eq!(matches.iter().map(|m| m.0).collect::<Vec<_>>(), vec![2, 4]);
+        assert!(top_matches(&[0.0; 4], &signatures, 3).is_empty());
+    }
+
+    #[test]
+    fn test_write_matches() {
+        let names = vec!["S1".to_owned(), "S2".to_owned()];
+        let signature_names = vec!["SBS1".to_owned(), "SBS5".to_owned()];
+        let signatures = vec![vec![0.9, 0.1], vec![0.5, 0.5]];
+        let profiles = vec![vec![9.0, 1.0], vec![0.0, 0.0]];
+        let mut out = Vec::new();
+        write_matches(
+            &mut out,
+            &names,
+            &profiles,
+            &signature_names,
+            &signatures,
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Sample\tRank\tSignature\tSimilarity\nS1\t1\tSBS1\t1\nS2\tNA\tNA\tNA\n"
+        );
+    }
+}
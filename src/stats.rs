@@ -0,0 +1,426 @@
+use crate::downsample::SplitMix64;
+
+/// The coefficients of the Lanczos approximation of the gamma function with g = 7.
+const LANCZOS: [f64; 9] = [
+    0.999_999_999_999_809_9,
+    676.520_368_121_885_1,
+    -1_259.139_216_722_402_8,
+    771.323_428_777_653_1,
+    -176.615_029_162_140_6,
+    12.507_343_278_686_905,
+    -0.138_571_095_265_720_12,
+    9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7,
+];
+
+/// The relative precision of the iterative approximations.
+const EPSILON: f64 = 1e-15;
+
+/// The maximum number of iterations of the iterative approximations.
+const MAX_ITERATIONS: usize = 10_000;
+
+/// The number of factorials computed exactly, larger ones are approximated by Stirling's series.
+const EXACT_FACTORIALS: u64 = 256;
+
+/// Return the natural logarithm of `n!`.
+pub fn ln_factorial(n: u64) -> f64 {
+    if n < EXACT_FACTORIALS {
+        return (2..=n).map(|i| (i as f64).ln()).sum();
+    }
+    let n = n as f64;
+    n * n.ln() - n + 0.5 * (2.0 * std::f64::consts::PI * n).ln() + 1.0 / (12.0 * n)
+        - 1.0 / (360.0 * n.powi(3))
+        + 1.0 / (1260.0 * n.powi(5))
+}
+
+/// Return the natural logarithm of the binomial coefficient `n` choose `k`.
+pub fn ln_choose(n: u64, k: u64) -> f64 {
+    ln_factorial(n) - ln_factorial(k) - ln_factorial(n - k)
+}
+
+/// Return the natural logarithm of the gamma function at `x > 0`.
+pub fn ln_gamma(x: f64) -> f64 {
+    if x < 0.5 {
+        // Reflection formula
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = LANCZOS[1..]
+        .iter()
+        .enumerate()
+        .fold(LANCZOS[0], |s, (i, c)| s + c / (x + i as f64 + 1.0));
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// Return the regularized upper incomplete gamma function `Q(a, x)`, i.e., the probability
+/// that a gamma distributed variable with shape `a` exceeds `x`.
+pub fn gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let ln_prefix = -x + a * x.ln() - ln_gamma(a);
+    if x < a + 1.0 {
+        // The series of the lower incomplete gamma function
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for i in 1..MAX_ITERATIONS {
+            term *= x / (a + i as f64);
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        1.0 - sum * ln_prefix.exp()
+    } else {
+        // The continued fraction of the upper incomplete gamma function by Lentz's method
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..MAX_ITERATIONS {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+        ln_prefix.exp() * h
+    }
+}
+
+/// Return the probability that a chi-square distributed variable with `df` degrees of freedom
+/// exceeds `x`.
+pub fn chi_square_sf(x: f64, df: f64) -> f64 {
+    gamma_q(df / 2.0, x / 2.0)
+}
+
+/// Return the two-sided p-value of the standard normal statistic `z`.
+pub fn normal_test(z: f64) -> f64 {
+    // P(|Z| > z) = erfc(|z| / √2) = Q(1/2, z² / 2)
+    gamma_q(0.5, z * z / 2.0)
+}
+
+/// Return the two-sided p-value of the z-test of the proportions `x1 / n1` and `x2 / n2`
+/// being equal, with the variance of the pooled proportion.
+pub fn two_proportion_test(x1: u64, n1: u64, x2: u64, n2: u64) -> f64 {
+    let (p1, p2) = (x1 as f64 / n1 as f64, x2 as f64 / n2 as f64);
+    let pooled = (x1 + x2) as f64 / (n1 + n2) as f64;
+    let se = (pooled * (1.0 - pooled) * (1.0 / n1 as f64 + 1.0 / n2 as f64)).sqrt();
+    if se == 0.0 {
+        return 1.0;
+    }
+    normal_test((p1 - p2) / se)
+}
+
+/// Return the two-sided p-value of Fisher's exact test of the 2×2 table with the rows
+/// `[a, b]` and `[c, d]`, i.e., the probability of any table with the same margins that is
+/// at most as likely as the observed one.
+pub fn fisher_exact_test(a: u64, b: u64, c: u64, d: u64) -> f64 {
+    let (row, column, n) = (a + b, a + c, a + b + c + d);
+    let ln_p = |x: u64| ln_choose(row, x) + ln_choose(n - row, column - x) - ln_choose(n, column);
+    let observed = ln_p(a);
+    // Tolerate the rounding errors of equally likely tables
+    let threshold = observed + 1e-7;
+    let lowest = (row + column).saturating_sub(n);
+    let p: f64 = (lowest..=row.min(column))
+        .map(ln_p)
+        .filter(|p| *p <= threshold)
+        .map(f64::exp)
+        .sum();
+    p.min(1.0)
+}
+
+/// Return the two-sided p-value of observing `k` of `n` successes with a success probability
+/// of one half, i.e., the probability of any outcome at least as far from `n / 2` as `k`.
+pub fn binomial_test(k: u64, n: u64) -> f64 {
+    let tail = k.min(n - k);
+    if 2 * tail == n {
+        return 1.0;
+    }
+    // Sum the probabilities of the lower tail in log-space, relative to its largest term
+    let ln_half = (0.5f64).ln() * n as f64;
+    let terms: Vec<f64> = (0..=tail).map(|i| ln_choose(n, i) + ln_half).collect();
+    let max = terms.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let ln_tail = max + terms.iter().map(|t| (t - max).exp()).sum::<f64>().ln();
+    (2.0 * ln_tail.exp()).min(1.0)
+}
+
+/// The chi-square test of the homogeneity of the counts of several samples.
+#[derive(Debug)]
+pub struct Homogeneity {
+    /// Pearson's chi-square statistic
+    pub statistic: f64,
+    /// The degrees of freedom
+    pub df: u64,
+    /// The p-value of the chi-square approximation
+    pub p: f64,
+    /// The smallest expected count of the contingency table
+    pub min_expected: f64,
+}
+
+/// Return Pearson's chi-square statistic of the contingency table of the `counts` per sample
+/// and channel, given the totals per sample and channel, and the smallest expected count.
+fn chi_square_statistic(counts: &[Vec<u64>], samples: &[u64], channels: &[u64]) -> (f64, f64) {
+    let total: u64 = samples.iter().sum();
+    let mut statistic = 0.0;
+    let mut min_expected = f64::INFINITY;
+    for (row, sample) in counts.iter().zip(samples) {
+        for (count, channel) in row.iter().zip(channels) {
+            let expected = *sample as f64 * *channel as f64 / total as f64;
+            statistic += (*count as f64 - expected).powi(2) / expected;
+            min_expected = min_expected.min(expected);
+        }
+    }
+    (statistic, min_expected)
+}
+
+/// Drop the samples and channels without any count from the `counts` per sample and channel,
+/// and return the remaining counts with their totals per sample and channel.
+fn observed_table(counts: &[Vec<u64>]) -> (Vec<Vec<u64>>, Vec<u64>, Vec<u64>) {
+    let n_channels = counts.first().map_or(0, |c| c.len());
+    let used: Vec<usize> = (0..n_channels)
+        .filter(|c| counts.iter().any(|row| row[*c] > 0))
+        .collect();
+    let table: Vec<Vec<u64>> = counts
+        .iter()
+        .filter(|row| row.iter().any(|c| *c > 0))
+        .map(|row| used.iter().map(|c| row[*c]).collect())
+        .collect();
+    let samples = table.iter().map(|row| row.iter().sum()).collect();
+    let channels = (0..used.len())
+        .map(|c| table.iter().map(|row| row[c]).sum())
+        .collect();
+    (table, samples, channels)
+}
+
+/// Test the `counts` per sample and channel for being drawn from the same distribution by
+/// Pearson's chi-square test of homogeneity. Samples and channels without any count are
+/// ignored; at least two of each must remain.
+pub fn homogeneity_test(counts: &[Vec<u64>]) -> Result<Homogeneity, String> {
+    let (table, samples, channels) = observed_table(counts);
+    if samples.len() < 2 || channels.len() < 2 {
+        return Err(
+            "Can not test the homogeneity of fewer than two samples or channels with counts"
+                .to_owned(),
+        );
+    }
+    let (statistic, min_expected) = chi_square_statistic(&table, &samples, &channels);
+    let df = (samples.len() as u64 - 1) * (channels.len() as u64 - 1);
+    Ok(Homogeneity {
+        statistic,
+        df,
+        p: chi_square_sf(statistic, df as f64),
+        min_expected,
+    })
+}
+
+/// Return the Monte Carlo p-value of the chi-square `statistic` of the `counts` per sample and
+/// channel by `permutations` random tables with the same margins, i.e., by randomly assigning
+/// the mutations to the samples. The p-value counts the observed table as one of the random
+/// ones, so that it is never zero.
+pub fn permutation_test(
+    counts: &[Vec<u64>],
+    statistic: f64,
+    permutations: u64,
+    rng: &mut SplitMix64,
+) -> f64 {
+    let (table, samples, channels) = observed_table(counts);
+    let mut mutations: Vec<usize> = channels
+        .iter()
+        .enumerate()
+        .flat_map(|(c, n)| (0..*n).map(move |_| c))
+        .collect();
+    // Tolerate the rounding errors of equally extreme tables
+    let threshold = statistic * (1.0 - 1e-9);
+    let mut extreme = 0u64;
+    let mut random = vec![vec![0u64; channels.len()]; table.len()];
+    for _ in 0..permutations {
+        for i in (1..mutations.len()).rev() {
+            let j = rng.below(i as u64 + 1) as usize;
+            mutations.swap(i, j);
+        }
+        let mut assigned = mutations.iter();
+        for (row, n) in random.iter_mut().zip(&samples) {
+            row.iter_mut().for_each(|c| *c = 0);
+            for c in assigned.by_ref().take(*n as usize) {
+                row[*c] += 1;
+            }
+        }
+        if chi_square_statistic(&random, &samples, &channels).0 >= threshold {
+            extreme += 1;
+        }
+    }
+    (extreme + 1) as f64 / (permutations + 1) as f64
+}
+
+/// Return the q-values of the `p_values` by the procedure of Benjamini and Hochberg.
+pub fn benjamini_hochberg(p_values: &[f64]) -> Vec<f64> {
+    let m = p_values.len();
+    let mut order: Vec<usize> = (0..m).collect();
+    order.sort_by(|a, b| p_values[*b].partial_cmp(&p_values[*a]).unwrap());
+    // Starting from the largest p-value, each q-value is at most the one of the next rank
+    let mut q_values = vec![0.0; m];
+    let mut min = 1.0f64;
+    for (i, idx) in order.iter().enumerate() {
+        let rank = m - i;
+        min = min.min(p_values[*idx] * m as f64 / rank as f64);
+        q_values[*idx] = min;
+    }
+    q_values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9 * b.abs().max(1e-300), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_ln_factorial() {
+        assert_eq!(ln_factorial(0), 0.0);
+        assert_eq!(ln_factorial(1), 0.0);
+        assert_close(ln_factorial(5), 120f64.ln());
+        // Stirling's series agrees with the exact sum
+        let exact: f64 = (2..=1000).map(|i| (i as f64).ln()).sum();
+        assert_close(ln_factorial(1000), exact);
+        assert!(ln_factorial(1_000_000_000).is_finite());
+    }
+
+    #[test]
+    fn test_binomial_test() {
+        // The values of R's binom.test(k, n)
+        assert_close(binomial_test(0, 10), 0.001953125);
+        assert_close(binomial_test(10, 10), 0.001953125);
+        assert_close(binomial_test(2, 10), 0.109375);
+        assert_close(binomial_test(7, 10), 0.34375);
+        assert_close(binomial_test(5, 10), 1.0);
+        assert_close(binomial_test(3, 7), 1.0);
+        assert_close(binomial_test(0, 0), 1.0);
+        assert_close(binomial_test(1, 20), 42.0 / 1048576.0);
+        // Large counts neither overflow nor underflow to NaN
+        let p = binomial_test(40_000, 100_000);
+        assert!((0.0..1e-100).contains(&p));
+        assert_close(binomial_test(50_010, 100_000), 0.9520893498136247);
+    }
+
+    #[test]
+    fn test_ln_gamma() {
+        assert!(ln_gamma(1.0).abs() < 1e-12);
+        assert_close(ln_gamma(0.5), std::f64::consts::PI.sqrt().ln());
+        assert_close(ln_gamma(10.0), ln_factorial(9));
+        assert_close(ln_gamma(0.1), 9.513507698668732f64.ln());
+    }
+
+    #[test]
+    fn test_normal_test() {
+        // The values of R's 2 * pnorm(-z)
+        assert_close(normal_test(0.0), 1.0);
+        assert_close(normal_test(1.959963984540054), 0.05);
+        assert_close(normal_test(-1.0), 0.3173105078629141);
+        assert_close(normal_test(5.0), 5.733031437583878e-07);
+    }
+
+    #[test]
+    fn test_two_proportion_test() {
+        // z = (0.3 - 0.1) / √(0.2 · 0.8 · 0.02) = 2.5 · √2, i.e., p = erfc(2.5)
+        assert_close(two_proportion_test(30, 100, 10, 100), 4.06952017444959e-4);
+        assert_close(two_proportion_test(0, 10, 0, 10), 1.0);
+    }
+
+    #[test]
+    fn test_fisher_exact_test() {
+        // The values of R's fisher.test(matrix(c(a, c, b, d), 2))
+        assert_close(fisher_exact_test(3, 1, 1, 3), 0.4857142857142857);
+        assert_close(fisher_exact_test(10, 0, 0, 10), 1.082508822446903e-05);
+        assert_close(fisher_exact_test(1, 9, 11, 3), 0.002759456185220);
+        assert_close(fisher_exact_test(0, 5, 0, 5), 1.0);
+    }
+
+    #[test]
+    fn test_chi_square_sf() {
+        // The values of R's pchisq(x, df, lower.tail = FALSE)
+        assert_close(chi_square_sf(3.841458820694124, 1.0), 0.05);
+        assert_close(chi_square_sf(10.0, 4.0), 0.0404276819945128);
+        assert_close(chi_square_sf(0.0, 3.0), 1.0);
+    }
+
+    #[test]
+    fn test_homogeneity_test() {
+        // Identical spectra are homogeneous, also at different totals
+        let identical = vec![vec![10, 20, 30], vec![20, 40, 60]];
+        let test = homogeneity_test(&identical).unwrap();
+        assert!(test.statistic.abs() < 1e-9);
+        assert_eq!(test.df, 2);
+        assert_close(test.p, 1.0);
+
+        // The values of R's chisq.test(matrix(c(10, 20, 20, 10), 2), correct = FALSE)
+        let test = homogeneity_test(&[vec![10, 20], vec![20, 10]]).unwrap();
+        assert_close(test.statistic, 20.0 / 3.0);
+        assert_eq!(test.df, 1);
+        assert_close(test.p, 0.009823274507519245);
+        assert_close(test.min_expected, 15.0);
+
+        // Wildly different spectra, where channels without counts are ignored
+        let different = vec![vec![500, 0, 10, 0], vec![5, 0, 400, 0], vec![0, 0, 0, 0]];
+        let test = homogeneity_test(&different).unwrap();
+        assert_eq!(test.df, 1);
+        assert!(test.p < 1e-100);
+
+        assert!(homogeneity_test(&[vec![1, 2, 3]]).is_err());
+        assert!(homogeneity_test(&[vec![1, 0], vec![2, 0]]).is_err());
+    }
+
+    #[test]
+    fn test_permutation_test() {
+        let identical = vec![vec![10, 20, 30], vec![20, 40, 60]];
+        let statistic = homogeneity_test(&identical).unwrap().statistic;
+        let p = permutation_test(&identical, statistic, 99, &mut SplitMix64::new(1));
+        assert!(p > 0.9);
+
+        let different = vec![vec![50, 0, 10], vec![5, 0, 40]];
+        let statistic = homogeneity_test(&different).unwrap().statistic;
+        let p = permutation_test(&different, statistic, 99, &mut SplitMix64::new(1));
+        assert_close(p, 0.01);
+        // The p-value is reproducible for a seed
+        let mut rng = SplitMix64::new(7);
+        let small = vec![vec![3, 1], vec![1, 3]];
+        let statistic = homogeneity_test(&small).unwrap().statistic;
+        let p = permutation_test(&small, statistic, 200, &mut rng);
+        let mut rng = SplitMix64::new(7);
+        assert_eq!(permutation_test(&small, statistic, 200, &mut rng), p);
+    }
+
+    #[test]
+    fn test_benjamini_hochberg() {
+        let p = [0.01, 0.04, 0.03, 0.005, 0.5];
+        let q = benjamini_hochberg(&p);
+        // The values of R's p.adjust(p, "BH")
+        let expected = [0.025, 0.05, 0.05, 0.025, 0.5];
+        for (q, e) in q.iter().zip(&expected) {
+            assert_close(*q, *e);
+        }
+        // The q-values are ordered as the p-values
+        let mut order: Vec<usize> = (0..p.len()).collect();
+        order.sort_by(|a, b| p[*a].partial_cmp(&p[*b]).unwrap());
+        for w in order.windows(2) {
+            assert!(q[w[0]] <= q[w[1]]);
+        }
+        assert!(benjamini_hochberg(&[]).is_empty());
+    }
+}
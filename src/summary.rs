@@ -0,0 +1,384 @@
+use crate::annotation::TranscriptionStrand;
+use crate::error::Error;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+
+/// The reason why a record, an alternative allele or a genotype was not counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Reason {
+    /// Insertions, deletions and multi-nucleotide alleles
+    NonSnv,
+    /// Alleles that are no doublet base substitutions, if these are counted
+    NonDbs,
+    /// Alleles that are no simple insertions or deletions, if these are counted
+    NonIndel,
+    /// Symbolic alleles (`<NON_REF>`, `<DEL>`, ...) and breakends
+    Symbolic,
+    /// Spanning deletion alleles (`*`)
+    SpanningDeletion,
+    /// Records repeating an earlier record, if requested
+    Duplicate,
+    /// Records on contigs that are not selected
+    OtherContig,
+    /// Records on contigs missing from the reference, if requested
+    UnknownContig,
+    /// Malformed records without a contig, if requested
+    MissingContig,
+    /// Records outside of the included regions
+    OutsideRegions,
+    /// Records inside of the excluded regions
+    ExcludedRegions,
+    /// Records without any of the included IDs
+    IdNotIncluded,
+    /// Records with any of the excluded IDs
+    IdExcluded,
+    /// Records not passing the FILTER column
+    Filtered,
+    /// Records with a QUAL below the threshold
+    LowQual,
+    /// Records without QUAL if it is required
+    MissingQual,
+    /// Records not matching the INFO expression
+    InfoFilter,
+    /// Alternative alleles with a population allele frequency above the threshold
+    PopulationAf,
+    /// Alternative alleles without population allele frequency, if required
+    MissingPopulationAf,
+    /// Records not marked as somatic, if required
+    NotSomatic,
+    /// Records in soft-masked (lowercase) reference regions, if requested
+    Softmasked,
+    /// Records with an ambiguous base (e.g., `N`) in the reference window
+    AmbiguousContext,
+    /// Records whose reference window is cut off by the start or end of the contig
+    TruncatedContext,
+    /// Records whose reference allele differs from the reference
+    RefMismatch,
+    /// Substitutions without a channel, e.g., to a non-standard base
+    Unclassified,
+    /// Records with more alternative alleles than allowed
+    Multiallelic,
+    /// Records where a selected sample is not (fully) genotyped, if required
+    IncompleteGenotypes,
+    /// Records without genotypes (FORMAT/GT), which are all missing
+    NoGenotypes,
+    /// Genotypes with a missing allele, if requested
+    MissingGenotype,
+    /// Genotypes whose per-sample filter (FORMAT/FT) is not `PASS`, if requested
+    SampleFiltered,
+    /// Genotypes with a FORMAT/DP below the threshold or missing
+    LowDepth,
+    /// Genotypes with a FORMAT/GQ below the threshold (or missing, if requested)
+    LowGq,
+    /// Alleles of genotypes with a variant allele fraction (FORMAT/AD) below the threshold
+    LowVaf,
+    /// Alleles of genotypes with a variant allele fraction (FORMAT/AD) above the threshold
+    HighVaf,
+    /// Mutations of a sample closer than the minimum distance to the previous one
+    Clustered,
+    /// Alleles of genotypes with fewer supporting reads (FORMAT/AD) than the threshold
+    LowAltReads,
+    /// Alleles of genotypes whose FORMAT/AD is missing (or zero for the allele fraction), if required
+    MissingAd,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::NonSnv => write!(f, "NON_SNV"),
+            Reason::NonDbs => write!(f, "NON_DBS"),
+            Reason::NonIndel => write!(f, "NON_INDEL"),
+            Reason::Symbolic => write!(f, "SYMBOLIC"),
+            Reason::SpanningDeletion => write!(f, "SPANNING_DELETION"),
+            Reason::Duplicate => write!(f, "DUPLICATE"),
+            Reason::OtherContig => write!(f, "OTHER_CONTIG"),
+            Reason::UnknownContig => write!(f, "UNKNOWN_CONTIG"),
+            Reason::MissingContig => write!(f, "MISSING_CONTIG"),
+            Reason::OutsideRegions => write!(f, "OUTSIDE_REGIONS"),
+            Reason::ExcludedRegions => write!(f, "EXCLUDED_REGIONS"),
+            Reason::IdNotIncluded => write!(f, "ID_NOT_INCLUDED"),
+            Reason::IdExcluded => write!(f, "ID_EXCLUDED"),
+            Reason::Filtered => write!(f, "FILTERED"),
+            Reason::LowQual => write!(f, "LOW_QUAL"),
+            Reason::MissingQual => write!(f, "MISSING_QUAL"),
+            Reason::InfoFilter => write!(f, "INFO_FILTER"),
+            Reason::PopulationAf => write!(f, "POP_AF"),
+            Reason::MissingPopulationAf => write!(f, "MISSING_POP_AF"),
+            Reason::NotSomatic => write!(f, "NOT_SOMATIC"),
+            Reason::Softmasked => write!(f, "SOFTMASKED"),
+            Reason::AmbiguousContext => write!(f, "AMBIGUOUS_CONTEXT"),
+            Reason::TruncatedContext => write!(f, "TRUNCATED_CONTEXT"),
+            Reason::RefMismatch => write!(f, "REF_MISMATCH"),
+            Reason::Unclassified => write!(f, "UNCLASSIFIED"),
+            Reason::Multiallelic => write!(f, "MULTIALLELIC"),
+            Reason::IncompleteGenotypes => write!(f, "INCOMPLETE_GENOTYPES"),
+            Reason::NoGenotypes => write!(f, "NO_GENOTYPES"),
+            Reason::MissingGenotype => write!(f, "MISSING_GENOTYPE"),
+            Reason::SampleFiltered => write!(f, "SAMPLE_FILTERED"),
+            Reason::LowDepth => write!(f, "LOW_DP"),
+            Reason::LowGq => write!(f, "LOW_GQ"),
+            Reason::LowVaf => write!(f, "LOW_VAF"),
+            Reason::HighVaf => write!(f, "HIGH_VAF"),
+            Reason::Clustered => write!(f, "CLUSTERED"),
+            Reason::LowAltReads => write!(f, "LOW_ALT_READS"),
+            Reason::MissingAd => write!(f, "MISSING_AD"),
+        }
+    }
+}
+
+/// Counters summarizing what happened to the records of a run.
+#[derive(Default)]
+pub struct Summary {
+    records: u64,
+    ignored_records: u64,
+    partial_contexts: u64,
+    skipped: BTreeMap<Reason, u64>,
+    masked: BTreeMap<Reason, u64>,
+    strands: BTreeMap<TranscriptionStrand, u64>,
+    downsampling: Vec<(u64, u64)>,
+    overflowed: bool,
+    skipped_log: Option<Box<dyn Write + Send>>,
+    /// The skipped records of a buffered summary, until merged into one with a log
+    skipped_lines: Option<String>,
+}
+
+impl Summary {
+    /// Create a new summary with all counters set to zero.
+    pub fn new() -> Self {
+        Summary::default()
+    }
+
+    /// Create a summary for the records classified apart from the others, e.g., by a worker
+    /// thread, which keeps their skipped records for `merge()` if `keep_skipped`.
+    pub(crate) fn buffered(keep_skipped: bool) -> Self {
+        Summary {
+            skipped_lines: keep_skipped.then(String::new),
+            ..Summary::default()
+        }
+    }
+
+    /// Return whether skipped records are logged or kept.
+    pub(crate) fn logs_skipped(&self) -> bool {
+        self.skipped_log.is_some() || self.skipped_lines.is_some()
+    }
+
+    /// Add the counters of `other` to this summary and log its skipped records after the
+    /// ones logged so far.
+    pub(crate) fn merge(&mut self, other: Summary) -> Result<(), Error> {
+        self.records += other.records;
+        self.ignored_records += other.ignored_records;
+        self.partial_contexts += other.partial_contexts;
+        for (reason, count) in other.skipped {
+            *self.skipped.entry(reason).or_insert(0) += count;
+        }
+        for (reason, count) in other.masked {
+            *self.masked.entry(reason).or_insert(0) += count;
+        }
+        for (strand, count) in other.strands {
+            *self.strands.entry(strand).or_insert(0) += count;
+        }
+        self.overflowed |= other.overflowed;
+        if let Some(lines) = other.skipped_lines {
+            self.write_skipped(&lines)?;
+        }
+        Ok(())
+    }
+
+    /// Count one more record read from the input.
+    pub fn record(&mut self) {
+        self.records += 1;
+    }
+
+    /// Count one more record of which no mutation was counted, whatever the reason.
+    pub fn ignore_record(&mut self) {
+        self.ignored_records += 1;
+    }
+
+    /// Count one more substitution that was counted without its context, as the context
+    /// contains an ambiguous base.
+    pub fn partial_context(&mut self) {
+        self.partial_contexts += 1;
+    }
+
+    /// Count one more record or allele that was skipped for `reason`.
+    pub fn skip(&mut self, reason: Reason) {
+        *self.skipped.entry(reason).or_insert(0) += 1;
+    }
+
+    /// Count `count` more genotypes of samples at a site that were masked for `reason`.
+    pub fn mask(&mut self, reason: Reason, count: u64) {
+        *self.masked.entry(reason).or_insert(0) += count;
+    }
+
+    /// Write all records skipped via `skip_record()` to `writer` as tab-separated lines
+    /// of contig, 1-based position, reference allele, alternative alleles and reason.
+    pub fn log_skipped_to(&mut self, mut writer: Box<dyn Write + Send>) -> Result<(), Error> {
+        if let Err(e) = writeln!(writer, "#CHROM\tPOS\tREF\tALT\tREASON") {
+            return Err(Error::Io {
+                action: "write skipped records".to_owned(),
+                source: e,
+            });
+        }
+        self.skipped_log = Some(writer);
+        Ok(())
+    }
+
+    /// Count one more record at the 0-based `position` on `contig` that was skipped for
+    /// `reason`, and write it to the skipped records log if there is one.
+    pub fn skip_record(
+        &mut self,
+        reason: Reason,
+        contig: &str,
+        position: i64,
+        alleles: &[&[u8]],
+    ) -> Result<(), Error> {
+        self.skip(reason);
+        if !self.logs_skipped() {
+            return Ok(());
+        }
+        let alternatives: Vec<String> = alleles
+            .iter()
+            .skip(1)
+            .map(|a| String::from_utf8_lossy(a).into_owned())
+            .collect();
+        self.write_skipped(&format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            contig,
+            position + 1,
+            String::from_utf8_lossy(alleles.first().cloned().unwrap_or(b".")),
+            if alternatives.is_empty() {
+                ".".to_owned()
+            } else {
+                alternatives.join(",")
+            },
+            reason
+        ))
+    }
+
+    /// Write `lines` of skipped records to the log, or keep them if the summary is buffered.
+    fn write_skipped(&mut self, lines: &str) -> Result<(), Error> {
+        if let Some(writer) = &mut self.skipped_log {
+            if let Err(e) = writer.write_all(lines.as_bytes()) {
+                return Err(Error::Io {
+                    action: "write skipped records".to_owned(),
+                    source: e,
+                });
+            }
+        } else if let Some(buffer) = &mut self.skipped_lines {
+            buffer.push_str(lines);
+        }
+        Ok(())
+    }
+
+    /// Flush the skipped records log.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if let Some(writer) = &mut self.skipped_log {
+            if let Err(e) = writer.flush() {
+                return Err(Error::Io {
+                    action: "write skipped records".to_owned(),
+                    source: e,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Count one more classified record located on a `strand`.
+    pub fn annotate(&mut self, strand: TranscriptionStrand) {
+        *self.strands.entry(strand).or_insert(0) += 1;
+    }
+
+    /// Record the number of mutations per sample before and after downsampling.
+    pub fn downsampled(&mut self, totals: Vec<(u64, u64)>) {
+        self.downsampling = totals;
+    }
+
+    /// Return the number of mutations per sample before and after downsampling, if the
+    /// mutations were downsampled.
+    pub fn downsampling(&self) -> &[(u64, u64)] {
+        &self.downsampling
+    }
+
+    /// Record that a count of the results overflowed and was saturated at the largest count.
+    pub fn overflow(&mut self) {
+        self.overflowed = true;
+    }
+
+    /// Check if a count of the results overflowed, such that the counts are lower bounds.
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Return the number of records read from the input.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Return the number of records of which no mutation was counted. Unlike the counters of
+    /// `skipped()`, which also count single alleles, this is the fraction of records lost.
+    pub fn ignored_records(&self) -> u64 {
+        self.ignored_records
+    }
+
+    /// Return the number of substitutions that were counted without their context.
+    pub fn partial_contexts(&self) -> u64 {
+        self.partial_contexts
+    }
+
+    /// Return the number of records or alleles that were skipped for `reason`.
+    pub fn skipped(&self, reason: Reason) -> u64 {
+        self.skipped.get(&reason).cloned().unwrap_or(0)
+    }
+
+    /// Return the number of records or alleles that were skipped for any reason.
+    pub fn skipped_total(&self) -> u64 {
+        self.skipped.values().sum()
+    }
+
+    /// Return the number of genotypes that were masked for `reason`.
+    pub fn masked(&self, reason: Reason) -> u64 {
+        self.masked.get(&reason).cloned().unwrap_or(0)
+    }
+
+    /// Write the counters to the log.
+    pub fn log(&self) {
+        info!("Processed {} records", self.records());
+        // Records not matching the reference hint at the wrong reference, so stand out
+        let mismatches = self.skipped(Reason::RefMismatch);
+        if mismatches > 0 {
+            warn!(
+                "{} of {} records ({:.2}%) do not match the reference (REF_MISMATCH)",
+                mismatches,
+                self.records(),
+                100.0 * mismatches as f64 / self.records() as f64
+            );
+        }
+        for reason in self.skipped.keys() {
+            info!("Skipped {}: {}", reason, self.skipped(*reason));
+        }
+        if self.ignored_records > 0 {
+            info!(
+                "Ignored {} of {} records ({:.2}%)",
+                self.ignored_records,
+                self.records,
+                100.0 * self.ignored_records as f64 / self.records as f64
+            );
+        }
+        if self.partial_contexts() > 0 {
+            info!(
+                "Counted without context (AMBIGUOUS_CONTEXT): {}",
+                self.partial_contexts()
+            );
+        }
+        for reason in self.masked.keys() {
+            info!("Masked genotypes {}: {}", reason, self.masked(*reason));
+        }
+        for (strand, count) in &self.strands {
+            info!("Records located {}: {}", strand, count);
+        }
+        if self.overflowed {
+            warn!("Some counts overflowed and were saturated at the largest count");
+        }
+    }
+}
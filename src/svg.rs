@@ -0,0 +1,139 @@
+use std::fmt::Write;
+
+/// An SVG document assembled from basic shapes, with the origin in the upper left corner.
+pub struct Svg {
+    width: f64,
+    height: f64,
+    elements: Vec<String>,
+}
+
+/// The horizontal alignment of a text relative to its position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    Start,
+    Middle,
+    End,
+}
+
+impl Anchor {
+    fn as_str(self) -> &'static str {
+        match self {
+            Anchor::Start => "start",
+            Anchor::Middle => "middle",
+            Anchor::End => "end",
+        }
+    }
+}
+
+impl Svg {
+    pub fn new(width: f64, height: f64) -> Self {
+        Svg {
+            width,
+            height,
+            elements: Vec::new(),
+        }
+    }
+
+    /// Add a rectangle with the upper left corner at `x` and `y`, filled with the `fill` color.
+    pub fn rect(&mut self, x: f64, y: f64, width: f64, height: f64, fill: &str) {
+        self.elements.push(format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            round(x),
+            round(y),
+            round(width),
+            round(height),
+            escape(fill)
+        ));
+    }
+
+    /// Add a black line from `x1`, `y1` to `x2`, `y2`.
+    pub fn line(&mut self, x1: f64, y1: f64, x2: f64, y2: f64) {
+        self.elements.push(format!(
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"black\"/>",
+            round(x1),
+            round(y1),
+            round(x2),
+            round(y2)
+        ));
+    }
+
+    /// Add the `content` as text at `x`, `y`, aligned by the `anchor` and rotated clockwise by
+    /// `rotation` degrees around its position.
+    pub fn text(&mut self, x: f64, y: f64, content: &str, anchor: Anchor, rotation: f64) {
+        let mut element = format!(
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"{}\"",
+            round(x),
+            round(y),
+            anchor.as_str()
+        );
+        if rotation != 0.0 {
+            write!(
+                element,
+                " transform=\"rotate({} {} {})\"",
+                round(rotation),
+                round(x),
+                round(y)
+            )
+            .unwrap();
+        }
+        write!(element, ">{}</text>", escape(content)).unwrap();
+        self.elements.push(element);
+    }
+
+    /// Return the document.
+    pub fn render(&self) -> String {
+        let mut document = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" font-family=\"sans-serif\" font-size=\"12\">\n",
+            round(self.width),
+            round(self.height)
+        );
+        for element in &self.elements {
+            document.push_str(element);
+            document.push('\n');
+        }
+        document.push_str("</svg>\n");
+        document
+    }
+}
+
+/// Return the `value` rounded to two decimals, which suffices for coordinates.
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Return the `text` with the characters reserved in XML escaped.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut svg = Svg::new(100.0, 50.0);
+        svg.rect(1.0, 2.0, 10.0, 20.0 / 3.0, "#ff0000");
+        svg.line(0.0, 0.0, 100.0, 0.0);
+        svg.text(5.0, 5.0, "C>T & T>C", Anchor::End, -90.0);
+        assert_eq!(
+            svg.render(),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"100\" height=\"50\" viewBox=\"0 0 100 50\" font-family=\"sans-serif\" font-size=\"12\">\n\
+             <rect x=\"1\" y=\"2\" width=\"10\" height=\"6.67\" fill=\"#ff0000\"/>\n\
+             <line x1=\"0\" y1=\"0\" x2=\"100\" y2=\"0\" stroke=\"black\"/>\n\
+             <text x=\"5\" y=\"5\" text-anchor=\"end\" transform=\"rotate(-90 5 5)\">C&gt;T &amp; T&gt;C</text>\n\
+             </svg>\n"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! Count the allocations of the counting by a counting allocator. The test binary holds a
+//! single test, such that no other test allocates meanwhile.
+use mutsig::MutsigRunner;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The system allocator counting its allocations
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn testdata(name: &str) -> String {
+    format!("{}/testdata/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// Write a VCF file of `n` substitutions cycling through the positions of the example
+/// reference, each carried by one of the two samples, and return its path.
+fn write_vcf(name: &str, n: usize) -> String {
+    let mut vcf = String::from(
+        "##fileformat=VCFv4.2\n\
+         ##contig=<ID=1,length=6>\n\
+         ##contig=<ID=2,length=6>\n\
+         ##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n\
+         #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tExample1\tExample2\n",
+    );
+    let sequences = [("1", "TCATGA"), ("2", "TCTAGA")];
+    for i in 0..n {
+        let (contig, sequence) = sequences[i % 2];
+        let pos = 2 + (i / 2) % 4;
+        let reference = sequence.as_bytes()[pos - 1] as char;
+        let bases: Vec<char> = "ACGT".chars().filter(|b| *b != reference).collect();
+        let genotypes = if i % 3 == 0 { "0/1\t1/1" } else { "0/0\t0/1" };
+        vcf.push_str(&format!(
+            "{}\t{}\t.\t{}\t{}\t.\tPASS\t.\tGT\t{}\n",
+            contig,
+            pos,
+            reference,
+            bases[(i / 8) % 3],
+            genotypes
+        ));
+    }
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, vcf).unwrap();
+    path.to_str().unwrap().to_owned()
+}
+
+/// Count the variants of `vcf` and return the counts along with the number of allocations.
+fn count(vcf: &str) -> (Vec<u64>, usize) {
+    let mut runner = MutsigRunner::new(vcf, testdata("ex2.fa")).window(1);
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let counts = runner.run().unwrap();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    let mut cells: Vec<u64> = counts.results.column_iter(0).collect();
+    cells.extend(counts.results.column_iter(1));
+    (cells, allocations)
+}
+
+#[test]
+fn test_allocations_per_record() {
+    let small = write_vcf("mutsig_allocations_small.vcf", 1000);
+    let large = write_vcf("mutsig_allocations_large.vcf", 5000);
+    let (small_counts, small_allocations) = count(&small);
+    let (large_counts, large_allocations) = count(&large);
+    // One mutation per record and another one per haplotype of the homozygous calls
+    assert_eq!(small_counts.iter().sum::<u64>(), 1000 + 2 * 334);
+    assert!(large_counts.iter().sum::<u64>() > small_counts.iter().sum::<u64>());
+
+    // The buffers of the records are reused, such that only the records themselves, their
+    // alleles and genotypes and the bases of the reference are allocated
+    let per_record = (large_allocations - small_allocations) as f64 / 4000.0;
+    assert!(per_record < 10.0, "{} allocations per record", per_record);
+}
@@ -0,0 +1,46 @@
+//! Compile and run the C program of `tests/ffi` against the shared library.
+#![cfg(feature = "ffi")]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Return the directory of the shared library, i.e., the directory of the dependencies that
+/// holds this test, too.
+fn library_dir() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    exe.parent().unwrap().to_path_buf()
+}
+
+#[test]
+fn test_c_program() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let library_dir = library_dir();
+    let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi_count");
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+    let status = Command::new(compiler)
+        .arg(root.join("tests/ffi/count.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-L")
+        .arg(&library_dir)
+        .args(["-lmutsig", "-o"])
+        .arg(&program)
+        .status()
+        .expect("Can not run the C compiler");
+    assert!(status.success(), "Can not compile the C program");
+
+    let output = Command::new(&program)
+        .arg(root.join("testdata/ex2.vcf"))
+        .arg(root.join("testdata/ex2.fa"))
+        .env("LD_LIBRARY_PATH", &library_dir)
+        .env("DYLD_LIBRARY_PATH", &library_dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("C>A\t2\n"));
+}
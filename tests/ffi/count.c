@@ -0,0 +1,51 @@
+/* Count the variants of the example with the C interface and check the counts. */
+#include <inttypes.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "mutsig.h"
+
+#define CHECK(condition)                                                      \
+    if (!(condition)) {                                                       \
+        fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__,     \
+                #condition);                                                  \
+        return 1;                                                             \
+    }
+
+int main(int argc, char **argv) {
+    CHECK(argc == 3);
+    MutsigResult *result = NULL;
+    MutsigOptions options = mutsig_options_default();
+    MutsigStatus status = mutsig_count(argv[1], argv[2], &options, &result);
+    if (status != MUTSIG_STATUS_OK) {
+        fprintf(stderr, "Can not count: %s\n", mutsig_last_error());
+        return 1;
+    }
+
+    CHECK(mutsig_result_sample_count(result) == 2);
+    CHECK(strcmp(mutsig_result_sample(result, 0), "Example1") == 0);
+    CHECK(mutsig_result_sample(result, 2) == NULL);
+    CHECK(mutsig_result_channel_count(result) == 6);
+
+    uint64_t total = 0;
+    for (size_t channel = 0; channel < 6; channel++) {
+        const char *label = mutsig_result_channel(result, channel);
+        uint64_t count = 0;
+        CHECK(mutsig_result_count(result, channel, 0, &count) == MUTSIG_STATUS_OK);
+        if (strcmp(label, "C>A") == 0) {
+            CHECK(count == 2);
+        }
+        total += count;
+        printf("%s\t%" PRIu64 "\n", label, count);
+    }
+    CHECK(total > 0);
+    CHECK(mutsig_result_count(result, 6, 0, &total) == MUTSIG_STATUS_INVALID_ARGUMENT);
+    mutsig_result_free(result);
+
+    /* A missing file is reported as error with a message */
+    status = mutsig_count("missing.vcf", argv[2], NULL, &result);
+    CHECK(status == MUTSIG_STATUS_ERROR);
+    CHECK(result == NULL);
+    CHECK(strstr(mutsig_last_error(), "missing.vcf") != NULL);
+    return 0;
+}
@@ -0,0 +1,266 @@
+//! Drive the counting by the `MutsigRunner` on the example data.
+use mutsig::summary::Reason;
+use mutsig::{Error, MutsigRunner, Progress};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+fn testdata(name: &str) -> String {
+    format!("{}/testdata/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn runner(vcf: &str) -> MutsigRunner {
+    MutsigRunner::new(testdata(vcf), testdata("ex2.fa"))
+}
+
+#[test]
+fn test_samples_and_window() {
+    let counts = runner("ex2.vcf")
+        .window(1)
+        .samples(["Example2"])
+        .run()
+        .unwrap();
+    assert_eq!(counts.samples, vec!["Example2"]);
+    assert_eq!(counts.results.dimensions().1, 1);
+    assert_eq!(counts.signatures.channel_count(), 96);
+    assert_eq!(counts.summary.records(), 2);
+}
+
+#[test]
+fn test_unknown_sample() {
+    let mut runner = runner("ex2.vcf").samples(["Example3"]);
+    // Whether the sample exists is only known once the VCF file is read
+    assert!(runner.error().is_none());
+    match runner.run() {
+        Err(Error::UnknownSample { name, available }) => {
+            assert_eq!(name, "Example3");
+            assert_eq!(available, vec!["Example1", "Example2"]);
+        }
+        other => panic!("Expected an unknown sample, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_invalid_options() {
+    let mut runner = runner("ex2.vcf").window(1).min_vaf(0.8).max_vaf(0.2);
+    assert!(matches!(runner.error(), Some(Error::InvalidOptions(_))));
+    assert!(matches!(runner.run(), Err(Error::InvalidOptions(_))));
+}
+
+#[test]
+fn test_progress() {
+    let records = Rc::new(Cell::new(0));
+    let reported = Rc::clone(&records);
+    let counts = runner("ex2_filter.vcf")
+        .progress(move |n| reported.set(n))
+        .run()
+        .unwrap();
+    assert_eq!(records.get(), 5);
+    assert_eq!(counts.summary.records(), 5);
+}
+
+#[test]
+fn test_report_progress() {
+    let report = |vcf: &str| {
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let reported = Rc::clone(&reports);
+        let counts = runner(vcf)
+            .window(1)
+            .pass_only(true)
+            .report_progress(move |p: &Progress| {
+                reported.borrow_mut().push((
+                    p.records,
+                    p.total,
+                    p.contig.map(str::to_owned),
+                    p.position,
+                    p.skipped,
+                ))
+            })
+            .run()
+            .unwrap();
+        let reports = reports.borrow().clone();
+        (counts, reports)
+    };
+
+    // The total is listed by the index of the VCF file
+    let (counts, reports) = report("ex2_indexed.vcf.gz");
+    assert_eq!(reports.len(), 9);
+    assert_eq!(reports[0], (1, Some(9), Some("1".to_owned()), Some(2), 0));
+    assert_eq!(reports[8].0, 9);
+    assert_eq!(reports[8].2.as_deref(), Some("2"));
+    assert_eq!(reports[8].3, Some(5));
+    assert!(reports.iter().all(|r| r.1 == Some(9)));
+    // The records are skipped after they are reported
+    assert!(reports.windows(2).all(|w| w[0].4 <= w[1].4));
+    assert!(reports[8].4 <= counts.summary.skipped_total());
+
+    let (_, reports) = report("ex2_filter.vcf");
+    assert_eq!(reports.len(), 5);
+    assert!(reports.iter().all(|r| r.1.is_none()));
+}
+
+#[test]
+fn test_pass_only() {
+    let counted = |pass_only| {
+        let counts = runner("ex2_filter.vcf").pass_only(pass_only).run().unwrap();
+        counts.results.column_iter(0).sum::<u64>()
+    };
+    assert_eq!(counted(false), 5);
+    // Only the records with FILTER PASS or missing
+    assert_eq!(counted(true), 2);
+}
+
+/// Write a VCF file of `n` records cycling through the positions of the example reference
+/// with varying alleles, filters and genotypes, and return its path.
+fn write_vcf(name: &str, n: usize) -> String {
+    let mut vcf = String::from(
+        "##fileformat=VCFv4.2\n\
+         ##FILTER=<ID=PASS,Description=\"All filters passed\">\n\
+         ##FILTER=<ID=LowQual,Description=\"Low quality\">\n\
+         ##contig=<ID=1,length=6>\n\
+         ##contig=<ID=2,length=6>\n\
+         ##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n\
+         #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tExample1\tExample2\n",
+    );
+    let sequences = [("1", "TCATGA"), ("2", "TCTAGA")];
+    let genotypes = ["0/1", "1/1", "0/0", "./."];
+    for i in 0..n {
+        let (contig, sequence) = sequences[i % 2];
+        let pos = 2 + (i / 2) % 4;
+        let reference = sequence.as_bytes()[pos - 1] as char;
+        let alternative = if i % 7 == 0 {
+            "<DEL>".to_owned()
+        } else {
+            let bases: Vec<char> = "ACGT".chars().filter(|b| *b != reference).collect();
+            bases[(i / 8) % 3].to_string()
+        };
+        vcf.push_str(&format!(
+            "{}\t{}\t.\t{}\t{}\t.\t{}\t.\tGT\t{}\t{}\n",
+            contig,
+            pos,
+            reference,
+            alternative,
+            if i % 3 == 0 { "LowQual" } else { "PASS" },
+            genotypes[i % 4],
+            genotypes[(i / 4) % 4]
+        ));
+    }
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, vcf).unwrap();
+    path.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn test_threads() {
+    // More records than are classified in one batch
+    let vcf = write_vcf("mutsig_test_threads.vcf", 2500);
+    let count = |threads: usize, min_distance: bool| {
+        let skipped = std::env::temp_dir().join(format!(
+            "mutsig_test_threads_{}_{}.tsv",
+            threads, min_distance
+        ));
+        let mut runner = MutsigRunner::new(vcf.clone(), testdata("ex2.fa"))
+            .window(1)
+            .threads(threads)
+            .pass_only(true)
+            .skipped(skipped.to_str().unwrap());
+        if min_distance {
+            // Filtered in the order of the records
+            runner = runner.min_distance(2);
+        }
+        let counts = runner.run().unwrap();
+        let log = std::fs::read_to_string(&skipped).unwrap();
+        (counts, log)
+    };
+    for min_distance in [false, true] {
+        let (expected, expected_log) = count(1, min_distance);
+        let (counts, log) = count(4, min_distance);
+        assert_eq!(counts.summary.records(), 2500);
+        for reason in [Reason::Filtered, Reason::Symbolic, Reason::Clustered] {
+            assert_eq!(
+                counts.summary.skipped(reason),
+                expected.summary.skipped(reason)
+            );
+            assert_eq!(
+                counts.summary.masked(reason),
+                expected.summary.masked(reason)
+            );
+        }
+        assert!(expected.summary.skipped(Reason::Filtered) > 0);
+        assert_eq!(log, expected_log);
+        let (n_variants, n_samples) = expected.results.dimensions();
+        assert_eq!(counts.results.dimensions(), (n_variants, n_samples));
+        for v in 0..n_variants {
+            for s in 0..n_samples {
+                assert_eq!(counts.results.get(v, s), expected.results.get(v, s));
+            }
+        }
+        assert!(expected.results.column_iter(0).sum::<u64>() > 0);
+    }
+}
+
+#[test]
+fn test_indexed_contigs() {
+    let count = |threads: usize, min_distance: bool| {
+        let skipped = std::env::temp_dir().join(format!(
+            "mutsig_test_indexed_{}_{}.tsv",
+            threads, min_distance
+        ));
+        let reports = Rc::new(Cell::new(0));
+        let reported = Rc::clone(&reports);
+        let mut runner = runner("ex2_indexed.vcf.gz")
+            .window(1)
+            .threads(threads)
+            .pass_only(true)
+            .skipped(skipped.to_str().unwrap())
+            .progress(move |_| reported.set(reported.get() + 1));
+        if min_distance {
+            // Filtered within each contig
+            runner = runner.min_distance(2);
+        }
+        let counts = runner.run().unwrap();
+        let log = std::fs::read_to_string(&skipped).unwrap();
+        (counts, log, reports.get())
+    };
+    for min_distance in [false, true] {
+        let (expected, expected_log, expected_reports) = count(1, min_distance);
+        let (counts, log, reports) = count(3, min_distance);
+        // Reported once per record, or once per contig if counted in parallel
+        assert_eq!(expected_reports, 9);
+        assert_eq!(reports, 3);
+        assert_eq!(counts.summary.records(), 9);
+        for reason in [Reason::Filtered, Reason::Symbolic, Reason::Clustered] {
+            assert_eq!(
+                counts.summary.skipped(reason),
+                expected.summary.skipped(reason)
+            );
+            assert_eq!(
+                counts.summary.masked(reason),
+                expected.summary.masked(reason)
+            );
+        }
+        assert_eq!(counts.summary.skipped(Reason::Filtered), 2);
+        assert_eq!(log, expected_log);
+        assert_eq!(counts.results.dimensions(), expected.results.dimensions());
+        for s in 0..2 {
+            assert_eq!(
+                counts.results.column_iter(s).collect::<Vec<u64>>(),
+                expected.results.column_iter(s).collect::<Vec<u64>>()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_cache_reference() {
+    let count = |cache: bool| {
+        let counts = runner("ex2_indexed.vcf.gz")
+            .window(1)
+            .cache_reference(cache)
+            .run()
+            .unwrap();
+        counts.results.column_iter(1).collect::<Vec<u64>>()
+    };
+    let expected = count(false);
+    assert!(expected.iter().sum::<u64>() > 0);
+    assert_eq!(count(true), expected);
+}